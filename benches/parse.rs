@@ -0,0 +1,196 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use haproxy::{write_fields_into, Field, LineReader, LogEntry, Plan};
+
+
+const LINES: usize = 10_000;
+const URIS: &[&str] = &[
+    "/",
+    "/index.html",
+    "/static/app.js",
+    "/api/v1/items?page=2",
+    "/api/v1/users/42",
+    "/search?q=haproxy",
+];
+
+// the corpora every benchmark runs over. like haproxy-gen's output, but fixed so numbers stay
+// comparable between runs and machines.
+struct Corpus {
+    name: &'static str,
+    captures: usize,
+    // the length of the query string added to every URI.
+    query: usize,
+}
+
+const CORPORA: &[Corpus] = &[
+    Corpus { name: "plain", captures: 0, query: 0 },
+    Corpus { name: "captures", captures: 2, query: 0 },
+    Corpus { name: "long_uris", captures: 0, query: 400 },
+];
+
+// xorshift64*, as in haproxy-gen.
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 33) as usize % n
+    }
+}
+
+impl Corpus {
+    fn generate(&self) -> Vec<u8> {
+        let mut rng = Rng { state: 0x9e37_79b9_7f4a_7c15 };
+        let mut out = String::new();
+        for i in 0..LINES {
+            let status = [200, 200, 200, 200, 304, 404, 503][rng.below(7)];
+            out.push_str(&format!(
+                "haproxy[14389]: 10.0.{}.{}:{} [06/Feb/2009:12:{:02}:{:02}.{:03}] http-in \
+                 backend{}/srv{} {}/0/{}/{}/{} {} {} - - ---- {}/{}/1/1/0 0/0 ",
+                rng.below(256), 1 + rng.below(254), 1024 + rng.below(64000), i / 60 % 60, i % 60,
+                rng.below(1000), 1 + rng.below(3), 1 + rng.below(2), rng.below(5), rng.below(3),
+                rng.below(800), rng.below(1000), status, 200 + rng.below(20000), rng.below(200),
+                rng.below(200)));
+            if self.captures >= 1 {
+                out.push_str("{www.example.com|Mozilla/5.0} ");
+            }
+            if self.captures >= 2 {
+                out.push_str("{text/html} ");
+            }
+            let uri = URIS[rng.below(URIS.len())];
+            let query = "q".repeat(self.query);
+            out.push_str(&format!("\"GET {}{} HTTP/1.1\"\n", uri, query));
+        }
+        out.into_bytes()
+    }
+}
+
+fn lines(corpus: &[u8]) -> impl Iterator<Item = &[u8]> {
+    corpus.split(|&c| c == b'\n').filter(|line| !line.is_empty())
+}
+
+fn fields(names: &str) -> Vec<Field> {
+    names.split(',').map(|name| Field::decode(name).unwrap()).collect()
+}
+
+// splitting the whole line into fields, with both parsers and a buffer at a time.
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for corpus in CORPORA {
+        let input = corpus.generate();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::new("from_bytes", corpus.name), &input, |b, input| {
+            b.iter(|| {
+                for line in lines(input) {
+                    black_box(LogEntry::from_bytes(black_box(line)).ok());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("single_pass", corpus.name), &input,
+                               |b, input| {
+            b.iter(|| {
+                for line in lines(input) {
+                    black_box(LogEntry::from_bytes_single_pass(black_box(line)).ok());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parse_many", corpus.name), &input, |b, input| {
+            let mut entries = Vec::new();
+            b.iter(|| black_box(LogEntry::parse_many(input, &mut entries)))
+        });
+    }
+    group.finish();
+}
+
+// parsing only as far as a few leading fields need, as haproxy-cut does.
+fn lazy_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lazy_parse");
+    let mut plan = Plan::new();
+    for field in fields("ip,status_code") {
+        plan.add_field(&field);
+    }
+    for corpus in CORPORA {
+        let input = corpus.generate();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::new("plan", corpus.name), &input, |b, input| {
+            b.iter(|| {
+                for line in lines(input) {
+                    black_box(plan.parse(black_box(line)).ok());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// getting at fields of an entry which is already parsed, as slices and converted.
+fn extract(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extract");
+    let input = CORPORA[0].generate();
+    let entries: Vec<LogEntry> = lines(&input).map(|line| LogEntry::from_bytes(line).unwrap())
+        .collect();
+    let selected = fields("ip,backend_name,status_code,Tt,http_uri");
+    group.throughput(Throughput::Elements(entries.len() as u64));
+    group.bench_function("slices", |b| {
+        b.iter(|| {
+            for entry in &entries {
+                for field in &selected {
+                    black_box(field.extract_content_from(entry));
+                }
+            }
+        })
+    });
+    group.bench_function("integers", |b| {
+        b.iter(|| {
+            for entry in &entries {
+                black_box(entry.status_code().ok());
+                black_box(entry.total_time().ok());
+                black_box(entry.bytes_read().ok());
+            }
+        })
+    });
+    group.bench_function("accept_date", |b| {
+        b.iter(|| {
+            for entry in &entries {
+                black_box(entry.accept_date_time().ok());
+            }
+        })
+    });
+    group.finish();
+}
+
+// what haproxy-cut -f does to each line without colors or dates: read it, parse it as far as the
+// fields need and write them out.
+fn cut(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cut");
+    let selected = fields("ip,status_code,Tt,http_uri");
+    let mut plan = Plan::new();
+    for field in &selected {
+        plan.add_field(field);
+    }
+    for corpus in CORPORA {
+        let input = corpus.generate();
+        let mut output = Vec::with_capacity(input.len());
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(corpus.name), &input, |b, input| {
+            b.iter(|| {
+                output.clear();
+                let mut reader = LineReader::new(&input[..]);
+                while let Some(line) = reader.next_line().unwrap() {
+                    if let Ok(entry) = plan.parse(line) {
+                        write_fields_into(&mut output, &entry, &selected, b"\t").unwrap();
+                    }
+                }
+                black_box(&output);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, lazy_parse, extract, cut);
+criterion_main!(benches);
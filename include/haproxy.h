@@ -0,0 +1,76 @@
+/* the C interface to haproxy.rs's log parser, generated by the `header` test in src/ffi.rs with
+ * HAPROXY_WRITE_HEADER=1 cargo test --features ffi. don't edit it by hand. */
+#ifndef HAPROXY_H
+#define HAPROXY_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* the ids of an entry's fields, in the order they're logged. */
+enum haproxy_field_id {
+    HAPROXY_FIELD_PROCESS_NAME = 0,
+    HAPROXY_FIELD_PID = 1,
+    HAPROXY_FIELD_CLIENT_IP = 2,
+    HAPROXY_FIELD_CLIENT_PORT = 3,
+    HAPROXY_FIELD_ACCEPT_DATE = 4,
+    HAPROXY_FIELD_FRONTEND_NAME = 5,
+    HAPROXY_FIELD_BACKEND_NAME = 6,
+    HAPROXY_FIELD_SERVER_NAME = 7,
+    HAPROXY_FIELD_REQUEST_TIME = 8,
+    HAPROXY_FIELD_QUEUE_TIME = 9,
+    HAPROXY_FIELD_CONNECT_TIME = 10,
+    HAPROXY_FIELD_RESPONSE_TIME = 11,
+    HAPROXY_FIELD_TOTAL_TIME = 12,
+    HAPROXY_FIELD_STATUS_CODE = 13,
+    HAPROXY_FIELD_BYTES_READ = 14,
+    HAPROXY_FIELD_CAPTURED_REQUEST_COOKIE = 15,
+    HAPROXY_FIELD_CAPTURED_RESPONSE_COOKIE = 16,
+    HAPROXY_FIELD_TERMINATION_STATE = 17,
+    HAPROXY_FIELD_ACTIVE_CONNECTIONS = 18,
+    HAPROXY_FIELD_FRONTEND_CONNECTIONS = 19,
+    HAPROXY_FIELD_BACKEND_CONNECTIONS = 20,
+    HAPROXY_FIELD_SERVER_CONNECTIONS = 21,
+    HAPROXY_FIELD_RETRIED_CONNECTIONS = 22,
+    HAPROXY_FIELD_SERVER_QUEUE = 23,
+    HAPROXY_FIELD_BACKEND_QUEUE = 24,
+    HAPROXY_FIELD_CAPTURED_REQUEST_HEADERS = 25,
+    HAPROXY_FIELD_CAPTURED_RESPONSE_HEADERS = 26,
+    HAPROXY_FIELD_HTTP_REQUEST = 27,
+    HAPROXY_FIELD_COUNT = 28
+};
+
+/* a field of the line that was parsed. it isn't nul terminated. */
+struct haproxy_field {
+    const char *data;
+    size_t len;
+};
+
+/* the fields of a parsed line, which point into the line and are good for as long as it is. */
+struct haproxy_entry {
+    struct haproxy_field fields[HAPROXY_FIELD_COUNT];
+};
+
+/* parses the `len` bytes at `line` into `entry`, a trailing newline is fine. 0 when the line
+ * parsed and -1 when it didn't, `entry` is left as it was then. */
+int haproxy_parse_line(const char *line, size_t len, struct haproxy_entry *entry);
+
+/* a field of `entry` with its length in `len`, which may be null, or null for an unknown id. */
+const char *haproxy_entry_field(const struct haproxy_entry *entry, int field, size_t *len);
+
+/* a numeric field of `entry` in `value`. 0 when it is a number and -1 when it isn't, such as
+ * for the names. the '+' haproxy puts before Tt and the retries is skipped, a -1 timer is -1. */
+int haproxy_entry_number(const struct haproxy_entry *entry, int field, int64_t *value);
+
+/* accept_date in `millis`, milliseconds since the epoch as though haproxy's local time were
+ * utc. 0 when the date parsed and -1 when it didn't. */
+int haproxy_entry_accept_date_ms(const struct haproxy_entry *entry, int64_t *millis);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,331 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+use crate::entry::LogEntry;
+use crate::field::Field;
+use crate::integer::parse_field;
+use crate::json::FieldSet;
+
+// the compression TDigest::default uses. larger keeps more centroids, about this many, and gives
+// more accurate quantiles.
+pub const DEFAULT_COMPRESSION: f64 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+// estimates quantiles of a stream of values without keeping them, in memory bounded by the
+// compression. this is the merging t-digest: values are buffered, then merged into centroids which
+// are kept small near either end of the distribution, so the extreme quantiles like p99 stay
+// accurate. the minimum and maximum are exact.
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    compression: f64,
+    // sorted by mean.
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> TDigest {
+        TDigest::new(DEFAULT_COMPRESSION)
+    }
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> TDigest {
+        TDigest {
+            compression: compression.max(10.0),
+            centroids: vec![],
+            buffer: vec![],
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    // NaN isn't anywhere in the distribution, so it's ignored.
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.push(Centroid { mean: value, weight: 1.0 }, value, value);
+        self.count += 1;
+        self.sum += value;
+    }
+
+    // adds everything `other` has seen, as if its values had been added to this one.
+    pub fn merge(&mut self, other: &TDigest) {
+        if other.count == 0 {
+            return;
+        }
+        for &centroid in other.centroids.iter().chain(&other.buffer) {
+            self.push(centroid, other.min, other.max);
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+
+    fn push(&mut self, centroid: Centroid, min: f64, max: f64) {
+        self.min = self.min.min(min);
+        self.max = self.max.max(max);
+        self.buffer.push(centroid);
+        if self.buffer.len() >= 5 * self.compression as usize {
+            self.centroids = self.compressed().into_owned();
+            self.buffer.clear();
+        }
+    }
+
+    // the centroids with the buffer merged in.
+    fn compressed(&self) -> Cow<'_, [Centroid]> {
+        if self.buffer.is_empty() {
+            return Cow::Borrowed(&self.centroids);
+        }
+        let mut all: Vec<Centroid> = self.centroids.iter().chain(&self.buffer).cloned().collect();
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+
+        // a centroid may grow until it spans one unit of the scale function k, which changes
+        // quickly near q = 0 and 1, so the centroids there stay small.
+        let k = |q: f64| self.compression / (2.0 * PI) * (2.0 * q - 1.0).asin();
+        let q_of = |k: f64| {
+            let x = k * 2.0 * PI / self.compression;
+            if x >= PI / 2.0 { 1.0 } else { (x.sin() + 1.0) / 2.0 }
+        };
+
+        let mut merged = Vec::with_capacity(all.len().min(self.compression as usize * 2));
+        let mut so_far = 0.0;
+        let mut current = all[0];
+        let mut limit = total * q_of(k(0.0) + 1.0);
+        for &next in &all[1..] {
+            if so_far + current.weight + next.weight <= limit {
+                let weight = current.weight + next.weight;
+                current.mean += (next.mean - current.mean) * next.weight / weight;
+                current.weight = weight;
+            } else {
+                so_far += current.weight;
+                merged.push(current);
+                limit = total * q_of(k(so_far / total) + 1.0);
+                current = next;
+            }
+        }
+        merged.push(current);
+        Cow::Owned(merged)
+    }
+
+    // the estimated value at quantile `q`, from 0 to 1, or none if nothing was added. values are
+    // interpolated between the centroids' means, and centroids of a single value are exact.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        if q <= 0.0 {
+            return Some(self.min);
+        }
+        if q >= 1.0 {
+            return Some(self.max);
+        }
+        let centroids = self.compressed();
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        let index = q * total;
+
+        // each centroid's mean is taken to be at the middle of the weight it covers.
+        let first = centroids[0];
+        if index < first.weight / 2.0 {
+            if first.weight == 1.0 {
+                return Some(self.min);
+            }
+            return Some(self.min + (first.mean - self.min) * index / (first.weight / 2.0));
+        }
+        let mut so_far = 0.0;
+        for pair in centroids.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            let left_middle = so_far + left.weight / 2.0;
+            let right_middle = so_far + left.weight + right.weight / 2.0;
+            if index < right_middle {
+                // the weight either side of a single value is that value.
+                if left.weight == 1.0 && index - left_middle <= 0.5 {
+                    return Some(left.mean);
+                }
+                if right.weight == 1.0 && right_middle - index < 0.5 {
+                    return Some(right.mean);
+                }
+                let fraction = (index - left_middle) / (right_middle - left_middle);
+                return Some(left.mean + (right.mean - left.mean) * fraction);
+            }
+            so_far += left.weight;
+        }
+        let last = centroids[centroids.len() - 1];
+        if last.weight == 1.0 {
+            return Some(self.max);
+        }
+        let into = (index - (total - last.weight / 2.0)) / (last.weight / 2.0);
+        Some(last.mean + (self.max - last.mean) * into.min(1.0))
+    }
+
+    // how many values were added.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        if self.count == 0 { None } else { Some(self.min) }
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        if self.count == 0 { None } else { Some(self.max) }
+    }
+}
+
+// a TDigest of a numeric field per group of entries with the same values of the group fields, e.g.
+// Tt by frontend and backend. entries where the value wasn't logged or is negative, like a timer of
+// -1 for a session which never got that far, aren't added.
+#[derive(Clone, Debug)]
+pub struct GroupedDigests {
+    groups: FieldSet,
+    value: Field,
+    compression: f64,
+    digests: BTreeMap<Vec<Vec<u8>>, TDigest>,
+}
+
+impl GroupedDigests {
+    pub fn new(groups: FieldSet, value: Field) -> GroupedDigests {
+        GroupedDigests {
+            groups,
+            value,
+            compression: DEFAULT_COMPRESSION,
+            digests: BTreeMap::new(),
+        }
+    }
+
+    // the compression of digests created from now on.
+    pub fn set_compression(&mut self, compression: f64) {
+        self.compression = compression;
+    }
+
+    pub fn add(&mut self, entry: &LogEntry) {
+        let field = self.value.extract_content_from(entry);
+        let value = match parse_field(field) {
+            Some(value) if value >= 0 => value,
+            _ => return,
+        };
+        let key = self.groups.iter()
+            .map(|(_, field)| field.extract_content_from(entry).to_vec())
+            .collect();
+        let compression = self.compression;
+        self.digests.entry(key).or_insert_with(|| TDigest::new(compression)).add(value as f64);
+    }
+
+    // the digest of the group with these values of the group fields, in order.
+    pub fn get(&self, key: &[&[u8]]) -> Option<&TDigest> {
+        let key: Vec<Vec<u8>> = key.iter().map(|value| value.to_vec()).collect();
+        self.digests.get(&key)
+    }
+
+    // each group's values of the group fields and its digest, ordered by the values.
+    pub fn iter(&self) -> impl Iterator<Item = (&[Vec<u8>], &TDigest)> {
+        self.digests.iter().map(|(key, digest)| (&key[..], digest))
+    }
+
+    // the names of the group fields, as they were given.
+    pub fn group_names(&self) -> impl Iterator<Item = &str> {
+        self.groups.iter().map(|(name, _)| name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.digests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GroupedDigests, TDigest};
+    use crate::entry::LogEntry;
+    use crate::field::Field;
+    use crate::json::FieldSet;
+
+    #[test]
+    fn quantiles() {
+        let mut digest = TDigest::default();
+        assert_eq!(digest.quantile(0.5), None);
+        for value in 1..=5 {
+            digest.add(value as f64);
+        }
+        assert_eq!(digest.quantile(0.5), Some(3.0));
+        assert_eq!(digest.quantile(0.99), Some(5.0));
+        assert_eq!(digest.quantile(0.0), Some(1.0));
+
+        // shuffled, so the buffer isn't merged in order.
+        let mut digest = TDigest::default();
+        for i in 0..100_000u64 {
+            digest.add((i * 7919 % 100_000) as f64);
+        }
+        assert_eq!(digest.count(), 100_000);
+        assert_eq!(digest.min(), Some(0.0));
+        assert_eq!(digest.max(), Some(99_999.0));
+        for &(q, expected) in &[(0.5, 50_000.0), (0.9, 90_000.0), (0.99, 99_000.0),
+                                (0.999, 99_900.0)] {
+            let estimate = digest.quantile(q).unwrap();
+            assert!((estimate - expected).abs() < 100_000.0 * (1.0 - q) * 0.05 + 10.0,
+                    "p{} was {}", q * 100.0, estimate);
+        }
+
+        let mut halves = (TDigest::default(), TDigest::default());
+        for i in 0..10_000 {
+            if i % 2 == 0 { halves.0.add(i as f64) } else { halves.1.add(i as f64) }
+        }
+        halves.0.merge(&halves.1);
+        assert_eq!(halves.0.count(), 10_000);
+        assert_eq!(halves.0.max(), Some(9_999.0));
+        assert!((halves.0.quantile(0.99).unwrap() - 9_900.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn groups() {
+        let lines: &[&[u8]] = &[
+            b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.2:33318 [06/Feb/2009:12:14:15.655] http-in static/srv2 \
+              10/0/30/2000/+2109 200 250 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.3:33319 [06/Feb/2009:12:14:16.655] http-in api/srv1 \
+              10/0/30/69/20 200 250 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.3:33319 [06/Feb/2009:12:14:16.655] http-in api/<NOSRV> \
+              -1/-1/-1/-1/-1 -1 0 - - CQ-- 1/1/1/0/0 0/5 \"GET / HTTP/1.1\"",
+        ];
+        let mut digests = GroupedDigests::new(FieldSet::parse("frontend,backend").unwrap(),
+                                              Field::TotalTime);
+        for line in lines {
+            digests.add(&LogEntry::from_bytes(line).unwrap());
+        }
+        assert_eq!(digests.len(), 2);
+        assert_eq!(digests.group_names().collect::<Vec<_>>(), ["frontend", "backend"]);
+
+        let stat = digests.get(&[b"http-in", b"static"]).unwrap();
+        assert_eq!(stat.count(), 2);
+        assert_eq!(stat.quantile(1.0), Some(2109.0));
+        assert_eq!(stat.quantile(0.5), Some(109.0));
+        let api = digests.get(&[b"http-in", b"api"]).unwrap();
+        assert_eq!(api.count(), 1);
+        assert_eq!(api.quantile(0.99), Some(20.0));
+        assert!(digests.get(&[b"http-in", b"missing"]).is_none());
+
+        let keys: Vec<&[Vec<u8>]> = digests.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys[0], [b"http-in".to_vec(), b"api".to_vec()]);
+    }
+}
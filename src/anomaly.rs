@@ -0,0 +1,310 @@
+use chrono::{DateTime, NaiveDateTime};
+
+use crate::agg::TDigest;
+use crate::entry::LogEntry;
+
+// how long a gap in the entries can be and still be taken as minutes without requests, rather than
+// the log being interrupted.
+const MAX_GAP_MINUTES: i64 = 60;
+
+// what AnomalyDetector watches, per minute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Signal {
+    // requests per second.
+    RequestRate,
+    // the percentage of requests with a 5xx or no response at all.
+    ErrorRate,
+    // the 95th percentile of Tt in milliseconds.
+    Latency,
+}
+
+impl Signal {
+    pub const ALL: [Signal; 3] = [Signal::RequestRate, Signal::ErrorRate, Signal::Latency];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::RequestRate => "rate",
+            Signal::ErrorRate => "error_rate",
+            Signal::Latency => "p95",
+        }
+    }
+
+    // the smallest deviation that counts as normal noise, so a signal which has been flat doesn't
+    // make every small change an anomaly: 3 requests a minute, a percentage point of errors or a
+    // millisecond.
+    fn min_scale(self) -> f64 {
+        match self {
+            Signal::RequestRate => 0.05,
+            Signal::ErrorRate => 1.0,
+            Signal::Latency => 1.0,
+        }
+    }
+}
+
+// a minute where a signal was far from what was expected of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AnomalyEvent {
+    pub signal: Signal,
+    // when the minute started, in the time zone entries were logged in.
+    pub start: NaiveDateTime,
+    pub value: f64,
+    pub expected: f64,
+    // how many typical deviations from the expected value it was, negative when below it.
+    pub score: f64,
+}
+
+// an exponentially weighted moving average of a signal and of its absolute deviation from that
+// average, which is less thrown by the odd outlier than a standard deviation.
+#[derive(Clone, Copy, Debug, Default)]
+struct Baseline {
+    mean: f64,
+    deviation: f64,
+    seen: u64,
+    score: Option<f64>,
+}
+
+impl Baseline {
+    fn scale(&self, signal: Signal) -> f64 {
+        // the mean absolute deviation of a normal distribution is about 0.8 of its standard
+        // deviation.
+        (1.25 * self.deviation).max(0.05 * self.mean.abs()).max(signal.min_scale())
+    }
+
+    fn observe(&mut self, signal: Signal, value: f64, detector: &AnomalyDetector) -> Option<f64> {
+        let scale = self.scale(signal);
+        self.score = if self.seen >= detector.warmup {
+            Some((value - self.mean) / scale)
+        } else {
+            None
+        };
+
+        // start with a plain average so the first minutes don't weigh too much, and once warmed up
+        // clamp what's learned from outliers, so one bad minute doesn't become the new normal.
+        let alpha = detector.smoothing.max(1.0 / (self.seen + 1) as f64);
+        let learned = if self.seen >= detector.warmup {
+            let limit = detector.threshold * scale;
+            value.clamp(self.mean - limit, self.mean + limit)
+        } else {
+            value
+        };
+        if self.seen == 0 {
+            self.mean = learned;
+        } else {
+            self.deviation += alpha * ((learned - self.mean).abs() - self.deviation);
+            self.mean += alpha * (learned - self.mean);
+        }
+        self.seen += 1;
+        self.score
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Minute {
+    requests: u64,
+    errors: u64,
+    latencies: TDigest,
+}
+
+// online anomaly detection over the request rate, error rate and p95 latency of a stream of
+// entries, a minute at a time. each minute is scored against a moving baseline of the minutes
+// before it, and yields an AnomalyEvent when its score is at least the threshold: for the request
+// rate in either direction, since traffic stopping matters as much as a flood, and for errors and
+// latency only when they're higher than expected.
+#[derive(Clone, Debug)]
+pub struct AnomalyDetector {
+    threshold: f64,
+    smoothing: f64,
+    warmup: u64,
+    // the current minute, as minutes since the epoch, and what's been seen of it.
+    current: Option<(i64, Minute)>,
+    baselines: [Baseline; 3],
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> AnomalyDetector {
+        AnomalyDetector::new()
+    }
+}
+
+impl AnomalyDetector {
+    pub fn new() -> AnomalyDetector {
+        AnomalyDetector {
+            threshold: 4.0,
+            smoothing: 0.1,
+            warmup: 15,
+            current: None,
+            baselines: [Baseline::default(); 3],
+        }
+    }
+
+    // the score at which a minute is an anomaly, 4 by default.
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = threshold;
+    }
+
+    // how much weight each minute gets in the baseline, between 0 and 1. 0.1 by default; larger
+    // follows changes in traffic more quickly.
+    pub fn set_smoothing(&mut self, smoothing: f64) {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+    }
+
+    // how many minutes to learn a baseline from before scoring any, 15 by default.
+    pub fn set_warmup(&mut self, minutes: u64) {
+        self.warmup = minutes;
+    }
+
+    // counts `entry` in the minute it was accepted, returning the anomalies of the minutes it shows
+    // have ended. entries are expected in roughly chronological order; late ones are counted in
+    // the current minute, and ones whose accept_date doesn't parse are left out.
+    pub fn add(&mut self, entry: &LogEntry) -> Vec<AnomalyEvent> {
+        let minute = match entry.accept_date_time() {
+            Ok(accepted) => accepted.and_utc().timestamp().div_euclid(60),
+            Err(_) => return vec![],
+        };
+        let events = self.advance_to(minute);
+        let (_, current) = self.current.get_or_insert_with(|| (minute, Minute::default()));
+        current.requests += 1;
+        if !entry.status_code().is_ok_and(|status| (100..500).contains(&status)) {
+            current.errors += 1;
+        }
+        if let Ok(total_time) = entry.total_time() {
+            if total_time >= 0 {
+                current.latencies.add(total_time as f64);
+            }
+        }
+        events
+    }
+
+    // ends the minutes before `now`, for when no entries are arriving to do it, e.g. because
+    // traffic stopped. `now` is in the time zone entries are logged in.
+    pub fn advance(&mut self, now: NaiveDateTime) -> Vec<AnomalyEvent> {
+        self.advance_to(now.and_utc().timestamp().div_euclid(60))
+    }
+
+    // ends the current minute, at the end of the stream.
+    pub fn finish(&mut self) -> Vec<AnomalyEvent> {
+        let mut events = vec![];
+        if let Some((start, minute)) = self.current.take() {
+            self.close(start, &minute, &mut events);
+        }
+        events
+    }
+
+    // the score of `signal` in the last minute which ended, none while warming up or if that
+    // minute had no requests to measure it by.
+    pub fn score(&self, signal: Signal) -> Option<f64> {
+        self.baselines[signal as usize].score
+    }
+
+    fn advance_to(&mut self, minute: i64) -> Vec<AnomalyEvent> {
+        let mut events = vec![];
+        let start = match self.current {
+            Some((start, _)) if start < minute => start,
+            _ => return events,
+        };
+        if let Some((_, ended)) = self.current.take() {
+            self.close(start, &ended, &mut events);
+        }
+        if minute - start <= MAX_GAP_MINUTES {
+            for empty in start + 1..minute {
+                self.close(empty, &Minute::default(), &mut events);
+            }
+        }
+        self.current = Some((minute, Minute::default()));
+        events
+    }
+
+    fn close(&mut self, start: i64, minute: &Minute, events: &mut Vec<AnomalyEvent>) {
+        let start = DateTime::from_timestamp(start * 60, 0).map(|start| start.naive_utc());
+        for signal in Signal::ALL {
+            let value = match signal {
+                Signal::RequestRate => Some(minute.requests as f64 / 60.0),
+                _ if minute.requests == 0 => None,
+                Signal::ErrorRate => Some(100.0 * minute.errors as f64 / minute.requests as f64),
+                Signal::Latency => minute.latencies.quantile(0.95),
+            };
+            let mut baseline = self.baselines[signal as usize];
+            let value = match value {
+                Some(value) => value,
+                None => {
+                    self.baselines[signal as usize].score = None;
+                    continue;
+                },
+            };
+            let expected = baseline.mean;
+            let score = baseline.observe(signal, value, self);
+            self.baselines[signal as usize] = baseline;
+
+            let anomalous = score.is_some_and(|score| {
+                score >= self.threshold
+                    || (signal == Signal::RequestRate && score <= -self.threshold)
+            });
+            if let (true, Some(score), Some(start)) = (anomalous, score, start) {
+                events.push(AnomalyEvent {
+                    signal,
+                    start,
+                    value,
+                    expected,
+                    score,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AnomalyDetector, Signal};
+    use crate::entry::test::TestLine;
+    use crate::entry::LogEntry;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn anomalies() {
+        let mut detector = AnomalyDetector::new();
+        detector.set_warmup(10);
+        let mut events = vec![];
+        for minute in 0..30 {
+            // about 2 requests a second, a few percent of errors, and 100ms or so.
+            let requests = if minute == 20 { 600 } else { 120 + minute % 3 * 5 };
+            for i in 0..requests {
+                let error = i % 40 == 0 || (minute == 25 && i % 2 == 0);
+                let status = if error { "503" } else { "200" };
+                let total_time = if minute == 27 { 2000 } else { 90 + i % 20 };
+                let time = format!("12:{:02}:{:02}", minute, i * 60 / requests);
+                let line = TestLine::new().time(&time)
+                    .timers(&format!("10/0/30/69/{}", total_time))
+                    .status(status)
+                    .to_string();
+                events.extend(detector.add(&LogEntry::from_bytes(line.as_bytes()).unwrap()));
+            }
+        }
+        // nothing for two minutes, then a request to end them.
+        let line = TestLine::new().time("12:32:00").timers("10/0/30/69/100").to_string();
+        events.extend(detector.add(&LogEntry::from_bytes(line.as_bytes()).unwrap()));
+        assert_eq!(events.len(), 5, "{:?}", events);
+
+        let at = |minute| NaiveDate::from_ymd_opt(2009, 2, 6).unwrap()
+            .and_hms_opt(12, minute, 0).unwrap();
+        let found = |signal, minute| {
+            events.iter().find(|event| event.signal == signal && event.start == at(minute))
+        };
+        let flood = found(Signal::RequestRate, 20).unwrap();
+        assert_eq!(flood.value, 10.0);
+        assert!(flood.expected > 2.0 && flood.expected < 2.2);
+        assert!(found(Signal::ErrorRate, 25).unwrap().score > 4.0);
+        assert!(found(Signal::Latency, 27).unwrap().value >= 2000.0);
+        assert!(found(Signal::RequestRate, 30).unwrap().score < -4.0);
+        assert!(found(Signal::RequestRate, 31).is_some());
+        assert!(found(Signal::ErrorRate, 20).is_none());
+        assert!(detector.score(Signal::RequestRate).unwrap() < -4.0);
+        assert_eq!(detector.score(Signal::Latency), None);
+
+        // the last minute had a single request.
+        let events = detector.finish();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].signal, events[0].start), (Signal::RequestRate, at(32)));
+    }
+}
@@ -0,0 +1,69 @@
+use bumpalo::Bump;
+
+use crate::entry::{LogEntry, Result};
+use crate::owned::{entry_from_ranges, field_ranges};
+
+// an entry whose line is copied into a bump arena, for keeping millions of entries around at once
+// without an allocation each: the line is one bump of the arena and the fields are slices of it.
+// everything is freed together when the arena is dropped or reset. a line which doesn't parse isn't
+// copied, so it takes up no room in the arena.
+#[derive(Debug, PartialEq)]
+pub struct ArenaLogEntry<'bump> {
+    line: &'bump [u8],
+    entry: LogEntry<'bump>,
+}
+
+impl<'bump> ArenaLogEntry<'bump> {
+    pub fn parse_in(line: &[u8], bump: &'bump Bump) -> Result<ArenaLogEntry<'bump>> {
+        let fields = field_ranges(line, &LogEntry::from_bytes(line)?);
+        let line = bump.alloc_slice_copy(line);
+        Ok(ArenaLogEntry { line, entry: entry_from_ranges(line, &fields) })
+    }
+
+    pub fn entry(&self) -> &LogEntry<'bump> {
+        &self.entry
+    }
+
+    pub fn line(&self) -> &'bump [u8] {
+        self.line
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'bump> serde::Serialize for ArenaLogEntry<'bump> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S)
+                                      -> std::result::Result<S::Ok, S::Error> {
+        self.entry.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ArenaLogEntry;
+    use crate::entry::LogEntry;
+    use bumpalo::Bump;
+
+    #[test]
+    fn parse_in() {
+        let bump = Bump::new();
+        let line: &[u8] = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                            static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                            {1wt.eu} {} \"GET /index.html HTTP/1.1\"";
+        let mut entries = vec![];
+        for _ in 0..1000 {
+            // a copy which is gone by the time the entry is used.
+            let copy = line.to_vec();
+            entries.push(ArenaLogEntry::parse_in(&copy, &bump).unwrap());
+        }
+        let expected = LogEntry::from_bytes(line).unwrap();
+        for entry in &entries {
+            assert_eq!(entry.entry(), &expected);
+            assert_eq!(entry.line(), line);
+        }
+
+        let allocated = bump.allocated_bytes();
+        let room = bump.chunk_capacity();
+        assert!(ArenaLogEntry::parse_in(b"garbage", &bump).is_err());
+        assert_eq!((bump.allocated_bytes(), bump.chunk_capacity()), (allocated, room));
+    }
+}
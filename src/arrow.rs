@@ -0,0 +1,253 @@
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
+
+use arrow_array::builder::{ArrayBuilder, Int16Builder, Int32Builder, StringBuilder,
+                           StringDictionaryBuilder, TimestampMillisecondBuilder, UInt16Builder,
+                           UInt32Builder, UInt64Builder};
+use arrow_array::types::Int32Type;
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+
+use crate::entry::LogEntry;
+use crate::integer::parse_field;
+
+// the columns of the batches ArrowBuilder makes, named after LogEntry's fields. names and the
+// termination state come from a handful of values so they're dictionaries, timers are Int32 and
+// accept_date is a timestamp without a zone since haproxy logs local time. a value which doesn't
+// parse is null, and so are the -1 of timers and the status code, which mean the request never
+// got that far.
+pub fn arrow_schema() -> SchemaRef {
+    let dictionary = || DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+    let fields = vec![
+        Field::new("process_name", dictionary(), false),
+        Field::new("pid", DataType::UInt32, true),
+        Field::new("client_ip", DataType::Utf8, false),
+        Field::new("client_port", DataType::UInt16, true),
+        Field::new("accept_date", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+        Field::new("frontend_name", dictionary(), false),
+        Field::new("backend_name", dictionary(), false),
+        Field::new("server_name", dictionary(), false),
+        Field::new("request_time", DataType::Int32, true),
+        Field::new("queue_time", DataType::Int32, true),
+        Field::new("connect_time", DataType::Int32, true),
+        Field::new("response_time", DataType::Int32, true),
+        Field::new("total_time", DataType::Int32, true),
+        Field::new("status_code", DataType::Int16, true),
+        Field::new("bytes_read", DataType::UInt64, true),
+        Field::new("captured_request_cookie", DataType::Utf8, false),
+        Field::new("captured_response_cookie", DataType::Utf8, false),
+        Field::new("termination_state", dictionary(), false),
+        Field::new("active_connections", DataType::UInt32, true),
+        Field::new("frontend_connections", DataType::UInt32, true),
+        Field::new("backend_connections", DataType::UInt32, true),
+        Field::new("server_connections", DataType::UInt32, true),
+        Field::new("retried_connections", DataType::UInt32, true),
+        Field::new("server_queue", DataType::UInt32, true),
+        Field::new("backend_queue", DataType::UInt32, true),
+        Field::new("captured_request_headers", DataType::Utf8, false),
+        Field::new("captured_response_headers", DataType::Utf8, false),
+        Field::new("http_request", DataType::Utf8, false),
+    ];
+    Arc::new(Schema::new(fields))
+}
+
+// collects entries into an arrow RecordBatch with arrow_schema's columns, for handing logs to
+// DataFusion, Polars or anything else which reads arrow. append entries and `finish` a batch
+// every so many of them, the builder starts on the next batch then.
+pub struct ArrowBuilder {
+    schema: SchemaRef,
+    process_name: StringDictionaryBuilder<Int32Type>,
+    pid: UInt32Builder,
+    client_ip: StringBuilder,
+    client_port: UInt16Builder,
+    accept_date: TimestampMillisecondBuilder,
+    frontend_name: StringDictionaryBuilder<Int32Type>,
+    backend_name: StringDictionaryBuilder<Int32Type>,
+    server_name: StringDictionaryBuilder<Int32Type>,
+    // Tq, Tw, Tc, Tr and Tt.
+    timers: [Int32Builder; 5],
+    status_code: Int16Builder,
+    bytes_read: UInt64Builder,
+    captured_request_cookie: StringBuilder,
+    captured_response_cookie: StringBuilder,
+    termination_state: StringDictionaryBuilder<Int32Type>,
+    // actconn, feconn, beconn, srv_conn, retries, srv_queue and backend_queue.
+    counts: [UInt32Builder; 7],
+    captures: [StringBuilder; 2],
+    http_request: StringBuilder,
+}
+
+impl Default for ArrowBuilder {
+    fn default() -> ArrowBuilder {
+        ArrowBuilder::new()
+    }
+}
+
+impl ArrowBuilder {
+    pub fn new() -> ArrowBuilder {
+        ArrowBuilder {
+            schema: arrow_schema(),
+            process_name: StringDictionaryBuilder::new(),
+            pid: UInt32Builder::new(),
+            client_ip: StringBuilder::new(),
+            client_port: UInt16Builder::new(),
+            accept_date: TimestampMillisecondBuilder::new(),
+            frontend_name: StringDictionaryBuilder::new(),
+            backend_name: StringDictionaryBuilder::new(),
+            server_name: StringDictionaryBuilder::new(),
+            timers: Default::default(),
+            status_code: Int16Builder::new(),
+            bytes_read: UInt64Builder::new(),
+            captured_request_cookie: StringBuilder::new(),
+            captured_response_cookie: StringBuilder::new(),
+            termination_state: StringDictionaryBuilder::new(),
+            counts: Default::default(),
+            captures: Default::default(),
+            http_request: StringBuilder::new(),
+        }
+    }
+
+    pub fn append(&mut self, entry: &LogEntry) {
+        self.process_name.append_value(text(entry.process_name));
+        self.pid.append_option(number(entry.pid));
+        self.client_ip.append_value(text(entry.client_ip));
+        self.client_port.append_option(number(entry.client_port));
+        let accept_date = entry.accept_date_time().ok();
+        self.accept_date.append_option(accept_date.map(|date| date.and_utc().timestamp_millis()));
+        self.frontend_name.append_value(text(entry.frontend_name));
+        self.backend_name.append_value(text(entry.backend_name));
+        self.server_name.append_value(text(entry.server_name));
+        let timers = [entry.request_time, entry.queue_time, entry.connect_time,
+                      entry.response_time, entry.total_time];
+        for (builder, timer) in self.timers.iter_mut().zip(timers) {
+            builder.append_option(number(timer).filter(|&timer| timer != -1));
+        }
+        self.status_code.append_option(number(entry.status_code).filter(|&status| status != -1));
+        self.bytes_read.append_option(number(entry.bytes_read));
+        self.captured_request_cookie.append_value(text(entry.captured_request_cookie));
+        self.captured_response_cookie.append_value(text(entry.captured_response_cookie));
+        self.termination_state.append_value(text(entry.termination_state));
+        let counts = [entry.active_connections, entry.frontend_connections,
+                      entry.backend_connections, entry.server_connections,
+                      entry.retried_connections, entry.server_queue, entry.backend_queue];
+        for (builder, count) in self.counts.iter_mut().zip(counts) {
+            builder.append_option(number(count));
+        }
+        for (builder, capture) in self.captures.iter_mut().zip(entry.captures) {
+            builder.append_value(text(capture));
+        }
+        self.http_request.append_value(text(entry.http_request));
+    }
+
+    // how many entries there are since the last batch.
+    pub fn len(&self) -> usize {
+        self.pid.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // a batch of the entries appended since the last one.
+    pub fn finish(&mut self) -> RecordBatch {
+        let [tq, tw, tc, tr, tt] = &mut self.timers;
+        let [actconn, feconn, beconn, srv_conn, retries, srv_queue, backend_queue] =
+            &mut self.counts;
+        let [request_headers, response_headers] = &mut self.captures;
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.process_name.finish()),
+            Arc::new(self.pid.finish()),
+            Arc::new(self.client_ip.finish()),
+            Arc::new(self.client_port.finish()),
+            Arc::new(self.accept_date.finish()),
+            Arc::new(self.frontend_name.finish()),
+            Arc::new(self.backend_name.finish()),
+            Arc::new(self.server_name.finish()),
+            Arc::new(tq.finish()),
+            Arc::new(tw.finish()),
+            Arc::new(tc.finish()),
+            Arc::new(tr.finish()),
+            Arc::new(tt.finish()),
+            Arc::new(self.status_code.finish()),
+            Arc::new(self.bytes_read.finish()),
+            Arc::new(self.captured_request_cookie.finish()),
+            Arc::new(self.captured_response_cookie.finish()),
+            Arc::new(self.termination_state.finish()),
+            Arc::new(actconn.finish()),
+            Arc::new(feconn.finish()),
+            Arc::new(beconn.finish()),
+            Arc::new(srv_conn.finish()),
+            Arc::new(retries.finish()),
+            Arc::new(srv_queue.finish()),
+            Arc::new(backend_queue.finish()),
+            Arc::new(request_headers.finish()),
+            Arc::new(response_headers.finish()),
+            Arc::new(self.http_request.finish()),
+        ];
+        // the columns are made to fit the schema, so this can't fail.
+        RecordBatch::try_new(self.schema.clone(), columns).unwrap()
+    }
+}
+
+fn text(field: &[u8]) -> String {
+    String::from_utf8_lossy(field).into_owned()
+}
+
+// a numeric field, or None if it doesn't fit its column.
+fn number<T: TryFrom<i64>>(field: &[u8]) -> Option<T> {
+    parse_field(field)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{arrow_schema, ArrowBuilder};
+    use crate::entry::LogEntry;
+use crate::integer::parse_field;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int16Type, Int32Type, TimestampMillisecondType, UInt32Type};
+    use arrow_array::Array;
+
+    #[test]
+    fn batches() {
+        let lines: &[&[u8]] = &[
+            b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu} {} \"GET / HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.3:33318 [06/Feb/2009:12:14:15.001] http-in static/<NOSRV> \
+              -1/-1/-1/-1/+3000 -1 0 - - CQ-- 1/1/1/0/+1 0/5 \"GET /x HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.2:33319 [06/Feb/2009:12:14:16.000] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+        ];
+        let mut builder = ArrowBuilder::new();
+        for line in &lines[..2] {
+            builder.append(&LogEntry::from_bytes(line).unwrap());
+        }
+        assert_eq!(builder.len(), 2);
+
+        let batch = builder.finish();
+        assert_eq!(batch.schema(), arrow_schema());
+        assert_eq!(batch.num_rows(), 2);
+        assert!(builder.is_empty());
+
+        let dates = batch.column_by_name("accept_date").unwrap()
+            .as_primitive::<TimestampMillisecondType>();
+        assert_eq!(dates.value(0), 1233922454655);
+        let total_time = batch.column_by_name("total_time").unwrap().as_primitive::<Int32Type>();
+        assert_eq!((total_time.value(0), total_time.value(1)), (109, 3000));
+        let queue_time = batch.column_by_name("queue_time").unwrap().as_primitive::<Int32Type>();
+        assert!(queue_time.is_valid(0) && queue_time.is_null(1));
+        let status = batch.column_by_name("status_code").unwrap().as_primitive::<Int16Type>();
+        assert!(status.value(0) == 200 && status.is_null(1));
+        let retries = batch.column_by_name("retried_connections").unwrap()
+            .as_primitive::<UInt32Type>();
+        assert_eq!(retries.value(1), 1);
+
+        let servers = batch.column_by_name("server_name").unwrap().as_dictionary::<Int32Type>();
+        let names = servers.values().as_string::<i32>();
+        assert_eq!(names.value(servers.keys().value(1) as usize), "<NOSRV>");
+        let headers = batch.column_by_name("captured_request_headers").unwrap().as_string::<i32>();
+        assert_eq!((headers.value(0), headers.value(1)), ("1wt.eu", ""));
+
+        builder.append(&LogEntry::from_bytes(lines[2]).unwrap());
+        assert_eq!(builder.finish().num_rows(), 1);
+    }
+}
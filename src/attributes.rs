@@ -0,0 +1,62 @@
+use crate::entry::LogEntry;
+use crate::expr::{Number, Value};
+use crate::integer::parse_i64;
+
+// the attributes of an entry for OpenTelemetry, under the semantic convention names where there is
+// one and under haproxy.* otherwise, which otel_attributes and haproxy-trace both send. the server
+// is haproxy's name for it in haproxy.server, not server.address, which is for its network
+// address. numbers which weren't logged, such as the status of a request which never got a
+// response, are left out.
+pub fn entry_attributes(entry: &LogEntry) -> Vec<(&'static str, Value)> {
+    let method = entry.http_method().unwrap_or(b"");
+    let uri = entry.http_uri().unwrap_or(b"");
+    let path = uri.split(|&c| c == b'?').next().unwrap_or(uri);
+    let text = |field: &[u8]| Value::Text(field.to_vec());
+    let number = |number: i64| Value::Number(Number::Integer(number));
+
+    let mut attributes = vec![
+        ("http.request.method", text(method)),
+        ("url.path", text(path)),
+        ("client.address", text(entry.client_ip)),
+        ("haproxy.frontend", text(entry.frontend_name)),
+        ("haproxy.backend", text(entry.backend_name)),
+        ("haproxy.server", text(entry.server_name)),
+        ("haproxy.termination_state", text(entry.termination_state)),
+    ];
+    if let Some(port) = parse_i64(entry.client_port) {
+        attributes.push(("client.port", number(port)));
+    }
+    if let Ok(status) = entry.status_code() {
+        if status >= 0 {
+            attributes.push(("http.response.status_code", number(status)));
+        }
+    }
+    if let Ok(bytes) = entry.bytes_read() {
+        attributes.push(("http.response.body.size", number(bytes as i64)));
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod test {
+    use super::entry_attributes;
+    use crate::entry::LogEntry;
+    use crate::expr::{Number, Value};
+
+    #[test]
+    fn attributes() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/<NOSRV> -1/-1/-1/-1/+3000 -1 0 - - CQ-- 1/1/1/0/0 0/5 \
+                     \"GET /?x=1 HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let attributes = entry_attributes(&entry);
+        let get = |key: &str| {
+            attributes.iter().find(|(name, _)| *name == key).map(|(_, value)| value.clone())
+        };
+        assert_eq!(get("url.path"), Some(Value::Text(b"/".to_vec())));
+        assert_eq!(get("haproxy.server"), Some(Value::Text(b"<NOSRV>".to_vec())));
+        assert_eq!(get("client.port"), Some(Value::Number(Number::Integer(33317))));
+        assert_eq!(get("http.response.status_code"), None);
+        assert_eq!(get("server.address"), None);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::entry::LogEntry;
+use crate::integer::parse_field;
+
+// the avro schema of what encode_avro writes, for registering with a schema registry. the fields
+// are LogEntry's, with numbers null where a field doesn't parse or is haproxy's -1 for a timer or
+// status the request never got to. accept_date is the local time haproxy logs. new fields will
+// only ever be added at the end with a default, so readers with this schema keep working.
+pub const AVRO_SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "LogEntry",
+  "namespace": "haproxy",
+  "fields": [
+    {"name": "process_name", "type": "string"},
+    {"name": "pid", "type": ["null", "long"]},
+    {"name": "client_ip", "type": "string"},
+    {"name": "client_port", "type": ["null", "int"]},
+    {"name": "accept_date",
+     "type": ["null", {"type": "long", "logicalType": "local-timestamp-millis"}]},
+    {"name": "frontend_name", "type": "string"},
+    {"name": "backend_name", "type": "string"},
+    {"name": "server_name", "type": "string"},
+    {"name": "request_time", "type": ["null", "int"]},
+    {"name": "queue_time", "type": ["null", "int"]},
+    {"name": "connect_time", "type": ["null", "int"]},
+    {"name": "response_time", "type": ["null", "int"]},
+    {"name": "total_time", "type": ["null", "int"]},
+    {"name": "status_code", "type": ["null", "int"]},
+    {"name": "bytes_read", "type": ["null", "long"]},
+    {"name": "captured_request_cookie", "type": "string"},
+    {"name": "captured_response_cookie", "type": "string"},
+    {"name": "termination_state", "type": "string"},
+    {"name": "active_connections", "type": ["null", "long"]},
+    {"name": "frontend_connections", "type": ["null", "long"]},
+    {"name": "backend_connections", "type": ["null", "long"]},
+    {"name": "server_connections", "type": ["null", "long"]},
+    {"name": "retried_connections", "type": ["null", "long"]},
+    {"name": "server_queue", "type": ["null", "long"]},
+    {"name": "backend_queue", "type": ["null", "long"]},
+    {"name": "captured_request_headers", "type": "string"},
+    {"name": "captured_response_headers", "type": "string"},
+    {"name": "http_request", "type": "string"}
+  ]
+}
+"#;
+
+// appends `entry` to `out` in avro's binary encoding with AVRO_SCHEMA, without any framing.
+pub fn encode_avro(entry: &LogEntry, out: &mut Vec<u8>) {
+    string(out, entry.process_name);
+    optional(out, parse_field(entry.pid));
+    string(out, entry.client_ip);
+    optional(out, parse_field(entry.client_port));
+    let accept_date = entry.accept_date_time().ok();
+    optional(out, accept_date.map(|date| date.and_utc().timestamp_millis()));
+    string(out, entry.frontend_name);
+    string(out, entry.backend_name);
+    string(out, entry.server_name);
+    let timers = [entry.request_time, entry.queue_time, entry.connect_time, entry.response_time,
+                  entry.total_time, entry.status_code];
+    for timer in timers {
+        optional(out, parse_field(timer).filter(|&timer| timer != -1));
+    }
+    optional(out, parse_field(entry.bytes_read));
+    string(out, entry.captured_request_cookie);
+    string(out, entry.captured_response_cookie);
+    string(out, entry.termination_state);
+    let counts = [entry.active_connections, entry.frontend_connections, entry.backend_connections,
+                  entry.server_connections, entry.retried_connections, entry.server_queue,
+                  entry.backend_queue];
+    for count in counts {
+        optional(out, parse_field(count));
+    }
+    string(out, entry.captures[0]);
+    string(out, entry.captures[1]);
+    string(out, entry.http_request);
+}
+
+// appends `entry` as a schema registry message: a zero byte, the id the registry gave
+// AVRO_SCHEMA as four big endian bytes, and then the entry as encode_avro writes it.
+pub fn encode_avro_message(schema_id: u32, entry: &LogEntry, out: &mut Vec<u8>) {
+    out.push(0);
+    out.extend_from_slice(&schema_id.to_be_bytes());
+    encode_avro(entry, out);
+}
+
+// avro's int and long are both zigzag varints.
+fn long(out: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+// a union of null and a number, by the index of the branch and then its value.
+fn optional(out: &mut Vec<u8>, value: Option<i64>) {
+    match value {
+        Some(value) => {
+            long(out, 1);
+            long(out, value);
+        },
+        None => long(out, 0),
+    }
+}
+
+// avro strings are utf8, anything else in a field is replaced.
+fn string(out: &mut Vec<u8>, field: &[u8]) {
+    let text = String::from_utf8_lossy(field);
+    long(out, text.len() as i64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::{encode_avro, encode_avro_message, long, AVRO_SCHEMA};
+    use crate::entry::LogEntry;
+use crate::integer::parse_field;
+
+    #[test]
+    fn zigzag() {
+        let encode = |value| {
+            let mut out = vec![];
+            long(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(-1), [0x01]);
+        assert_eq!(encode(1), [0x02]);
+        assert_eq!(encode(64), [0x80, 0x01]);
+        assert_eq!(encode(-65), [0x81, 0x01]);
+        assert_eq!(encode(i64::MIN), [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    }
+
+    #[test]
+    fn entries() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/<NOSRV> -1/-1/-1/-1/+3000 -1 0 - - CQ-- 1/1/1/0/+1 0/5 {a|b} \
+                     \"GET / HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let mut out = vec![];
+        encode_avro_message(7, &entry, &mut out);
+        assert_eq!(out[..5], [0, 0, 0, 0, 7]);
+
+        let mut expected = vec![14];
+        expected.extend_from_slice(b"haproxy");
+        expected.extend_from_slice(&[2, 0xea, 0xe0, 0x01]);
+        expected.push(16);
+        expected.extend_from_slice(b"10.0.1.2");
+        expected.extend_from_slice(&[2, 0xca, 0x88, 0x04]);
+        expected.extend_from_slice(&[2, 0xfe, 0xd1, 0x93, 0xb8, 0xe9, 0x47]);
+        assert_eq!(out[5..5 + expected.len()], expected[..]);
+
+        // the -1 timers and status are nulls, Tt and the retries lose their '+'.
+        let end = b"\x0e<NOSRV>\x00\x00\x00\x00\x02\xf0\x2e\x00\x02\x00\x02-\x02-\x08CQ--\
+                    \x02\x02\x02\x02\x02\x02\x02\x00\x02\x02\x02\x00\x02\x0a\
+                    \x06a|b\x00\x1cGET / HTTP/1.1";
+        assert!(out.ends_with(end));
+
+        let mut bare = vec![];
+        encode_avro(&entry, &mut bare);
+        assert_eq!(bare, out[5..]);
+
+        let schema: serde_json::Value = serde_json::from_str(AVRO_SCHEMA).unwrap();
+        assert_eq!(schema["fields"].as_array().unwrap().len(), 28);
+    }
+}
@@ -0,0 +1,438 @@
+use chrono::Local;
+use docopt::Docopt;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use haproxy::{AnomalyDetector, Expr, ExprError, Filter, Follow, LogEntry, Objective, Outcome,
+              Signal, SloCounts, Value};
+
+
+const MAX_LINE_LENGTH: usize = 1024;
+const DEFAULT_WINDOW: u64 = 60;
+const DEFAULT_INTERVAL: u64 = 10;
+const DEFAULT_OBJECTIVE: f64 = 99.9;
+
+static USAGE: &str = "
+Watch haproxy log entries as they're written to <file> (or standard input) and raise an alert when
+a rule is broken.
+
+Usage:
+    haproxy-alert -r RULE... [-w EXPR]... [options] [--] [<file>]
+    haproxy-alert -h | --help
+
+Options:
+    -r, --rule=RULE         alert when RULE holds over the window, see below. may be given more
+                            than once.
+    --window=SECS           evaluate rules over the entries of the last SECS seconds. (default: 60)
+    --interval=SECS         evaluate rules every SECS seconds. (default: 10)
+    --min-requests=N        don't evaluate rules while the window has fewer than N entries, so a
+                            single slow request at night doesn't page anyone. (default: 1)
+    --objective=PCT         the percentage of requests which should be good, for burn_rate rules.
+                            (default: 99.9)
+    --latency=MS            for burn_rate rules, requests must also complete (Tt) in under MS
+                            milliseconds to be good.
+    --exec=COMMAND          run COMMAND with sh -c when an alert fires or resolves.
+    --webhook=URL           POST a JSON description of the alert to URL when it fires or resolves.
+    --from-start            read <file> from the beginning instead of only new entries.
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    -h, --help              display this help and exit
+
+Rules compare a statistic of the window with a number, using one of > >= < <=:
+
+    error_rate > 1          the percentage of entries with a 5xx or no response at all
+    burn_rate > 14.4        how many times faster than the objective allows its error budget is
+                            being spent, as haproxy-slo reports it
+    rate < 5                entries per second
+    count > 1000            entries
+    p99(Tr) > 2000          the 50th, 90th, 99th percentile, the average, minimum, maximum or sum
+    avg(Tt) > 800           of a field or expression, see haproxy-cut --help-fields. e.g.
+    sum(retries) > 10       p50(...), p90(...), min(...), max(...).
+    max(srv_queue) > 5
+    anomaly(rate) < -4      how unusual the last whole minute's rate, error_rate or p95 of Tt was,
+    anomaly(error_rate) > 4 in typical deviations from a baseline learned from the minutes before
+    anomaly(p95) > 4        it, negative when it was lower. entries are taken to be logged in local
+                            time, and there's no score for the first 15 minutes.
+
+Each alert is printed to standard output when it fires and when it resolves. --exec commands get
+the details in the HAPROXY_ALERT_RULE, HAPROXY_ALERT_STATE (firing or resolved) and
+HAPROXY_ALERT_VALUE environment variables, and webhooks the same as a JSON object with rule, state,
+value and window keys.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_rule: Vec<String>,
+    flag_window: Option<u64>,
+    flag_interval: Option<u64>,
+    flag_min_requests: Option<usize>,
+    flag_objective: Option<f64>,
+    flag_latency: Option<i64>,
+    flag_exec: Option<String>,
+    flag_webhook: Option<String>,
+    flag_from_start: bool,
+    flag_where: Vec<String>,
+    arg_file: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Aggregate {
+    Percentile(f64),
+    Average,
+    Minimum,
+    Maximum,
+    Sum,
+}
+
+enum Statistic {
+    ErrorRate,
+    BurnRate,
+    Rate,
+    Count,
+    Anomaly(Signal),
+    Of(Aggregate, Expr),
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+        }
+    }
+}
+
+struct Rule {
+    source: String,
+    statistic: Statistic,
+    comparison: Comparison,
+    threshold: f64,
+    firing: bool,
+}
+
+impl Rule {
+    fn parse(rule: &str) -> Result<Rule, ExprError> {
+        // longer operators first so `>=` isn't read as `>`.
+        let operators = [
+            (">=", Comparison::GreaterOrEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">", Comparison::Greater),
+            ("<", Comparison::Less),
+        ];
+        let (statistic, comparison, threshold) = operators.iter()
+            .find_map(|&(operator, comparison)| {
+                rule.split_once(operator).map(|(lhs, rhs)| (lhs.trim(), comparison, rhs.trim()))
+            })
+            .ok_or_else(|| {
+                ExprError::Syntax(format!("expected a comparison like > or <= in '{}'", rule))
+            })?;
+
+        let threshold = threshold.trim_end_matches('%').parse().map_err(|_| {
+            ExprError::Syntax(format!("could not parse threshold '{}'", threshold))
+        })?;
+
+        let statistic = match statistic {
+            "error_rate" => Statistic::ErrorRate,
+            "burn_rate" => Statistic::BurnRate,
+            "rate" => Statistic::Rate,
+            "count" => Statistic::Count,
+            _ => {
+                let (name, expr) = statistic.strip_suffix(')')
+                    .and_then(|statistic| statistic.split_once('('))
+                    .ok_or_else(|| {
+                        ExprError::Syntax(format!("unknown statistic '{}'", statistic))
+                    })?;
+                if name.trim() == "anomaly" {
+                    let signal = Signal::ALL.iter()
+                        .find(|signal| signal.name() == expr.trim())
+                        .ok_or_else(|| {
+                            ExprError::Syntax(format!("unknown anomaly signal '{}', expected rate, \
+                                                       error_rate or p95", expr.trim()))
+                        })?;
+                    return Ok(Rule::new(rule, Statistic::Anomaly(*signal), comparison, threshold));
+                }
+                let aggregate = match name.trim() {
+                    "p50" => Aggregate::Percentile(50.0),
+                    "p90" => Aggregate::Percentile(90.0),
+                    "p99" => Aggregate::Percentile(99.0),
+                    "avg" => Aggregate::Average,
+                    "min" => Aggregate::Minimum,
+                    "max" => Aggregate::Maximum,
+                    "sum" => Aggregate::Sum,
+                    other => {
+                        return Err(ExprError::Syntax(format!("unknown aggregate '{}'", other)));
+                    },
+                };
+                Statistic::Of(aggregate, Expr::parse(expr)?)
+            },
+        };
+
+        Ok(Rule::new(rule, statistic, comparison, threshold))
+    }
+
+    fn new(source: &str, statistic: Statistic, comparison: Comparison, threshold: f64) -> Rule {
+        Rule {
+            source: source.to_string(),
+            statistic,
+            comparison,
+            threshold,
+            firing: false,
+        }
+    }
+
+    // the value of the rule's statistic over `samples`, where `index` is the position of this
+    // rule's values in each sample.
+    fn evaluate(&self, samples: &VecDeque<Sample>, index: usize, window: Duration,
+                objective: &Objective, detector: &AnomalyDetector) -> Option<f64> {
+        if let Statistic::Anomaly(signal) = self.statistic {
+            return detector.score(signal);
+        }
+        if samples.is_empty() {
+            return None;
+        }
+
+        let aggregate = match self.statistic {
+            Statistic::ErrorRate => {
+                let errors = samples.iter().filter(|sample| sample.error).count();
+                return Some(100.0 * errors as f64 / samples.len() as f64);
+            },
+            Statistic::BurnRate => {
+                let mut counts = SloCounts::default();
+                for sample in samples {
+                    counts.add(sample.outcome);
+                }
+                return Some(counts.burn_rate(objective));
+            },
+            Statistic::Rate => return Some(samples.len() as f64 / window.as_secs_f64()),
+            Statistic::Count => return Some(samples.len() as f64),
+            Statistic::Anomaly(_) => return None,
+            Statistic::Of(aggregate, _) => aggregate,
+        };
+
+        let mut values: Vec<f64> =
+            samples.iter().filter_map(|sample| sample.values[index]).collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(match aggregate {
+            Aggregate::Percentile(p) => {
+                // nearest-rank.
+                values.sort_by(|a, b| a.total_cmp(b));
+                let rank = (p / 100.0 * values.len() as f64).ceil() as usize;
+                values[rank.max(1) - 1]
+            },
+            Aggregate::Average => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregate::Minimum => values.iter().cloned().fold(f64::INFINITY, f64::min),
+            Aggregate::Maximum => values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            Aggregate::Sum => values.iter().sum(),
+        })
+    }
+}
+
+// what we keep of each entry in the window: whether it was an error, how it did against the
+// objective and, for each rule, the value of its expression.
+struct Sample {
+    received: Instant,
+    error: bool,
+    outcome: Outcome,
+    values: Vec<Option<f64>>,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Firing,
+    Resolved,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            State::Firing => write!(f, "firing"),
+            State::Resolved => write!(f, "resolved"),
+        }
+    }
+}
+
+struct Notifier {
+    exec: Option<String>,
+    webhook: Option<String>,
+    window: Duration,
+}
+
+impl Notifier {
+    fn notify(&self, rule: &Rule, state: State, value: f64) {
+        println!("{} {} {} (value: {:.2})", Local::now().format("%Y-%m-%d %H:%M:%S"), state,
+                 rule.source, value);
+
+        if let Some(ref command) = self.exec {
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("HAPROXY_ALERT_RULE", &rule.source)
+                .env("HAPROXY_ALERT_STATE", state.to_string())
+                .env("HAPROXY_ALERT_VALUE", value.to_string())
+                .status();
+            if let Err(err) = status {
+                eprintln!("could not run --exec command: {}", err);
+            }
+        }
+
+        if let Some(ref url) = self.webhook {
+            let body = json!({
+                "rule": rule.source,
+                "state": state.to_string(),
+                "value": value,
+                "window": self.window.as_secs(),
+            });
+            let response = ureq::post(url)
+                .set("Content-Type", "application/json")
+                .send_string(&body.to_string());
+            if let Err(err) = response {
+                eprintln!("could not post to --webhook: {}", err);
+            }
+        }
+    }
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn sample(entry: &LogEntry, rules: &[Rule], objective: &Objective) -> Sample {
+    let values = rules.iter()
+        .map(|rule| match rule.statistic {
+            Statistic::Of(_, ref expr) => match expr.evaluate(entry) {
+                Some(Value::Number(number)) => Some(number.as_decimal()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    Sample {
+        received: Instant::now(),
+        error: !entry.status_code().is_ok_and(|status| (100..500).contains(&status)),
+        outcome: objective.classify(entry),
+        values,
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    let mut rules: Vec<Rule> = args.flag_rule.iter()
+        .map(|rule| Rule::parse(rule).unwrap_or_else(usage_error))
+        .collect();
+    let window = Duration::from_secs(args.flag_window.unwrap_or(DEFAULT_WINDOW).max(1));
+    let interval = Duration::from_secs(args.flag_interval.unwrap_or(DEFAULT_INTERVAL).max(1));
+    let min_requests = args.flag_min_requests.unwrap_or(1);
+    let mut objective = Objective::new(args.flag_objective.unwrap_or(DEFAULT_OBJECTIVE));
+    if let Some(latency) = args.flag_latency {
+        objective.set_latency(latency);
+    }
+    let notifier = Notifier {
+        exec: args.flag_exec.clone(),
+        webhook: args.flag_webhook.clone(),
+        window,
+    };
+
+    let mut follow = match args.arg_file {
+        Some(ref path) if args.flag_from_start => Some(Follow::from_start(path)),
+        Some(ref path) => Some(Follow::new(path)),
+        None => None,
+    }.transpose().unwrap_or_else(|err| {
+        docopt::Error::Argv(format!("could not open input: {}", err)).exit()
+    });
+
+    // read on a separate thread so rules are still evaluated while no entries are written, which
+    // is often exactly when something is wrong.
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        loop {
+            let mut line_buffer: Vec<u8> = Vec::with_capacity(MAX_LINE_LENGTH);
+            let read = match follow {
+                Some(ref mut follow) => follow.read_line(&mut line_buffer),
+                None => stdin.read_until(b'\n', &mut line_buffer),
+            };
+            match read {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if sender.send(line_buffer).is_err() {
+                        break;
+                    }
+                },
+            }
+        }
+    });
+
+    let mut samples: VecDeque<Sample> = VecDeque::new();
+    let mut detector = AnomalyDetector::new();
+    let mut next_evaluation = Instant::now() + interval;
+    loop {
+        let timeout = next_evaluation.saturating_duration_since(Instant::now());
+        let input_closed = match receiver.recv_timeout(timeout) {
+            Ok(line) => {
+                if let Ok(entry) = LogEntry::from_bytes(&line) {
+                    if filter.matches(&entry) {
+                        samples.push_back(sample(&entry, &rules, &objective));
+                        // rules look at the scores rather than the events.
+                        detector.add(&entry);
+                    }
+                }
+                false
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => false,
+            Err(mpsc::RecvTimeoutError::Disconnected) => true,
+        };
+
+        if input_closed || Instant::now() >= next_evaluation {
+            let now = Instant::now();
+            detector.advance(Local::now().naive_local());
+            while let Some(sample) = samples.front() {
+                if now.duration_since(sample.received) <= window {
+                    break;
+                }
+                samples.pop_front();
+            }
+
+            for (i, rule) in rules.iter_mut().enumerate() {
+                let value = if samples.len() >= min_requests {
+                    rule.evaluate(&samples, i, window, &objective, &detector)
+                } else {
+                    None
+                };
+                let breached =
+                    value.is_some_and(|value| rule.comparison.holds(value, rule.threshold));
+                if breached != rule.firing {
+                    rule.firing = breached;
+                    let state = if breached { State::Firing } else { State::Resolved };
+                    notifier.notify(rule, state, value.unwrap_or(0.0));
+                }
+            }
+            next_evaluation = now + interval;
+        }
+
+        if input_closed {
+            break;
+        }
+    }
+}
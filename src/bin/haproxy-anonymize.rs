@@ -0,0 +1,218 @@
+use docopt::Docopt;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::ops::Range;
+use std::process;
+use std::str;
+
+use haproxy::{Inputs, LogEntry};
+
+
+static USAGE: &str = "
+Mask or hash personal data in haproxy log entries from each <file> and print them to standard output
+in the original log format.
+
+Usage:
+    haproxy-anonymize [-p NAME]... [options] [--] [<file> [<file> ...]]
+    haproxy-anonymize -h | --help
+
+Options:
+    -m, --mode=MODE         mask replaces data with placeholders, hash replaces it with a keyed hash
+                            so the same value always gets the same replacement. (default: mask)
+    -k, --key=KEY           the secret key for --mode=hash.
+    --key-file=FILE         read the secret key for --mode=hash from FILE.
+    -p, --param=NAME        also anonymize the value of query string parameter NAME. may be given
+                            more than once.
+    --keep-ips              leave client IP addresses alone.
+    --keep-cookies          leave captured cookies alone.
+    --keep-invalid          print lines that failed to parse unchanged. (default: drop them, since
+                            there's no telling what's in them)
+    -h, --help              display this help and exit
+
+Masked client IPs keep their first three octets (10.0.1.2 becomes 10.0.1.0) and hashed ones are
+mapped into 10.0.0.0/8 so tools grouping by client still work. Cookies and parameter values become
+xxxx when masked or 16 hex digits when hashed.
+";
+
+#[derive(RustcDecodable, Clone, Copy)]
+enum Mode {
+    Mask,
+    Hash,
+}
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_mode: Option<Mode>,
+    flag_key: Option<String>,
+    flag_key_file: Option<String>,
+    flag_param: Vec<String>,
+    flag_keep_ips: bool,
+    flag_keep_cookies: bool,
+    flag_keep_invalid: bool,
+    arg_file: Vec<String>,
+}
+
+struct Anonymizer {
+    mode: Mode,
+    key: Vec<u8>,
+    params: Vec<String>,
+    ips: bool,
+    cookies: bool,
+}
+
+impl Anonymizer {
+    fn digest(&self, value: &[u8]) -> [u8; 32] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("hmac takes any key size");
+        mac.update(value);
+        mac.finalize().into_bytes().into()
+    }
+
+    fn ip(&self, ip: &[u8]) -> Vec<u8> {
+        let anonymized = match self.mode {
+            Mode::Mask => {
+                let ip: Ipv4Addr = str::from_utf8(ip).ok()
+                    .and_then(|ip| ip.parse().ok())
+                    .unwrap_or(Ipv4Addr::UNSPECIFIED);
+                let [a, b, c, _] = ip.octets();
+                Ipv4Addr::new(a, b, c, 0)
+            },
+            Mode::Hash => {
+                let digest = self.digest(ip);
+                Ipv4Addr::new(10, digest[0], digest[1], digest[2])
+            },
+        };
+        anonymized.to_string().into_bytes()
+    }
+
+    fn token(&self, value: &[u8]) -> Vec<u8> {
+        match self.mode {
+            Mode::Mask => b"xxxx".to_vec(),
+            Mode::Hash => {
+                let digest = self.digest(value);
+                let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+                hex.into_bytes()
+            },
+        }
+    }
+
+    // a captured cookie of `-` means there was none, which is worth keeping.
+    fn cookie(&self, cookie: &[u8]) -> Vec<u8> {
+        if cookie.is_empty() || cookie == b"-" {
+            cookie.to_vec()
+        } else {
+            self.token(cookie)
+        }
+    }
+
+    fn query(&self, query: &[u8]) -> Vec<u8> {
+        let mut anonymized = Vec::with_capacity(query.len());
+        for (i, pair) in query.split(|&c| c == b'&').enumerate() {
+            if i != 0 {
+                anonymized.push(b'&');
+            }
+            let mut parts = pair.splitn(2, |&c| c == b'=');
+            let name = parts.next().unwrap_or(b"");
+            match parts.next() {
+                Some(value) if self.params.iter().any(|param| param.as_bytes() == name) => {
+                    anonymized.extend_from_slice(name);
+                    anonymized.push(b'=');
+                    anonymized.extend(self.token(value));
+                },
+                _ => anonymized.extend_from_slice(pair),
+            }
+        }
+        anonymized
+    }
+
+    // the replacements to make in `line`, as ranges of it and what goes there instead, in order.
+    fn edits(&self, line: &[u8], entry: &LogEntry) -> Vec<(Range<usize>, Vec<u8>)> {
+        let mut edits = vec![];
+        if self.ips {
+            edits.push((span(line, entry.client_ip), self.ip(entry.client_ip)));
+        }
+        if self.cookies {
+            let request = entry.captured_request_cookie;
+            let response = entry.captured_response_cookie;
+            edits.push((span(line, request), self.cookie(request)));
+            edits.push((span(line, response), self.cookie(response)));
+        }
+        if !self.params.is_empty() {
+            let query = entry.http_uri().and_then(|uri| {
+                uri.iter().position(|&c| c == b'?').map(|question| &uri[question + 1..])
+            });
+            if let Some(query) = query {
+                edits.push((span(line, query), self.query(query)));
+            }
+        }
+        edits
+    }
+}
+
+// the position of `part`, which must be a slice of `line`, within it.
+fn span(line: &[u8], part: &[u8]) -> Range<usize> {
+    let start = part.as_ptr() as usize - line.as_ptr() as usize;
+    start..start + part.len()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mode = args.flag_mode.unwrap_or(Mode::Mask);
+    let key = match (&args.flag_key, &args.flag_key_file) {
+        (Some(key), _) => key.as_bytes().to_vec(),
+        (None, Some(path)) => {
+            let key = fs::read(path).unwrap_or_else(|err| {
+                docopt::Error::Argv(format!("could not read key file: {}", err)).exit()
+            });
+            key.trim_ascii_end().to_vec()
+        },
+        (None, None) => vec![],
+    };
+    if matches!(mode, Mode::Hash) && key.is_empty() {
+        docopt::Error::Argv("--mode=hash needs a --key or --key-file".to_string()).exit();
+    }
+
+    let anonymizer = Anonymizer {
+        mode,
+        key,
+        params: args.flag_param.clone(),
+        ips: !args.flag_keep_ips,
+        cookies: !args.flag_keep_cookies,
+    };
+
+    let mut reader = Inputs::new(&args.arg_file);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                stdout.flush().unwrap();
+                eprintln!("haproxy-anonymize: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        match LogEntry::from_bytes(line) {
+            Ok(entry) => {
+                let mut position = 0;
+                for (range, replacement) in anonymizer.edits(line, &entry) {
+                    stdout.write_all(&line[position..range.start]).unwrap();
+                    stdout.write_all(&replacement).unwrap();
+                    position = range.end;
+                }
+                stdout.write_all(&line[position..]).unwrap();
+            },
+            Err(_) => {
+                if args.flag_keep_invalid {
+                    stdout.write_all(line).unwrap();
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use docopt::Docopt;
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::time::{Duration, Instant};
+
+use haproxy::{write_fields_into, Field, LogEntry, Plan, Table};
+
+
+const DEFAULT_ITERATIONS: usize = 5;
+const DEFAULT_FIELDS: &str = "status_code,Tt,http_uri";
+
+static USAGE: &str = "
+Measure how fast haproxy log entries from <file> can be parsed, using several strategies.
+
+Usage:
+    haproxy-bench [options] [--] <file>
+    haproxy-bench -h | --help
+
+Options:
+    -n, --iterations=N      time each strategy over the whole file N times and report the fastest.
+                            (default: 5)
+    -f, --fields=LIST       the fields to extract with the select and plan strategies, as in
+                            haproxy-cut. (default: status_code,Tt,http_uri)
+    -s, --strategy=NAME     only run the strategy NAME.
+    -d, --delimiter=STRING  separate columns with STRING instead of aligning them.
+    -h, --help              display this help and exit
+
+The file is read into memory first, so the numbers don't include disk I/O. The strategies are:
+
+    read                    only split the input into lines, the baseline for the others
+    lazy                    parse each entry; fields stay slices of the line until they're used
+    scan                    lazy, but find every field with a single pass over the line
+    select                  parse each entry and format the --fields from it into a buffer
+    plan                    select, but only parse as far as the --fields need, like haproxy-cut
+    full                    parse each entry and convert every number and the date
+
+To see whether the SIMD delimiter search suits your logs, compare a build with `--features memchr`
+against a default one on the same file.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_iterations: Option<usize>,
+    flag_fields: Option<String>,
+    flag_strategy: Option<Strategy>,
+    flag_delimiter: Option<String>,
+    arg_file: String,
+}
+
+#[derive(RustcDecodable, Clone, Copy, PartialEq)]
+enum Strategy {
+    Read,
+    Lazy,
+    Scan,
+    Select,
+    Plan,
+    Full,
+}
+
+const STRATEGIES: &[(Strategy, &str)] = &[
+    (Strategy::Read, "read"),
+    (Strategy::Lazy, "lazy"),
+    (Strategy::Scan, "scan"),
+    (Strategy::Select, "select"),
+    (Strategy::Plan, "plan"),
+    (Strategy::Full, "full"),
+];
+
+// touch every conversion the library offers, so none of the work can be skipped.
+fn convert_all(entry: &LogEntry) {
+    black_box(entry.pid().ok());
+    black_box(entry.request_time().ok());
+    black_box(entry.queue_time().ok());
+    black_box(entry.connect_time().ok());
+    black_box(entry.response_time().ok());
+    black_box(entry.total_time().ok());
+    black_box(entry.status_code().ok());
+    black_box(entry.bytes_read().ok());
+    black_box(entry.active_connections().ok());
+    black_box(entry.frontend_connections().ok());
+    black_box(entry.backend_connections().ok());
+    black_box(entry.server_connections().ok());
+    black_box(entry.retried_connections().ok());
+    black_box(entry.server_queue().ok());
+    black_box(entry.backend_queue().ok());
+    black_box(entry.accept_date_time().ok());
+}
+
+// one pass over `input` with `strategy`, returning how many lines parsed.
+fn run(strategy: Strategy, input: &[u8], fields: &[Field], plan: &Plan) -> u64 {
+    let mut parsed = 0;
+    let mut output: Vec<u8> = Vec::new();
+    for line in input.split(|&c| c == b'\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let entry = match strategy {
+            Strategy::Read => {
+                black_box(line);
+                parsed += 1;
+                continue;
+            },
+            Strategy::Scan => match LogEntry::from_bytes_single_pass(black_box(line)) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            },
+            Strategy::Plan => match plan.parse(black_box(line)) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            },
+            _ => match LogEntry::from_bytes(black_box(line)) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            },
+        };
+        parsed += 1;
+
+        match strategy {
+            Strategy::Select | Strategy::Plan => {
+                output.clear();
+                write_fields_into(&mut output, &entry, fields, b"\t").unwrap();
+                black_box(&output);
+            },
+            Strategy::Full => convert_all(&entry),
+            _ => {
+                black_box(&entry);
+            },
+        }
+    }
+    parsed
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let input = fs::read(&args.arg_file).unwrap_or_else(|err| {
+        docopt::Error::Argv(format!("could not read {}: {}", args.arg_file, err)).exit()
+    });
+    let fields: Vec<Field> = args.flag_fields.as_deref().unwrap_or(DEFAULT_FIELDS)
+        .split(',')
+        .map(|name| {
+            Field::decode(name.trim())
+                .unwrap_or_else(|err| docopt::Error::Argv(err.to_string()).exit())
+        })
+        .collect();
+    let mut plan = Plan::new();
+    for field in &fields {
+        plan.add_field(field);
+    }
+    let iterations = args.flag_iterations.unwrap_or(DEFAULT_ITERATIONS).max(1);
+    let lines = input.split(|&c| c == b'\n').filter(|line| !line.is_empty()).count();
+    let megabytes = input.len() as f64 / (1024.0 * 1024.0);
+
+    let mut table = Table::new(&["strategy", "parsed", "lines/s", "MB/s", "ns/line"]);
+    for &(strategy, name) in STRATEGIES {
+        if args.flag_strategy.is_some_and(|only| only != strategy) {
+            continue;
+        }
+
+        let mut fastest = Duration::MAX;
+        let mut parsed = 0;
+        for _ in 0..iterations {
+            let start = Instant::now();
+            parsed = run(strategy, &input, &fields, &plan);
+            fastest = fastest.min(start.elapsed());
+        }
+
+        let seconds = fastest.as_secs_f64().max(f64::MIN_POSITIVE);
+        table.push(vec![
+            name.to_string(),
+            parsed.to_string(),
+            format!("{:.0}", lines as f64 / seconds),
+            format!("{:.1}", megabytes / seconds),
+            format!("{:.0}", fastest.as_nanos() as f64 / lines.max(1) as f64),
+        ]);
+    }
+
+    let mut stdout = io::stdout();
+    match args.flag_delimiter {
+        Some(ref delimiter) => table.write_delimited(&mut stdout, delimiter).unwrap(),
+        None => table.write_aligned(&mut stdout).unwrap(),
+    }
+}
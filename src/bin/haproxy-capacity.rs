@@ -0,0 +1,340 @@
+use chrono::DateTime;
+use docopt::Docopt;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::iter;
+use std::process;
+
+use haproxy::{Condition, ConcurrencyTrends, Correlation, ExprError, Filter, Gauge, Inputs, LogEntry,
+              QueueAnalysis, Table};
+
+
+const DEFAULT_WINDOW: i64 = 60;
+const DEFAULT_SLOW: i64 = 1000;
+// upper bounds of the queue depth ranges Tw is compared across.
+const DEPTH_RANGES: &[u64] = &[1, 10, 100];
+
+static USAGE: &str = "
+Report when haproxy was saturated according to the connection counts and queue lengths logged with
+each entry from each <file>, and which servers hit their maxconn.
+
+Usage:
+    haproxy-capacity [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-capacity -h | --help
+
+Options:
+    --window=SECS           report connections and queueing for every SECS seconds. (default: 60)
+    -a, --all               list every window, not only those where requests were queued.
+    -c, --concurrency       report the max and mean connection counts of each frontend and
+                            backend in every window instead.
+    --slow=MS               count requests taking (Tt) MS milliseconds or more as slow when
+                            telling whether they were slow from queueing. (default: 1000)
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -d, --delimiter=STRING  separate columns with STRING instead of aligning them.
+    -h, --help              display this help and exit
+
+haproxy only queues a request for a server once the server has maxconn connections, and only in the
+backend queue once every server has, so any entry logged with a non-zero srv_queue means its server
+was at maxconn. The connection counts (actconn, feconn, beconn, srv_conn) and queue lengths
+(srv_queue, backend_queue) are those haproxy saw when the entry was logged.
+
+With --concurrency, frontends get actconn and feconn, and backends beconn and the srv_conn of the
+servers their requests went to. Means are over the entries logged, so they're weighted by requests
+rather than time.
+
+A slow request is queue-dominated when it spent at least half of Tt waiting in a queue (Tw). A
+backend whose slow requests are mostly queue-dominated would be helped by a higher maxconn or more
+servers, while one whose slow requests aren't has servers which are slow themselves, and giving
+them more connections would only make that worse.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_window: Option<i64>,
+    flag_all: bool,
+    flag_concurrency: bool,
+    flag_slow: Option<i64>,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_delimiter: Option<String>,
+    arg_file: Vec<String>,
+}
+
+#[derive(Default)]
+struct Window {
+    requests: u64,
+    queued: u64,
+    max_active: u64,
+    max_frontend: u64,
+    max_backend: u64,
+    max_server_queue: u64,
+    max_backend_queue: u64,
+    queued_wait: i64,
+}
+
+#[derive(Default)]
+struct Server {
+    requests: u64,
+    queued: u64,
+    max_connections: u64,
+    max_queue: u64,
+    queued_wait: i64,
+    first_queued: Option<i64>,
+    last_queued: Option<i64>,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn format_time(timestamp: Option<i64>) -> String {
+    match timestamp.and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)) {
+        Some(time) => time.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn mean(sum: i64, count: u64) -> String {
+    if count == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}", sum as f64 / count as f64)
+    }
+}
+
+fn percentage(fraction: Option<f64>) -> String {
+    fraction.map_or("-".to_string(), |fraction| format!("{:.2}", 100.0 * fraction))
+}
+
+fn coefficient(r: Option<f64>) -> String {
+    r.map_or("-".to_string(), |r| format!("{:.3}", r))
+}
+
+fn gauge_columns(gauge: &Gauge) -> [String; 2] {
+    [gauge.max.to_string(), format!("{:.1}", gauge.mean())]
+}
+
+fn write_concurrency<F>(out: &mut io::StdoutLock, trends: &ConcurrencyTrends, write_table: &F)
+where
+    F: Fn(&mut io::StdoutLock, &str, &Table) -> io::Result<()>,
+{
+    let mut frontends = Table::new(&["window", "frontend", "requests", "max actconn",
+                                     "mean actconn", "max feconn", "mean feconn"]);
+    let mut backends = Table::new(&["window", "backend", "requests", "max beconn", "mean beconn",
+                                    "max srv_conn", "mean srv_conn"]);
+    let total = trends.total();
+    let intervals = trends.iter()
+        .map(|(start, interval)| (start.format("%Y-%m-%d %H:%M:%S").to_string(), interval))
+        .chain(iter::once(("total".to_string(), &total)));
+    for (label, interval) in intervals {
+        for (name, frontend) in &interval.frontends {
+            let mut row = vec![label.clone(), name.clone(), frontend.active.samples.to_string()];
+            row.extend(gauge_columns(&frontend.active));
+            row.extend(gauge_columns(&frontend.frontend));
+            frontends.push(row);
+        }
+        for (name, backend) in &interval.backends {
+            let mut row = vec![label.clone(), name.clone(), backend.backend.samples.to_string()];
+            row.extend(gauge_columns(&backend.backend));
+            row.extend(gauge_columns(&backend.server));
+            backends.push(row);
+        }
+    }
+    write_table(out, "frontends:", &frontends).unwrap();
+    write_table(out, "backends:", &backends).unwrap();
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    let window = args.flag_window.unwrap_or(DEFAULT_WINDOW).max(1);
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut windows: BTreeMap<i64, Window> = BTreeMap::new();
+    let mut servers: BTreeMap<(Vec<u8>, Vec<u8>), Server> = BTreeMap::new();
+    let mut correlation = Correlation::default();
+    let mut analysis = QueueAnalysis::new(args.flag_slow.unwrap_or(DEFAULT_SLOW));
+    let mut trends = ConcurrencyTrends::new(window);
+    // requests and summed Tw per queue depth range, the last one being everything above the bounds.
+    let mut depths = vec![(0u64, 0i64); DEPTH_RANGES.len() + 1];
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-capacity: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        if args.flag_concurrency {
+            trends.add(&entry);
+            continue;
+        }
+        analysis.add(&entry);
+
+        let timestamp = entry.accept_date_time().ok().map(|d| d.and_utc().timestamp());
+        let server_queue = entry.server_queue().unwrap_or(0);
+        let backend_queue = entry.backend_queue().unwrap_or(0);
+        let queued = server_queue > 0 || backend_queue > 0;
+        // Tw is -1 for sessions which never got out of the queue.
+        let queue_time = entry.queue_time().ok().filter(|&t| t >= 0);
+
+        if let Some(timestamp) = timestamp {
+            let start = timestamp.div_euclid(window) * window;
+            let summary = windows.entry(start).or_default();
+            summary.requests += 1;
+            summary.max_active = summary.max_active
+                .max(entry.active_connections().unwrap_or(0));
+            summary.max_frontend = summary.max_frontend
+                .max(entry.frontend_connections().unwrap_or(0));
+            summary.max_backend = summary.max_backend
+                .max(entry.backend_connections().unwrap_or(0));
+            summary.max_server_queue = summary.max_server_queue.max(server_queue);
+            summary.max_backend_queue = summary.max_backend_queue.max(backend_queue);
+            if queued {
+                summary.queued += 1;
+                summary.queued_wait += queue_time.unwrap_or(0);
+            }
+        }
+
+        let key = (entry.backend_name.to_vec(), entry.server_name.to_vec());
+        let server = servers.entry(key).or_default();
+        server.requests += 1;
+        server.max_connections = server.max_connections
+            .max(entry.server_connections().unwrap_or(0));
+        server.max_queue = server.max_queue.max(server_queue);
+        if server_queue > 0 {
+            server.queued += 1;
+            server.queued_wait += queue_time.unwrap_or(0);
+            if server.first_queued.is_none() {
+                server.first_queued = timestamp;
+            }
+            server.last_queued = timestamp.or(server.last_queued);
+        }
+
+        if let Some(queue_time) = queue_time {
+            let depth = server_queue + backend_queue;
+            correlation.add(depth as f64, queue_time as f64);
+            let range = DEPTH_RANGES.iter()
+                .position(|&bound| depth < bound)
+                .unwrap_or(DEPTH_RANGES.len());
+            depths[range].0 += 1;
+            depths[range].1 += queue_time;
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let write_table = |out: &mut io::StdoutLock, title: &str, table: &Table| {
+        writeln!(out, "{}", title)?;
+        match args.flag_delimiter {
+            Some(ref delimiter) => table.write_delimited(out, delimiter)?,
+            None => table.write_aligned(out)?,
+        }
+        writeln!(out)
+    };
+
+    if args.flag_concurrency {
+        write_concurrency(&mut stdout, &trends, &write_table);
+        return;
+    }
+
+    let mut table = Table::new(&["window", "requests", "queued", "actconn", "feconn", "beconn",
+                                 "srv_queue", "backend_queue", "queued Tw"]);
+    for (&start, summary) in &windows {
+        if !args.flag_all && summary.queued == 0 {
+            continue;
+        }
+        table.push(vec![
+            format_time(Some(start)),
+            summary.requests.to_string(),
+            summary.queued.to_string(),
+            summary.max_active.to_string(),
+            summary.max_frontend.to_string(),
+            summary.max_backend.to_string(),
+            summary.max_server_queue.to_string(),
+            summary.max_backend_queue.to_string(),
+            mean(summary.queued_wait, summary.queued),
+        ]);
+    }
+    let title = if args.flag_all { "windows:" } else { "windows with queued requests:" };
+    write_table(&mut stdout, title, &table).unwrap();
+
+    let mut table = Table::new(&["server", "requests", "srv_conn", "queued", "queued%",
+                                 "srv_queue", "queued Tw", "first queued", "last queued"]);
+    for ((backend, server), summary) in &servers {
+        // <NOSRV> and the like aren't servers and never have their own queue.
+        if server.first() == Some(&b'<') {
+            continue;
+        }
+        table.push(vec![
+            format!("{}/{}", String::from_utf8_lossy(backend), String::from_utf8_lossy(server)),
+            summary.requests.to_string(),
+            summary.max_connections.to_string(),
+            summary.queued.to_string(),
+            format!("{:.2}", 100.0 * summary.queued as f64 / summary.requests as f64),
+            summary.max_queue.to_string(),
+            mean(summary.queued_wait, summary.queued),
+            format_time(summary.first_queued),
+            format_time(summary.last_queued),
+        ]);
+    }
+    write_table(&mut stdout, "servers (any queued request means srv_conn hit maxconn):",
+                &table).unwrap();
+
+    let mut table = Table::new(&["queue depth", "requests", "mean Tw"]);
+    for (i, &(requests, wait)) in depths.iter().enumerate() {
+        let range = match i {
+            0 => "0".to_string(),
+            i if i == DEPTH_RANGES.len() => format!(">={}", DEPTH_RANGES[i - 1]),
+            i => format!("{}-{}", DEPTH_RANGES[i - 1], DEPTH_RANGES[i] - 1),
+        };
+        table.push(vec![range, requests.to_string(), mean(wait, requests)]);
+    }
+    write_table(&mut stdout, "Tw by srv_queue + backend_queue:", &table).unwrap();
+    match correlation.coefficient() {
+        Some(r) => writeln!(stdout, "correlation between queue depth and Tw: {:.3}", r).unwrap(),
+        None => writeln!(stdout, "correlation between queue depth and Tw: -").unwrap(),
+    }
+    writeln!(stdout).unwrap();
+
+    let mut table = Table::new(&["backend", "requests", "queued", "slow", "slow queued",
+                                 "queue-dominated%", "queued Tt%", "r(depth, Tt)", "r(Tw, Tt)"]);
+    let total = analysis.total();
+    for (backend, stats) in analysis.iter().chain(Some(("total", &total))) {
+        table.push(vec![
+            backend.to_string(),
+            stats.requests.to_string(),
+            stats.queued.to_string(),
+            stats.slow.to_string(),
+            stats.slow_queued.to_string(),
+            percentage(stats.queue_dominated_fraction()),
+            percentage(stats.slow_wait_fraction()),
+            coefficient(stats.depth_correlation()),
+            coefficient(stats.wait_correlation()),
+        ]);
+    }
+    let title = format!("backends (slow is Tt of {}ms or more, queued Tt% is the share of the slow \
+                         requests' Tt spent in Tw):", analysis.slow());
+    write_table(&mut stdout, &title, &table).unwrap();
+}
@@ -0,0 +1,197 @@
+use docopt::Docopt;
+use serde_json::{Map, Value};
+use std::io;
+use std::io::Write;
+use std::process;
+use std::time::Duration;
+
+use haproxy::{Address, Runtime, RuntimeClient, StatsCsv, StatsPage, Table, TableEntry,
+              TableQuery};
+
+
+const DEFAULT_SOCKET: &str = "/var/run/haproxy.sock";
+
+// the columns of `show stat` shown unless --all is given.
+const STAT_COLUMNS: &[&str] = &[
+    "pxname", "svname", "status", "weight", "qcur", "scur", "smax", "stot", "bin", "bout", "ereq",
+    "econ", "eresp", "hrsp_5xx", "lastchg",
+];
+
+static USAGE: &str = "
+Send commands to haproxy's runtime API over its stats socket and print the answer.
+
+Usage:
+    haproxy-cli [options] show stat
+    haproxy-cli [options] show info
+    haproxy-cli [options] show table [<table>]
+    haproxy-cli [options] disable server <target>
+    haproxy-cli [options] enable server <target>
+    haproxy-cli [options] raw <command>...
+    haproxy-cli -h | --help
+
+Options:
+    -s, --socket=ADDRESS    the stats socket to connect to, a path or ipv4@host:port for one
+                            listening on tcp. (default: /var/run/haproxy.sock)
+    --timeout=MS            give up on haproxy after MS milliseconds. (default: wait)
+    --stats-url=URL         read show stat from the http stats page at URL instead of the socket.
+    --auth=USER:PASSWORD    the credentials of the stats page's `stats auth`.
+    --json                  print the answer as JSON instead of a table.
+    -a, --all               show every column of show stat instead of a useful subset.
+    -d, --delimiter=STRING  separate columns with STRING instead of aligning them.
+    -h, --help              display this help and exit
+
+<target> is backend/server, as in the `target` field of haproxy-cut. The socket must be
+configured with `stats socket <path> level admin` to enable or disable servers. Any other
+command can be sent with raw, whose answer is printed as haproxy wrote it.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    cmd_show: bool,
+    cmd_stat: bool,
+    cmd_info: bool,
+    cmd_table: bool,
+    cmd_disable: bool,
+    cmd_enable: bool,
+    flag_socket: Option<String>,
+    flag_timeout: Option<u64>,
+    flag_stats_url: Option<String>,
+    flag_auth: Option<String>,
+    flag_json: bool,
+    flag_all: bool,
+    flag_delimiter: Option<String>,
+    arg_table: Option<String>,
+    arg_target: Option<String>,
+    arg_command: Vec<String>,
+}
+
+fn fail<T>(err: io::Error) -> T {
+    eprintln!("haproxy-cli: {}", err);
+    process::exit(1)
+}
+
+fn write_table(args: &Args, table: &Table) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    match args.flag_delimiter {
+        Some(ref delimiter) => table.write_delimited(&mut stdout, delimiter),
+        None => table.write_aligned(&mut stdout),
+    }
+}
+
+fn write_json(value: &Value) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)
+}
+
+fn show_stat(args: &Args, stats: &StatsCsv) -> io::Result<()> {
+    if args.flag_json {
+        let rows = stats.rows().iter().map(|row| {
+            let object: Map<String, Value> = stats.columns()
+                .iter()
+                .zip(row)
+                .filter(|&(_, value)| !value.is_empty())
+                .map(|(column, value)| (column.clone(), Value::String(value.clone())))
+                .collect();
+            Value::Object(object)
+        });
+        return write_json(&Value::Array(rows.collect()));
+    }
+
+    let columns: Vec<&str> = if args.flag_all {
+        stats.columns().iter().map(|c| c.as_str()).collect()
+    } else {
+        STAT_COLUMNS.iter().cloned().filter(|c| stats.columns().iter().any(|s| s == c)).collect()
+    };
+    let mut table = Table::new(&columns);
+    for row in stats.rows() {
+        table.push(columns.iter().map(|c| stats.value(row, c).unwrap_or("").to_string()).collect());
+    }
+    write_table(args, &table)
+}
+
+fn show_info(args: &Args, info: &[(String, String)]) -> io::Result<()> {
+    if args.flag_json {
+        let object: Map<String, Value> = info.iter()
+            .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+            .collect();
+        return write_json(&Value::Object(object));
+    }
+
+    let mut table = Table::new(&["name", "value"]);
+    for (name, value) in info {
+        table.push(vec![name.clone(), value.clone()]);
+    }
+    write_table(args, &table)
+}
+
+fn show_table(args: &Args, entries: &[TableEntry]) -> io::Result<()> {
+    if args.flag_json {
+        let entries = entries.iter().map(|entry| {
+            let object: Map<String, Value> = entry.values.iter()
+                .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                .collect();
+            Value::Object(object)
+        });
+        return write_json(&Value::Array(entries.collect()));
+    }
+
+    // entries of one table share their columns, but take the union to be safe.
+    let mut columns: Vec<&str> = vec![];
+    for (name, _) in entries.iter().flat_map(|entry| &entry.values) {
+        if !columns.contains(&name.as_str()) {
+            columns.push(name);
+        }
+    }
+    let mut table = Table::new(&columns);
+    for entry in entries {
+        table.push(columns.iter()
+            .map(|c| entry.values.iter().find(|(name, _)| name == c).map_or("", |(_, value)| value))
+            .map(|value| value.to_string())
+            .collect());
+    }
+    write_table(args, &table)
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+    let address = Address::parse(args.flag_socket.as_deref().unwrap_or(DEFAULT_SOCKET));
+    let mut client = RuntimeClient::connect_to(address);
+    client.set_timeout(args.flag_timeout.map(Duration::from_millis));
+
+    let result = if args.cmd_show && args.cmd_stat {
+        let stats = match args.flag_stats_url {
+            Some(ref url) => {
+                let timeout = args.flag_timeout.map(Duration::from_millis);
+                let mut page = StatsPage::with_timeout(url, timeout);
+                if let Some((user, password)) = args.flag_auth.as_deref().and_then(|auth| {
+                    auth.split_once(':')
+                }) {
+                    page.set_credentials(user, password);
+                }
+                page.fetch()
+            },
+            None => client.show_stat_csv(),
+        };
+        stats.and_then(|stats| show_stat(&args, &stats))
+    } else if args.cmd_show && args.cmd_info {
+        client.show_info().and_then(|info| show_info(&args, &info.values))
+    } else if args.cmd_show && args.cmd_table {
+        match args.arg_table {
+            Some(ref name) => client.show_table(name, &TableQuery::default())
+                .and_then(|entries| show_table(&args, &entries)),
+            // without a table name haproxy lists the tables themselves, which is already readable.
+            None => client.execute("show table")
+                .and_then(|response| io::stdout().write_all(response.as_bytes())),
+        }
+    } else if args.cmd_disable {
+        client.disable_server(args.arg_target.as_deref().unwrap_or(""))
+    } else if args.cmd_enable {
+        client.enable_server(args.arg_target.as_deref().unwrap_or(""))
+    } else {
+        let response = client.execute(&args.arg_command.join(" ")).unwrap_or_else(fail);
+        io::stdout().write_all(response.as_bytes())
+    };
+
+    result.unwrap_or_else(fail);
+}
@@ -0,0 +1,297 @@
+use docopt::Docopt;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::Ipv4Addr;
+use std::process;
+
+use haproxy::{Address, ClientRates, Condition, ExprError, Filter, Inputs, LogEntry, Runtime,
+              RuntimeClient, Table};
+
+
+const DEFAULT_TOP: usize = 20;
+const DEFAULT_PEAK_WINDOW: i64 = 10;
+const DEFAULT_LIMIT_WINDOW: i64 = 60;
+
+static USAGE: &str = "
+Summarize haproxy log entries from each <file> per client IP address, to spot scrapers and abusive
+clients.
+
+Usage:
+    haproxy-clients [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-clients -h | --help
+
+Options:
+    -s, --sort=COLUMN       sort clients by requests, bytes, errors, uris or peak, largest first.
+                            (default: requests)
+    --top=N                 only list the top N clients, 0 lists all of them. (default: 20)
+    --prefix=BITS           group IPv4 clients by network, e.g. 24 for every /24. (default: 32)
+    --peak-window=SECS      measure peak request rates over windows of SECS seconds. (default: 10)
+    --limit=N               only list the clients which sent more than N requests within one
+                            window, see --limit-window, with the responses they got.
+    --limit-window=SECS     the sliding window --limit counts requests over. (default: 60)
+    --deny-map=MAP          add each client over the --limit to the map MAP through the runtime
+                            api, with its peak request rate as the value.
+    --socket=ADDRESS        the stats socket for --deny-map, a path or ipv4@host:port. without it
+                            the add map commands are printed, to review or pipe to socat.
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -d, --delimiter=STRING  separate columns with STRING instead of aligning them.
+    -h, --help              display this help and exit
+
+Each row shows the requests and bytes sent to a client, the percentage of its requests which got a
+4xx or a 5xx (or no response), the number of distinct URI paths it asked for, its average request
+rate between its first and last request and its busiest --peak-window in requests per second.
+Peaks assume entries are in the order haproxy logged them.
+
+With --limit each row is a client over the limit instead: its requests, the most it sent within a
+window and that in requests per second, the first and last windows it was over the limit and the
+percentage of its requests which got each class of response (err for none at all). A map used as
+a deny list might be checked with `http-request deny if { src,map_ip(MAP) -m found }`.
+";
+
+#[derive(RustcDecodable)]
+enum SortColumn {
+    Requests,
+    Bytes,
+    Errors,
+    Uris,
+    Peak,
+}
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_sort: Option<SortColumn>,
+    flag_top: Option<usize>,
+    flag_prefix: Option<u32>,
+    flag_peak_window: Option<i64>,
+    flag_limit: Option<u64>,
+    flag_limit_window: Option<i64>,
+    flag_deny_map: Option<String>,
+    flag_socket: Option<String>,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_delimiter: Option<String>,
+    arg_file: Vec<String>,
+}
+
+#[derive(Default)]
+struct Client {
+    requests: u64,
+    bytes: u64,
+    client_errors: u64,
+    server_errors: u64,
+    // hashes of the paths rather than the paths themselves, a scraper can ask for millions.
+    paths: HashSet<u64>,
+    first: Option<i64>,
+    last: Option<i64>,
+    window: i64,
+    window_requests: u64,
+    peak_requests: u64,
+}
+
+impl Client {
+    fn add(&mut self, entry: &LogEntry, peak_window: i64) {
+        self.requests += 1;
+        self.bytes += entry.bytes_read().unwrap_or(0);
+        match entry.status_code() {
+            Ok(400..=499) => self.client_errors += 1,
+            Ok(100..=399) => {},
+            _ => self.server_errors += 1,
+        }
+
+        // group URIs by path, the query string would make almost every one unique.
+        let uri = entry.http_uri().unwrap_or(b"");
+        let path = uri.split(|&c| c == b'?').next().unwrap_or(uri);
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        self.paths.insert(hasher.finish());
+
+        if let Ok(accepted) = entry.accept_date_time() {
+            let timestamp = accepted.and_utc().timestamp();
+            self.first = Some(self.first.map_or(timestamp, |first| first.min(timestamp)));
+            self.last = Some(self.last.map_or(timestamp, |last| last.max(timestamp)));
+
+            let window = timestamp.div_euclid(peak_window);
+            if self.window_requests == 0 || window != self.window {
+                self.window = window;
+                self.window_requests = 0;
+            }
+            self.window_requests += 1;
+            self.peak_requests = self.peak_requests.max(self.window_requests);
+        }
+    }
+
+    fn errors(&self) -> u64 {
+        self.client_errors + self.server_errors
+    }
+
+    fn rate(&self) -> f64 {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => self.requests as f64 / (last - first + 1) as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+// the network a client belongs to with --prefix, IPv6 and unparseable addresses are left alone.
+fn network(client_ip: &[u8], prefix: u32) -> String {
+    let text = String::from_utf8_lossy(client_ip);
+    if prefix >= 32 {
+        return text.into_owned();
+    }
+    match text.parse::<Ipv4Addr>() {
+        Ok(ip) => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            format!("{}/{}", Ipv4Addr::from(u32::from(ip) & mask), prefix)
+        },
+        Err(_) => text.into_owned(),
+    }
+}
+
+fn percentage(count: u64, total: u64) -> String {
+    format!("{:.2}", 100.0 * count as f64 / total.max(1) as f64)
+}
+
+fn report_offenders(args: &Args, filter: &Filter, limit: u64) {
+    let window = args.flag_limit_window.unwrap_or(DEFAULT_LIMIT_WINDOW).max(1);
+    let mut rates = ClientRates::new(window, limit);
+    let mut reader = Inputs::new(&args.arg_file);
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-clients: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        if let Ok(entry) = LogEntry::from_bytes(line) {
+            if filter.matches(&entry) {
+                rates.add(&entry);
+            }
+        }
+    }
+    let offenders = rates.offenders();
+
+    if let Some(ref map) = args.flag_deny_map {
+        let mut runtime = args.flag_socket.as_ref()
+            .map(|socket| RuntimeClient::connect_to(Address::parse(socket)));
+        for offender in &offenders {
+            let rate = format!("{:.1}", offender.peak as f64 / window as f64);
+            match runtime {
+                Some(ref mut runtime) => {
+                    if let Err(err) = runtime.add_map(map, &offender.client, &rate) {
+                        eprintln!("haproxy-clients: could not add {}: {}", offender.client, err);
+                    }
+                },
+                None => println!("add map {} {} {}", map, offender.client, rate),
+            }
+        }
+        return;
+    }
+
+    let mut table = Table::new(&["client", "requests", "peak", "peak/s", "first", "last", "2xx%",
+                                 "3xx%", "4xx%", "5xx%", "err%"]);
+    for offender in &offenders {
+        let mut row = vec![
+            offender.client.clone(),
+            offender.requests.to_string(),
+            offender.peak.to_string(),
+            format!("{:.1}", offender.peak as f64 / window as f64),
+            offender.first_exceeded.format("%Y-%m-%d %H:%M:%S").to_string(),
+            offender.last_exceeded.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ];
+        for class in [2, 3, 4, 5, 0] {
+            row.push(format!("{:.2}", offender.status_percentage(class)));
+        }
+        table.push(row);
+    }
+    let mut stdout = io::stdout();
+    match args.flag_delimiter {
+        Some(ref delimiter) => table.write_delimited(&mut stdout, delimiter).unwrap(),
+        None => table.write_aligned(&mut stdout).unwrap(),
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    if let Some(limit) = args.flag_limit {
+        return report_offenders(&args, &filter, limit);
+    }
+    let prefix = args.flag_prefix.unwrap_or(32);
+    let peak_window = args.flag_peak_window.unwrap_or(DEFAULT_PEAK_WINDOW).max(1);
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut clients: HashMap<String, Client> = HashMap::new();
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-clients: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        let client = network(entry.client_ip, prefix);
+        clients.entry(client).or_default().add(&entry, peak_window);
+    }
+
+    let mut clients: Vec<(String, Client)> = clients.into_iter().collect();
+    match args.flag_sort.unwrap_or(SortColumn::Requests) {
+        SortColumn::Requests => clients.sort_by_key(|c| (!c.1.requests, c.0.clone())),
+        SortColumn::Bytes => clients.sort_by_key(|c| (!c.1.bytes, c.0.clone())),
+        SortColumn::Errors => clients.sort_by_key(|c| (!c.1.errors(), c.0.clone())),
+        SortColumn::Uris => clients.sort_by_key(|c| (!c.1.paths.len(), c.0.clone())),
+        SortColumn::Peak => clients.sort_by_key(|c| (!c.1.peak_requests, c.0.clone())),
+    }
+    let top = match args.flag_top.unwrap_or(DEFAULT_TOP) {
+        0 => clients.len(),
+        top => top,
+    };
+
+    let mut table = Table::new(&["client", "requests", "bytes", "4xx%", "5xx%", "uris", "req/s",
+                                 "peak/s"]);
+    for (client, summary) in clients.iter().take(top) {
+        table.push(vec![
+            client.clone(),
+            summary.requests.to_string(),
+            summary.bytes.to_string(),
+            percentage(summary.client_errors, summary.requests),
+            percentage(summary.server_errors, summary.requests),
+            summary.paths.len().to_string(),
+            format!("{:.2}", summary.rate()),
+            format!("{:.1}", summary.peak_requests as f64 / peak_window as f64),
+        ]);
+    }
+
+    let mut stdout = io::stdout();
+    match args.flag_delimiter {
+        Some(ref delimiter) => table.write_delimited(&mut stdout, delimiter).unwrap(),
+        None => table.write_aligned(&mut stdout).unwrap(),
+    }
+}
@@ -0,0 +1,327 @@
+use docopt::Docopt;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::process;
+
+use haproxy::{Condition, Expr, ExprError, Filter, Inputs, LogEntry, TDigest, Table};
+
+
+const DEFAULT_ALPHA: f64 = 0.01;
+const DEFAULT_MIN_REQUESTS: u64 = 30;
+
+static USAGE: &str = "
+Compare two sets of haproxy log entries, e.g. from before and after a deploy, and report the
+statistically significant differences in latency and status codes, overall and per endpoint.
+
+Usage:
+    haproxy-diff [-w EXPR]... [options] [--] <a> <b>
+    haproxy-diff -h | --help
+
+Options:
+    --a-where=EXPR          only count entries of <a> where EXPR is true.
+    --b-where=EXPR          only count entries of <b> where EXPR is true. with --a-where this
+                            compares parts of the same log, e.g. two backends.
+    -g, --group=EXPR        compare endpoints grouped by EXPR. (default: the path of http_uri)
+    --alpha=P               call a difference significant if its p-value is below P.
+                            (default: 0.01)
+    --min-requests=N        only compare endpoints with at least N requests on both sides.
+                            (default: 30)
+    --all                   list every endpoint compared, not only those with a significant
+                            difference.
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -d, --delimiter=STRING  separate columns with STRING instead of aligning them.
+    -h, --help              display this help and exit
+
+Latency is the total session time (Tt) in milliseconds, whose percentiles are estimated, compared
+with a Mann-Whitney U test, and the share of each status class is compared with a two-proportion
+z-test. Significant differences are marked with a *. With many endpoints some will look
+significant by chance, so a lower --alpha is worth considering.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_a_where: Option<String>,
+    flag_b_where: Option<String>,
+    flag_group: Option<String>,
+    flag_alpha: Option<f64>,
+    flag_min_requests: Option<u64>,
+    flag_all: bool,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_delimiter: Option<String>,
+    arg_a: String,
+    arg_b: String,
+}
+
+#[derive(Default)]
+struct Summary {
+    requests: u64,
+    // indexed by the first digit of the status code, 0 being entries without a response.
+    status_classes: [u64; 6],
+    total_times: TDigest,
+    // every Tt as well, sorted by finish(), since the Mann-Whitney test ranks them all.
+    samples: Vec<i64>,
+}
+
+impl Summary {
+    fn add(&mut self, entry: &LogEntry) {
+        self.requests += 1;
+        let class = match entry.status_code() {
+            Ok(status) if (100..600).contains(&status) => (status / 100) as usize,
+            _ => 0,
+        };
+        self.status_classes[class] += 1;
+        if let Ok(total_time) = entry.total_time() {
+            if total_time >= 0 {
+                self.total_times.add(total_time as f64);
+                self.samples.push(total_time);
+            }
+        }
+    }
+
+    fn errors(&self) -> u64 {
+        self.status_classes[0] + self.status_classes[5]
+    }
+
+    fn finish(&mut self) {
+        self.samples.sort_unstable();
+    }
+
+    fn percentile(&self, p: f64) -> Option<i64> {
+        self.total_times.quantile(p / 100.0).map(|time| time.round() as i64)
+    }
+}
+
+// the probability of a standard normal variable being at least `z` away from 0.
+fn two_sided_p(z: f64) -> f64 {
+    // Abramowitz and Stegun 7.1.26, good to about 1e-7.
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let poly = t * (0.254_829_592 +
+        t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    (poly * (-x * x).exp()).clamp(0.0, 1.0)
+}
+
+fn two_proportion_p(hits_a: u64, n_a: u64, hits_b: u64, n_b: u64) -> Option<f64> {
+    if n_a == 0 || n_b == 0 {
+        return None;
+    }
+    let (n_a, n_b) = (n_a as f64, n_b as f64);
+    let pooled = (hits_a + hits_b) as f64 / (n_a + n_b);
+    let se = (pooled * (1.0 - pooled) * (1.0 / n_a + 1.0 / n_b)).sqrt();
+    if se == 0.0 {
+        return Some(1.0);
+    }
+    Some(two_sided_p((hits_a as f64 / n_a - hits_b as f64 / n_b) / se))
+}
+
+// Mann-Whitney U test with the normal approximation and a correction for ties, on sorted samples.
+fn mann_whitney_p(a: &[i64], b: &[i64]) -> Option<f64> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+
+    // walk both sorted samples at once, giving each run of equal values its average rank.
+    let (mut i, mut j) = (0, 0);
+    let mut rank_sum_a = 0.0;
+    let mut tie_term = 0.0;
+    while i < a.len() || j < b.len() {
+        let value = match (a.get(i), b.get(j)) {
+            (Some(&x), Some(&y)) => x.min(y),
+            (Some(&x), None) => x,
+            (None, Some(&y)) => y,
+            (None, None) => unreachable!(),
+        };
+        let first_rank = (i + j + 1) as f64;
+        let start_a = i;
+        while a.get(i) == Some(&value) {
+            i += 1;
+        }
+        while b.get(j) == Some(&value) {
+            j += 1;
+        }
+        let ties = (i + j) as f64 - first_rank + 1.0;
+        rank_sum_a += (i - start_a) as f64 * (first_rank + (ties - 1.0) / 2.0);
+        tie_term += ties * ties * ties - ties;
+    }
+
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let n = n_a + n_b;
+    let u = rank_sum_a - n_a * (n_a + 1.0) / 2.0;
+    let variance = n_a * n_b / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return Some(1.0);
+    }
+    Some(two_sided_p((u - n_a * n_b / 2.0) / variance.sqrt()))
+}
+
+fn format_p(p: Option<f64>, alpha: f64) -> String {
+    match p {
+        Some(p) if p < alpha => format!("{:.4}*", p),
+        Some(p) => format!("{:.4}", p),
+        None => "-".to_string(),
+    }
+}
+
+fn format_millis(millis: Option<i64>) -> String {
+    millis.map_or("-".to_string(), |millis| millis.to_string())
+}
+
+fn percent(count: u64, total: u64) -> String {
+    if total == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}", 100.0 * count as f64 / total as f64)
+    }
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn read(path: &str, filter: &Filter, group: &Option<Expr>,
+        total: &mut Summary, groups: &mut HashMap<Vec<u8>, Summary>) {
+    let mut reader = Inputs::new(&[path.to_string()]);
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-diff: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        total.add(&entry);
+        let key = match *group {
+            Some(ref group) => match group.evaluate(&entry) {
+                Some(value) => value.as_bytes().into_owned(),
+                None => continue,
+            },
+            None => {
+                let uri = entry.http_uri().unwrap_or(b"");
+                uri.split(|&c| c == b'?').next().unwrap_or(uri).to_vec()
+            },
+        };
+        groups.entry(key).or_default().add(&entry);
+    }
+
+    total.finish();
+    for summary in groups.values_mut() {
+        summary.finish();
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filters = vec![];
+    for side in &[&args.flag_a_where, &args.flag_b_where] {
+        let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+        if let Some(ref since) = args.flag_since {
+            filter.push(Condition::since(since).unwrap_or_else(usage_error));
+        }
+        if let Some(ref until) = args.flag_until {
+            filter.push(Condition::until(until).unwrap_or_else(usage_error));
+        }
+        if let Some(condition) = side {
+            filter.push(Condition::parse(condition).unwrap_or_else(usage_error));
+        }
+        filters.push(filter);
+    }
+    let group = args.flag_group.as_ref().map(|g| Expr::parse(g).unwrap_or_else(usage_error));
+    let alpha = args.flag_alpha.unwrap_or(DEFAULT_ALPHA);
+    let min_requests = args.flag_min_requests.unwrap_or(DEFAULT_MIN_REQUESTS);
+
+    let (mut total_a, mut total_b) = (Summary::default(), Summary::default());
+    let (mut groups_a, mut groups_b) = (HashMap::new(), HashMap::new());
+    read(&args.arg_a, &filters[0], &group, &mut total_a, &mut groups_a);
+    read(&args.arg_b, &filters[1], &group, &mut total_b, &mut groups_b);
+
+    let mut overall = Table::new(&["", "a", "b", "change", "p-value"]);
+    overall.push(vec!["requests".to_string(), total_a.requests.to_string(),
+                      total_b.requests.to_string(),
+                      (total_b.requests as i64 - total_a.requests as i64).to_string(),
+                      "-".to_string()]);
+    let latency_p = mann_whitney_p(&total_a.samples, &total_b.samples);
+    for &(name, p) in &[("Tt p50", 50.0), ("Tt p90", 90.0), ("Tt p99", 99.0)] {
+        let (a, b) = (total_a.percentile(p), total_b.percentile(p));
+        let change = match (a, b) {
+            (Some(a), Some(b)) => (b - a).to_string(),
+            _ => "-".to_string(),
+        };
+        overall.push(vec![name.to_string(), format_millis(a), format_millis(b), change,
+                          format_p(latency_p, alpha)]);
+    }
+    let classes = ["err%", "1xx%", "2xx%", "3xx%", "4xx%", "5xx%"];
+    for (class, name) in classes.iter().enumerate() {
+        let (hits_a, hits_b) = (total_a.status_classes[class], total_b.status_classes[class]);
+        if hits_a == 0 && hits_b == 0 {
+            continue;
+        }
+        let share_a = 100.0 * hits_a as f64 / total_a.requests.max(1) as f64;
+        let share_b = 100.0 * hits_b as f64 / total_b.requests.max(1) as f64;
+        let p = two_proportion_p(hits_a, total_a.requests, hits_b, total_b.requests);
+        overall.push(vec![name.to_string(), percent(hits_a, total_a.requests),
+                          percent(hits_b, total_b.requests),
+                          format!("{:+.2}", share_b - share_a), format_p(p, alpha)]);
+    }
+
+    let mut endpoints = vec![];
+    let keys: BTreeSet<&Vec<u8>> =
+        groups_a.keys().filter(|key| groups_b.contains_key(*key)).collect();
+    for key in keys {
+        let (a, b) = (&groups_a[key], &groups_b[key]);
+        if a.requests < min_requests || b.requests < min_requests {
+            continue;
+        }
+        let errors_p = two_proportion_p(a.errors(), a.requests, b.errors(), b.requests);
+        let latency_p = mann_whitney_p(&a.samples, &b.samples);
+        let best_p = errors_p.unwrap_or(1.0).min(latency_p.unwrap_or(1.0));
+        if !args.flag_all && best_p >= alpha {
+            continue;
+        }
+        endpoints.push((best_p, vec![
+            String::from_utf8_lossy(key).into_owned(),
+            a.requests.to_string(),
+            b.requests.to_string(),
+            percent(a.errors(), a.requests),
+            percent(b.errors(), b.requests),
+            format_p(errors_p, alpha),
+            format_millis(a.percentile(50.0)),
+            format_millis(b.percentile(50.0)),
+            format_millis(a.percentile(99.0)),
+            format_millis(b.percentile(99.0)),
+            format_p(latency_p, alpha),
+        ]));
+    }
+    endpoints.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let name = if args.flag_group.is_some() { "group" } else { "path" };
+    let mut per_endpoint = Table::new(&[name, "a reqs", "b reqs", "a err%", "b err%", "err p",
+                                        "a p50", "b p50", "a p99", "b p99", "Tt p"]);
+    for (_, row) in endpoints {
+        per_endpoint.push(row);
+    }
+
+    let mut stdout = io::stdout();
+    for (i, table) in [overall, per_endpoint].iter().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        match args.flag_delimiter {
+            Some(ref delimiter) => table.write_delimited(&mut stdout, delimiter).unwrap(),
+            None => table.write_aligned(&mut stdout).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use docopt::Docopt;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{Condition, ExprError, Filter, Inputs, LogEntry, RetryStorm, RetryStorms, Table};
+
+
+const DEFAULT_TOP: usize = 10;
+const DEFAULT_MIN_REQUESTS: u64 = 20;
+const DEFAULT_STORM_WINDOW: i64 = 60;
+const DEFAULT_STORM_RETRIED: u64 = 5;
+// upper bounds of the queue time (Tw) ranges errors are compared across, in milliseconds.
+const QUEUE_RANGES: &[i64] = &[1, 10, 100, 1000];
+const COUNT_COLUMNS: &[&str] = &[
+    "requests", "4xx", "5xx", "err", "err%", "retries", "redispatches",
+];
+
+static USAGE: &str = "
+Break down the failures in haproxy log entries from each <file>: why sessions ended, which
+backends, URIs and servers produced errors, and how errors relate to queueing.
+
+Usage:
+    haproxy-errors [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-errors -h | --help
+
+Options:
+    --top=N                 list the N URIs and servers with the most errors. (default: 10)
+    --min-requests=N        leave servers with fewer than N requests out of the suspicious
+                            server ranking. (default: 20)
+    --storm-window=SECS     look for retry storms in windows of SECS seconds. (default: 60)
+    --storm-retried=N       count a window where a server had N or more retried or redispatched
+                            requests as part of a retry storm. (default: 5)
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -d, --delimiter=STRING  separate columns with STRING instead of aligning them.
+    -h, --help              display this help and exit
+
+Errors are entries with a 4xx, a 5xx or no response at all (err). Retries count the connection
+retries haproxy logged, and redispatches the entries whose retries were redispatched to another
+server (logged with a leading +).
+
+Servers are ranked by how much worse they do than the rest of their backend: the share of their
+requests which got a 5xx, no response or ended with a server-side termination state (S or s), minus
+the same share for the other servers of the backend.
+
+A retry storm is a run of consecutive windows in which a server kept having requests retried or
+redispatched, often the first sign of a server flapping between up and down before its health
+checks catch on. Each storm is listed with its time range, and each backend with the servers and
+the span of time its storms covered.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_top: Option<usize>,
+    flag_min_requests: Option<u64>,
+    flag_storm_window: Option<i64>,
+    flag_storm_retried: Option<u64>,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_delimiter: Option<String>,
+    arg_file: Vec<String>,
+}
+
+#[derive(Default, Clone)]
+struct Counts {
+    requests: u64,
+    client_errors: u64,
+    server_errors: u64,
+    no_response: u64,
+    // 5xx, no response or a server-side termination.
+    server_failures: u64,
+    retries: u64,
+    redispatches: u64,
+    queue_times: Vec<i64>,
+}
+
+impl Counts {
+    fn add(&mut self, entry: &LogEntry) {
+        self.requests += 1;
+        let status = entry.status_code().unwrap_or(-1);
+        match status {
+            400..=499 => self.client_errors += 1,
+            500..=599 => self.server_errors += 1,
+            100..=399 => {},
+            _ => self.no_response += 1,
+        }
+        let server_side = matches!(entry.termination_state.first(), Some(b'S') | Some(b's'));
+        if !(100..500).contains(&status) || server_side {
+            self.server_failures += 1;
+        }
+        self.retries += entry.retried_connections().unwrap_or(0);
+        if entry.retried_connections.first() == Some(&b'+') {
+            self.redispatches += 1;
+        }
+        if let Ok(queue_time) = entry.queue_time() {
+            if queue_time >= 0 {
+                self.queue_times.push(queue_time);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Counts) {
+        self.requests += other.requests;
+        self.client_errors += other.client_errors;
+        self.server_errors += other.server_errors;
+        self.no_response += other.no_response;
+        self.server_failures += other.server_failures;
+        self.retries += other.retries;
+        self.redispatches += other.redispatches;
+    }
+
+    fn errors(&self) -> u64 {
+        self.client_errors + self.server_errors + self.no_response
+    }
+
+    fn failure_rate(&self) -> f64 {
+        ratio(self.server_failures, self.requests)
+    }
+
+    fn mean_queue_time(&self) -> Option<f64> {
+        if self.queue_times.is_empty() {
+            None
+        } else {
+            Some(self.queue_times.iter().sum::<i64>() as f64 / self.queue_times.len() as f64)
+        }
+    }
+
+    fn row(&self, name: String) -> Vec<String> {
+        vec![
+            name,
+            self.requests.to_string(),
+            self.client_errors.to_string(),
+            self.server_errors.to_string(),
+            self.no_response.to_string(),
+            format!("{:.2}", 100.0 * ratio(self.errors(), self.requests)),
+            self.retries.to_string(),
+            self.redispatches.to_string(),
+        ]
+    }
+}
+
+fn ratio(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+// what the first two characters of the termination state say about how a session ended.
+fn describe_termination(state: &[u8]) -> String {
+    let cause = match state.first() {
+        Some(b'-') => "normal",
+        Some(b'C') => "client aborted",
+        Some(b'S') => "server aborted or errored",
+        Some(b'c') => "client timed out",
+        Some(b's') => "server timed out",
+        Some(b'P') => "haproxy aborted",
+        Some(b'L') => "haproxy answered itself",
+        Some(b'R') => "haproxy ran out of resources",
+        Some(b'I') => "haproxy internal error",
+        Some(b'D') => "server was marked down",
+        Some(b'U') => "server was marked up",
+        Some(b'K') => "killed by an admin",
+        _ => "unknown",
+    };
+    let phase = match state.get(1) {
+        Some(b'-') => "",
+        Some(b'R') => " waiting for the request",
+        Some(b'Q') => " in the queue",
+        Some(b'C') => " while connecting",
+        Some(b'H') => " waiting for response headers",
+        Some(b'D') => " during the data transfer",
+        Some(b'L') => " while sending the last data",
+        Some(b'T') => " during tarpit",
+        _ => " in an unknown phase",
+    };
+    format!("{}{}", cause, phase)
+}
+
+fn format_storm_time(storm: &RetryStorm) -> String {
+    let end = if storm.end.date() == storm.start.date() {
+        storm.end.format("%H:%M:%S")
+    } else {
+        storm.end.format("%Y-%m-%d %H:%M:%S")
+    };
+    format!("{} - {}", storm.start.format("%Y-%m-%d %H:%M:%S"), end)
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    let top = args.flag_top.unwrap_or(DEFAULT_TOP);
+    let min_requests = args.flag_min_requests.unwrap_or(DEFAULT_MIN_REQUESTS);
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut total = Counts::default();
+    let mut terminations: BTreeMap<Vec<u8>, u64> = BTreeMap::new();
+    let mut backends: BTreeMap<Vec<u8>, Counts> = BTreeMap::new();
+    let mut servers: HashMap<(Vec<u8>, Vec<u8>), Counts> = HashMap::new();
+    let mut uris: HashMap<Vec<u8>, Counts> = HashMap::new();
+    // requests and errors per queue time range, the last one being everything above the bounds.
+    let mut queueing = vec![(0u64, 0u64); QUEUE_RANGES.len() + 1];
+    let mut storms = RetryStorms::new(args.flag_storm_window.unwrap_or(DEFAULT_STORM_WINDOW),
+                                      args.flag_storm_retried.unwrap_or(DEFAULT_STORM_RETRIED));
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-errors: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        storms.add(&entry);
+        let mut counts = Counts::default();
+        counts.add(&entry);
+        total.merge(&counts);
+        let state = entry.termination_state.get(..2).unwrap_or(entry.termination_state);
+        *terminations.entry(state.to_vec()).or_default() += 1;
+        backends.entry(entry.backend_name.to_vec()).or_default().add(&entry);
+        let server = (entry.backend_name.to_vec(), entry.server_name.to_vec());
+        servers.entry(server).or_default().merge(&counts);
+        // group URIs by path, the query string would make almost every one unique.
+        let uri = entry.http_uri().unwrap_or(b"");
+        let path = uri.split(|&c| c == b'?').next().unwrap_or(uri);
+        uris.entry(path.to_vec()).or_default().merge(&counts);
+
+        if let Ok(queue_time) = entry.queue_time() {
+            if queue_time >= 0 {
+                let range = QUEUE_RANGES.iter()
+                    .position(|&bound| queue_time < bound)
+                    .unwrap_or(QUEUE_RANGES.len());
+                queueing[range].0 += 1;
+                if counts.errors() > 0 {
+                    queueing[range].1 += 1;
+                }
+            }
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let write_table = |out: &mut io::StdoutLock, title: &str, table: &Table| {
+        writeln!(out, "{}", title)?;
+        match args.flag_delimiter {
+            Some(ref delimiter) => table.write_delimited(out, delimiter)?,
+            None => table.write_aligned(out)?,
+        }
+        writeln!(out)
+    };
+
+    writeln!(stdout, "{} requests, {} errors ({:.2}%): {} 4xx, {} 5xx, {} without a response, \
+                      {} retries, {} redispatched\n",
+             total.requests, total.errors(), 100.0 * ratio(total.errors(), total.requests),
+             total.client_errors, total.server_errors, total.no_response, total.retries,
+             total.redispatches).unwrap();
+
+    let mut table = Table::new(&["state", "count", "%", "meaning"]);
+    let mut states: Vec<(Vec<u8>, u64)> = terminations.into_iter().collect();
+    states.sort_by_key(|s| !s.1);
+    for (state, count) in states {
+        table.push(vec![
+            String::from_utf8_lossy(&state).into_owned(),
+            count.to_string(),
+            format!("{:.2}", 100.0 * ratio(count, total.requests)),
+            describe_termination(&state),
+        ]);
+    }
+    write_table(&mut stdout, "termination states:", &table).unwrap();
+
+    let mut table = Table::new(&[&["backend"], COUNT_COLUMNS, &["mean Tw"]].concat());
+    for (backend, counts) in &backends {
+        let mut row = counts.row(String::from_utf8_lossy(backend).into_owned());
+        row.push(counts.mean_queue_time().map_or("-".to_string(), |t| format!("{:.1}", t)));
+        table.push(row);
+    }
+    write_table(&mut stdout, "errors by backend:", &table).unwrap();
+
+    let mut by_errors: Vec<(Vec<u8>, Counts)> = uris.into_iter().collect();
+    by_errors.retain(|(_, counts)| counts.errors() > 0);
+    by_errors.sort_by(|a, b| b.1.errors().cmp(&a.1.errors()).then_with(|| a.0.cmp(&b.0)));
+    let mut table = Table::new(&[&["uri"], COUNT_COLUMNS].concat());
+    for (uri, counts) in by_errors.iter().take(top) {
+        table.push(counts.row(String::from_utf8_lossy(uri).into_owned()));
+    }
+    write_table(&mut stdout, "URIs with the most errors:", &table).unwrap();
+
+    let mut table = Table::new(&["Tw", "requests", "errors", "err%"]);
+    for (i, &(requests, errors)) in queueing.iter().enumerate() {
+        let range = match i {
+            0 => "0".to_string(),
+            i if i == QUEUE_RANGES.len() => format!(">={}", QUEUE_RANGES[i - 1]),
+            i => format!("{}-{}", QUEUE_RANGES[i - 1], QUEUE_RANGES[i] - 1),
+        };
+        table.push(vec![range, requests.to_string(), errors.to_string(),
+                        format!("{:.2}", 100.0 * ratio(errors, requests))]);
+    }
+    write_table(&mut stdout, "errors by queue time (ms):", &table).unwrap();
+
+    // compare each server to the rest of its backend, so a backend which is failing as a whole
+    // doesn't make every one of its servers look suspicious.
+    let mut suspicious: Vec<(String, f64, &Counts)> = servers.iter()
+        // <NOSRV> and the like aren't servers, they're requests which never got to one.
+        .filter(|((_, server), counts)| counts.requests >= min_requests && server[0] != b'<')
+        .map(|((backend, server), counts)| {
+            let mut rest = backends[backend].clone();
+            rest.requests -= counts.requests;
+            rest.server_failures -= counts.server_failures;
+            let excess = counts.failure_rate() - rest.failure_rate();
+            let name = format!("{}/{}", String::from_utf8_lossy(backend),
+                               String::from_utf8_lossy(server));
+            (name, excess, counts)
+        })
+        .filter(|&(_, excess, _)| excess > 0.0)
+        .collect();
+    suspicious.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let mut table = Table::new(&["server", "requests", "failures", "failure%", "backend%",
+                                 "retries", "redispatches"]);
+    for (name, excess, counts) in suspicious.into_iter().take(top) {
+        let failure_rate = 100.0 * counts.failure_rate();
+        table.push(vec![
+            name,
+            counts.requests.to_string(),
+            counts.server_failures.to_string(),
+            format!("{:.2}", failure_rate),
+            format!("{:.2}", failure_rate - 100.0 * excess),
+            counts.retries.to_string(),
+            counts.redispatches.to_string(),
+        ]);
+    }
+    write_table(&mut stdout, "most suspicious servers:", &table).unwrap();
+
+    let storms = storms.storms();
+    let mut table = Table::new(&["time", "server", "requests", "retried", "retried%", "retries",
+                                 "redispatches"]);
+    for storm in &storms {
+        table.push(vec![
+            format_storm_time(storm),
+            format!("{}/{}", storm.backend, storm.server),
+            storm.counts.requests.to_string(),
+            storm.counts.retried.to_string(),
+            format!("{:.2}", 100.0 * ratio(storm.counts.retried, storm.counts.requests)),
+            storm.counts.retries.to_string(),
+            storm.counts.redispatches.to_string(),
+        ]);
+    }
+    write_table(&mut stdout, "retry storms:", &table).unwrap();
+
+    // the storms of each backend, its servers which had them, and the span they covered.
+    let mut affected: BTreeMap<&str, (usize, Vec<&str>, RetryStorm)> = BTreeMap::new();
+    for storm in &storms {
+        let (count, servers, span) = affected.entry(&storm.backend)
+            .or_insert_with(|| (0, vec![], storm.clone()));
+        if *count > 0 {
+            span.start = span.start.min(storm.start);
+            span.end = span.end.max(storm.end);
+            span.counts.merge(&storm.counts);
+        }
+        *count += 1;
+        if !servers.contains(&&*storm.server) {
+            servers.push(&storm.server);
+        }
+    }
+    let mut table = Table::new(&["backend", "storms", "servers", "time", "retried",
+                                 "redispatches"]);
+    for (backend, (count, mut servers, span)) in affected {
+        servers.sort_unstable();
+        table.push(vec![
+            backend.to_string(),
+            count.to_string(),
+            servers.join(","),
+            format_storm_time(&span),
+            span.counts.retried.to_string(),
+            span.counts.redispatches.to_string(),
+        ]);
+    }
+    write_table(&mut stdout, "backends with retry storms:", &table).unwrap();
+}
@@ -0,0 +1,214 @@
+use docopt::Docopt;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use haproxy::{ExprError, FieldSet, Filter, Follow, LogEntry, LogMetrics};
+
+
+const MAX_LINE_LENGTH: usize = 1024;
+const MAX_DATAGRAM_LENGTH: usize = 65536;
+const DEFAULT_LISTEN: &str = "0.0.0.0:9101";
+const DEFAULT_TOP_SIZE: usize = 10;
+// how long a scrape may take to send its request or read the response before it's dropped.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+static USAGE: &str = "
+Follow haproxy log entries written to <file> (or standard input, or sent over syslog) and serve
+Prometheus metrics about them.
+
+Usage:
+    haproxy-exporter [-w EXPR]... [options] [--] [<file>]
+    haproxy-exporter -h | --help
+
+Options:
+    -l, --listen=ADDR       serve metrics at http://ADDR/metrics. (default: 0.0.0.0:9101)
+    --syslog=ADDR           receive entries as syslog messages on UDP address ADDR, e.g.
+                            0.0.0.0:514, instead of reading <file>.
+    --from-start            read <file> from the beginning instead of only new entries.
+    --buckets=LIST          latency histogram bucket upper bounds, as comma separated seconds.
+                            (default: 0.005,0.01,0.025,0.05,0.1,0.25,0.5,1,2.5,5,10)
+    --quantiles=LIST        also serve summaries of Tr and Tt at these comma separated quantiles,
+                            e.g. 0.5,0.9,0.99, estimated without keeping every time.
+    --top=LIST              also serve the estimated requests of the most frequent values of each
+                            of these comma separated fields, e.g. client_ip,http_uri.
+    --top-size=N            how many values of each --top field to serve. (default: 10)
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    -h, --help              display this help and exit
+
+Metrics:
+    haproxy_log_requests_total              entries, by frontend, backend and status_class
+    haproxy_log_bytes_read_total            bytes sent to clients, by the same labels
+    haproxy_log_response_time_seconds       histogram of Tr, by frontend and backend
+    haproxy_log_total_time_seconds          histogram of Tt, by frontend and backend
+    haproxy_log_invalid_lines_total         lines that failed to parse
+    haproxy_log_response_time_quantiles_seconds
+                                            summary of Tr, with --quantiles
+    haproxy_log_total_time_quantiles_seconds
+                                            summary of Tt, with --quantiles
+    haproxy_log_top_requests                requests of the busiest values, by field and value,
+                                            with --top
+
+Timers of -1, for sessions which never got that far, aren't observed by the histograms or summaries.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_listen: Option<String>,
+    flag_syslog: Option<String>,
+    flag_from_start: bool,
+    flag_buckets: Option<String>,
+    flag_quantiles: Option<String>,
+    flag_top: Option<String>,
+    flag_top_size: Option<usize>,
+    flag_where: Vec<String>,
+    arg_file: Option<String>,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn argv_error<T>(msg: String) -> T {
+    docopt::Error::Argv(msg).exit()
+}
+
+// syslog messages start with a priority, date and hostname like `<134>Feb  6 12:14:14 lb1 `
+// before the process name, which is the word right before `[pid]: `.
+fn strip_syslog_header(message: &[u8]) -> &[u8] {
+    let pid_end = match message.windows(3).position(|w| w == b"]: ") {
+        Some(pid_end) => pid_end,
+        None => return message,
+    };
+    let pid_start = message[..pid_end].iter().rposition(|&c| c == b'[').unwrap_or(0);
+    let name_start = message[..pid_start].iter().rposition(|&c| c == b' ').map_or(0, |i| i + 1);
+    &message[name_start..]
+}
+
+fn record(metrics: &Mutex<LogMetrics>, filter: &Filter, line: &[u8]) {
+    match LogEntry::from_bytes(line) {
+        Ok(entry) => {
+            if filter.matches(&entry) {
+                metrics.lock().unwrap().add(&entry);
+            }
+        },
+        Err(_) => metrics.lock().unwrap().add_invalid_line(),
+    }
+}
+
+fn read_lines(args: &Args, filter: Filter, metrics: Arc<Mutex<LogMetrics>>) -> io::Result<()> {
+    let mut follow = match args.arg_file {
+        Some(ref path) if args.flag_from_start => Some(Follow::from_start(path)?),
+        Some(ref path) => Some(Follow::new(path)?),
+        None => None,
+    };
+
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let mut line_buffer: Vec<u8> = Vec::with_capacity(MAX_LINE_LENGTH);
+        loop {
+            line_buffer.clear();
+            let read = match follow {
+                Some(ref mut follow) => follow.read_line(&mut line_buffer),
+                None => stdin.read_until(b'\n', &mut line_buffer),
+            };
+            match read {
+                Ok(0) | Err(_) => break,
+                Ok(_) => record(&metrics, &filter, &line_buffer),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn receive_syslog(address: &str, filter: Filter, metrics: Arc<Mutex<LogMetrics>>)
+                  -> io::Result<()> {
+    let socket = UdpSocket::bind(address)?;
+    thread::spawn(move || {
+        let mut datagram = vec![0; MAX_DATAGRAM_LENGTH];
+        while let Ok(length) = socket.recv(&mut datagram) {
+            record(&metrics, &filter, strip_syslog_header(&datagram[..length]));
+        }
+    });
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream, metrics: &Mutex<LogMetrics>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+    // the headers don't matter, but they have to be read before responding.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split(' ').nth(1).unwrap_or("");
+    if path == "/metrics" || path.starts_with("/metrics?") {
+        let body = metrics.lock().unwrap().render();
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                        Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    } else {
+        let body = "see /metrics\n";
+        write!(stream, "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\n\
+                        Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    let mut metrics = match args.flag_buckets {
+        Some(ref buckets) => {
+            let buckets = buckets.split(',')
+                .map(|bound| bound.trim().parse().unwrap_or_else(|_| {
+                    argv_error(format!("could not parse bucket bound '{}'", bound))
+                }))
+                .collect();
+            LogMetrics::new(buckets)
+        },
+        None => LogMetrics::default(),
+    };
+    if let Some(ref quantiles) = args.flag_quantiles {
+        let quantiles = quantiles.split(',')
+            .map(|q| match q.trim().parse() {
+                Ok(parsed) if (0.0..=1.0).contains(&parsed) => parsed,
+                _ => argv_error(format!("could not parse quantile '{}'", q)),
+            })
+            .collect();
+        metrics.set_quantiles(quantiles);
+    }
+    if let Some(ref top) = args.flag_top {
+        let size = args.flag_top_size.unwrap_or(DEFAULT_TOP_SIZE);
+        for (name, field) in FieldSet::parse(top).unwrap_or_else(usage_error).iter() {
+            metrics.add_heavy_hitters(name, field, size);
+        }
+    }
+    let metrics = Arc::new(Mutex::new(metrics));
+
+    let listen = args.flag_listen.as_deref().unwrap_or(DEFAULT_LISTEN);
+    let listener = TcpListener::bind(listen)
+        .unwrap_or_else(|err| argv_error(format!("could not listen on {}: {}", listen, err)));
+
+    let started = match args.flag_syslog {
+        Some(ref address) => receive_syslog(address, filter, Arc::clone(&metrics)),
+        None => read_lines(&args, filter, Arc::clone(&metrics)),
+    };
+    if let Err(err) = started {
+        argv_error::<()>(format!("could not open input: {}", err));
+    }
+
+    // each connection on its own thread, so a client which never sends its request doesn't hold
+    // up the scrapes after it.
+    for stream in listener.incoming().flatten() {
+        let metrics = Arc::clone(&metrics);
+        thread::spawn(move || serve(stream, &metrics));
+    }
+}
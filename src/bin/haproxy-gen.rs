@@ -0,0 +1,314 @@
+use chrono::{Duration, Local, NaiveDateTime};
+use docopt::Docopt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::thread;
+use std::time::Instant;
+
+use haproxy::{parse_date, ACCEPT_DATE_FORMAT};
+
+
+const DEFAULT_COUNT: u64 = 1000;
+const DEFAULT_SEED: u64 = 1;
+const DEFAULT_STATUS_MIX: &str = "200:90,304:3,404:4,500:1,503:1,504:1";
+const DEFAULT_LATENCY_MEDIAN: f64 = 40.0;
+const DEFAULT_LATENCY_P99: f64 = 800.0;
+const DEFAULT_BACKENDS: usize = 3;
+const DEFAULT_SERVERS: usize = 2;
+// the z-score of the 99th percentile of a normal distribution.
+const Z_99: f64 = 2.326;
+
+const DEFAULT_URIS: &[&str] = &[
+    "/",
+    "/index.html",
+    "/favicon.ico",
+    "/static/app.js",
+    "/static/style.css",
+    "/api/v1/items",
+    "/api/v1/items?page=2",
+    "/api/v1/users/42",
+    "/login",
+    "/search?q=haproxy",
+];
+const USER_AGENTS: &[&str] = &[
+    "curl/8.5.0",
+    "Mozilla/5.0",
+    "python-requests/2.31",
+    "Go-http-client/1.1",
+];
+const HOSTS: &[&str] = &["example.com", "www.example.com", "api.example.com"];
+
+static USAGE: &str = "
+Generate realistic haproxy log entries, for load testing log pipelines or as test fixtures.
+
+Usage:
+    haproxy-gen [options]
+    haproxy-gen -h | --help
+
+Options:
+    -n, --count=N           generate N entries, or never stop if N is 0. (default: 1000)
+    -r, --rate=N            write N entries per second of real time. (default: as fast as possible)
+    --seed=N                seed the random generator with N. the same seed and options always
+                            give the same output. (default: 1)
+    --start=DATE            accept the first request at DATE. (default: now)
+    --interval=MS           the average time between requests in the log. (default: 1000 / --rate,
+                            or 10)
+    --status-mix=SPEC       how often each status code occurs, as comma separated code:weight
+                            pairs. (default: 200:90,304:3,404:4,500:1,503:1,504:1)
+    --latency-median=MS     the median response time (Tr). (default: 40)
+    --latency-p99=MS        the 99th percentile of the response time. (default: 800)
+    --uris=FILE             pick request URIs from the lines of FILE. (default: a built-in set)
+    --backends=N            spread requests over N backends... (default: 3)
+    --servers=N             ...of N servers each. (default: 2)
+    --captures=N            add N blocks of captured headers, 0, 1 (request) or 2 (request and
+                            response). (default: 0)
+    -h, --help              display this help and exit
+
+Response times follow a log-normal distribution fitted to the median and 99th percentile, and the
+termination state, timers and bytes of 5xx responses are consistent with how haproxy logs them.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_count: Option<u64>,
+    flag_rate: Option<f64>,
+    flag_seed: Option<u64>,
+    flag_start: Option<String>,
+    flag_interval: Option<f64>,
+    flag_status_mix: Option<String>,
+    flag_latency_median: Option<f64>,
+    flag_latency_p99: Option<f64>,
+    flag_uris: Option<String>,
+    flag_backends: Option<usize>,
+    flag_servers: Option<usize>,
+    flag_captures: Option<usize>,
+}
+
+// xorshift64*, small and fast, and unlike the generators of most crates its output will never
+// change under us, which matters for fixtures.
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // a zero state would only ever produce zeroes.
+        Rng { state: (seed ^ 0x9e37_79b9_7f4a_7c15) | 1 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // uniform in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    // standard normal, by the Box-Muller transform.
+    fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.next_f64();
+        let v = self.next_f64();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    fn exponential(&mut self, mean: f64) -> f64 {
+        -mean * (1.0 - self.next_f64()).ln()
+    }
+}
+
+struct StatusMix {
+    statuses: Vec<(u16, f64)>,
+    total: f64,
+}
+
+impl StatusMix {
+    fn parse(spec: &str) -> Result<StatusMix, String> {
+        let mut statuses = vec![];
+        for pair in spec.split(',') {
+            let (status, weight) = pair.split_once(':')
+                .ok_or_else(|| format!("expected code:weight, got '{}'", pair))?;
+            let status = status.trim().parse()
+                .map_err(|_| format!("could not parse status code '{}'", status))?;
+            let weight: f64 = weight.trim().parse()
+                .map_err(|_| format!("could not parse weight '{}'", weight))?;
+            if weight < 0.0 {
+                return Err(format!("weights can't be negative, got '{}'", weight));
+            }
+            statuses.push((status, weight));
+        }
+
+        let total = statuses.iter().map(|&(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return Err("the status mix needs at least one positive weight".to_string());
+        }
+        Ok(StatusMix { statuses, total })
+    }
+
+    fn pick(&self, rng: &mut Rng) -> u16 {
+        let mut point = rng.next_f64() * self.total;
+        for &(status, weight) in &self.statuses {
+            if point < weight {
+                return status;
+            }
+            point -= weight;
+        }
+        self.statuses[self.statuses.len() - 1].0
+    }
+}
+
+struct Generator {
+    rng: Rng,
+    statuses: StatusMix,
+    // the parameters of the log-normal distribution of Tr.
+    mu: f64,
+    sigma: f64,
+    uris: Vec<String>,
+    backends: usize,
+    servers: usize,
+    captures: usize,
+}
+
+impl Generator {
+    fn write_entry<W: Write>(&mut self, out: &mut W, accepted: NaiveDateTime) -> io::Result<()> {
+        let rng = &mut self.rng;
+        let status = self.statuses.pick(rng);
+        let uri = rng.pick(&self.uris).clone();
+        let backend = rng.below(self.backends) + 1;
+        let server = rng.below(self.servers) + 1;
+
+        let request_time = rng.below(5) as i64;
+        let mut queue_time = 0;
+        let mut connect_time = rng.below(3) as i64;
+        let mut response_time = (self.mu + self.sigma * rng.normal()).exp().round() as i64;
+        let mut bytes = 200 + rng.exponential(4000.0) as u64;
+        let mut termination_state = "----";
+        let mut server_name = format!("srv{}", server);
+        match status {
+            // no server was available, so the request was queued until it timed out.
+            503 => {
+                queue_time = 1000 + rng.below(4000) as i64;
+                connect_time = -1;
+                response_time = -1;
+                bytes = 212;
+                termination_state = "sQ--";
+                server_name = "<NOSRV>".to_string();
+            },
+            // the server didn't answer in time.
+            504 => {
+                response_time = -1;
+                bytes = 194;
+                termination_state = "sH--";
+            },
+            500 | 502 => {
+                bytes = 200 + rng.below(300) as u64;
+                termination_state = if status == 502 { "SH--" } else { "----" };
+            },
+            304 => bytes = 150 + rng.below(100) as u64,
+            _ => {},
+        }
+        let data_time = rng.below(10) as i64;
+        let total_time = request_time + queue_time + connect_time.max(0) + response_time.max(0) +
+            data_time + if response_time < 0 { 5000 } else { 0 };
+
+        let active = 1 + rng.below(200);
+        let backend_connections = 1 + rng.below(active);
+        let server_connections = 1 + rng.below(backend_connections);
+        let retries = if rng.below(100) == 0 { 1 } else { 0 };
+
+        write!(out, "haproxy[14389]: 10.{}.{}.{}:{} [{}] http-in backend{}/{} ",
+               rng.below(4), rng.below(256), 1 + rng.below(254), 1024 + rng.below(64000),
+               accepted.format(ACCEPT_DATE_FORMAT), backend, server_name)?;
+        write!(out, "{}/{}/{}/{}/{} {} {} - - {} {}/{}/{}/{}/{} 0/0 ",
+               request_time, queue_time, connect_time, response_time, total_time, status, bytes,
+               termination_state, active, active, backend_connections, server_connections,
+               retries)?;
+        if self.captures >= 1 {
+            write!(out, "{{{}|{}}} ", rng.pick(HOSTS), rng.pick(USER_AGENTS))?;
+        }
+        if self.captures >= 2 {
+            write!(out, "{{{}}} ", if status == 304 { "" } else { "text/html" })?;
+        }
+        let method = if uri.starts_with("/api/") && rng.below(4) == 0 { "POST" } else { "GET" };
+        writeln!(out, "\"{} {} HTTP/1.1\"", method, uri)
+    }
+}
+
+fn argv_error<T>(msg: String) -> T {
+    docopt::Error::Argv(msg).exit()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let statuses = StatusMix::parse(args.flag_status_mix.as_deref().unwrap_or(DEFAULT_STATUS_MIX))
+        .unwrap_or_else(argv_error);
+    let median = args.flag_latency_median.unwrap_or(DEFAULT_LATENCY_MEDIAN).max(1.0);
+    let p99 = args.flag_latency_p99.unwrap_or(DEFAULT_LATENCY_P99).max(median);
+    let uris: Vec<String> = match args.flag_uris {
+        Some(ref path) => {
+            let uris = fs::read_to_string(path)
+                .unwrap_or_else(|err| argv_error(format!("could not read --uris: {}", err)));
+            uris.lines().map(str::trim).filter(|uri| !uri.is_empty()).map(String::from).collect()
+        },
+        None => DEFAULT_URIS.iter().map(|uri| uri.to_string()).collect(),
+    };
+    if uris.is_empty() {
+        argv_error::<()>("--uris file has no URIs in it".to_string());
+    }
+    let start = match args.flag_start {
+        Some(ref start) => parse_date(start)
+            .unwrap_or_else(|| argv_error(format!("could not parse date '{}'", start))),
+        None => Local::now().naive_local(),
+    };
+
+    let mut generator = Generator {
+        rng: Rng::new(args.flag_seed.unwrap_or(DEFAULT_SEED)),
+        statuses,
+        mu: median.ln(),
+        sigma: (p99 / median).ln() / Z_99,
+        uris,
+        backends: args.flag_backends.unwrap_or(DEFAULT_BACKENDS).max(1),
+        servers: args.flag_servers.unwrap_or(DEFAULT_SERVERS).max(1),
+        captures: args.flag_captures.unwrap_or(0).min(2),
+    };
+
+    let count = args.flag_count.unwrap_or(DEFAULT_COUNT);
+    let rate = args.flag_rate.filter(|&rate| rate > 0.0);
+    let interval = args.flag_interval.unwrap_or_else(|| rate.map_or(10.0, |rate| 1000.0 / rate));
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let started = Instant::now();
+    let mut accepted = start;
+    let mut written: u64 = 0;
+    while count == 0 || written < count {
+        if generator.write_entry(&mut stdout, accepted).is_err() {
+            break;
+        }
+        written += 1;
+        let gap = generator.rng.exponential(interval);
+        accepted += Duration::microseconds((gap * 1000.0) as i64);
+
+        if let Some(rate) = rate {
+            stdout.flush().ok();
+            let due = started + std::time::Duration::from_secs_f64(written as f64 / rate);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+    }
+}
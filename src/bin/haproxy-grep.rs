@@ -0,0 +1,123 @@
+use docopt::Docopt;
+use libc::consts::os::posix88::STDOUT_FILENO;
+use libc::funcs::posix88::unistd;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{Condition, ExprError, Filter, Inputs, LogEntry};
+
+
+static USAGE: &str = "
+Print haproxy log entries from each <file> which match all of the given conditions to standard
+output.  Matching lines are printed exactly as they were read.
+
+Usage:
+    haproxy-grep [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-grep -h | --help
+
+Options:
+    -w, --where=EXPR        only print entries where EXPR is true. may be given more than once.
+    --since=DATE            only print entries accepted at or after DATE.
+    --until=DATE            only print entries accepted before DATE.
+    -v, --invert-match      print the entries which don't match instead.
+    -c, --count             only print the number of matching entries.
+    --line-buffered         flush output on every line (default: buffered unless stdout is a TTY)
+    --show-invalid          print out lines that failed to parse to stderr (default: don't show)
+    -h, --help              display this help and exit
+
+Conditions compare two expressions, where an expression is a field name (as listed by
+`haproxy-cut --help-fields`), an integer, a \"double quoted\" string, or any of those combined with
++, -, * and /:
+
+    status_code >= 500
+    backend_name == \"static\"
+    Tt - Tr > 1000
+
+Numbers are compared numerically and everything else byte by byte.  The supported comparisons are
+==, !=, <, <=, > and >=.  A field can also be matched against a regular expression with ~ (or !~
+to require that it doesn't match):
+
+    http_uri ~ \"^/api/\"
+
+Dates for --since and --until can be written like 06/Feb/2009:12:14:14, 2009-02-06T12:14:14,
+2009-02-06 12:14:14 or just 2009-02-06, and are compared with accept_date as-is.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_invert_match: bool,
+    flag_count: bool,
+    flag_line_buffered: bool,
+    flag_show_invalid: bool,
+    arg_file: Vec<String>,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+
+    let mut reader = Inputs::new(&args.arg_file);
+    let stdout_is_interactive = unsafe { unistd::isatty(STDOUT_FILENO) == 1 };
+    let line_buffered = stdout_is_interactive || args.flag_line_buffered;
+
+    let mut stdout = io::stdout();
+    let mut stderr = io::stderr();
+
+    let mut count: u64 = 0;
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                stdout.flush().unwrap();
+                eprintln!("haproxy-grep: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        match LogEntry::from_bytes(line) {
+            Ok(entry) => {
+                if filter.matches(&entry) == args.flag_invert_match {
+                    continue;
+                }
+
+                count += 1;
+                if args.flag_count {
+                    continue;
+                }
+
+                stdout.write_all(line).unwrap();
+                if !line.ends_with(b"\n") {
+                    stdout.write_all(b"\n").unwrap();
+                }
+
+                if line_buffered {
+                    stdout.flush().unwrap();
+                }
+            },
+            Err(_) => {
+                if args.flag_show_invalid {
+                    stderr.write_all(line).unwrap();
+                }
+            },
+        }
+    }
+
+    if args.flag_count {
+        writeln!(stdout, "{}", count).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+use chrono::DateTime;
+use docopt::Docopt;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{Buckets, Condition, Expr, ExprError, Filter, Inputs, LogEntry, Value};
+
+
+const DEFAULT_BUCKETS: usize = 16;
+const DEFAULT_COLUMNS: i64 = 80;
+// --interval is picked from these so the heatmap fits in --columns.
+const INTERVALS: &[i64] = &[1, 5, 10, 30, 60, 300, 600, 900, 1800, 3600, 10800, 21600, 86400];
+// from no entries to the most entries in any cell.
+const SHADES: &[char] = &[' ', '.', '░', '▒', '▓', '█'];
+
+const CELL_WIDTH: f64 = 8.0;
+const CELL_HEIGHT: f64 = 16.0;
+const LABEL_WIDTH: f64 = 70.0;
+
+static USAGE: &str = "
+Draw a heatmap of a latency field of haproxy log entries from each <file> over time, with time
+along the bottom, latency going up and darker cells where more entries landed.
+
+Usage:
+    haproxy-heatmap [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-heatmap -h | --help
+
+Options:
+    -f, --field=EXPR        the field to plot, e.g. Tr or Tt-Tr. (default: Tt)
+    --interval=SECS         make each column cover SECS seconds of accept_date.
+                            (default: chosen to fit in --columns)
+    --columns=N             the most columns to use when picking the interval. (default: 80)
+    -n, --buckets=N         split the range of values into N rows. (default: 16)
+    --linear                make rows equally tall instead of growing exponentially.
+    --min=N                 ignore values below N. (default: the smallest value seen)
+    --max=N                 ignore values above N. (default: the largest value seen)
+    --svg                   write an SVG image instead of drawing with block characters.
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -h, --help              display this help and exit
+
+Shading is logarithmic in the number of entries per cell, so a few slow outliers still show up next
+to the bulk of the traffic. Timers of -1, for sessions which never completed, are ignored.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_field: Option<String>,
+    flag_interval: Option<i64>,
+    flag_columns: Option<i64>,
+    flag_buckets: Option<usize>,
+    flag_linear: bool,
+    flag_min: Option<f64>,
+    flag_max: Option<f64>,
+    flag_svg: bool,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    arg_file: Vec<String>,
+}
+
+// entry counts per time column and value bucket.
+struct Heatmap {
+    start: i64,
+    interval: i64,
+    buckets: Buckets,
+    // columns[time][bucket]
+    columns: Vec<Vec<u64>>,
+}
+
+impl Heatmap {
+    fn max_count(&self) -> u64 {
+        self.columns.iter().flatten().cloned().max().unwrap_or(0)
+    }
+
+    // 0 for empty cells, up to 1 for the fullest, on a log scale.
+    fn intensity(&self, count: u64) -> f64 {
+        let max = self.max_count();
+        if count == 0 || max == 0 {
+            0.0
+        } else {
+            (1.0 + count as f64).ln() / (1.0 + max as f64).ln()
+        }
+    }
+
+    fn column_start(&self, column: usize) -> String {
+        format_time(self.start + column as i64 * self.interval)
+    }
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn format_time(timestamp: i64) -> String {
+    match DateTime::from_timestamp(timestamp, 0) {
+        Some(time) => time.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+fn format_bound(bound: f64) -> String {
+    if bound >= 100.0 || bound.fract() == 0.0 {
+        format!("{:.0}", bound)
+    } else {
+        format!("{:.1}", bound)
+    }
+}
+
+fn write_blocks<W: Write>(out: &mut W, heatmap: &Heatmap) -> io::Result<()> {
+    let labels: Vec<String> = (0..heatmap.buckets.len())
+        .map(|i| format!("<{}", format_bound(heatmap.buckets.bounds(i).1)))
+        .collect();
+    let label_width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+
+    for bucket in (0..heatmap.buckets.len()).rev() {
+        let cells: String = heatmap.columns.iter()
+            .map(|column| {
+                let shade = heatmap.intensity(column[bucket]) * (SHADES.len() - 1) as f64;
+                SHADES[shade.ceil() as usize]
+            })
+            .collect();
+        writeln!(out, "{:>width$} |{}", labels[bucket], cells, width = label_width)?;
+    }
+
+    let columns = heatmap.columns.len();
+    writeln!(out, "{:>width$} +{}", "", "-".repeat(columns), width = label_width)?;
+    let first = heatmap.column_start(0);
+    let last = heatmap.column_start(columns - 1);
+    let gap = (columns + 1).saturating_sub(first.len() + last.len()).max(2);
+    writeln!(out, "{:>width$}  {}{}{}", "", first, " ".repeat(gap), last, width = label_width)?;
+    writeln!(out, "{} second columns, the darkest cells have {} entries", heatmap.interval,
+             heatmap.max_count())
+}
+
+fn write_svg<W: Write>(out: &mut W, heatmap: &Heatmap) -> io::Result<()> {
+    let rows = heatmap.buckets.len();
+    let width = LABEL_WIDTH + CELL_WIDTH * heatmap.columns.len() as f64 + 10.0;
+    let height = CELL_HEIGHT * rows as f64 + 50.0;
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                   font-family=\"sans-serif\" font-size=\"11\">", width, height)?;
+    writeln!(out, "<rect width=\"{}\" height=\"{}\" fill=\"white\"/>", width, height)?;
+
+    for bucket in 0..rows {
+        let y = CELL_HEIGHT * (rows - bucket - 1) as f64 + 10.0;
+        writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">&lt;{}</text>",
+                 LABEL_WIDTH - 6.0, y + CELL_HEIGHT - 4.0,
+                 format_bound(heatmap.buckets.bounds(bucket).1))?;
+        for (column, counts) in heatmap.columns.iter().enumerate() {
+            let intensity = heatmap.intensity(counts[bucket]);
+            if intensity == 0.0 {
+                continue;
+            }
+            // from a pale yellow to a dark red.
+            let red = 255.0 - 120.0 * intensity;
+            let green = 240.0 * (1.0 - intensity);
+            let blue = 160.0 * (1.0 - intensity);
+            writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                           fill=\"rgb({:.0},{:.0},{:.0})\"><title>{}: {}</title></rect>",
+                     LABEL_WIDTH + CELL_WIDTH * column as f64, y, CELL_WIDTH, CELL_HEIGHT,
+                     red, green, blue, heatmap.column_start(column), counts[bucket])?;
+        }
+    }
+
+    let bottom = CELL_HEIGHT * rows as f64 + 10.0;
+    writeln!(out, "<text x=\"{}\" y=\"{}\">{}</text>",
+             LABEL_WIDTH, bottom + 16.0, heatmap.column_start(0))?;
+    writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+             width - 10.0, bottom + 16.0, heatmap.column_start(heatmap.columns.len() - 1))?;
+    writeln!(out, "<text x=\"{}\" y=\"{}\">{} second columns, the darkest cells have {} \
+                   entries</text>",
+             LABEL_WIDTH, bottom + 32.0, heatmap.interval, heatmap.max_count())?;
+    writeln!(out, "</svg>")
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let field = Expr::parse(args.flag_field.as_deref().unwrap_or("Tt"))
+        .unwrap_or_else(usage_error);
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut points: Vec<(i64, f64)> = vec![];
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-heatmap: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        let value = match field.evaluate(&entry) {
+            Some(Value::Number(number)) => number.as_decimal(),
+            _ => continue,
+        };
+        if value < 0.0 ||
+            args.flag_min.is_some_and(|min| value < min) ||
+            args.flag_max.is_some_and(|max| value > max) {
+            continue;
+        }
+        if let Ok(accepted) = entry.accept_date_time() {
+            points.push((accepted.and_utc().timestamp(), value));
+        }
+    }
+    if points.is_empty() {
+        return;
+    }
+
+    let (first, last, min, max) = points.iter().fold(
+        (i64::MAX, i64::MIN, f64::INFINITY, f64::NEG_INFINITY),
+        |(first, last, min, max), &(timestamp, value)| {
+            (first.min(timestamp), last.max(timestamp), min.min(value), max.max(value))
+        });
+    let interval = match args.flag_interval {
+        Some(interval) => interval.max(1),
+        None => {
+            let columns = args.flag_columns.unwrap_or(DEFAULT_COLUMNS).max(1);
+            let fits = INTERVALS.iter().find(|&&interval| (last - first) / interval < columns);
+            *fits.unwrap_or(&INTERVALS[INTERVALS.len() - 1])
+        },
+    };
+    let min = args.flag_min.unwrap_or(min);
+    let max = args.flag_max.unwrap_or(max);
+    let bucket_count = args.flag_buckets.unwrap_or(DEFAULT_BUCKETS);
+    let buckets = if args.flag_linear {
+        Buckets::linear(min, max, bucket_count)
+    } else {
+        Buckets::logarithmic(min, max, bucket_count)
+    };
+
+    let start = first.div_euclid(interval) * interval;
+    let column_count = ((last - start) / interval + 1) as usize;
+    let mut heatmap = Heatmap {
+        start,
+        interval,
+        columns: vec![vec![0; buckets.len()]; column_count],
+        buckets,
+    };
+    for &(timestamp, value) in &points {
+        if let Some(bucket) = heatmap.buckets.index_of(value) {
+            heatmap.columns[((timestamp - start) / interval) as usize][bucket] += 1;
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let result = if args.flag_svg {
+        write_svg(&mut stdout, &heatmap)
+    } else {
+        write_blocks(&mut stdout, &heatmap)
+    };
+    result.unwrap();
+}
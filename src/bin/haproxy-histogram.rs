@@ -0,0 +1,171 @@
+use docopt::Docopt;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{Buckets, Condition, Expr, ExprError, Filter, Inputs, LogEntry, Value};
+
+
+const DEFAULT_BUCKETS: usize = 20;
+const DEFAULT_WIDTH: usize = 60;
+
+static USAGE: &str = "
+Print the distribution of a numeric field of haproxy log entries from each <file>.
+
+Usage:
+    haproxy-histogram -f EXPR [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-histogram -h | --help
+
+Options:
+    -f, --field=EXPR        the field to bucket, e.g. Tt or bytes_read. computed fields like Tt-Tr
+                            work too, see haproxy-cut --help-fields.
+    -g, --group=EXPR        print a separate histogram for each value of EXPR, e.g. backend_name.
+    -n, --buckets=N         split the range of values into N buckets. (default: 20)
+    --log                   make bucket widths grow exponentially instead of being equal, which
+                            suits latencies better.
+    --min=N                 ignore values below N. (default: the smallest value seen)
+    --max=N                 ignore values above N. (default: the largest value seen)
+    --csv                   print group, lower bound, upper bound and count as CSV instead of bars.
+    --width=N               the width of the longest bar. (default: 60)
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -h, --help              display this help and exit
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_field: String,
+    flag_group: Option<String>,
+    flag_buckets: Option<usize>,
+    flag_log: bool,
+    flag_min: Option<f64>,
+    flag_max: Option<f64>,
+    flag_csv: bool,
+    flag_width: Option<usize>,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    arg_file: Vec<String>,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn format_bound(bound: f64) -> String {
+    if bound.fract() == 0.0 {
+        format!("{}", bound)
+    } else {
+        format!("{:.2}", bound)
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let field = Expr::parse(&args.flag_field).unwrap_or_else(usage_error);
+    let group = args.flag_group.as_ref().map(|g| Expr::parse(g).unwrap_or_else(usage_error));
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut values: BTreeMap<Vec<u8>, Vec<f64>> = BTreeMap::new();
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-histogram: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        let value = match field.evaluate(&entry) {
+            Some(Value::Number(number)) => number.as_decimal(),
+            _ => continue,
+        };
+        if args.flag_min.is_some_and(|min| value < min) ||
+            args.flag_max.is_some_and(|max| value > max) {
+            continue;
+        }
+
+        let key = group.as_ref()
+            .and_then(|group| group.evaluate(&entry))
+            .map_or(vec![], |value| value.as_bytes().into_owned());
+        values.entry(key).or_default().push(value);
+    }
+
+    // every group shares the same buckets so they can be compared.
+    let all = values.values().flat_map(|values| values.iter().cloned());
+    let (min, max) = all.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+    if min > max {
+        return;
+    }
+    let min = args.flag_min.unwrap_or(min);
+    let max = args.flag_max.unwrap_or(max);
+    let bucket_count = args.flag_buckets.unwrap_or(DEFAULT_BUCKETS);
+    let buckets = if args.flag_log {
+        Buckets::logarithmic(min, max, bucket_count)
+    } else {
+        Buckets::linear(min, max, bucket_count)
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if args.flag_csv {
+        writeln!(stdout, "group,lower,upper,count").unwrap();
+    }
+
+    let width = args.flag_width.unwrap_or(DEFAULT_WIDTH);
+    for (i, (key, values)) in values.iter().enumerate() {
+        let counts = buckets.count(values.iter().cloned());
+        let name = String::from_utf8_lossy(key);
+
+        if args.flag_csv {
+            for (j, count) in counts.iter().enumerate() {
+                let (lower, upper) = buckets.bounds(j);
+                let (lower, upper) = (format_bound(lower), format_bound(upper));
+                writeln!(stdout, "{},{},{},{}", name, lower, upper, count).unwrap();
+            }
+            continue;
+        }
+
+        if group.is_some() {
+            if i != 0 {
+                writeln!(stdout).unwrap();
+            }
+            writeln!(stdout, "{} ({} values)", name, values.len()).unwrap();
+        }
+
+        let labels: Vec<String> = (0..buckets.len())
+            .map(|j| {
+                let (lower, upper) = buckets.bounds(j);
+                format!("{} - {}", format_bound(lower), format_bound(upper))
+            })
+            .collect();
+        let label_width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+        let most = counts.iter().cloned().max().unwrap_or(0).max(1);
+        for (label, &count) in labels.iter().zip(counts.iter()) {
+            let bar = (count as f64 / most as f64 * width as f64).round() as usize;
+            writeln!(stdout, "{:>lw$}  {:<bw$}  {}", label, "#".repeat(bar), count,
+                     lw = label_width, bw = width).unwrap();
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use docopt::Docopt;
+use serde_json::{json, Value};
+use std::io;
+use std::io::Write;
+use std::process;
+use std::str;
+
+use haproxy::{Condition, ExprError, Filter, Inputs, LogEntry};
+
+
+static USAGE: &str = "
+Convert haproxy log entries from each <file> to JSON documents, one per line.
+
+Usage:
+    haproxy-json [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-json -h | --help
+
+Options:
+    --pretty                indent each document over several lines instead of one per line.
+    -w, --where=EXPR        only convert entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only convert entries accepted at or after DATE.
+    --until=DATE            only convert entries accepted before DATE.
+    --show-invalid          print out lines that failed to parse to stderr (default: don't show)
+    -h, --help              display this help and exit
+
+Unlike haproxy-cut, related fields are grouped into nested objects:
+
+    {\"process\": {\"name\", \"pid\"}, \"client\": {\"ip\", \"port\"},
+     \"accept_date\", \"timestamp\", \"frontend\", \"backend\", \"server\",
+     \"timers\": {\"request\", \"queue\", \"connect\", \"response\", \"total\"},
+     \"status_code\", \"bytes_read\", \"cookies\": {\"request\", \"response\"},
+     \"termination_state\",
+     \"connections\": {\"active\", \"frontend\", \"backend\", \"server\", \"retries\"},
+     \"queue\": {\"server\", \"backend\"}, \"captures\": [[...], [...]],
+     \"http\": {\"method\", \"path\", \"query\", \"version\"}}
+
+Numbers are JSON numbers, timestamp is accept_date in ISO 8601 without a timezone, and fields
+haproxy logged as - (or couldn't parse) are null.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_pretty: bool,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_show_invalid: bool,
+    arg_file: Vec<String>,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn text(content: &[u8]) -> Value {
+    if content.is_empty() || content == b"-" {
+        Value::Null
+    } else {
+        Value::String(String::from_utf8_lossy(content).into_owned())
+    }
+}
+
+fn number<T: Into<Value>, E>(parsed: Result<T, E>) -> Value {
+    parsed.map_or(Value::Null, Into::into)
+}
+
+fn http(entry: &LogEntry) -> Value {
+    let (path, query) = match entry.http_uri() {
+        Some(uri) => {
+            let mut parts = uri.splitn(2, |&c| c == b'?');
+            (parts.next().map_or(Value::Null, text), parts.next().map_or(Value::Null, text))
+        },
+        None => (Value::Null, Value::Null),
+    };
+
+    json!({
+        "method": entry.http_method().map_or(Value::Null, text),
+        "path": path,
+        "query": query,
+        "version": entry.http_version().map_or(Value::Null, text),
+    })
+}
+
+fn document(entry: &LogEntry) -> Value {
+    // only the blocks haproxy actually logged are included, as lists of the captured headers.
+    let captures: Vec<Value> = entry.captures
+        .iter()
+        .take_while(|block| !block.is_empty())
+        .map(|block| block.split(|&c| c == b'|').map(text).collect())
+        .collect();
+
+    let port = str::from_utf8(entry.client_port).ok().and_then(|port| port.parse::<u16>().ok());
+    let timestamp = entry.accept_date_time()
+        .ok()
+        .map(|date| date.format("%Y-%m-%dT%H:%M:%S%.3f").to_string());
+
+    json!({
+        "process": {"name": text(entry.process_name), "pid": number(entry.pid())},
+        "client": {"ip": text(entry.client_ip), "port": port},
+        "accept_date": text(entry.accept_date),
+        "timestamp": timestamp,
+        "frontend": text(entry.frontend_name),
+        "backend": text(entry.backend_name),
+        "server": text(entry.server_name),
+        "timers": {
+            "request": number(entry.request_time()),
+            "queue": number(entry.queue_time()),
+            "connect": number(entry.connect_time()),
+            "response": number(entry.response_time()),
+            "total": number(entry.total_time()),
+        },
+        "status_code": number(entry.status_code()),
+        "bytes_read": number(entry.bytes_read()),
+        "cookies": {
+            "request": text(entry.captured_request_cookie),
+            "response": text(entry.captured_response_cookie),
+        },
+        "termination_state": text(entry.termination_state),
+        "connections": {
+            "active": number(entry.active_connections()),
+            "frontend": number(entry.frontend_connections()),
+            "backend": number(entry.backend_connections()),
+            "server": number(entry.server_connections()),
+            "retries": number(entry.retried_connections()),
+        },
+        "queue": {"server": number(entry.server_queue()), "backend": number(entry.backend_queue())},
+        "captures": captures,
+        "http": http(entry),
+    })
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+
+    let mut reader = Inputs::new(&args.arg_file);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut stderr = io::stderr();
+
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                stdout.flush().unwrap();
+                eprintln!("haproxy-json: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        match LogEntry::from_bytes(line) {
+            Ok(entry) => {
+                if !filter.matches(&entry) {
+                    continue;
+                }
+
+                let document = document(&entry);
+                if args.flag_pretty {
+                    serde_json::to_writer_pretty(&mut stdout, &document).unwrap();
+                } else {
+                    serde_json::to_writer(&mut stdout, &document).unwrap();
+                }
+                stdout.write_all(b"\n").unwrap();
+            },
+            Err(_) => {
+                if args.flag_show_invalid {
+                    stderr.write_all(line).unwrap();
+                }
+            },
+        }
+    }
+}
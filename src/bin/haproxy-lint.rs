@@ -0,0 +1,267 @@
+use docopt::Docopt;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{Config, Inputs, LogEntry, UnknownName};
+
+
+const DEFAULT_SAMPLES: usize = 3;
+
+static USAGE: &str = "
+Check that haproxy log entries from each <file> parse and are internally consistent.
+
+Usage:
+    haproxy-lint [options] [--] [<file> [<file> ...]]
+    haproxy-lint -h | --help
+
+Options:
+    -s, --samples=N         show up to N example lines of each problem. (default: 3)
+    --haproxy-config=FILE   also check that each entry's frontend, backend and server are in
+                            haproxy's configuration.
+    -q, --quiet             don't print anything, only set the exit status.
+    -h, --help              display this help and exit
+
+Prints how many lines have each kind of problem, with examples, and exits with status 1 if any
+line has a problem. This is most useful after changing the log-format of haproxy, to catch
+entries the tools can no longer read or read wrong.
+
+With --haproxy-config, entries naming a proxy or server the configuration doesn't have are
+problems too. They come from an older configuration, or from another load balancer's logs mixed
+in with these.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_samples: Option<usize>,
+    flag_quiet: bool,
+    flag_haproxy_config: Option<String>,
+    arg_file: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Problem {
+    Unparseable,
+    BadNumber,
+    BadDate,
+    TimerSum,
+    NegativeTimer,
+    ImpossibleStatus,
+    StatusTermination,
+    BadTerminationState,
+    ConnectionCounts,
+    UnknownProxy,
+    UnknownServer,
+}
+
+const PROBLEMS: &[Problem] = &[
+    Problem::Unparseable,
+    Problem::BadNumber,
+    Problem::BadDate,
+    Problem::TimerSum,
+    Problem::NegativeTimer,
+    Problem::ImpossibleStatus,
+    Problem::StatusTermination,
+    Problem::BadTerminationState,
+    Problem::ConnectionCounts,
+    Problem::UnknownProxy,
+    Problem::UnknownServer,
+];
+
+impl Problem {
+    fn name(self) -> &'static str {
+        match self {
+            Problem::Unparseable => "unparseable",
+            Problem::BadNumber => "bad_number",
+            Problem::BadDate => "bad_date",
+            Problem::TimerSum => "timer_sum",
+            Problem::NegativeTimer => "negative_timer",
+            Problem::ImpossibleStatus => "impossible_status",
+            Problem::StatusTermination => "status_termination",
+            Problem::BadTerminationState => "bad_termination_state",
+            Problem::ConnectionCounts => "connection_counts",
+            Problem::UnknownProxy => "unknown_proxy",
+            Problem::UnknownServer => "unknown_server",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Problem::Unparseable => "the line isn't in the haproxy HTTP log format",
+            Problem::BadNumber => "a timer, counter or the status code isn't a number",
+            Problem::BadDate => "accept_date isn't a valid date",
+            Problem::TimerSum => "Tq+Tw+Tc+Tr is more than Tt",
+            Problem::NegativeTimer => "a timer is negative but not -1",
+            Problem::ImpossibleStatus => "the status code is neither -1 nor between 100 and 599",
+            Problem::StatusTermination => "the status code contradicts the termination state",
+            Problem::BadTerminationState => "the termination state isn't 4 characters",
+            Problem::ConnectionCounts => "more frontend than active or server than backend conns",
+            Problem::UnknownProxy => "the frontend or backend isn't in --haproxy-config",
+            Problem::UnknownServer => "the server isn't in its backend in --haproxy-config",
+        }
+    }
+}
+
+// the problems with `entry`, in the order of PROBLEMS.
+fn check(entry: &LogEntry, config: Option<&Config>) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    let timers = [
+        entry.request_time(),
+        entry.queue_time(),
+        entry.connect_time(),
+        entry.response_time(),
+        entry.total_time(),
+    ];
+    let numbers_ok = timers.iter().all(|timer| timer.is_ok()) &&
+        entry.status_code().is_ok() &&
+        entry.bytes_read().is_ok() &&
+        entry.active_connections().is_ok() &&
+        entry.frontend_connections().is_ok() &&
+        entry.backend_connections().is_ok() &&
+        entry.server_connections().is_ok() &&
+        entry.retried_connections().is_ok() &&
+        entry.server_queue().is_ok() &&
+        entry.backend_queue().is_ok();
+    if !numbers_ok {
+        problems.push(Problem::BadNumber);
+    }
+
+    if entry.accept_date_time().is_err() {
+        problems.push(Problem::BadDate);
+    }
+
+    let timers: Vec<i64> = timers.iter().map(|timer| *timer.as_ref().unwrap_or(&-1)).collect();
+    // with `option logasap` Tt is prefixed with a + and only covers the time until logging, so
+    // the phases may well add up to more.
+    let logged_early = entry.total_time.starts_with(b"+");
+    let phases: i64 = timers[..4].iter().filter(|&&timer| timer > 0).sum();
+    if !logged_early && timers[4] >= 0 && phases > timers[4] {
+        problems.push(Problem::TimerSum);
+    }
+    if timers.iter().any(|&timer| timer < -1) {
+        problems.push(Problem::NegativeTimer);
+    }
+
+    let status = entry.status_code().unwrap_or(-1);
+    if status != -1 && !(100..600).contains(&status) {
+        problems.push(Problem::ImpossibleStatus);
+    }
+
+    let termination_state = entry.termination_state;
+    // a session without a response must have been cut short somehow, and haproxy answers queue
+    // and server header timeouts itself with a 503 and a 504.
+    let contradiction = if status == -1 {
+        termination_state.first() == Some(&b'-')
+    } else {
+        match termination_state.get(..2) {
+            Some(b"sQ") => status != 503,
+            Some(b"sH") => status != 504,
+            _ => false,
+        }
+    };
+    if contradiction {
+        problems.push(Problem::StatusTermination);
+    }
+    if termination_state.len() != 4 {
+        problems.push(Problem::BadTerminationState);
+    }
+
+    let impossible_counts = match (entry.active_connections(), entry.frontend_connections()) {
+        (Ok(active), Ok(frontend)) => frontend > active,
+        _ => false,
+    } || match (entry.backend_connections(), entry.server_connections()) {
+        (Ok(backend), Ok(server)) => server > backend,
+        _ => false,
+    };
+    if impossible_counts {
+        problems.push(Problem::ConnectionCounts);
+    }
+
+    let unknown = config.map_or(vec![], |config| config.unknown_names(entry));
+    if unknown.iter().any(|name| !matches!(*name, UnknownName::Server(..))) {
+        problems.push(Problem::UnknownProxy);
+    }
+    if unknown.iter().any(|name| matches!(*name, UnknownName::Server(..))) {
+        problems.push(Problem::UnknownServer);
+    }
+
+    problems
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+    let max_samples = args.flag_samples.unwrap_or(DEFAULT_SAMPLES);
+    let config = args.flag_haproxy_config.as_ref().map(|path| {
+        Config::from_file(path).unwrap_or_else(|err| {
+            eprintln!("haproxy-lint: {}: {}", path, err);
+            process::exit(1);
+        })
+    });
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut lines: u64 = 0;
+    let mut lines_with_problems: u64 = 0;
+    let mut counts = vec![0u64; PROBLEMS.len()];
+    let mut samples: Vec<Vec<(u64, String)>> = vec![vec![]; PROBLEMS.len()];
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-lint: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        lines += 1;
+        let problems = match LogEntry::from_bytes(line) {
+            Ok(entry) => check(&entry, config.as_ref()),
+            Err(_) => vec![Problem::Unparseable],
+        };
+        if problems.is_empty() {
+            continue;
+        }
+
+        lines_with_problems += 1;
+        for problem in problems {
+            let i = PROBLEMS.iter().position(|&p| p == problem).unwrap();
+            counts[i] += 1;
+            if samples[i].len() < max_samples {
+                let line = String::from_utf8_lossy(line).trim_end().to_string();
+                samples[i].push((lines, line));
+            }
+        }
+    }
+
+    if !args.flag_quiet {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "checked {} lines, {} with problems", lines, lines_with_problems).unwrap();
+
+        if lines_with_problems > 0 {
+            writeln!(stdout).unwrap();
+            writeln!(stdout, "{:<22} {:>8}  description", "problem", "lines").unwrap();
+            for (i, &problem) in PROBLEMS.iter().enumerate() {
+                if counts[i] > 0 {
+                    writeln!(stdout, "{:<22} {:>8}  {}", problem.name(), counts[i],
+                             problem.description()).unwrap();
+                }
+            }
+        }
+
+        for (i, &problem) in PROBLEMS.iter().enumerate() {
+            if samples[i].is_empty() {
+                continue;
+            }
+            writeln!(stdout, "\n{}:", problem.name()).unwrap();
+            for &(number, ref line) in &samples[i] {
+                writeln!(stdout, "    line {}: {}", number, line).unwrap();
+            }
+        }
+    }
+
+    if lines_with_problems > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,153 @@
+use chrono::{Duration, NaiveDateTime};
+use docopt::Docopt;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{Input, LogEntry};
+
+
+const DEFAULT_MAX_DELAY: i64 = 300;
+
+static USAGE: &str = "
+Merge haproxy log entries from each <file> into a single stream ordered by accept_date.
+
+Usage:
+    haproxy-merge [options] [--] <file>...
+    haproxy-merge -h | --help
+
+Options:
+    --max-delay=SECS        the longest a session can last, see below. (default: 300)
+    --drop-invalid          leave out lines that fail to parse. (default: keep them right after
+                            the entry before them)
+    -h, --help              display this help and exit
+
+Gzipped files are decompressed and - reads standard input.
+
+haproxy logs a session when it ends, so within a single log entries are only roughly ordered by
+accept_date: a slow request is written after faster ones accepted later. Entries are held back
+until every file has moved past their accept_date by --max-delay, so the output is ordered as long
+as no session took longer than that. Setting it higher costs memory, not correctness.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_max_delay: Option<i64>,
+    flag_drop_invalid: bool,
+    arg_file: Vec<String>,
+}
+
+struct Source {
+    path: String,
+    input: Input,
+    // the latest accept_date read so far, the one of the entry read last, and whether everything
+    // has been read.
+    latest: Option<NaiveDateTime>,
+    previous: Option<NaiveDateTime>,
+    finished: bool,
+    lines: u64,
+}
+
+impl Source {
+    fn open(path: &str) -> io::Result<Source> {
+        Ok(Source {
+            path: path.to_string(),
+            input: Input::open(path)?,
+            latest: None,
+            previous: None,
+            finished: false,
+            lines: 0,
+        })
+    }
+
+    // entries accepted before this can't show up in this source anymore.
+    fn watermark(&self, max_delay: Duration) -> Option<NaiveDateTime> {
+        if self.finished {
+            Some(NaiveDateTime::MAX)
+        } else {
+            self.latest.map(|latest| latest - max_delay)
+        }
+    }
+}
+
+// ordered by accept_date, then by where the line came from so the merge is stable.
+type Pending = Reverse<(NaiveDateTime, usize, u64, Vec<u8>)>;
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+    let max_delay = Duration::seconds(args.flag_max_delay.unwrap_or(DEFAULT_MAX_DELAY).max(0));
+
+    let mut sources: Vec<Source> = args.arg_file.iter()
+        .map(|path| {
+            Source::open(path).unwrap_or_else(|err| {
+                docopt::Error::Argv(format!("could not open {}: {}", path, err)).exit()
+            })
+        })
+        .collect();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut pending: BinaryHeap<Pending> = BinaryHeap::new();
+    loop {
+        // read from whichever source is furthest behind, until everything pending is safe to
+        // write, i.e. older than what every source could still produce.
+        let lagging = sources.iter()
+            .enumerate()
+            .filter(|(_, source)| !source.finished)
+            .min_by_key(|(_, source)| source.watermark(max_delay))
+            .map(|(i, _)| i);
+        let watermark = match lagging {
+            Some(i) => sources[i].watermark(max_delay),
+            None => Some(NaiveDateTime::MAX),
+        };
+
+        while let Some(&Reverse((accepted, _, _, _))) = pending.peek() {
+            if watermark.is_none_or(|watermark| accepted > watermark) {
+                break;
+            }
+            let Reverse((_, _, _, line)) = pending.pop().unwrap();
+            if stdout.write_all(&line).is_err() {
+                return;
+            }
+        }
+
+        let i = match lagging {
+            Some(i) => i,
+            None => break,
+        };
+        let source = &mut sources[i];
+        match source.input.next_line() {
+            Ok(None) => source.finished = true,
+            Err(err) => {
+                stdout.flush().unwrap();
+                eprintln!("haproxy-merge: {}: {}", source.path, err);
+                process::exit(1);
+            },
+            Ok(Some(line)) => {
+                let mut line = line.to_vec();
+                if !line.ends_with(b"\n") {
+                    line.push(b'\n');
+                }
+                source.lines += 1;
+
+                let accepted = LogEntry::from_bytes(&line)
+                    .ok()
+                    .and_then(|entry| entry.accept_date_time().ok());
+                let accepted = match (accepted, source.previous) {
+                    (Some(accepted), _) => {
+                        source.latest = Some(source.latest.map_or(accepted, |l| l.max(accepted)));
+                        source.previous = Some(accepted);
+                        accepted
+                    },
+                    _ if args.flag_drop_invalid => continue,
+                    // keep invalid lines next to whatever came before them.
+                    (None, Some(previous)) => previous,
+                    (None, None) => NaiveDateTime::MIN,
+                };
+                pending.push(Reverse((accepted, i, source.lines, line)));
+            },
+        }
+    }
+}
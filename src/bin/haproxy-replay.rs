@@ -0,0 +1,254 @@
+use chrono::NaiveDateTime;
+use docopt::Docopt;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::process;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use haproxy::{Condition, ExprError, Filter, Inputs, LogEntry, TDigest, Table};
+
+
+const DEFAULT_CONCURRENCY: usize = 10;
+const DEFAULT_TIMEOUT: u64 = 30;
+
+static USAGE: &str = "
+Replay the HTTP requests logged in each <file> against another server, keeping their original
+timing, and compare the responses with the logged ones.
+
+Usage:
+    haproxy-replay -t URL [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-replay -h | --help
+
+Options:
+    -t, --target=URL        send requests to URL, e.g. http://staging:8080, followed by the logged
+                            path and query string.
+    --speed=N               replay N times faster than the requests were logged, e.g. 0.5 for half
+                            speed. 0 sends requests as fast as --concurrency allows. (default: 1)
+    -c, --concurrency=N     send at most N requests at once. (default: 10)
+    --timeout=SECS          give up on a request after SECS seconds. (default: 30)
+    --all-methods           also replay requests which aren't GET or HEAD, without their bodies
+                            since haproxy doesn't log them. (default: skip them)
+    -w, --where=EXPR        only replay entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only replay entries accepted at or after DATE.
+    --until=DATE            only replay entries accepted before DATE.
+    -v, --verbose           print the logged and replayed status and time of each request.
+    -h, --help              display this help and exit
+
+The report compares the response time haproxy logged (Tr) with the time the target took to send
+its response headers, in milliseconds, and counts how often each logged status code turned into
+each replayed one. Requests which failed without a response are counted as status err.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_target: String,
+    flag_speed: Option<f64>,
+    flag_concurrency: Option<usize>,
+    flag_timeout: Option<u64>,
+    flag_all_methods: bool,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_verbose: bool,
+    arg_file: Vec<String>,
+}
+
+struct Request {
+    method: String,
+    uri: String,
+    status: String,
+    response_time: Option<i64>,
+}
+
+struct Outcome {
+    request: Request,
+    // the replayed status, or None if the request failed without a response.
+    status: Option<u16>,
+    response_time: i64,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn replay(agent: &ureq::Agent, target: &str, request: Request) -> Outcome {
+    let url = format!("{}{}", target.trim_end_matches('/'), request.uri);
+    let start = Instant::now();
+    let response = match agent.request(&request.method, &url).call() {
+        Ok(response) => Some(response),
+        Err(ureq::Error::Status(_, response)) => Some(response),
+        Err(ureq::Error::Transport(_)) => None,
+    };
+    let response_time = start.elapsed().as_millis() as i64;
+
+    // read the body so the connection can be reused, it isn't part of the comparison though.
+    let status = response.map(|response| {
+        let status = response.status();
+        let _ = io::copy(&mut response.into_reader(), &mut io::sink());
+        status
+    });
+
+    Outcome {
+        request,
+        status,
+        response_time,
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    let speed = args.flag_speed.unwrap_or(1.0);
+    if speed < 0.0 {
+        docopt::Error::Argv("--speed can't be negative".to_string()).exit();
+    }
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(args.flag_timeout.unwrap_or(DEFAULT_TIMEOUT)))
+        .redirects(0)
+        .build();
+
+    // a rendezvous channel, so reading blocks (and falls behind schedule) once every worker is
+    // busy instead of queueing requests up.
+    let (requests, receiver) = mpsc::sync_channel::<Request>(0);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let (outcomes, results) = mpsc::channel();
+    let workers: Vec<_> = (0..args.flag_concurrency.unwrap_or(DEFAULT_CONCURRENCY).max(1))
+        .map(|_| {
+            let agent = agent.clone();
+            let target = args.flag_target.clone();
+            let receiver = Arc::clone(&receiver);
+            let outcomes = outcomes.clone();
+            thread::spawn(move || loop {
+                let request = match receiver.lock().unwrap().recv() {
+                    Ok(request) => request,
+                    Err(_) => break,
+                };
+                if outcomes.send(replay(&agent, &target, request)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+    drop(outcomes);
+
+    let mut reader = Inputs::new(&args.arg_file);
+    let mut skipped = 0;
+    let mut first: Option<(NaiveDateTime, Instant)> = None;
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-replay: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        let (method, uri) = match (entry.http_method(), entry.http_uri()) {
+            (Some(method), Some(uri)) if uri.starts_with(b"/") => (method, uri),
+            _ => {
+                skipped += 1;
+                continue;
+            },
+        };
+        if !args.flag_all_methods && method != b"GET" && method != b"HEAD" {
+            skipped += 1;
+            continue;
+        }
+
+        if speed > 0.0 {
+            if let Ok(accepted) = entry.accept_date_time() {
+                let &mut (first_accepted, started) =
+                    first.get_or_insert((accepted, Instant::now()));
+                let offset = (accepted - first_accepted).to_std().unwrap_or_default();
+                let due = started + offset.div_f64(speed);
+                let now = Instant::now();
+                if due > now {
+                    thread::sleep(due - now);
+                }
+            }
+        }
+
+        let request = Request {
+            method: String::from_utf8_lossy(method).into_owned(),
+            uri: String::from_utf8_lossy(uri).into_owned(),
+            status: String::from_utf8_lossy(entry.status_code).into_owned(),
+            response_time: entry.response_time().ok().filter(|&t| t >= 0),
+        };
+        if requests.send(request).is_err() {
+            break;
+        }
+    }
+    drop(requests);
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut replayed = 0;
+    // estimated rather than kept, so long replays fit in memory.
+    let mut logged_times = TDigest::default();
+    let mut replayed_times = TDigest::default();
+    let mut transitions: BTreeMap<(String, String), u64> = BTreeMap::new();
+    for outcome in results {
+        let status = outcome.status.map_or("err".to_string(), |status| status.to_string());
+        if args.flag_verbose {
+            let request = &outcome.request;
+            let logged_time = request.response_time.map_or("-".to_string(), |t| t.to_string());
+            writeln!(stdout, "{} {} {} {} {} {}", request.method, request.uri, request.status,
+                     status, logged_time, outcome.response_time).unwrap();
+        }
+
+        replayed += 1;
+        if let Some(response_time) = outcome.request.response_time {
+            logged_times.add(response_time as f64);
+        }
+        if outcome.status.is_some() {
+            replayed_times.add(outcome.response_time as f64);
+        }
+        *transitions.entry((outcome.request.status, status)).or_insert(0) += 1;
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    if args.flag_verbose {
+        writeln!(stdout).unwrap();
+    }
+    writeln!(stdout, "replayed {} requests, skipped {}", replayed, skipped).unwrap();
+    writeln!(stdout).unwrap();
+
+    let mut times = Table::new(&["", "p50", "p90", "p99", "max"]);
+    for &(name, digest) in &[("logged", &logged_times), ("replayed", &replayed_times)] {
+        let mut row = vec![name.to_string()];
+        for &p in &[50.0, 90.0, 99.0, 100.0] {
+            let time = digest.quantile(p / 100.0).map(|time| time.round() as i64);
+            row.push(time.map_or("-".to_string(), |t| t.to_string()));
+        }
+        times.push(row);
+    }
+    times.write_aligned(&mut stdout).unwrap();
+    writeln!(stdout).unwrap();
+
+    let mut statuses = Table::new(&["logged", "replayed", "requests"]);
+    for ((logged, replayed), count) in transitions {
+        statuses.push(vec![logged, replayed, count.to_string()]);
+    }
+    statuses.write_aligned(&mut stdout).unwrap();
+}
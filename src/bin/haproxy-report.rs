@@ -0,0 +1,381 @@
+use chrono::DateTime;
+use docopt::Docopt;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::process;
+
+use haproxy::{Condition, ExprError, Filter, Inputs, LogEntry, TDigest};
+
+
+const DEFAULT_TOP: usize = 20;
+// --interval is picked from these so the charts have at most MAX_POINTS points.
+const INTERVALS: &[i64] = &[1, 5, 10, 30, 60, 300, 600, 1800, 3600, 21600, 86400];
+const MAX_POINTS: i64 = 300;
+
+const CHART_WIDTH: f64 = 900.0;
+const CHART_HEIGHT: f64 = 220.0;
+const CHART_MARGIN: f64 = 40.0;
+
+static USAGE: &str = "
+Write a self-contained HTML report of the traffic, errors, latency and busiest endpoints and clients
+in haproxy log entries from each <file>.
+
+Usage:
+    haproxy-report [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-report -h | --help
+
+Options:
+    -o, --output=FILE       write the report to FILE instead of standard output.
+    -t, --title=TEXT        the title of the report. (default: haproxy report)
+    --interval=SECS         chart requests and latency in buckets of SECS seconds.
+                            (default: chosen from the time range)
+    --top=N                 list the N busiest backends, URIs and clients. (default: 20)
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -h, --help              display this help and exit
+
+The report has no external dependencies, the charts are inline SVG, so it can be attached to an
+email or a postmortem as a single file. Errors are entries with a 5xx or no response at all, and
+latencies are the total session time (Tt) in milliseconds.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_output: Option<String>,
+    flag_title: Option<String>,
+    flag_interval: Option<i64>,
+    flag_top: Option<usize>,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    arg_file: Vec<String>,
+}
+
+#[derive(Default)]
+struct Summary {
+    requests: u64,
+    errors: u64,
+    bytes: u64,
+    total_times: TDigest,
+}
+
+impl Summary {
+    fn add(&mut self, error: bool, total_time: Option<i64>, bytes: u64) {
+        self.requests += 1;
+        if error {
+            self.errors += 1;
+        }
+        self.bytes += bytes;
+        if let Some(total_time) = total_time {
+            self.total_times.add(total_time as f64);
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            100.0 * self.errors as f64 / self.requests as f64
+        }
+    }
+
+    fn percentile(&self, p: f64) -> Option<i64> {
+        // estimated from a digest rather than every time, so large logs fit in memory.
+        self.total_times.quantile(p / 100.0).map(|time| time.round() as i64)
+    }
+}
+
+// what's kept of each entry to draw the charts once the time range is known.
+struct Point {
+    timestamp: i64,
+    error: bool,
+    total_time: Option<i64>,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_time(timestamp: i64) -> String {
+    match DateTime::from_timestamp(timestamp, 0) {
+        Some(time) => time.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+fn format_millis(millis: Option<i64>) -> String {
+    millis.map_or("-".to_string(), |millis| millis.to_string())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < units.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+// a line chart of each series against the bucket start times, scaled to the largest value.
+fn line_chart<W: Write>(out: &mut W, starts: &[i64], series: &[(&str, &str, Vec<f64>)],
+                        unit: &str) -> io::Result<()> {
+    let max = series.iter()
+        .flat_map(|(_, _, values)| values.iter().cloned())
+        .fold(0.0, f64::max)
+        .max(1.0);
+    let plot_width = CHART_WIDTH - 2.0 * CHART_MARGIN;
+    let plot_height = CHART_HEIGHT - 2.0 * CHART_MARGIN;
+    let x = |i: usize| {
+        CHART_MARGIN + plot_width * i as f64 / (starts.len().max(2) - 1) as f64
+    };
+    let y = |value: f64| CHART_MARGIN + plot_height * (1.0 - value / max);
+
+    writeln!(out, "<svg width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+             CHART_WIDTH, CHART_HEIGHT, CHART_WIDTH, CHART_HEIGHT)?;
+    writeln!(out, "<rect class=\"plot\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>",
+             CHART_MARGIN, CHART_MARGIN, plot_width, plot_height)?;
+    writeln!(out, "<text x=\"{}\" y=\"{}\">{:.1} {}</text>",
+             CHART_MARGIN, CHART_MARGIN - 6.0, max, escape(unit))?;
+    if let (Some(&first), Some(&last)) = (starts.first(), starts.last()) {
+        writeln!(out, "<text x=\"{}\" y=\"{}\">{}</text>",
+                 CHART_MARGIN, CHART_HEIGHT - 12.0, format_time(first))?;
+        writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+                 CHART_WIDTH - CHART_MARGIN, CHART_HEIGHT - 12.0, format_time(last))?;
+    }
+
+    let mut legend_x = CHART_WIDTH - CHART_MARGIN;
+    for (name, color, values) in series.iter().rev() {
+        let points: Vec<String> = values.iter()
+            .enumerate()
+            .map(|(i, &value)| format!("{:.1},{:.1}", x(i), y(value)))
+            .collect();
+        writeln!(out, "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>",
+                 color, points.join(" "))?;
+        writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\" fill=\"{}\">{}</text>",
+                 legend_x, CHART_MARGIN - 6.0, color, escape(name))?;
+        legend_x -= 12.0 + 8.0 * name.len() as f64;
+    }
+    writeln!(out, "</svg>")
+}
+
+// a table of the busiest groups, with a bar showing each one's share of all requests.
+fn top_table<W: Write>(out: &mut W, title: &str, name: &str, groups: HashMap<String, Summary>,
+                       total: u64, top: usize) -> io::Result<()> {
+    let mut groups: Vec<(String, Summary)> = groups.into_iter().collect();
+    groups.sort_by(|a, b| b.1.requests.cmp(&a.1.requests).then_with(|| a.0.cmp(&b.0)));
+
+    writeln!(out, "<h2>{}</h2>", escape(title))?;
+    writeln!(out, "<table>")?;
+    writeln!(out, "<tr><th>{}</th><th>requests</th><th></th><th>errors</th><th>err%</th>\
+                   <th>bytes</th><th>p50</th><th>p99</th></tr>", escape(name))?;
+    for (key, summary) in groups.iter().take(top) {
+        let share = 100.0 * summary.requests as f64 / total.max(1) as f64;
+        writeln!(out, "<tr><td class=\"name\">{}</td><td>{}</td>\
+                       <td class=\"bar\"><div style=\"width: {:.1}%\"></div></td>\
+                       <td>{}</td><td>{:.2}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                 escape(key), summary.requests, share, summary.errors, summary.error_rate(),
+                 format_bytes(summary.bytes), format_millis(summary.percentile(50.0)),
+                 format_millis(summary.percentile(99.0)))?;
+    }
+    writeln!(out, "</table>")
+}
+
+fn write_report<W: Write>(out: &mut W, args: &Args, total: Summary, points: &[Point],
+                          statuses: BTreeMap<String, u64>, terminations: BTreeMap<String, u64>,
+                          groups: Vec<(&str, &str, HashMap<String, Summary>)>) -> io::Result<()> {
+    let title = args.flag_title.as_deref().unwrap_or("haproxy report");
+    let top = args.flag_top.unwrap_or(DEFAULT_TOP);
+
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", escape(title))?;
+    writeln!(out, "<style>
+body {{ font-family: sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; margin-bottom: 1em; }}
+th, td {{ padding: 2px 10px; text-align: right; border-bottom: 1px solid #ddd; }}
+td.name, th:first-child {{ text-align: left; font-family: monospace; }}
+td.bar {{ width: 120px; }}
+td.bar div {{ background: #4c78a8; height: 10px; }}
+svg text {{ font-size: 12px; }}
+rect.plot {{ fill: none; stroke: #ccc; }}
+</style>\n</head>\n<body>")?;
+    writeln!(out, "<h1>{}</h1>", escape(title))?;
+
+    let first = points.iter().map(|p| p.timestamp).min();
+    let last = points.iter().map(|p| p.timestamp).max();
+    writeln!(out, "<table>")?;
+    if let (Some(first), Some(last)) = (first, last) {
+        writeln!(out, "<tr><th>time range</th><td>{} to {}</td></tr>",
+                 format_time(first), format_time(last))?;
+    }
+    writeln!(out, "<tr><th>requests</th><td>{}</td></tr>", total.requests)?;
+    writeln!(out, "<tr><th>errors</th><td>{} ({:.2}%)</td></tr>",
+             total.errors, total.error_rate())?;
+    writeln!(out, "<tr><th>bytes sent</th><td>{}</td></tr>", format_bytes(total.bytes))?;
+    writeln!(out, "<tr><th>Tt p50 / p90 / p99 / max</th><td>{} / {} / {} / {} ms</td></tr>",
+             format_millis(total.percentile(50.0)), format_millis(total.percentile(90.0)),
+             format_millis(total.percentile(99.0)), format_millis(total.percentile(100.0)))?;
+    writeln!(out, "</table>")?;
+
+    if let (Some(first), Some(last)) = (first, last) {
+        let span = last - first + 1;
+        let interval = match args.flag_interval {
+            Some(interval) => interval.max(1),
+            None => {
+                let fits = INTERVALS.iter().find(|&&interval| span / interval < MAX_POINTS);
+                *fits.unwrap_or(&INTERVALS[INTERVALS.len() - 1])
+            },
+        };
+        let base = first.div_euclid(interval) * interval;
+        let count = ((last - base) / interval + 1) as usize;
+        let mut buckets: Vec<Summary> = (0..count).map(|_| Summary::default()).collect();
+        for point in points {
+            let bucket = &mut buckets[((point.timestamp - base) / interval) as usize];
+            bucket.add(point.error, point.total_time, 0);
+        }
+        let starts: Vec<i64> = (0..count as i64).map(|i| base + i * interval).collect();
+
+        let per_second = |value: u64| value as f64 / interval as f64;
+        writeln!(out, "<h2>Traffic</h2>")?;
+        line_chart(out, &starts, &[
+            ("requests/s", "#4c78a8", buckets.iter().map(|b| per_second(b.requests)).collect()),
+            ("errors/s", "#e45756", buckets.iter().map(|b| per_second(b.errors)).collect()),
+        ], "req/s")?;
+
+        let latency = |p: f64| -> Vec<f64> {
+            buckets.iter().map(|b| b.percentile(p).unwrap_or(0) as f64).collect()
+        };
+        writeln!(out, "<h2>Latency</h2>")?;
+        line_chart(out, &starts, &[
+            ("p50", "#54a24b", latency(50.0)),
+            ("p90", "#f58518", latency(90.0)),
+            ("p99", "#e45756", latency(99.0)),
+        ], "ms")?;
+        writeln!(out, "<p>{} second buckets.</p>", interval)?;
+    }
+
+    for (title, counts) in [("Status codes", statuses), ("Termination states", terminations)] {
+        writeln!(out, "<h2>{}</h2>\n<table>", title)?;
+        for (key, count) in counts {
+            let share = 100.0 * count as f64 / total.requests.max(1) as f64;
+            writeln!(out, "<tr><td class=\"name\">{}</td><td>{}</td><td>{:.2}%</td>\
+                           <td class=\"bar\"><div style=\"width: {:.1}%\"></div></td></tr>",
+                     escape(&key), count, share, share)?;
+        }
+        writeln!(out, "</table>")?;
+    }
+
+    for (title, name, groups) in groups {
+        top_table(out, title, name, groups, total.requests, top)?;
+    }
+
+    writeln!(out, "</body>\n</html>")
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut total = Summary::default();
+    let mut points = vec![];
+    let mut statuses: BTreeMap<String, u64> = BTreeMap::new();
+    let mut terminations: BTreeMap<String, u64> = BTreeMap::new();
+    let mut backends: HashMap<String, Summary> = HashMap::new();
+    let mut uris: HashMap<String, Summary> = HashMap::new();
+    let mut clients: HashMap<String, Summary> = HashMap::new();
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-report: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        let status = entry.status_code().unwrap_or(-1);
+        let error = !(100..500).contains(&status);
+        // Tt is -1 for sessions which never completed, which would skew the percentiles.
+        let total_time = entry.total_time().ok().filter(|&t| t >= 0);
+        let bytes = entry.bytes_read().unwrap_or(0);
+
+        total.add(error, total_time, bytes);
+        if let Ok(accepted) = entry.accept_date_time() {
+            let timestamp = accepted.and_utc().timestamp();
+            points.push(Point { timestamp, error, total_time });
+        }
+        *statuses.entry(status.to_string()).or_default() += 1;
+        let state = String::from_utf8_lossy(entry.termination_state);
+        *terminations.entry(state.chars().take(2).collect()).or_default() += 1;
+
+        let backend = String::from_utf8_lossy(entry.backend_name).into_owned();
+        backends.entry(backend).or_default().add(error, total_time, bytes);
+        // group URIs by path, the query string would make almost every one unique.
+        let uri = entry.http_uri().unwrap_or(b"");
+        let path = uri.split(|&c| c == b'?').next().unwrap_or(uri);
+        let path = String::from_utf8_lossy(path).into_owned();
+        uris.entry(path).or_default().add(error, total_time, bytes);
+        let client = String::from_utf8_lossy(entry.client_ip).into_owned();
+        clients.entry(client).or_default().add(error, total_time, bytes);
+    }
+
+    let groups = vec![
+        ("Top backends", "backend", backends),
+        ("Top URIs", "path", uris),
+        ("Top clients", "client", clients),
+    ];
+    let result = match args.flag_output {
+        Some(ref path) => File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write_report(&mut out, &args, total, &points, statuses, terminations, groups)?;
+            out.flush()
+        }),
+        None => {
+            let stdout = io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            write_report(&mut out, &args, total, &points, statuses, terminations, groups)
+                .and_then(|_| out.flush())
+        },
+    };
+    if let Err(err) = result {
+        docopt::Error::Argv(format!("could not write the report: {}", err)).exit();
+    }
+}
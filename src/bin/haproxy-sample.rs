@@ -0,0 +1,169 @@
+use docopt::Docopt;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use haproxy::{Condition, Expr, ExprError, Filter, Inputs, LogEntry};
+
+
+const DEFAULT_SIZE: usize = 100;
+
+static USAGE: &str = "
+Print a uniform random sample of exactly -n haproxy log lines from each <file>, however many lines
+there are, to use as representative test fixtures.
+
+Usage:
+    haproxy-sample [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-sample -h | --help
+
+Options:
+    -n, --size=N            the number of lines to sample, or to sample for each value of --by.
+                            (default: 100)
+    -b, --by=EXPR           stratify the sample: take -n lines for each value of EXPR, e.g.
+                            status_class to get as many errors as successes.
+    --seed=N                seed the random generator with N, the same seed and input always give
+                            the same sample. (default: random)
+    --keep-invalid          sample lines which failed to parse too, which are a stratum of their
+                            own with --by. (default: skip them)
+    -w, --where=EXPR        only sample entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only sample entries accepted at or after DATE.
+    --until=DATE            only sample entries accepted before DATE.
+    -h, --help              display this help and exit
+
+Lines are printed unmodified and in the order they were read. A stratum with fewer than -n lines is
+printed in full. The input is read once and only the sample is kept in memory.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_size: Option<usize>,
+    flag_by: Option<String>,
+    flag_seed: Option<u64>,
+    flag_keep_invalid: bool,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    arg_file: Vec<String>,
+}
+
+// xorshift64*, plenty for picking lines and keeps samples reproducible with --seed.
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // a zero state would only ever produce zeroes.
+        Rng { state: (seed ^ 0x9e37_79b9_7f4a_7c15) | 1 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // uniform in [0, n).
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+// algorithm R: after seeing `seen` lines each one is in the reservoir with probability size/seen.
+struct Reservoir {
+    seen: u64,
+    // the line number each line was read at, so the sample can be printed in order.
+    lines: Vec<(u64, Vec<u8>)>,
+}
+
+impl Reservoir {
+    fn new() -> Reservoir {
+        Reservoir {
+            seen: 0,
+            lines: vec![],
+        }
+    }
+
+    fn offer(&mut self, rng: &mut Rng, size: usize, line_number: u64, line: &[u8]) {
+        self.seen += 1;
+        if self.lines.len() < size {
+            self.lines.push((line_number, line.to_vec()));
+        } else {
+            let slot = rng.below(self.seen) as usize;
+            if slot < size {
+                self.lines[slot] = (line_number, line.to_vec());
+            }
+        }
+    }
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let by = args.flag_by.as_ref().map(|by| Expr::parse(by).unwrap_or_else(usage_error));
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    let size = args.flag_size.unwrap_or(DEFAULT_SIZE);
+    let seed = args.flag_seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+    });
+    let mut rng = Rng::new(seed);
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    // keyed by the value of --by, invalid lines go under None.
+    let mut strata: BTreeMap<Option<Vec<u8>>, Reservoir> = BTreeMap::new();
+    let mut line_number = 0;
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-sample: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        line_number += 1;
+        let key = match LogEntry::from_bytes(line) {
+            Ok(entry) => {
+                if !filter.matches(&entry) {
+                    continue;
+                }
+                let value = by.as_ref().and_then(|by| by.evaluate(&entry));
+                Some(value.map_or(vec![], |value| value.as_bytes().into_owned()))
+            },
+            Err(_) if args.flag_keep_invalid => {
+                // without --by every line shares one reservoir.
+                by.as_ref().map_or(Some(vec![]), |_| None)
+            },
+            Err(_) => continue,
+        };
+        let reservoir = strata.entry(key).or_insert_with(Reservoir::new);
+        reservoir.offer(&mut rng, size, line_number, line);
+    }
+
+    let mut sample: Vec<(u64, Vec<u8>)> = strata.into_values().flat_map(|r| r.lines).collect();
+    sample.sort_unstable_by_key(|&(line_number, _)| line_number);
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for (_, line) in sample {
+        stdout.write_all(&line).unwrap();
+        // the last line of a file might not have a newline.
+        if !line.ends_with(b"\n") {
+            stdout.write_all(b"\n").unwrap();
+        }
+    }
+}
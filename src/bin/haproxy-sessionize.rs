@@ -0,0 +1,129 @@
+use chrono::Duration;
+use docopt::Docopt;
+use serde_json::json;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{Condition, Expr, ExprError, Filter, Inputs, LogEntry, Session, Sessionizer};
+
+
+const DEFAULT_GAP: i64 = 1800;
+const SESSION_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f";
+
+static USAGE: &str = "
+Group haproxy log entries from each <file> into sessions: runs of requests from the same client
+without a long pause between them. Prints one record per session.
+
+Usage:
+    haproxy-sessionize [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-sessionize -h | --help
+
+Options:
+    -g, --gap=SECS          end a session once its client has been idle for SECS seconds.
+                            (default: 1800)
+    -k, --key=EXPR          tell clients apart by EXPR as well as their IP address, e.g.
+                            captured_request_cookie or captured_header[0][1].
+    --max-uris=N            only list the first N URIs of each session. (default: all of them)
+    --json                  print each session as a JSON document instead of tab separated fields.
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -h, --help              display this help and exit
+
+Each session has the client IP, the --key value, when its first and last requests were accepted,
+the number of requests, bytes sent to the client and errors (5xx or no response), and the URIs it
+requested in order. Entries are expected in roughly chronological order, as haproxy writes them.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_gap: Option<i64>,
+    flag_key: Option<String>,
+    flag_max_uris: Option<usize>,
+    flag_json: bool,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    arg_file: Vec<String>,
+}
+
+fn write_session<W: Write>(session: &Session, out: &mut W, as_json: bool) -> io::Result<()> {
+    let start = session.start.format(SESSION_DATE_FORMAT).to_string();
+    let end = session.end.format(SESSION_DATE_FORMAT).to_string();
+    if as_json {
+        let document = json!({
+            "client": session.client,
+            "key": session.key,
+            "start": start,
+            "end": end,
+            "duration": session.duration().num_milliseconds() as f64 / 1000.0,
+            "requests": session.requests,
+            "bytes": session.bytes,
+            "errors": session.errors,
+            "uris": session.uris,
+        });
+        serde_json::to_writer(&mut *out, &document)?;
+        writeln!(out)
+    } else {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", session.client, session.key, start, end,
+                 session.requests, session.bytes, session.errors, session.uris.join(" "))
+    }
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    let gap = Duration::seconds(args.flag_gap.unwrap_or(DEFAULT_GAP).max(0));
+    let mut sessionizer = Sessionizer::new(gap);
+    if let Some(ref key) = args.flag_key {
+        sessionizer.set_key(Expr::parse(key).unwrap_or_else(usage_error));
+    }
+    if let Some(max_uris) = args.flag_max_uris {
+        sessionizer.set_max_uris(max_uris);
+    }
+
+    let mut reader = Inputs::new(&args.arg_file);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if !args.flag_json {
+        writeln!(stdout, "client\tkey\tstart\tend\trequests\tbytes\terrors\turis").unwrap();
+    }
+
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                stdout.flush().unwrap();
+                eprintln!("haproxy-sessionize: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+        for session in sessionizer.add(&entry) {
+            write_session(&session, &mut stdout, args.flag_json).unwrap();
+        }
+    }
+
+    for session in sessionizer.finish() {
+        write_session(&session, &mut stdout, args.flag_json).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::DateTime;
+use docopt::Docopt;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{Condition, ExprError, Filter, Inputs, LogEntry, Objective, SloCounts, Table};
+
+
+const DEFAULT_OBJECTIVE: f64 = 99.9;
+const DEFAULT_LATENCY: i64 = 500;
+const DEFAULT_WINDOW: i64 = 3600;
+const DEFAULT_TOP: usize = 10;
+
+static USAGE: &str = "
+Report how well haproxy log entries from each <file> meet a service level objective, e.g. 99.9% of
+requests answered without a 5xx in under 500ms.
+
+Usage:
+    haproxy-slo [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-slo -h | --help
+
+Options:
+    -o, --objective=PCT     the percentage of requests which should be good. (default: 99.9)
+    -l, --latency=MS        requests must complete (Tt) in under MS milliseconds to be good.
+                            (default: 500)
+    --window=SECS           report compliance and error budget burn for every SECS seconds.
+                            (default: 3600)
+    --top=N                 list the N backends and URIs with the most bad requests. (default: 10)
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -d, --delimiter=STRING  separate columns with STRING instead of aligning them.
+    -h, --help              display this help and exit
+
+A request is bad if haproxy got no response or a 5xx for it (unavailable) or if it took the
+latency threshold or longer (slow). The error budget is the number of bad requests the objective
+allows, and the burn rate is how fast a window used it up: 1 spends exactly the budget, 2 would
+spend it in half the time.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_objective: Option<f64>,
+    flag_latency: Option<i64>,
+    flag_window: Option<i64>,
+    flag_top: Option<usize>,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_delimiter: Option<String>,
+    arg_file: Vec<String>,
+}
+
+fn counts_row(counts: &SloCounts, name: String, objective: &Objective) -> Vec<String> {
+    vec![
+        name,
+        counts.requests.to_string(),
+        counts.unavailable.to_string(),
+        counts.slow.to_string(),
+        format!("{:.3}", counts.compliance()),
+        format!("{:.2}", counts.burn_rate(objective)),
+    ]
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn write_table<W: Write>(out: &mut W, table: &Table, delimiter: &Option<String>) -> io::Result<()> {
+    match *delimiter {
+        Some(ref delimiter) => table.write_delimited(out, delimiter),
+        None => table.write_aligned(out),
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    let objective = args.flag_objective.unwrap_or(DEFAULT_OBJECTIVE);
+    if !(0.0..=100.0).contains(&objective) {
+        docopt::Error::Argv("--objective must be between 0 and 100".to_string()).exit();
+    }
+    let latency = args.flag_latency.unwrap_or(DEFAULT_LATENCY);
+    let mut slo = Objective::new(objective);
+    slo.set_latency(latency);
+    let window = args.flag_window.unwrap_or(DEFAULT_WINDOW).max(1);
+    let top = args.flag_top.unwrap_or(DEFAULT_TOP);
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut total = SloCounts::default();
+    let mut windows: BTreeMap<i64, SloCounts> = BTreeMap::new();
+    let mut backends: HashMap<Vec<u8>, SloCounts> = HashMap::new();
+    let mut uris: HashMap<Vec<u8>, SloCounts> = HashMap::new();
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-slo: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        let outcome = slo.classify(&entry);
+        total.add(outcome);
+        if let Ok(accepted) = entry.accept_date_time() {
+            let start = accepted.and_utc().timestamp().div_euclid(window) * window;
+            windows.entry(start).or_default().add(outcome);
+        }
+        backends.entry(entry.backend_name.to_vec()).or_default().add(outcome);
+        // group URIs by path, the query string would make almost every one unique.
+        let uri = entry.http_uri().unwrap_or(b"");
+        let path = uri.split(|&c| c == b'?').next().unwrap_or(uri);
+        uris.entry(path.to_vec()).or_default().add(outcome);
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let budget = total.budget(&slo);
+    let spent = if budget > 0.0 {
+        format!("{:.1}%", 100.0 * total.bad() as f64 / budget)
+    } else {
+        "-".to_string()
+    };
+    writeln!(stdout, "objective:    {}% of requests without a 5xx in under {}ms",
+             objective, latency).unwrap();
+    writeln!(stdout, "compliance:   {:.3}% of {} requests ({} unavailable, {} slow)",
+             total.compliance(), total.requests, total.unavailable, total.slow).unwrap();
+    writeln!(stdout, "error budget: {:.1} bad requests allowed, {} spent", budget, spent).unwrap();
+    writeln!(stdout).unwrap();
+
+    let columns = ["requests", "unavailable", "slow", "compliance%", "burn"];
+    let mut table = Table::new(&[&["window"], &columns[..]].concat());
+    for (&start, counts) in &windows {
+        let start = match DateTime::from_timestamp(start, 0) {
+            Some(start) => start.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => start.to_string(),
+        };
+        table.push(counts_row(counts, start, &slo));
+    }
+    write_table(&mut stdout, &table, &args.flag_delimiter).unwrap();
+
+    for (name, groups) in [("backend", backends), ("uri", uris)] {
+        let mut groups: Vec<(Vec<u8>, SloCounts)> =
+            groups.into_iter().filter(|(_, counts)| counts.bad() > 0).collect();
+        groups.sort_by(|a, b| b.1.bad().cmp(&a.1.bad()).then_with(|| a.0.cmp(&b.0)));
+
+        let mut table = Table::new(&[&[name], &columns[..], &["budget%"]].concat());
+        for (key, counts) in groups.into_iter().take(top) {
+            let mut row = counts_row(&counts, String::from_utf8_lossy(&key).into_owned(), &slo);
+            row.push(if budget > 0.0 {
+                format!("{:.1}", 100.0 * counts.bad() as f64 / budget)
+            } else {
+                "-".to_string()
+            });
+            table.push(row);
+        }
+        writeln!(stdout).unwrap();
+        write_table(&mut stdout, &table, &args.flag_delimiter).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use docopt::Docopt;
+use std::collections::HashMap;
+use std::io;
+use std::process;
+
+use haproxy::{Condition, ExprError, Filter, Inputs, LogEntry, TDigest, Table};
+
+
+static USAGE: &str = "
+Summarize haproxy log entries from each <file> per frontend, backend or server.
+
+Usage:
+    haproxy-stats [-w EXPR]... [options] [--] [<file> [<file> ...]]
+    haproxy-stats -h | --help
+
+Options:
+    -b, --by=LEVEL          group entries by frontend, backend or server. (default: backend)
+    -s, --sort=COLUMN       sort groups by requests, errors, bytes or p99, largest first.
+                            (default: requests)
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only count entries accepted at or after DATE.
+    --until=DATE            only count entries accepted before DATE.
+    -d, --delimiter=STRING  separate columns with STRING instead of aligning them.
+    -h, --help              display this help and exit
+
+Each row shows the number of requests and how many got each class of response (err counts entries
+where haproxy didn't get a response at all), the percentage of 5xx or err responses, the total bytes
+sent to clients, and percentiles of the total session time (Tt) in milliseconds, which are estimated
+within a few percent for large groups.
+";
+
+#[derive(RustcDecodable)]
+enum Level {
+    Frontend,
+    Backend,
+    Server,
+}
+
+#[derive(RustcDecodable)]
+enum SortColumn {
+    Requests,
+    Errors,
+    Bytes,
+    P99,
+}
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_by: Option<Level>,
+    flag_sort: Option<SortColumn>,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_delimiter: Option<String>,
+    arg_file: Vec<String>,
+}
+
+#[derive(Default)]
+struct Summary {
+    requests: u64,
+    // indexed by the first digit of the status code, 0 being entries without a response.
+    status_classes: [u64; 6],
+    bytes: u64,
+    total_times: TDigest,
+}
+
+impl Summary {
+    fn add(&mut self, entry: &LogEntry) {
+        self.requests += 1;
+
+        let class = match entry.status_code() {
+            Ok(status) if (100..600).contains(&status) => (status / 100) as usize,
+            _ => 0,
+        };
+        self.status_classes[class] += 1;
+
+        self.bytes += entry.bytes_read().unwrap_or(0);
+
+        // Tt is -1 for sessions which never completed, which would skew the percentiles.
+        if let Ok(total_time) = entry.total_time() {
+            if total_time >= 0 {
+                self.total_times.add(total_time as f64);
+            }
+        }
+    }
+
+    fn errors(&self) -> u64 {
+        self.status_classes[0] + self.status_classes[5]
+    }
+
+    fn percentile(&self, p: f64) -> Option<i64> {
+        // estimated from a digest rather than every time, so large logs fit in memory.
+        self.total_times.quantile(p / 100.0).map(|time| time.round() as i64)
+    }
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    let level = args.flag_by.unwrap_or(Level::Backend);
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut summaries: HashMap<Vec<u8>, Summary> = HashMap::new();
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-stats: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        if let Ok(entry) = LogEntry::from_bytes(line) {
+            if !filter.matches(&entry) {
+                continue;
+            }
+
+            let key = match level {
+                Level::Frontend => entry.frontend_name.to_vec(),
+                Level::Backend => entry.backend_name.to_vec(),
+                Level::Server => [entry.backend_name, b"/", entry.server_name].concat(),
+            };
+            summaries.entry(key).or_default().add(&entry);
+        }
+    }
+
+    let mut summaries: Vec<(Vec<u8>, Summary)> = summaries.into_iter().collect();
+    match args.flag_sort.unwrap_or(SortColumn::Requests) {
+        SortColumn::Requests => summaries.sort_by_key(|s| (!s.1.requests, s.0.clone())),
+        SortColumn::Errors => summaries.sort_by_key(|s| (!s.1.errors(), s.0.clone())),
+        SortColumn::Bytes => summaries.sort_by_key(|s| (!s.1.bytes, s.0.clone())),
+        SortColumn::P99 => {
+            summaries.sort_by_key(|s| (-s.1.percentile(99.0).unwrap_or(-1), s.0.clone()))
+        },
+    }
+
+    let name = match level {
+        Level::Frontend => "frontend",
+        Level::Backend => "backend",
+        Level::Server => "server",
+    };
+    let mut table = Table::new(&[name, "requests", "1xx", "2xx", "3xx", "4xx", "5xx", "err",
+                                 "err%", "bytes", "p50", "p90", "p99", "max"]);
+    for (key, summary) in &summaries {
+        let mut row = vec![String::from_utf8_lossy(key).into_owned(), summary.requests.to_string()];
+        for class in 1..6 {
+            row.push(summary.status_classes[class].to_string());
+        }
+        row.push(summary.status_classes[0].to_string());
+        row.push(format!("{:.2}", 100.0 * summary.errors() as f64 / summary.requests as f64));
+        row.push(summary.bytes.to_string());
+        for &p in &[50.0, 90.0, 99.0, 100.0] {
+            row.push(summary.percentile(p).map_or("-".to_string(), |t| t.to_string()));
+        }
+        table.push(row);
+    }
+
+    let mut stdout = io::stdout();
+    match args.flag_delimiter {
+        Some(ref delimiter) => table.write_delimited(&mut stdout, delimiter).unwrap(),
+        None => table.write_aligned(&mut stdout).unwrap(),
+    }
+}
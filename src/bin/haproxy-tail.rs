@@ -0,0 +1,202 @@
+use docopt::Docopt;
+use libc::consts::os::posix88::STDOUT_FILENO;
+use libc::funcs::posix88::unistd;
+use std::io;
+use std::io::{BufRead, Write};
+
+use haproxy::{color_for, ExprError, Field, Filter, Follow, LogEntry, COLOR_RESET};
+
+
+const MAX_LINE_LENGTH: usize = 1024;
+const DEFAULT_SLOW_THRESHOLD: i64 = 1000;
+const TARGET_WIDTH: usize = 28;
+const TIMERS_WIDTH: usize = 24;
+
+static USAGE: &str = "
+Follow haproxy log entries as they're written to <file> (or standard input) and print them in a
+readable, colorized layout.
+
+Usage:
+    haproxy-tail [-w EXPR]... [options] [--] [<file>]
+    haproxy-tail -h | --help
+
+Options:
+    --from-start            read <file> from the beginning instead of only new entries.
+    -e, --errors            only show entries that got a 5xx or no response at all, or which
+                            haproxy terminated abnormally.
+    -b, --backend=NAME      only show entries for backend NAME.
+    --only-slow             only show entries whose total time (Tt) is at least the --slow
+                            threshold.
+    --slow=MS               highlight timers of at least MS milliseconds. (default: 1000)
+    -w, --where=EXPR        only show entries where EXPR is true, see haproxy-grep --help.
+    --color=WHEN            colorize output: auto (only if stdout is a TTY), always or never.
+                            (default: auto)
+    -h, --help              display this help and exit
+
+Each entry is printed as its accept time, status code, termination state, the Tq/Tw/Tc/Tr/Tt timers
+in milliseconds, the backend and server, and the HTTP request.
+";
+
+#[derive(RustcDecodable)]
+enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_from_start: bool,
+    flag_errors: bool,
+    flag_backend: Option<String>,
+    flag_only_slow: bool,
+    flag_slow: Option<i64>,
+    flag_where: Vec<String>,
+    flag_color: Option<ColorWhen>,
+    arg_file: Option<String>,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn is_error(entry: &LogEntry) -> bool {
+    let abnormal = entry.termination_state.iter().any(|&c| c != b'-');
+    match entry.status_code() {
+        Ok(status) => !(100..500).contains(&status) || abnormal,
+        Err(_) => true,
+    }
+}
+
+struct Printer {
+    colorize: bool,
+    slow_threshold: i64,
+}
+
+impl Printer {
+    fn write_field<W: Write>(&self, out: &mut W, field: Field, content: &[u8]) -> io::Result<()> {
+        match color_for(&field, content, self.slow_threshold).filter(|_| self.colorize) {
+            Some(color) => {
+                out.write_all(color)?;
+                out.write_all(content)?;
+                out.write_all(COLOR_RESET)
+            },
+            None => out.write_all(content),
+        }
+    }
+
+    // e.g. `12:14:14.655 200 ---- 10/0/30/69/109       static/srv1        GET /index.html HTTP/1.1`
+    fn write_entry<W: Write>(&self, out: &mut W, entry: &LogEntry) -> io::Result<()> {
+        // the date rarely changes while watching, so only the time of day is shown.
+        let time = match entry.accept_date.iter().position(|&c| c == b':') {
+            Some(colon) => &entry.accept_date[colon + 1..],
+            None => entry.accept_date,
+        };
+        out.write_all(time)?;
+        out.write_all(b" ")?;
+
+        self.write_field(out, Field::StatusCode, entry.status_code)?;
+        pad(out, entry.status_code.len(), 4)?;
+        self.write_field(out, Field::TerminationState, entry.termination_state)?;
+        pad(out, entry.termination_state.len(), 5)?;
+
+        let timers = [
+            (Field::RequestTime, entry.request_time),
+            (Field::QueueTime, entry.queue_time),
+            (Field::ConnectTime, entry.connect_time),
+            (Field::ResponseTime, entry.response_time),
+            (Field::TotalTime, entry.total_time),
+        ];
+        let mut timers_len = 0;
+        for (i, &(field, content)) in timers.iter().enumerate() {
+            if i != 0 {
+                out.write_all(b"/")?;
+                timers_len += 1;
+            }
+            self.write_field(out, field, content)?;
+            timers_len += content.len();
+        }
+        pad(out, timers_len, TIMERS_WIDTH + 1)?;
+
+        let target_len = entry.backend_name.len() + 1 + entry.server_name.len();
+        out.write_all(entry.backend_name)?;
+        out.write_all(b"/")?;
+        out.write_all(entry.server_name)?;
+        pad(out, target_len, TARGET_WIDTH + 1)?;
+
+        out.write_all(entry.http_request)?;
+        out.write_all(b"\n")
+    }
+}
+
+// pad a column holding `len` bytes out to `width`, always leaving at least one space.
+fn pad<W: Write>(out: &mut W, len: usize, width: usize) -> io::Result<()> {
+    let padding = width.saturating_sub(len).max(1);
+    write!(out, "{:1$}", "", padding)
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    let slow_threshold = args.flag_slow.unwrap_or(DEFAULT_SLOW_THRESHOLD);
+    let stdout_is_interactive = unsafe { unistd::isatty(STDOUT_FILENO) == 1 };
+    let printer = Printer {
+        colorize: match args.flag_color {
+            Some(ColorWhen::Auto) | None => stdout_is_interactive,
+            Some(ColorWhen::Always) => true,
+            Some(ColorWhen::Never) => false,
+        },
+        slow_threshold,
+    };
+
+    let mut follow = match args.arg_file {
+        Some(ref path) if args.flag_from_start => Some(Follow::from_start(path)),
+        Some(ref path) => Some(Follow::new(path)),
+        None => None,
+    }.transpose().unwrap_or_else(|err| {
+        docopt::Error::Argv(format!("could not open input: {}", err)).exit()
+    });
+
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut line_buffer: Vec<u8> = Vec::with_capacity(MAX_LINE_LENGTH);
+    loop {
+        line_buffer.clear();
+        let read = match follow {
+            Some(ref mut follow) => follow.read_line(&mut line_buffer),
+            None => stdin.read_until(b'\n', &mut line_buffer),
+        };
+        match read {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let entry = match LogEntry::from_bytes(&line_buffer) {
+                    Ok(entry) => entry,
+                    Err(_) => continue,
+                };
+
+                if args.flag_errors && !is_error(&entry) {
+                    continue;
+                }
+                if let Some(ref backend) = args.flag_backend {
+                    if entry.backend_name != backend.as_bytes() {
+                        continue;
+                    }
+                }
+                if args.flag_only_slow && !entry.total_time().is_ok_and(|t| t >= slow_threshold) {
+                    continue;
+                }
+                if !filter.matches(&entry) {
+                    continue;
+                }
+
+                // a viewer is only useful if every entry shows up as soon as it's read.
+                if printer.write_entry(&mut stdout, &entry).and_then(|_| stdout.flush()).is_err() {
+                    break;
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use docopt::Docopt;
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::{Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::BufRead;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use haproxy::{ExprError, Filter, Follow, LogEntry, TDigest, TopK};
+
+
+const MAX_LINE_LENGTH: usize = 1024;
+const DEFAULT_WINDOW: u64 = 60;
+// how many URIs, clients and backends to keep counts of since the start, far more than fit on the
+// screen so the counts of the top ones are close.
+const TOP_CAPACITY: usize = 1000;
+
+static USAGE: &str = "
+Show live statistics for haproxy log entries as they're written to <file> (or standard input).
+
+Usage:
+    haproxy-top [-w EXPR]... [options] [--] [<file>]
+    haproxy-top -h | --help
+
+Options:
+    --window=SECS           compute statistics over the last SECS seconds. (default: 60)
+    --from-start            read <file> from the beginning instead of only new entries.
+    -w, --where=EXPR        only count entries where EXPR is true, see haproxy-grep --help.
+    -h, --help              display this help and exit
+
+Keys:
+    tab                     switch between URIs, clients and backends
+    s                       change the sort column
+    a                       switch between the window and everything since the start, which only
+                            counts requests, estimated for the busiest URIs, clients or backends
+    p                       pause or resume updating
+    q                       quit
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_window: Option<u64>,
+    flag_from_start: bool,
+    flag_where: Vec<String>,
+    arg_file: Option<String>,
+}
+
+// what we keep of each entry in the window.
+struct Sample {
+    received: Instant,
+    error: bool,
+    total_time: Option<i64>,
+    uri: String,
+    client: String,
+    backend: String,
+}
+
+impl Sample {
+    fn from_entry(entry: &LogEntry) -> Sample {
+        let status = entry.status_code().unwrap_or(-1);
+        let uri = entry.http_uri().unwrap_or(b"");
+        // group URIs by path, the query string would make almost every one unique.
+        let path = uri.split(|&c| c == b'?').next().unwrap_or(uri);
+
+        Sample {
+            received: Instant::now(),
+            error: !(100..500).contains(&status),
+            total_time: entry.total_time().ok().filter(|&t| t >= 0),
+            uri: String::from_utf8_lossy(path).into_owned(),
+            client: String::from_utf8_lossy(entry.client_ip).into_owned(),
+            backend: String::from_utf8_lossy(entry.backend_name).into_owned(),
+        }
+    }
+}
+
+const VIEWS: [View; 3] = [View::Uris, View::Clients, View::Backends];
+
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Uris,
+    Clients,
+    Backends,
+}
+
+impl View {
+    fn next(self) -> View {
+        match self {
+            View::Uris => View::Clients,
+            View::Clients => View::Backends,
+            View::Backends => View::Uris,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            View::Uris => "uri",
+            View::Clients => "client",
+            View::Backends => "backend",
+        }
+    }
+
+    fn key(self, sample: &Sample) -> &str {
+        match self {
+            View::Uris => &sample.uri,
+            View::Clients => &sample.client,
+            View::Backends => &sample.backend,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+    Requests,
+    Errors,
+    P99,
+}
+
+impl SortColumn {
+    fn next(self) -> SortColumn {
+        match self {
+            SortColumn::Requests => SortColumn::Errors,
+            SortColumn::Errors => SortColumn::P99,
+            SortColumn::P99 => SortColumn::Requests,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Summary {
+    requests: u64,
+    errors: u64,
+    total_times: TDigest,
+}
+
+impl Summary {
+    fn add(&mut self, sample: &Sample) {
+        self.requests += 1;
+        if sample.error {
+            self.errors += 1;
+        }
+        if let Some(total_time) = sample.total_time {
+            self.total_times.add(total_time as f64);
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            100.0 * self.errors as f64 / self.requests as f64
+        }
+    }
+
+    fn percentile(&self, p: f64) -> Option<i64> {
+        // estimated from a digest, rather than sorting every time in the window at each refresh.
+        self.total_times.quantile(p / 100.0).map(|time| time.round() as i64)
+    }
+}
+
+fn format_millis(millis: Option<i64>) -> String {
+    millis.map_or("-".to_string(), |millis| format!("{}ms", millis))
+}
+
+struct App {
+    window: Duration,
+    samples: VecDeque<Sample>,
+    // the busiest of each view since the start, indexed like VIEWS.
+    tops: [TopK; 3],
+    started: Instant,
+    since_start: bool,
+    view: View,
+    sort: SortColumn,
+    paused: bool,
+    input_closed: bool,
+}
+
+impl App {
+    fn record(&mut self, sample: Sample) {
+        for (view, top) in VIEWS.iter().zip(&mut self.tops) {
+            top.add(view.key(&sample).as_bytes());
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn prune(&mut self) {
+        let now = Instant::now();
+        while let Some(sample) = self.samples.front() {
+            if now.duration_since(sample.received) <= self.window {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [summary_area, table_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        let mut total = Summary::default();
+        let mut groups: HashMap<&str, Summary> = HashMap::new();
+        for sample in &self.samples {
+            total.add(sample);
+            groups.entry(self.view.key(sample)).or_default().add(sample);
+        }
+
+        let window_secs = self.window.as_secs_f64();
+        let mut status = format!("{:.1} req/s   {:.2}% errors   Tt p50 {}  p90 {}  p99 {}",
+                                 total.requests as f64 / window_secs,
+                                 total.error_rate(),
+                                 format_millis(total.percentile(50.0)),
+                                 format_millis(total.percentile(90.0)),
+                                 format_millis(total.percentile(99.0)));
+        if self.paused {
+            status.push_str("   [paused]");
+        }
+        if self.input_closed {
+            status.push_str("   [end of input]");
+        }
+        let title = format!(" haproxy-top: last {}s ", self.window.as_secs());
+        frame.render_widget(Paragraph::new(status).block(Block::bordered().title(title)),
+                            summary_area);
+
+        let mut rows: Vec<(&str, Summary, Option<i64>, Option<i64>)> = groups
+            .into_iter()
+            .map(|(key, summary)| {
+                let p50 = summary.percentile(50.0);
+                let p99 = summary.percentile(99.0);
+                (key, summary, p50, p99)
+            })
+            .collect();
+        match self.sort {
+            SortColumn::Requests => rows.sort_by_key(|r| (!r.1.requests, r.0)),
+            SortColumn::Errors => rows.sort_by_key(|r| (!r.1.errors, r.0)),
+            SortColumn::P99 => rows.sort_by_key(|r| (-r.3.unwrap_or(-1), r.0)),
+        }
+
+        let header_style = |column: SortColumn| {
+            let style = Style::default().add_modifier(Modifier::BOLD);
+            if column == self.sort {
+                style.add_modifier(Modifier::REVERSED)
+            } else {
+                style
+            }
+        };
+        let header = Row::new(vec![
+            Line::from(self.view.title()).style(Style::default().add_modifier(Modifier::BOLD)),
+            Line::from("requests").style(header_style(SortColumn::Requests)),
+            Line::from("req/s").style(Style::default().add_modifier(Modifier::BOLD)),
+            Line::from("errors").style(header_style(SortColumn::Errors)),
+            Line::from("err%").style(Style::default().add_modifier(Modifier::BOLD)),
+            Line::from("p50").style(Style::default().add_modifier(Modifier::BOLD)),
+            Line::from("p99").style(header_style(SortColumn::P99)),
+        ]);
+        let visible = table_area.height.saturating_sub(3) as usize;
+        let body: Vec<Row> = if self.since_start {
+            let top = &self.tops[VIEWS.iter().position(|&view| view == self.view).unwrap_or(0)];
+            let elapsed = self.started.elapsed().as_secs_f64().max(1.0);
+            top.top(visible).into_iter().map(|hitter| {
+                Row::new(vec![
+                    String::from_utf8_lossy(hitter.key).into_owned(),
+                    hitter.count.to_string(),
+                    format!("{:.1}", hitter.count as f64 / elapsed),
+                    "-".to_string(),
+                    "-".to_string(),
+                    "-".to_string(),
+                    "-".to_string(),
+                ])
+            }).collect()
+        } else {
+            rows.iter().take(visible).map(|&(key, ref summary, p50, p99)| {
+                Row::new(vec![
+                    key.to_string(),
+                    summary.requests.to_string(),
+                    format!("{:.1}", summary.requests as f64 / window_secs),
+                    summary.errors.to_string(),
+                    format!("{:.2}", summary.error_rate()),
+                    format_millis(p50),
+                    format_millis(p99),
+                ])
+            }).collect()
+        };
+        let widths = [
+            Constraint::Fill(1),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(7),
+            Constraint::Length(9),
+            Constraint::Length(9),
+        ];
+        let block = if self.since_start {
+            Block::bordered().title(" since start, estimated ")
+        } else {
+            Block::bordered()
+        };
+        let table = Table::new(body, widths).header(header).block(block);
+        frame.render_widget(table, table_area);
+
+        let help = "tab: switch view   s: sort   a: since start   p: pause   q: quit";
+        frame.render_widget(Paragraph::new(help), help_area);
+    }
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+// parse entries on a separate thread so a burst of log lines never blocks the UI.
+fn spawn_reader(args: &Args, filter: Filter, samples: mpsc::Sender<Sample>) -> io::Result<()> {
+    let mut follow = match args.arg_file {
+        Some(ref path) if args.flag_from_start => Some(Follow::from_start(path)?),
+        Some(ref path) => Some(Follow::new(path)?),
+        None => None,
+    };
+
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let mut line_buffer: Vec<u8> = Vec::with_capacity(MAX_LINE_LENGTH);
+        loop {
+            line_buffer.clear();
+            let read = match follow {
+                Some(ref mut follow) => follow.read_line(&mut line_buffer),
+                None => stdin.read_until(b'\n', &mut line_buffer),
+            };
+            match read {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let entry = match LogEntry::from_bytes(&line_buffer) {
+                        Ok(entry) => entry,
+                        Err(_) => continue,
+                    };
+                    if filter.matches(&entry) && samples.send(Sample::from_entry(&entry)).is_err() {
+                        break;
+                    }
+                },
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    let (sender, receiver) = mpsc::channel();
+    if let Err(err) = spawn_reader(&args, filter, sender) {
+        docopt::Error::Argv(format!("could not open input: {}", err)).exit();
+    }
+
+    let mut app = App {
+        window: Duration::from_secs(args.flag_window.unwrap_or(DEFAULT_WINDOW).max(1)),
+        samples: VecDeque::new(),
+        tops: [TopK::new(TOP_CAPACITY), TopK::new(TOP_CAPACITY), TopK::new(TOP_CAPACITY)],
+        started: Instant::now(),
+        since_start: false,
+        view: View::Uris,
+        sort: SortColumn::Requests,
+        paused: false,
+        input_closed: false,
+    };
+
+    let mut terminal = ratatui::init();
+    let tick = Duration::from_secs(1);
+    let mut last_draw = Instant::now() - tick;
+    loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(sample) => app.record(sample),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    app.input_closed = true;
+                    break;
+                },
+            }
+        }
+
+        if !app.paused && last_draw.elapsed() >= tick {
+            app.prune();
+            if terminal.draw(|frame| app.draw(frame)).is_err() {
+                break;
+            }
+            last_draw = Instant::now();
+        }
+
+        if event::poll(Duration::from_millis(100)).unwrap_or(false) {
+            if let Ok(Event::Key(key)) = event::read() {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Tab => app.view = app.view.next(),
+                    KeyCode::Char('s') => app.sort = app.sort.next(),
+                    KeyCode::Char('a') => app.since_start = !app.since_start,
+                    KeyCode::Char('p') => app.paused = !app.paused,
+                    _ => continue,
+                }
+                // redraw right away so the change is visible even while paused.
+                app.prune();
+                let _ = terminal.draw(|frame| app.draw(frame));
+                last_draw = Instant::now();
+            }
+        }
+    }
+    ratatui::restore();
+}
@@ -0,0 +1,306 @@
+use docopt::Docopt;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::io::Write;
+use std::process;
+
+use haproxy::{entry_attributes, Condition, Expr, ExprError, Filter, Inputs, LogEntry, Number,
+              TimeZoneArg};
+
+
+const DEFAULT_ENDPOINT: &str = "http://localhost:4318/v1/traces";
+const DEFAULT_BATCH: usize = 512;
+// span kinds and status codes from the OTLP protocol.
+const SPAN_KIND_SERVER: u8 = 2;
+const SPAN_KIND_INTERNAL: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+static USAGE: &str = "
+Convert haproxy log entries from each <file> to OpenTelemetry spans and export them to an OTLP/HTTP
+collector.
+
+Usage:
+    haproxy-trace [-w EXPR]... [-H HEADER]... [options] [--] [<file> [<file> ...]]
+    haproxy-trace -h | --help
+
+Options:
+    -e, --endpoint=URL      the OTLP/HTTP traces endpoint.
+                            (default: http://localhost:4318/v1/traces)
+    -H, --header=HEADER     send `Name: value` with every export, e.g. for authentication.
+    --service=NAME          the service.name of the exported spans. (default: haproxy)
+    --trace-id=EXPR         take the trace ID from EXPR, e.g. captured_header[0][2] when haproxy
+                            captures a unique-id header. (default: derived from the log line)
+    --events                record the timers as events on the span instead of child spans.
+    --batch=N               export N entries per request. (default: 512)
+    --assume-tz=ZONE        the timezone accept_date was logged in, UTC, Local or an IANA name
+                            like Europe/Paris. (default: Local)
+    --dry-run               print each export request to standard output instead of sending it.
+    -w, --where=EXPR        only export entries where EXPR is true, see haproxy-grep --help.
+    --since=DATE            only export entries accepted at or after DATE.
+    --until=DATE            only export entries accepted before DATE.
+    -h, --help              display this help and exit
+
+Each entry becomes a server span from accept_date lasting Tt, with child spans for the request
+(Tq), queue (Tw), connect (Tc) and response (Tr) phases laid end to end. Timers logged as -1 are
+left out. Spans are errors when haproxy got no response or a 5xx.
+
+A --trace-id of 32 hex digits (or a UUID) is used as is, anything else is hashed into one so
+entries sharing an ID still end up in the same trace.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_endpoint: Option<String>,
+    flag_header: Vec<String>,
+    flag_service: Option<String>,
+    flag_trace_id: Option<String>,
+    flag_events: bool,
+    flag_batch: Option<usize>,
+    flag_assume_tz: Option<TimeZoneArg>,
+    flag_dry_run: bool,
+    flag_where: Vec<String>,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    arg_file: Vec<String>,
+}
+
+fn usage_error<T>(err: ExprError) -> T {
+    docopt::Error::Argv(err.to_string()).exit()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn digest(parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+        // keep ["ab", "c"] and ["a", "bc"] apart.
+        hasher.update([0]);
+    }
+    hasher.finalize().to_vec()
+}
+
+fn trace_id(value: Option<&[u8]>, line: &[u8]) -> String {
+    match value {
+        Some(value) if !value.is_empty() && value != b"-" => {
+            let text = String::from_utf8_lossy(value).replace('-', "").to_ascii_lowercase();
+            if text.len() == 32 && text.bytes().all(|c| c.is_ascii_hexdigit()) {
+                text
+            } else {
+                hex(&digest(&[value])[..16])
+            }
+        },
+        _ => hex(&digest(&[line])[..16]),
+    }
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    let value = match value {
+        Value::Number(ref number) if number.is_i64() => json!({"intValue": number.to_string()}),
+        Value::String(_) => json!({"stringValue": value}),
+        _ => json!({"stringValue": value.to_string()}),
+    };
+    json!({"key": key, "value": value})
+}
+
+// one of entry_attributes' values as JSON, for attribute.
+fn json_value(value: &haproxy::Value) -> Value {
+    match *value {
+        haproxy::Value::Number(Number::Integer(number)) => json!(number),
+        haproxy::Value::Number(Number::Decimal(number)) => json!(number),
+        haproxy::Value::Text(ref text) => json!(String::from_utf8_lossy(text)),
+    }
+}
+
+// the server span for an entry followed by its phases as child spans, or with them as events.
+fn spans(entry: &LogEntry, line: &[u8], trace_id: &str, start: i64, events: bool) -> Vec<Value> {
+    let millis = |timer: haproxy::Result<i64>| timer.ok().filter(|&t| t >= 0);
+    let total = millis(entry.total_time()).unwrap_or(0);
+    let span_id = hex(&digest(&[trace_id.as_bytes(), line])[..8]);
+    let method = entry.http_method().unwrap_or(b"");
+    let uri = entry.http_uri().unwrap_or(b"");
+    let path = uri.split(|&c| c == b'?').next().unwrap_or(uri);
+    let status = entry.status_code().unwrap_or(-1);
+    let attributes: Vec<Value> = entry_attributes(entry).iter()
+        .map(|(key, value)| attribute(key, json_value(value)))
+        .collect();
+
+    let mut phases = vec![];
+    let mut offset = 0;
+    let timers = [("request", entry.request_time()), ("queue", entry.queue_time()),
+                  ("connect", entry.connect_time()), ("response", entry.response_time())];
+    for (name, timer) in timers {
+        if let Some(duration) = millis(timer) {
+            let phase_start = start + offset * 1_000_000;
+            phases.push((name, phase_start, phase_start + duration * 1_000_000));
+            offset += duration;
+        }
+    }
+
+    let name = format!("{} {}", String::from_utf8_lossy(method), String::from_utf8_lossy(path));
+    let mut span = json!({
+        "traceId": trace_id,
+        "spanId": span_id,
+        "name": name.trim(),
+        "kind": SPAN_KIND_SERVER,
+        "startTimeUnixNano": start.to_string(),
+        "endTimeUnixNano": (start + total * 1_000_000).to_string(),
+        "attributes": attributes,
+    });
+    if !(100..500).contains(&status) {
+        span["status"] = json!({"code": STATUS_ERROR});
+    }
+
+    if events {
+        let events: Vec<Value> = phases.iter()
+            .map(|&(name, phase_start, phase_end)| {
+                // each event marks the end of its phase and says how long it took.
+                let duration = json!((phase_end - phase_start) / 1_000_000);
+                json!({
+                    "timeUnixNano": phase_end.to_string(),
+                    "name": name,
+                    "attributes": [attribute("haproxy.duration_ms", duration)],
+                })
+            })
+            .collect();
+        span["events"] = Value::Array(events);
+        return vec![span];
+    }
+
+    let mut spans = vec![span];
+    for (name, phase_start, phase_end) in phases {
+        spans.push(json!({
+            "traceId": trace_id,
+            "spanId": hex(&digest(&[span_id.as_bytes(), name.as_bytes()])[..8]),
+            "parentSpanId": span_id,
+            "name": name,
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": phase_start.to_string(),
+            "endTimeUnixNano": phase_end.to_string(),
+        }));
+    }
+    spans
+}
+
+struct Exporter {
+    endpoint: String,
+    headers: Vec<(String, String)>,
+    service: String,
+    dry_run: bool,
+}
+
+impl Exporter {
+    fn export(&self, spans: Vec<Value>) -> Result<(), String> {
+        let request = json!({
+            "resourceSpans": [{
+                "resource": {"attributes": [attribute("service.name", json!(self.service))]},
+                "scopeSpans": [{"scope": {"name": "haproxy-trace"}, "spans": spans}],
+            }],
+        });
+
+        if self.dry_run {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            return writeln!(stdout, "{}", request).map_err(|err| err.to_string());
+        }
+
+        let mut post = ureq::post(&self.endpoint).set("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            post = post.set(name, value);
+        }
+        post.send_string(&request.to_string()).map(|_| ()).map_err(|err| err.to_string())
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+
+    let mut filter = Filter::parse(&args.flag_where).unwrap_or_else(usage_error);
+    if let Some(ref since) = args.flag_since {
+        filter.push(Condition::since(since).unwrap_or_else(usage_error));
+    }
+    if let Some(ref until) = args.flag_until {
+        filter.push(Condition::until(until).unwrap_or_else(usage_error));
+    }
+    let trace_expr = args.flag_trace_id.as_ref()
+        .map(|expr| Expr::parse(expr).unwrap_or_else(usage_error));
+    let mut headers = vec![];
+    for header in &args.flag_header {
+        match header.split_once(':') {
+            Some((name, value)) => {
+                headers.push((name.trim().to_string(), value.trim().to_string()))
+            },
+            None => {
+                docopt::Error::Argv(format!("expected `Name: value`, got '{}'", header)).exit()
+            },
+        }
+    }
+    let exporter = Exporter {
+        endpoint: args.flag_endpoint.clone().unwrap_or(DEFAULT_ENDPOINT.to_string()),
+        headers,
+        service: args.flag_service.clone().unwrap_or("haproxy".to_string()),
+        dry_run: args.flag_dry_run,
+    };
+    let timezone = args.flag_assume_tz.unwrap_or(TimeZoneArg::Local);
+    let batch_size = args.flag_batch.unwrap_or(DEFAULT_BATCH).max(1);
+
+    let mut reader = Inputs::new(&args.arg_file);
+
+    let mut batch = vec![];
+    let mut entries = 0;
+    let mut exported = 0;
+    loop {
+        let line = match reader.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haproxy-trace: {}: {}", reader.path().unwrap_or("-"), err);
+                process::exit(1);
+            },
+        };
+        let entry = match LogEntry::from_bytes(line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+        // nanoseconds since the unix epoch.
+        let start = entry.accept_date_time().ok()
+            .and_then(|accepted| timezone.localize(&accepted))
+            .and_then(|accepted| accepted.timestamp_nanos_opt());
+        let start = match start {
+            Some(start) => start,
+            None => continue,
+        };
+
+        let value = trace_expr.as_ref()
+            .and_then(|expr| expr.evaluate(&entry))
+            .map(|value| value.as_bytes().into_owned());
+        let trace_id = trace_id(value.as_deref(), line);
+        batch.extend(spans(&entry, line, &trace_id, start, args.flag_events));
+        entries += 1;
+
+        if entries % batch_size == 0 {
+            if let Err(err) = exporter.export(std::mem::take(&mut batch)) {
+                eprintln!("haproxy-trace: could not export spans: {}", err);
+                process::exit(1);
+            }
+            exported = entries;
+        }
+    }
+
+    if !batch.is_empty() {
+        if let Err(err) = exporter.export(batch) {
+            eprintln!("haproxy-trace: could not export spans: {}", err);
+            process::exit(1);
+        }
+        exported = entries;
+    }
+    if !args.flag_dry_run {
+        eprintln!("exported {} entries", exported);
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::NaiveDateTime;
+
+use crate::entry::{LogEntry, ACCEPT_DATE_FORMAT};
+
+// which side of a proxy sent what haproxy couldn't parse.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+// one invalid request or response from `show errors`, which haproxy keeps the last of for each
+// proxy and direction. `proxy` is the frontend for a request and the backend for a response, and
+// `other_proxy` the one on the other side if there was one by then.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CapturedError {
+    pub date: Option<NaiveDateTime>,
+    pub direction: Direction,
+    pub proxy: String,
+    pub other_proxy: Option<String>,
+    pub server: Option<String>,
+    pub event: u64,
+    // the client's address and port, as in the client_ip and client_port of its log line.
+    pub source: String,
+    pub length: Option<usize>,
+    // where in `data` haproxy gave up.
+    pub position: Option<usize>,
+    // the captured bytes, from haproxy's escaped dump of them.
+    pub data: Vec<u8>,
+}
+
+impl CapturedError {
+    // whether `entry` is the log line of the same request: the same client connection through the
+    // proxy on the same side. its termination state says what haproxy did about it, often PR--.
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        let (frontend, backend) = match self.direction {
+            Direction::Request => (Some(&self.proxy), self.other_proxy.as_ref()),
+            Direction::Response => (self.other_proxy.as_ref(), Some(&self.proxy)),
+        };
+        let source = self.source.rsplit_once(':');
+        source == Some((as_str(entry.client_ip), as_str(entry.client_port)))
+            && frontend.is_none_or(|frontend| {
+                as_str(entry.frontend_name).trim_end_matches('~') == frontend
+            })
+            && backend.is_none_or(|backend| as_str(entry.backend_name) == backend)
+    }
+}
+
+fn as_str(s: &[u8]) -> &str {
+    std::str::from_utf8(s).unwrap_or("")
+}
+
+// the events in the answer to `show errors`, oldest first as haproxy lists them. an event starts
+// with a line like `[10/Jul/2023:14:01:55.456] frontend fe (#2): invalid request` and the dump of
+// what was captured is at the end of it, one line of it per line of the data.
+pub fn parse_errors(response: &str) -> Vec<CapturedError> {
+    let mut errors: Vec<CapturedError> = vec![];
+    for line in response.lines() {
+        if line.starts_with('[') {
+            if let Some(error) = parse_header(line) {
+                errors.push(error);
+            }
+            continue;
+        }
+        let error = match errors.last_mut() {
+            Some(error) => error,
+            None => continue,
+        };
+        let line = line.trim_start();
+        if let Some(data) = dump_line(line) {
+            unescape_into(&mut error.data, data);
+            continue;
+        }
+        for part in line.split(',').map(str::trim) {
+            let words: Vec<&str> = part.split_whitespace().collect();
+            match words[..] {
+                ["backend", name, _] | ["frontend", name, _] => error.other_proxy = named(name),
+                ["server", name, _] => error.server = named(name),
+                ["event", event] => {
+                    error.event = event.trim_start_matches('#').parse().unwrap_or(0)
+                },
+                ["src", source] => error.source = source.to_string(),
+                ["len", length] => error.length = length.parse().ok(),
+                ["error", "at", "position", position] => error.position = position.parse().ok(),
+                _ => {},
+            }
+        }
+    }
+    errors
+}
+
+fn parse_header(line: &str) -> Option<CapturedError> {
+    let (date, rest) = line[1..].split_once("] ")?;
+    let (proxy, kind) = rest.split_once(": ")?;
+    let direction = match kind.trim() {
+        "invalid request" => Direction::Request,
+        "invalid response" => Direction::Response,
+        _ => return None,
+    };
+    // `frontend fe (#2)`.
+    let proxy = proxy.split_whitespace().nth(1)?;
+    Some(CapturedError {
+        date: NaiveDateTime::parse_from_str(date, ACCEPT_DATE_FORMAT).ok(),
+        direction,
+        proxy: proxy.to_string(),
+        other_proxy: None,
+        server: None,
+        event: 0,
+        source: String::new(),
+        length: None,
+        position: None,
+        data: vec![],
+    })
+}
+
+// haproxy says <NONE> for a backend or server it hadn't picked yet.
+fn named(name: &str) -> Option<String> {
+    if name == "<NONE>" { None } else { Some(name.to_string()) }
+}
+
+// the text of a line of the dump, which starts with the offset of its first byte and a '+' when it
+// carries on from the last line rather than starting after a newline.
+fn dump_line(line: &str) -> Option<&str> {
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+    if digits != 5 {
+        return None;
+    }
+    let rest = &line[digits..];
+    rest.strip_prefix("  ").or_else(|| rest.strip_prefix("+ "))
+}
+
+// the dump escapes control characters as \r, \n, \t or \xNN and backslashes as \\.
+fn unescape_into(data: &mut Vec<u8>, text: &str) {
+    let mut bytes = text.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            data.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'r') => data.push(b'\r'),
+            Some(b'n') => data.push(b'\n'),
+            Some(b't') => data.push(b'\t'),
+            Some(b'x') => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                let value = std::str::from_utf8(&hex).ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                data.extend(value);
+            },
+            Some(other) => data.push(other),
+            None => data.push(b'\\'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_errors, Direction};
+    use crate::entry::LogEntry;
+
+    static ERRORS: &str = "\
+Total events captured on [10/Jul/2023:14:02:03.123] : 2
+
+[10/Jul/2023:14:01:55.456] frontend http-in (#2): invalid request
+  backend <NONE> (#-1), server <NONE> (#-1), event #1, src 10.0.1.2:33317
+  buffer starts at 0 (including 0 out), 16364 free,
+  len 34, wraps at 16336, error at position 3
+  H1 connection flags 0x00000000, H1 stream flags 0x00000012
+  H1 msg state MSG_RQMETH(2), H1 msg flags 0x00001400
+  H1 chunk len 0 bytes, H1 body len 0 bytes :
+
+  00000  GET\\x01/ HTTP/1.1\\r\\n
+  00019  Host: \\\\example\\r\\n
+  00034+ \\t
+
+[10/Jul/2023:14:01:58.001] backend static (#3): invalid response
+  frontend http-in (#2), server srv1 (#1), event #0, src 10.0.1.3:40000
+  buffer starts at 0 (including 0 out), 16000 free,
+  len 12, wraps at 16336, error at position 9
+  00000  HTTP/1.1 2x0
+";
+
+    #[test]
+    fn errors() {
+        let errors = parse_errors(ERRORS);
+        assert_eq!(errors.len(), 2);
+
+        let request = &errors[0];
+        assert_eq!(request.direction, Direction::Request);
+        assert_eq!(request.date.unwrap().to_string(), "2023-07-10 14:01:55.456");
+        assert_eq!(request.proxy, "http-in");
+        assert_eq!(request.other_proxy, None);
+        assert_eq!(request.server, None);
+        assert_eq!(request.event, 1);
+        assert_eq!(request.source, "10.0.1.2:33317");
+        assert_eq!(request.length, Some(34));
+        assert_eq!(request.position, Some(3));
+        assert_eq!(request.data, b"GET\x01/ HTTP/1.1\r\nHost: \\example\r\n\t");
+
+        let response = &errors[1];
+        assert_eq!(response.direction, Direction::Response);
+        assert_eq!(response.proxy, "static");
+        assert_eq!(response.other_proxy.as_deref(), Some("http-in"));
+        assert_eq!(response.server.as_deref(), Some("srv1"));
+        assert_eq!(response.data, b"HTTP/1.1 2x0");
+
+        assert!(parse_errors("Total events captured on [10/Jul/2023:14:02:03.123] : 0\n")
+                .is_empty());
+    }
+
+    #[test]
+    fn matches() {
+        let errors = parse_errors(ERRORS);
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [10/Jul/2023:14:01:55.456] http-in~ \
+                     http-in/<NOSRV> -1/-1/-1/-1/0 400 187 - - PR-- 1/1/0/0/0 0/0 \"<BADREQ>\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        assert!(errors[0].matches(&entry));
+        assert!(!errors[1].matches(&entry));
+    }
+}
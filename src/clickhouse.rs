@@ -0,0 +1,354 @@
+use std::io;
+use std::io::Write;
+
+use crate::entry::LogEntry;
+use crate::integer::{parse_field, parse_i64};
+
+const DEFAULT_BATCH_ROWS: usize = 10_000;
+
+// the table ClickHouseWriter inserts into, columns and all. accept_date is haproxy's local time
+// stored as though it were UTC, and numbers are null where a field doesn't parse or is haproxy's
+// -1 for a timer or status the request never got to. the table name and engine are only a
+// suggestion, the columns are what the rows have to match.
+pub const CLICKHOUSE_DDL: &str = "CREATE TABLE haproxy_logs (
+    process_name LowCardinality(String),
+    pid Nullable(UInt32),
+    client_ip String,
+    client_port Nullable(UInt16),
+    accept_date Nullable(DateTime64(3, 'UTC')),
+    frontend_name LowCardinality(String),
+    backend_name LowCardinality(String),
+    server_name LowCardinality(String),
+    request_time Nullable(Int32),
+    queue_time Nullable(Int32),
+    connect_time Nullable(Int32),
+    response_time Nullable(Int32),
+    total_time Nullable(Int32),
+    status_code Nullable(Int16),
+    bytes_read Nullable(UInt64),
+    captured_request_cookie String,
+    captured_response_cookie String,
+    termination_state LowCardinality(String),
+    active_connections Nullable(UInt32),
+    frontend_connections Nullable(UInt32),
+    backend_connections Nullable(UInt32),
+    server_connections Nullable(UInt32),
+    retried_connections Nullable(UInt32),
+    server_queue Nullable(UInt32),
+    backend_queue Nullable(UInt32),
+    captured_request_headers String,
+    captured_response_headers String,
+    http_request String
+) ENGINE = MergeTree ORDER BY (frontend_name, backend_name, accept_date)
+";
+
+// how rows are sent. RowBinary is smaller and cheaper for the server to take in, JSONEachRow is
+// easier to read when something goes wrong.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClickHouseFormat {
+    RowBinary,
+    JsonEachRow,
+}
+
+impl ClickHouseFormat {
+    // the name ClickHouse knows the format by, for `INSERT ... FORMAT`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ClickHouseFormat::RowBinary => "RowBinary",
+            ClickHouseFormat::JsonEachRow => "JSONEachRow",
+        }
+    }
+}
+
+// a column's value, the type it has in CLICKHOUSE_DDL aside.
+enum Column<'a> {
+    Text(&'a [u8]),
+    // the width of the column's integer type in bytes, and whether it's signed.
+    Number(Option<i64>, usize, bool),
+    // milliseconds since the epoch.
+    Date(Option<i64>),
+}
+
+fn columns<'a>(entry: &LogEntry<'a>) -> Vec<(&'static str, Column<'a>)> {
+    let timer = |field: &[u8]| {
+        Column::Number(parse_field(field).filter(|&timer| timer != -1), 4, true)
+    };
+    let count = |field: &[u8]| Column::Number(parse_field(field), 4, false);
+    let accept_date = entry.accept_date_time().ok();
+    vec![
+        ("process_name", Column::Text(entry.process_name)),
+        ("pid", Column::Number(parse_i64(entry.pid), 4, false)),
+        ("client_ip", Column::Text(entry.client_ip)),
+        ("client_port", Column::Number(parse_i64(entry.client_port), 2, false)),
+        ("accept_date", Column::Date(accept_date.map(|date| date.and_utc().timestamp_millis()))),
+        ("frontend_name", Column::Text(entry.frontend_name)),
+        ("backend_name", Column::Text(entry.backend_name)),
+        ("server_name", Column::Text(entry.server_name)),
+        ("request_time", timer(entry.request_time)),
+        ("queue_time", timer(entry.queue_time)),
+        ("connect_time", timer(entry.connect_time)),
+        ("response_time", timer(entry.response_time)),
+        ("total_time", timer(entry.total_time)),
+        ("status_code", Column::Number(parse_i64(entry.status_code).filter(|&s| s != -1), 2,
+                                      true)),
+        ("bytes_read", Column::Number(parse_field(entry.bytes_read), 8, false)),
+        ("captured_request_cookie", Column::Text(entry.captured_request_cookie)),
+        ("captured_response_cookie", Column::Text(entry.captured_response_cookie)),
+        ("termination_state", Column::Text(entry.termination_state)),
+        ("active_connections", count(entry.active_connections)),
+        ("frontend_connections", count(entry.frontend_connections)),
+        ("backend_connections", count(entry.backend_connections)),
+        ("server_connections", count(entry.server_connections)),
+        ("retried_connections", count(entry.retried_connections)),
+        ("server_queue", count(entry.server_queue)),
+        ("backend_queue", count(entry.backend_queue)),
+        ("captured_request_headers", Column::Text(entry.captures[0])),
+        ("captured_response_headers", Column::Text(entry.captures[1])),
+        ("http_request", Column::Text(entry.http_request)),
+    ]
+}
+
+// appends `entry` to `out` as a row of CLICKHOUSE_DDL's table in `format`. JSONEachRow rows end
+// with a newline, RowBinary ones aren't delimited.
+pub fn encode_clickhouse_row(entry: &LogEntry, format: ClickHouseFormat, out: &mut Vec<u8>) {
+    match format {
+        ClickHouseFormat::RowBinary => row_binary(entry, out),
+        ClickHouseFormat::JsonEachRow => json_each_row(entry, out),
+    }
+}
+
+fn row_binary(entry: &LogEntry, out: &mut Vec<u8>) {
+    for (_, column) in columns(entry) {
+        match column {
+            // strings are bytes to ClickHouse, so fields go as they were logged.
+            Column::Text(text) => {
+                leb128(out, text.len() as u64);
+                out.extend_from_slice(text);
+            },
+            Column::Number(number, width, signed) => {
+                // in range for its type, or null.
+                let number = number.filter(|&number| fits(number, width, signed));
+                match number {
+                    Some(number) => {
+                        out.push(0);
+                        out.extend_from_slice(&number.to_le_bytes()[..width]);
+                    },
+                    None => out.push(1),
+                }
+            },
+            Column::Date(Some(millis)) => {
+                out.push(0);
+                out.extend_from_slice(&millis.to_le_bytes());
+            },
+            Column::Date(None) => out.push(1),
+        }
+    }
+}
+
+fn json_each_row(entry: &LogEntry, out: &mut Vec<u8>) {
+    out.push(b'{');
+    for (i, (name, column)) in columns(entry).into_iter().enumerate() {
+        if i != 0 {
+            out.push(b',');
+        }
+        write!(out, "\"{}\":", name).unwrap();
+        match column {
+            Column::Text(text) => {
+                serde_json::to_writer(&mut *out, &*String::from_utf8_lossy(text)).unwrap();
+            },
+            Column::Number(Some(number), width, signed) if fits(number, width, signed) => {
+                write!(out, "{}", number).unwrap();
+            },
+            Column::Date(Some(millis)) => {
+                let date = chrono::DateTime::from_timestamp_millis(millis).unwrap_or_default();
+                write!(out, "\"{}\"", date.format("%Y-%m-%d %H:%M:%S%.3f")).unwrap();
+            },
+            Column::Number(..) | Column::Date(None) => out.extend_from_slice(b"null"),
+        }
+    }
+    out.extend_from_slice(b"}\n");
+}
+
+fn fits(number: i64, width: usize, signed: bool) -> bool {
+    let bits = width as u32 * 8;
+    match (signed, bits) {
+        (_, 64) => signed || number >= 0,
+        (true, _) => number >= -(1 << (bits - 1)) && number < 1 << (bits - 1),
+        (false, _) => number >= 0 && number < 1 << bits,
+    }
+}
+
+fn leb128(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+// inserts entries into a table made with CLICKHOUSE_DDL through ClickHouse's HTTP interface, an
+// INSERT every `batch_size` entries. entries written since the last insert are only sent by
+// `flush`, which should be called at the end and whenever the input pauses.
+pub struct ClickHouseWriter {
+    url: String,
+    table: String,
+    format: ClickHouseFormat,
+    credentials: Option<(String, String)>,
+    agent: ureq::Agent,
+    batch: Vec<u8>,
+    rows: usize,
+    batch_size: usize,
+}
+
+impl ClickHouseWriter {
+    // `url` is the HTTP interface's, e.g. http://localhost:8123.
+    pub fn new(url: &str, table: &str, format: ClickHouseFormat) -> ClickHouseWriter {
+        ClickHouseWriter {
+            url: url.trim_end_matches('/').to_string(),
+            table: table.to_string(),
+            format,
+            credentials: None,
+            agent: ureq::Agent::new(),
+            batch: vec![],
+            rows: 0,
+            batch_size: DEFAULT_BATCH_ROWS,
+        }
+    }
+
+    pub fn set_credentials(&mut self, user: &str, password: &str) {
+        self.credentials = Some((user.to_string(), password.to_string()));
+    }
+
+    // how many entries go in an INSERT, ten thousand by default.
+    pub fn set_batch_size(&mut self, entries: usize) {
+        self.batch_size = entries.max(1);
+    }
+
+    pub fn write(&mut self, entry: &LogEntry) -> io::Result<()> {
+        encode_clickhouse_row(entry, self.format, &mut self.batch);
+        self.rows += 1;
+        if self.rows >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // inserts the entries written since the last insert, if there are any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let query = format!("INSERT INTO {} FORMAT {}", self.table, self.format.name());
+        let mut request = self.agent.post(&self.url).query("query", &query);
+        if let Some((ref user, ref password)) = self.credentials {
+            request = request.set("X-ClickHouse-User", user).set("X-ClickHouse-Key", password);
+        }
+        match request.send_bytes(&self.batch) {
+            Ok(_) => {},
+            Err(ureq::Error::Status(status, response)) => {
+                // ClickHouse says what was wrong with the insert in the body.
+                let message = response.into_string().unwrap_or_default();
+                return Err(io::Error::other(format!("{} answered {}: {}", self.url, status,
+                                                    message.trim())));
+            },
+            Err(ureq::Error::Transport(err)) => return Err(io::Error::other(err)),
+        }
+        self.batch.clear();
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{encode_clickhouse_row, ClickHouseFormat, ClickHouseWriter, CLICKHOUSE_DDL};
+    use crate::entry::LogEntry;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const LINE: &[u8] = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                          static/<NOSRV> -1/-1/-1/-1/+3000 -1 0 - - CQ-- 1/1/1/0/+1 0/5 {a|b} \
+                          \"GET / HTTP/1.1\"";
+
+    #[test]
+    fn rows() {
+        let entry = LogEntry::from_bytes(LINE).unwrap();
+        let mut out = vec![];
+        encode_clickhouse_row(&entry, ClickHouseFormat::JsonEachRow, &mut out);
+        let row: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(row.as_object().unwrap().len(), CLICKHOUSE_DDL.matches(",\n").count() + 1);
+        assert_eq!(row["accept_date"], "2009-02-06 12:14:14.655");
+        assert_eq!(row["pid"], 14389);
+        assert_eq!(row["request_time"], serde_json::Value::Null);
+        assert_eq!(row["total_time"], 3000);
+        assert_eq!(row["status_code"], serde_json::Value::Null);
+        assert_eq!(row["retried_connections"], 1);
+        assert_eq!(row["captured_request_headers"], "a|b");
+        assert!(out.ends_with(b"}\n"));
+
+        let mut out = vec![];
+        encode_clickhouse_row(&entry, ClickHouseFormat::RowBinary, &mut out);
+        let mut expected = vec![7];
+        expected.extend_from_slice(b"haproxy");
+        expected.extend_from_slice(&[0, 0x35, 0x38, 0, 0]);
+        expected.push(8);
+        expected.extend_from_slice(b"10.0.1.2");
+        expected.extend_from_slice(&[0, 0x25, 0x82]);
+        expected.push(0);
+        expected.extend_from_slice(&1233922454655i64.to_le_bytes());
+        assert_eq!(out[..expected.len()], expected[..]);
+        // four null timers, then Tt, a null status, bytes_read and the empty cookies.
+        let timers = b"\x07<NOSRV>\x01\x01\x01\x01\x00\xb8\x0b\x00\x00\x01\
+                       \x00\x00\x00\x00\x00\x00\x00\x00\x00\x01-\x01-";
+        assert!(out.windows(timers.len()).any(|window| window == timers));
+        assert!(out.ends_with(b"\x03a|b\x00\x0eGET / HTTP/1.1"));
+    }
+
+    #[test]
+    fn inserts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for answer in &["200 OK", "400 Bad Request"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    request.push(line.trim_end().to_string());
+                }
+                assert_eq!(request[0], "POST /?query=INSERT+INTO+logs+FORMAT+JSONEachRow HTTP/1.1");
+                assert!(request.contains(&"X-ClickHouse-User: writer".to_string()));
+                let length: usize = request.iter()
+                    .find_map(|header| header.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                assert_eq!(body.iter().filter(|&&c| c == b'\n').count(), 2);
+
+                let message = "Code: 27. Cannot parse input";
+                write!(&stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                       answer, message.len(), message).unwrap();
+            }
+        });
+
+        let entry = LogEntry::from_bytes(LINE).unwrap();
+        let mut writer = ClickHouseWriter::new(&url, "logs", ClickHouseFormat::JsonEachRow);
+        writer.set_credentials("writer", "s3cret");
+        writer.set_batch_size(2);
+        writer.write(&entry).unwrap();
+        writer.write(&entry).unwrap();
+        writer.flush().unwrap();
+        writer.write(&entry).unwrap();
+        let err = writer.write(&entry).unwrap_err();
+        assert!(err.to_string().ends_with("answered 400: Code: 27. Cannot parse input"));
+        server.join().unwrap();
+    }
+}
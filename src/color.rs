@@ -0,0 +1,71 @@
+use std::str;
+
+use crate::field::Field;
+
+pub const COLOR_RESET: &[u8] = b"\x1b[0m";
+pub const COLOR_GREEN: &[u8] = b"\x1b[32m";
+pub const COLOR_YELLOW: &[u8] = b"\x1b[33m";
+pub const COLOR_RED: &[u8] = b"\x1b[31m";
+pub const COLOR_BOLD_RED: &[u8] = b"\x1b[1;31m";
+
+// the ANSI color to print `content` of `field` in, if it deserves one.
+pub fn color_for(field: &Field, content: &[u8], slow_threshold: i64) -> Option<&'static [u8]> {
+    match *field {
+        Field::StatusCode | Field::StatusClass => match content.first() {
+            Some(b'1') | Some(b'2') | Some(b'3') => Some(COLOR_GREEN),
+            Some(b'4') => Some(COLOR_YELLOW),
+            Some(_) => Some(COLOR_RED),
+            None => None,
+        },
+        Field::RequestTime |
+        Field::QueueTime |
+        Field::ConnectTime |
+        Field::ResponseTime |
+        Field::TotalTime => {
+            // timers are -1 when the corresponding phase never completed, which is as
+            // interesting as a slow one.
+            let millis: i64 = str::from_utf8(content).ok()?.parse().ok()?;
+            if millis < 0 || millis >= slow_threshold {
+                Some(COLOR_RED)
+            } else {
+                None
+            }
+        },
+        Field::TerminationState => {
+            if content.is_empty() || content.iter().all(|&c| c == b'-') {
+                None
+            } else {
+                Some(COLOR_BOLD_RED)
+            }
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{color_for, COLOR_BOLD_RED, COLOR_GREEN, COLOR_RED, COLOR_YELLOW};
+    use crate::field::Field;
+
+    #[test]
+    fn status() {
+        assert_eq!(color_for(&Field::StatusCode, b"200", 1000), Some(COLOR_GREEN));
+        assert_eq!(color_for(&Field::StatusCode, b"404", 1000), Some(COLOR_YELLOW));
+        assert_eq!(color_for(&Field::StatusCode, b"-1", 1000), Some(COLOR_RED));
+        assert_eq!(color_for(&Field::StatusClass, b"5xx", 1000), Some(COLOR_RED));
+    }
+
+    #[test]
+    fn timers() {
+        assert_eq!(color_for(&Field::TotalTime, b"999", 1000), None);
+        assert_eq!(color_for(&Field::TotalTime, b"1000", 1000), Some(COLOR_RED));
+        assert_eq!(color_for(&Field::ConnectTime, b"-1", 1000), Some(COLOR_RED));
+    }
+
+    #[test]
+    fn termination_state() {
+        assert_eq!(color_for(&Field::TerminationState, b"----", 1000), None);
+        assert_eq!(color_for(&Field::TerminationState, b"sD--", 1000), Some(COLOR_BOLD_RED));
+        assert_eq!(color_for(&Field::BackendName, b"static", 1000), None);
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDateTime};
+
+use crate::entry::LogEntry;
+
+// the samples of a connection count, as logged with each entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Gauge {
+    pub samples: u64,
+    pub sum: u64,
+    pub max: u64,
+}
+
+impl Gauge {
+    pub fn add(&mut self, value: u64) {
+        self.samples += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &Gauge) {
+        self.samples += other.samples;
+        self.sum += other.sum;
+        self.max = self.max.max(other.max);
+    }
+
+    // the mean of the samples, 0 without any.
+    pub fn mean(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.samples as f64
+        }
+    }
+}
+
+// the concurrency a frontend saw: actconn, the process-wide connections, and feconn, its own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FrontendConcurrency {
+    pub active: Gauge,
+    pub frontend: Gauge,
+}
+
+// the concurrency a backend saw: beconn, its own connections, and srv_conn, those of whichever of
+// its servers each request went to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BackendConcurrency {
+    pub backend: Gauge,
+    pub server: Gauge,
+}
+
+// the concurrency of every frontend and backend within an interval.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConcurrencyInterval {
+    pub frontends: BTreeMap<String, FrontendConcurrency>,
+    pub backends: BTreeMap<String, BackendConcurrency>,
+}
+
+// the max and mean of the connection counts logged with each entry, per frontend and backend and
+// per interval of `interval` seconds, for capacity planning from logs alone when no history of
+// haproxy's stats was kept. the counts are those haproxy saw when it logged each entry, so the
+// means are weighted by requests rather than time: a busy interval's mean is a good estimate, one
+// with a handful of requests isn't.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyTrends {
+    interval: i64,
+    intervals: BTreeMap<i64, ConcurrencyInterval>,
+}
+
+impl ConcurrencyTrends {
+    pub fn new(interval: i64) -> ConcurrencyTrends {
+        ConcurrencyTrends {
+            interval: interval.max(1),
+            intervals: BTreeMap::new(),
+        }
+    }
+
+    // counts `entry` in the interval it was accepted in, entries whose accept_date doesn't parse
+    // are left out. connection counts which don't parse are left out of their gauge.
+    pub fn add(&mut self, entry: &LogEntry) {
+        let start = match entry.accept_date_time() {
+            Ok(accepted) => {
+                accepted.and_utc().timestamp().div_euclid(self.interval) * self.interval
+            },
+            Err(_) => return,
+        };
+        let interval = self.intervals.entry(start).or_default();
+
+        let frontend = String::from_utf8_lossy(entry.frontend_name).into_owned();
+        let frontend = interval.frontends.entry(frontend).or_default();
+        if let Ok(active) = entry.active_connections() {
+            frontend.active.add(active);
+        }
+        if let Ok(connections) = entry.frontend_connections() {
+            frontend.frontend.add(connections);
+        }
+
+        let backend = String::from_utf8_lossy(entry.backend_name).into_owned();
+        let backend = interval.backends.entry(backend).or_default();
+        if let Ok(connections) = entry.backend_connections() {
+            backend.backend.add(connections);
+        }
+        // <NOSRV> and the like never had a connection to a server.
+        if entry.server_name.first() != Some(&b'<') {
+            if let Ok(connections) = entry.server_connections() {
+                backend.server.add(connections);
+            }
+        }
+    }
+
+    pub fn interval(&self) -> i64 {
+        self.interval
+    }
+
+    // the intervals with any entries, by when they started in the time zone entries were logged
+    // in.
+    pub fn iter(&self) -> impl Iterator<Item = (NaiveDateTime, &ConcurrencyInterval)> {
+        self.intervals.iter().filter_map(|(&start, interval)| {
+            let start = DateTime::from_timestamp(start, 0)?.naive_utc();
+            Some((start, interval))
+        })
+    }
+
+    // every interval together.
+    pub fn total(&self) -> ConcurrencyInterval {
+        let mut total = ConcurrencyInterval::default();
+        for interval in self.intervals.values() {
+            for (name, frontend) in &interval.frontends {
+                let merged = total.frontends.entry(name.clone()).or_default();
+                merged.active.merge(&frontend.active);
+                merged.frontend.merge(&frontend.frontend);
+            }
+            for (name, backend) in &interval.backends {
+                let merged = total.backends.entry(name.clone()).or_default();
+                merged.backend.merge(&backend.backend);
+                merged.server.merge(&backend.server);
+            }
+        }
+        total
+    }
+
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConcurrencyTrends, Gauge};
+    use crate::entry::test::TestLine;
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn trends() {
+        let lines = [
+            TestLine::new().time("12:00:05").connections("10/8/4/2/0"),
+            TestLine::new().time("12:00:40").server("srv2").connections("20/12/6/3/0"),
+            TestLine::new().time("12:00:50").frontend("https-in").backend("dynamic")
+                .connections("21/9/5/5/0"),
+            TestLine::new().time("12:01:10").server("<NOSRV>").connections("4/2/1/0/0"),
+        ].map(|line| line.to_string());
+        let mut trends = ConcurrencyTrends::new(60);
+        for line in &lines {
+            trends.add(&LogEntry::from_bytes(line.as_bytes()).unwrap());
+        }
+        assert_eq!(trends.len(), 2);
+
+        let (start, first) = trends.iter().next().unwrap();
+        assert_eq!(start.to_string(), "2009-02-06 12:00:00");
+        let frontend = first.frontends["http-in"];
+        assert_eq!(frontend.active, Gauge { samples: 2, sum: 30, max: 20 });
+        assert_eq!(frontend.frontend.mean(), 10.0);
+        assert_eq!(first.frontends["https-in"].active.max, 21);
+        let backend = first.backends["static"];
+        assert_eq!((backend.backend.max, backend.server.max), (6, 3));
+        assert_eq!(backend.server.mean(), 2.5);
+
+        let (_, second) = trends.iter().nth(1).unwrap();
+        assert_eq!(second.backends["static"].server, Gauge::default());
+        assert_eq!(second.backends["static"].server.mean(), 0.0);
+
+        let total = trends.total();
+        assert_eq!(total.frontends["http-in"].frontend, Gauge { samples: 3, sum: 22, max: 12 });
+        assert_eq!(total.backends["static"].backend.samples, 3);
+    }
+}
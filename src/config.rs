@@ -0,0 +1,646 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::entry::LogEntry;
+use crate::format::{HTTPLOG_FORMAT, HTTPSLOG_FORMAT, TCPLOG_FORMAT};
+
+// the keywords which start a new section. only the proxies and global/defaults get their own
+// SectionKind, the rest are kept as Other so nothing in a configuration is lost.
+const SECTION_KEYWORDS: &[&str] = &[
+    "global", "defaults", "frontend", "backend", "listen", "userlist", "peers", "resolvers",
+    "mailers", "program", "http-errors", "ring", "cache", "log-forward", "crt-store", "traces",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum SectionKind {
+    Global,
+    Defaults,
+    Frontend,
+    Backend,
+    Listen,
+    Other(String),
+}
+
+impl SectionKind {
+    fn from_keyword(keyword: &str) -> SectionKind {
+        match keyword {
+            "global" => SectionKind::Global,
+            "defaults" => SectionKind::Defaults,
+            "frontend" => SectionKind::Frontend,
+            "backend" => SectionKind::Backend,
+            "listen" => SectionKind::Listen,
+            other => SectionKind::Other(other.to_string()),
+        }
+    }
+
+    // whether the section takes connections, as a frontend and a listen do.
+    pub fn is_frontend(&self) -> bool {
+        matches!(*self, SectionKind::Frontend | SectionKind::Listen)
+    }
+
+    // whether the section forwards to servers, as a backend and a listen do.
+    pub fn is_backend(&self) -> bool {
+        matches!(*self, SectionKind::Backend | SectionKind::Listen)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// one line of a section, split into words the way haproxy does it. the keyword can be more than
+// one word, like `capture request header` or `option httplog`, so it's up to the caller to say how
+// many words to compare with `is`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Directive {
+    line: usize,
+    words: Vec<String>,
+}
+
+impl Directive {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.words[0]
+    }
+
+    // the words after the keyword.
+    pub fn args(&self) -> &[String] {
+        &self.words[1..]
+    }
+
+    // whether the directive starts with `words`, e.g. `is(&["option", "httplog"])`.
+    pub fn is(&self, words: &[&str]) -> bool {
+        self.words.len() >= words.len() && self.words.iter().zip(words).all(|(a, b)| a == b)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Section {
+    kind: SectionKind,
+    name: Option<String>,
+    // the defaults section named with `from`, if any.
+    from: Option<String>,
+    line: usize,
+    directives: Vec<Directive>,
+}
+
+impl Section {
+    pub fn kind(&self) -> &SectionKind {
+        &self.kind
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn directives(&self) -> &[Directive] {
+        &self.directives
+    }
+
+    // every directive starting with `words`, in order.
+    pub fn all<'a>(&'a self, words: &'a [&str]) -> impl Iterator<Item = &'a Directive> + 'a {
+        self.directives.iter().filter(move |directive| directive.is(words))
+    }
+
+    // the last directive starting with `words`, which is the one haproxy goes by for settings
+    // given more than once.
+    pub fn get(&self, words: &[&str]) -> Option<&Directive> {
+        self.directives.iter().rev().find(|directive| directive.is(words))
+    }
+
+    // whether the section has a server named `name`, from a `server` line or one of the servers a
+    // `server-template` makes: `server-template www 1-3 ...` is www1, www2 and www3. servers added
+    // through the runtime api aren't in the configuration.
+    pub fn has_server(&self, name: &str) -> bool {
+        self.all(&["server"]).any(|directive| directive.args().first().is_some_and(|n| n == name))
+            || self.all(&["server-template"]).any(|directive| {
+                let (prefix, range) = match directive.args() {
+                    [prefix, range, ..] => (prefix, range),
+                    _ => return false,
+                };
+                let (first, last) = range.split_once('-').unwrap_or(("1", range));
+                let number = name.strip_prefix(prefix.as_str()).and_then(|n| n.parse::<u32>().ok());
+                match (number, first.parse::<u32>(), last.parse::<u32>()) {
+                    (Some(number), Ok(first), Ok(last)) => (first..=last).contains(&number),
+                    _ => false,
+                }
+            })
+    }
+
+    // the capture slots the section declares, in the order haproxy logs them. `capture request
+    // header`, `http-request capture ... len` and `declare capture request` all add a slot to the
+    // same list, as do their response counterparts. a declared slot is named by the first
+    // `http-request capture ... id` which fills it.
+    pub fn captures(&self) -> Captures {
+        let mut captures = Captures::default();
+        for directive in &self.directives {
+            let words: Vec<&str> = directive.words.iter().map(|word| word.as_str()).collect();
+            let length = |i: usize| words.get(i).and_then(|length| length.parse().ok());
+            match words[..] {
+                ["capture", "request", "header", name, ..] => {
+                    captures.request.push(CaptureSlot::new(name, length(5)));
+                },
+                ["capture", "response", "header", name, ..] => {
+                    captures.response.push(CaptureSlot::new(name, length(5)));
+                },
+                ["declare", "capture", "request", ..] => {
+                    captures.request.push(CaptureSlot::new("", length(4)));
+                },
+                ["declare", "capture", "response", ..] => {
+                    captures.response.push(CaptureSlot::new("", length(4)));
+                },
+                ["http-request", "capture", sample, "len", ..] => {
+                    captures.request.push(CaptureSlot::new(sample, length(4)));
+                },
+                [action, "capture", sample, "id", id, ..] => {
+                    let slots = match action {
+                        "http-request" => &mut captures.request,
+                        "http-response" => &mut captures.response,
+                        _ => continue,
+                    };
+                    if let Some(slot) = id.parse().ok().and_then(|id: usize| slots.get_mut(id)) {
+                        if slot.name.is_empty() {
+                            slot.name = sample.to_string();
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+        captures
+    }
+}
+
+// one captured value: a header name, or the sample expression of an `http-request capture`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CaptureSlot {
+    pub name: String,
+    pub length: Option<usize>,
+}
+
+impl CaptureSlot {
+    fn new(name: &str, length: Option<usize>) -> CaptureSlot {
+        CaptureSlot { name: name.to_string(), length }
+    }
+}
+
+// the capture slots of a frontend. the log line has a `{...}` block for each of the two lists
+// which isn't empty, request first, which is why captured_header[0] can be either.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Captures {
+    pub request: Vec<CaptureSlot>,
+    pub response: Vec<CaptureSlot>,
+}
+
+impl Captures {
+    // which block of LogEntry::captures the request captures are in.
+    pub fn request_block(&self) -> Option<usize> {
+        if self.request.is_empty() { None } else { Some(0) }
+    }
+
+    pub fn response_block(&self) -> Option<usize> {
+        match (self.request.is_empty(), self.response.is_empty()) {
+            (_, true) => None,
+            (true, false) => Some(0),
+            (false, false) => Some(1),
+        }
+    }
+
+    // where a captured request header is, as the indices of Field::CapturedHeader. header names
+    // are compared case-insensitively.
+    pub fn request_header(&self, name: &str) -> Option<(usize, usize)> {
+        let j = self.request.iter().position(|slot| slot.name.eq_ignore_ascii_case(name))?;
+        Some((self.request_block()?, j))
+    }
+
+    pub fn response_header(&self, name: &str) -> Option<(usize, usize)> {
+        let j = self.response.iter().position(|slot| slot.name.eq_ignore_ascii_case(name))?;
+        Some((self.response_block()?, j))
+    }
+
+    // what captured_header[i][j] holds, as "request_header[Host]" or "response_header[...]".
+    pub fn describe(&self, i: usize, j: usize) -> Option<String> {
+        if Some(i) == self.request_block() {
+            Some(format!("request_header[{}]", self.request.get(j)?.name))
+        } else if Some(i) == self.response_block() {
+            Some(format!("response_header[{}]", self.response.get(j)?.name))
+        } else {
+            None
+        }
+    }
+}
+
+// a haproxy configuration file, as a list of sections in the order they're written. nothing is
+// checked beyond the syntax, each directive is kept as the words haproxy would split it into.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Config {
+    sections: Vec<Section>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut sections: Vec<Section> = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let number = i + 1;
+            let words = split_words(line).map_err(|message| ConfigError { line: number, message })?;
+            if words.is_empty() {
+                continue;
+            }
+
+            if SECTION_KEYWORDS.contains(&words[0].as_str()) {
+                let from = match words.get(2).map(|word| word.as_str()) {
+                    Some("from") => words.get(3).cloned(),
+                    _ => None,
+                };
+                sections.push(Section {
+                    kind: SectionKind::from_keyword(&words[0]),
+                    name: words.get(1).cloned(),
+                    from,
+                    line: number,
+                    directives: vec![],
+                });
+                continue;
+            }
+
+            match sections.last_mut() {
+                Some(section) => section.directives.push(Directive { line: number, words }),
+                None => {
+                    let message = format!("'{}' is outside of any section", words[0]);
+                    return Err(ConfigError { line: number, message });
+                },
+            }
+        }
+        Ok(Config { sections })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let text = fs::read_to_string(path)?;
+        Config::parse(&text)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn global(&self) -> Option<&Section> {
+        self.sections.iter().find(|section| section.kind == SectionKind::Global)
+    }
+
+    // the frontends and listen sections, the proxies which write log lines.
+    pub fn frontends(&self) -> impl Iterator<Item = &Section> {
+        self.sections.iter().filter(|section| section.kind.is_frontend())
+    }
+
+    // the backends and listen sections.
+    pub fn backends(&self) -> impl Iterator<Item = &Section> {
+        self.sections.iter().filter(|section| section.kind.is_backend())
+    }
+
+    pub fn frontend(&self, name: &str) -> Option<&Section> {
+        self.frontends().find(|section| section.name() == Some(name))
+    }
+
+    pub fn backend(&self, name: &str) -> Option<&Section> {
+        self.backends().find(|section| section.name() == Some(name))
+    }
+
+    // the defaults section `section` takes its settings from: the one it names with `from`, or
+    // else the last one before it.
+    pub fn defaults_for(&self, section: &Section) -> Option<&Section> {
+        let mut defaults = self.sections.iter().rev().filter(|s| s.kind == SectionKind::Defaults);
+        match section.from {
+            Some(ref from) => defaults.find(|s| s.name.as_ref() == Some(from)),
+            None => defaults.find(|s| s.line < section.line),
+        }
+    }
+
+    // the last directive starting with `words` in `section`, or in its defaults if it has none.
+    pub fn setting<'a>(&'a self, section: &'a Section, words: &[&str]) -> Option<&'a Directive> {
+        section.get(words).or_else(|| self.defaults_for(section)?.get(words))
+    }
+
+    // the log-format `section` logs with, from its last `log-format`, `option httplog`, `option
+    // httpslog` or `option tcplog`, or else its defaults. `option httplog clf` has no format here,
+    // its unquoted request can't be told apart from the fields around it.
+    pub fn log_format(&self, section: &Section) -> Option<String> {
+        let find = |section: &Section| {
+            section.directives.iter().rev().find(|directive| {
+                directive.is(&["log-format"]) || directive.is(&["option", "httplog"])
+                    || directive.is(&["option", "httpslog"]) || directive.is(&["option", "tcplog"])
+            }).cloned()
+        };
+        let directive = find(section).or_else(|| find(self.defaults_for(section)?))?;
+        match directive.words.get(1).map(|word| word.as_str()) {
+            _ if directive.is(&["option", "httplog", "clf"]) => None,
+            Some("httplog") => Some(HTTPLOG_FORMAT.to_string()),
+            Some("httpslog") => Some(HTTPSLOG_FORMAT.to_string()),
+            Some("tcplog") => Some(TCPLOG_FORMAT.to_string()),
+            _ => Some(directive.args().join(" ")),
+        }
+    }
+}
+
+// a name in a log entry which the configuration doesn't have, as when the entry was logged by an
+// older configuration or by another load balancer than the one the configuration is for.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum UnknownName {
+    Frontend(String),
+    Backend(String),
+    // the backend, which is in the configuration, and the server.
+    Server(String, String),
+}
+
+impl fmt::Display for UnknownName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnknownName::Frontend(ref name) => write!(f, "no frontend '{}'", name),
+            UnknownName::Backend(ref name) => write!(f, "no backend '{}'", name),
+            UnknownName::Server(ref backend, ref server) => {
+                write!(f, "no server '{}' in backend '{}'", server, backend)
+            },
+        }
+    }
+}
+
+impl Config {
+    // the frontend, backend and server names of `entry` which aren't in the configuration. the
+    // backend is the frontend when a request wasn't sent to one, and names in angle brackets,
+    // like <NOSRV> and <STATS>, are haproxy's own rather than the configuration's.
+    pub fn unknown_names(&self, entry: &LogEntry) -> Vec<UnknownName> {
+        let name = |field: &[u8]| String::from_utf8_lossy(field).into_owned();
+        // a '~' after the frontend's name means the connection was over ssl.
+        let frontend = name(entry.frontend_name);
+        let frontend = frontend.trim_end_matches('~');
+        let backend = name(entry.backend_name);
+        let server = name(entry.server_name);
+
+        let mut unknown = vec![];
+        if self.frontend(frontend).is_none() {
+            unknown.push(UnknownName::Frontend(frontend.to_string()));
+        }
+        match self.backend(&backend) {
+            Some(section) => {
+                if !server.starts_with('<') && !section.has_server(&server) {
+                    unknown.push(UnknownName::Server(backend, server));
+                }
+            },
+            None if backend == frontend || backend.starts_with('<') => {},
+            None => unknown.push(UnknownName::Backend(backend)),
+        }
+        unknown
+    }
+}
+
+// split a line into words as haproxy does: words are separated by spaces or tabs, a '#' outside
+// quotes starts a comment, a backslash escapes the next character outside single quotes, and
+// quotes join text with spaces into one word. environment variables in double quotes are left as
+// they are.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    // whether `word` has started, even if it's still empty as `""` is.
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => {
+                match chars.next() {
+                    Some('n') => word.push('\n'),
+                    Some('r') => word.push('\r'),
+                    Some('t') => word.push('\t'),
+                    Some('x') => {
+                        let hex: String = chars.by_ref().take(2).collect();
+                        let byte = u8::from_str_radix(&hex, 16)
+                            .map_err(|_| format!("invalid escape '\\x{}'", hex))?;
+                        word.push(char::from(byte));
+                    },
+                    Some(c) => word.push(c),
+                    None => return Err("a backslash ends the line".to_string()),
+                }
+                in_word = true;
+            },
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            },
+            (None, '#') => break,
+            (None, ' ') | (None, '\t') => {
+                if in_word {
+                    words.push(word.split_off(0));
+                    in_word = false;
+                }
+            },
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            },
+        }
+    }
+    if let Some(quote) = quote {
+        let quote = if quote == '"' { "double" } else { "single" };
+        return Err(format!("unterminated {} quote", quote));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_words, CaptureSlot, Config, ConfigError, SectionKind, UnknownName};
+    use crate::entry::test::TestLine;
+    use crate::entry::LogEntry;
+
+    static CONFIG: &str = r#"
+# comment
+global
+    log /dev/log local0
+    stats socket /run/haproxy.sock mode 660 level admin
+
+defaults
+    mode http
+    log global
+    option httplog
+    timeout client 30s
+
+frontend http-in
+    bind :80
+    capture request header Host len 32
+    log-format "%ci:%cp [%tr] %ft %b/%s %ST %B"   # custom
+    default_backend static
+
+defaults tcp
+    mode tcp
+
+backend static
+    server srv1 10.0.0.1:80 check
+    server srv2 10.0.0.2:80 check
+
+listen stats from tcp
+    bind :8404
+    option httplog
+    option tcplog
+"#;
+
+    #[test]
+    fn sections() {
+        let config = Config::parse(CONFIG).unwrap();
+        let kinds: Vec<&SectionKind> = config.sections().iter().map(|s| s.kind()).collect();
+        assert_eq!(kinds, [&SectionKind::Global, &SectionKind::Defaults, &SectionKind::Frontend,
+                           &SectionKind::Defaults, &SectionKind::Backend, &SectionKind::Listen]);
+
+        let names: Vec<_> = config.frontends().map(|s| s.name().unwrap()).collect();
+        assert_eq!(names, ["http-in", "stats"]);
+        let names: Vec<_> = config.backends().map(|s| s.name().unwrap()).collect();
+        assert_eq!(names, ["static", "stats"]);
+
+        let frontend = config.frontend("http-in").unwrap();
+        assert_eq!(frontend.line(), 13);
+        let log_format = frontend.get(&["log-format"]).unwrap();
+        assert_eq!(log_format.args(), ["%ci:%cp [%tr] %ft %b/%s %ST %B"]);
+        assert_eq!(log_format.line(), 16);
+        assert_eq!(frontend.get(&["capture", "request", "header"]).unwrap().args()[2], "Host");
+
+        let servers: Vec<_> = config.backend("static").unwrap().all(&["server"])
+            .map(|directive| directive.args()[0].as_str())
+            .collect();
+        assert_eq!(servers, ["srv1", "srv2"]);
+
+        // settings fall back to the defaults section before, or the one named with `from`.
+        assert_eq!(config.setting(frontend, &["mode"]).unwrap().args(), ["http"]);
+        let stats = config.frontend("stats").unwrap();
+        assert_eq!(config.setting(stats, &["mode"]).unwrap().args(), ["tcp"]);
+        assert!(config.setting(stats, &["timeout", "client"]).is_none());
+        assert_eq!(stats.get(&["option"]).unwrap().args(), ["tcplog"]);
+
+        assert_eq!(config.global().unwrap().get(&["stats", "socket"]).unwrap().words()[2],
+                   "/run/haproxy.sock");
+        assert!(config.frontend("static").is_none());
+    }
+
+    #[test]
+    fn captures() {
+        let config = Config::parse(concat!(
+            "frontend www\n",
+            "    capture request header Host len 32\n",
+            "    declare capture request len 64\n",
+            "    http-request capture req.hdr(User-Agent) len 100 if { path /ua }\n",
+            "    http-request capture req.hdr(X-Id) id 1\n",
+            "    capture response header Content-Type len 20\n",
+            "frontend api\n",
+            "    capture response header Location len 200\n",
+            "frontend none\n",
+        )).unwrap();
+
+        let www = config.frontend("www").unwrap().captures();
+        let names: Vec<&str> = www.request.iter().map(|slot| slot.name.as_str()).collect();
+        assert_eq!(names, ["Host", "req.hdr(X-Id)", "req.hdr(User-Agent)"]);
+        assert_eq!(www.response, [CaptureSlot { name: "Content-Type".into(), length: Some(20) }]);
+        assert_eq!(www.request_header("host"), Some((0, 0)));
+        assert_eq!(www.response_header("Content-Type"), Some((1, 0)));
+        assert_eq!(www.describe(0, 2).unwrap(), "request_header[req.hdr(User-Agent)]");
+        assert_eq!(www.describe(1, 0).unwrap(), "response_header[Content-Type]");
+        assert!(www.describe(1, 1).is_none());
+
+        // without request captures, the response ones are the first block.
+        let api = config.frontend("api").unwrap().captures();
+        assert!(api.request.is_empty());
+        assert_eq!(api.response_header("Location"), Some((0, 0)));
+        assert_eq!(api.describe(0, 0).unwrap(), "response_header[Location]");
+        assert!(api.describe(1, 0).is_none());
+
+        let none = config.frontend("none").unwrap().captures();
+        assert_eq!((none.request_block(), none.response_block()), (None, None));
+    }
+
+    #[test]
+    fn unknown_names() {
+        let config = Config::parse(&format!("{}{}", CONFIG, concat!(
+            "backend dynamic\n",
+            "    server-template web 2-4 web.example.com:80\n",
+            "    server-template api 3 api.example.com:80\n",
+        ))).unwrap();
+        let check = |frontend: &str, target: &str| {
+            let (backend, server) = target.split_once('/').unwrap();
+            let line = TestLine::new().frontend(frontend).backend(backend).server(server)
+                .to_string();
+            config.unknown_names(&LogEntry::from_bytes(line.as_bytes()).unwrap())
+        };
+
+        assert!(check("http-in", "static/srv1").is_empty());
+        assert!(check("http-in~", "static/srv2").is_empty());
+        assert!(check("http-in", "http-in/<NOSRV>").is_empty());
+        assert!(check("stats", "stats/<STATS>").is_empty());
+        assert!(check("http-in", "dynamic/web2").is_empty());
+        assert!(check("http-in", "dynamic/api3").is_empty());
+        assert_eq!(check("http-in", "dynamic/web5"),
+                   [UnknownName::Server("dynamic".into(), "web5".into())]);
+        assert_eq!(check("http-in", "static/srv3")[0].to_string(),
+                   "no server 'srv3' in backend 'static'");
+        assert_eq!(check("www", "old/srv1"),
+                   [UnknownName::Frontend("www".into()), UnknownName::Backend("old".into())]);
+    }
+
+    #[test]
+    fn errors() {
+        let message = "'bind' is outside of any section".to_string();
+        assert_eq!(Config::parse("\n  bind :80\n"), Err(ConfigError { line: 2, message }));
+        // it's an Error, so `?` takes it into a Box<dyn Error>.
+        let parse = |text| -> Result<Config, Box<dyn std::error::Error>> {
+            Ok(Config::parse(text)?)
+        };
+        assert_eq!(parse("global\n  log \"/dev/log\n").unwrap_err().to_string(),
+                   "line 2: unterminated double quote");
+        assert!(Config::parse("").unwrap().sections().is_empty());
+    }
+
+    #[test]
+    fn words() {
+        let split = |line: &str| split_words(line).unwrap();
+        assert_eq!(split("  a\tb  c # d"), ["a", "b", "c"]);
+        assert_eq!(split(r#"log-format %ci\ %cp "x y"z 'a "b" \c' "#), ["log-format", "%ci %cp",
+                                                                        "x yz", r#"a "b" \c"#]);
+        assert_eq!(split(r#"a "" '' b\#c \x41"#), ["a", "", "", "b#c", "A"]);
+        assert_eq!(split(r#""a # b" "\"" "#), ["a # b", "\""]);
+        assert!(split("# all comment").is_empty());
+        assert!(split_words("a \\").is_err());
+        assert!(split_words("a 'b").is_err());
+    }
+}
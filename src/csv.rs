@@ -0,0 +1,75 @@
+use std::io;
+use std::io::Write;
+
+use crate::entry::{LogEntry, HEADER_FIELD_NAMES};
+
+// writes entries as csv rows through a csv::Writer, which takes care of quoting, after a header
+// row of LogEntry's field names. fields are written as they are in the line, bytes and all.
+pub struct CsvLogWriter<W: Write> {
+    writer: ::csv::Writer<W>,
+    header_written: bool,
+}
+
+impl<W: Write> CsvLogWriter<W> {
+    pub fn new(out: W) -> CsvLogWriter<W> {
+        CsvLogWriter::from_writer(::csv::Writer::from_writer(out))
+    }
+
+    // a writer set up by the caller, e.g. with another delimiter. it shouldn't have written
+    // anything yet, the header is written before the first entry.
+    pub fn from_writer(writer: ::csv::Writer<W>) -> CsvLogWriter<W> {
+        CsvLogWriter { writer, header_written: false }
+    }
+
+    // the header row, which `write` writes before the first entry.
+    pub fn header() -> Vec<&'static str> {
+        let mut header = HEADER_FIELD_NAMES.to_vec();
+        header.extend(["captured_request_headers", "captured_response_headers", "http_request"]);
+        header
+    }
+
+    pub fn write(&mut self, entry: &LogEntry) -> ::csv::Result<()> {
+        if !self.header_written {
+            self.writer.write_record(CsvLogWriter::<W>::header())?;
+            self.header_written = true;
+        }
+        let fields = entry.header_fields();
+        self.writer.write_record(fields.iter().chain(&entry.captures).chain([&entry.http_request]))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    // flushes what's buffered and hands back where the rows were written.
+    pub fn into_inner(self) -> io::Result<W> {
+        self.writer.into_inner().map_err(|err| err.into_error())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CsvLogWriter;
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn quoting() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                     {a,b|\"c\"} {} \"GET /?x=1,2 HTTP/1.1\"";
+        let mut writer = CsvLogWriter::new(vec![]);
+        let entry = LogEntry::from_bytes(line).unwrap();
+        writer.write(&entry).unwrap();
+        writer.write(&entry).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("process_name,pid,client_ip,client_port,accept_date,"));
+        assert!(lines[0].ends_with(",captured_response_headers,http_request"));
+        assert_eq!(lines[0].split(',').count(), 28);
+        assert!(lines[1].starts_with("haproxy,14389,10.0.1.2,33317,06/Feb/2009:12:14:14.655,"));
+        assert!(lines[1].ends_with(",\"a,b|\"\"c\"\"\",,\"GET /?x=1,2 HTTP/1.1\""));
+        assert_eq!(lines[1], lines[2]);
+    }
+}
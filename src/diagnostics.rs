@@ -0,0 +1,215 @@
+// the answers to `show fd` and `show activity`, which are about haproxy itself rather than the
+// traffic: what each file descriptor is for and how its threads' polling loops are doing.
+
+// one file descriptor from `show fd`, from a line like
+// `  12 : st=0x0121(cl heopi W:sRa R:srA) ... iocb=0x55f0(sock_conn_iocb) ... fe=http-in mux=H1`.
+// every `name=value` is in `values` in order, as the fields differ with what the fd is for and
+// between versions.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileDescriptor {
+    pub fd: u32,
+    // the state flags, `st=` with their decoding in parentheses.
+    pub state: String,
+    // the function haproxy calls when the fd is ready, e.g. sock_conn_iocb or listener_accept.
+    pub handler: Option<String>,
+    pub frontend: Option<String>,
+    pub backend: Option<String>,
+    pub values: Vec<(String, String)>,
+}
+
+impl FileDescriptor {
+    pub fn parse(line: &str) -> Option<FileDescriptor> {
+        let (fd, rest) = line.split_once(':')?;
+        let values = pairs(rest);
+        let value = |name: &str| {
+            values.iter().find(|(n, _)| n == name).map(|(_, value)| value.to_string())
+        };
+        // `iocb=0x55f0c0a1b2c0(sock_conn_iocb)`.
+        let handler = value("iocb").and_then(|iocb| {
+            let (_, name) = iocb.split_once('(')?;
+            Some(name.trim_end_matches(')').to_string())
+        });
+        Some(FileDescriptor {
+            fd: fd.trim().parse().ok()?,
+            state: value("st").unwrap_or_default(),
+            handler,
+            frontend: value("fe"),
+            backend: value("be"),
+            values,
+        })
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+// the `name=value` words of a line, where a value can have spaces in parentheses. words without
+// a '=', such as the flags newer versions put at the end, aren't pairs.
+fn pairs(line: &str) -> Vec<(String, String)> {
+    let mut words = vec![];
+    let mut start = None;
+    let mut depth = 0u32;
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ' ' | '\t' if depth == 0 => {
+                if let Some(start) = start.take() {
+                    words.push(&line[start..i]);
+                }
+                continue;
+            },
+            _ => {},
+        }
+        start.get_or_insert(i);
+    }
+    words.extend(start.map(|start| &line[start..]));
+    words.into_iter()
+        .filter_map(|word| word.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+// the file descriptors in the answer to `show fd`, or None if a line isn't one, which is what an
+// error message would look like.
+pub fn parse_fds(response: &str) -> Option<Vec<FileDescriptor>> {
+    response.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(FileDescriptor::parse)
+        .collect()
+}
+
+// the answer to `show activity`. counters are the total and then each thread's in brackets, like
+// `loops: 6391 [ 3195 3196 ]`, older versions only have each thread's.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Activity {
+    // how many times the threads went around their polling loop.
+    pub loops: Option<u64>,
+    pub context_switches: Option<u64>,
+    // how many tasks and tasklets were run.
+    pub task_switches: Option<u64>,
+    // how many times a thread found its run queue empty, and how many times too full to finish.
+    pub empty_run_queues: Option<u64>,
+    pub long_run_queues: Option<u64>,
+    // how many times a poll returned with fds ready, and how many times it timed out.
+    pub poll_io: Option<u64>,
+    pub poll_expired: Option<u64>,
+    pub stream_calls: Option<u64>,
+    pub accepted: Option<u64>,
+    pub values: Vec<(String, String)>,
+}
+
+impl Activity {
+    pub fn parse(response: &str) -> Activity {
+        let values: Vec<(String, String)> = response.lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_string(), value.trim().to_string()))
+            })
+            .collect();
+        let mut activity = Activity {
+            loops: None,
+            context_switches: None,
+            task_switches: None,
+            empty_run_queues: None,
+            long_run_queues: None,
+            poll_io: None,
+            poll_expired: None,
+            stream_calls: None,
+            accepted: None,
+            values,
+        };
+        activity.loops = activity.total("loops");
+        activity.context_switches = activity.total("ctxsw");
+        activity.task_switches = activity.total("tasksw");
+        activity.empty_run_queues = activity.total("empty_rq");
+        activity.long_run_queues = activity.total("long_rq");
+        activity.poll_io = activity.total("poll_io");
+        activity.poll_expired = activity.total("poll_exp");
+        activity.stream_calls = activity.total("stream_calls");
+        activity.accepted = activity.total("accepted");
+        activity
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    // a counter's total, which for versions without one is the sum of each thread's.
+    pub fn total(&self, name: &str) -> Option<u64> {
+        let value = self.value(name)?;
+        if value.contains('[') {
+            value.split_whitespace().next()?.parse().ok()
+        } else {
+            value.split_whitespace().map(|count| count.parse::<u64>().ok()).sum()
+        }
+    }
+
+    // each thread's count of a counter, in thread order.
+    pub fn per_thread(&self, name: &str) -> Vec<u64> {
+        let value = self.value(name).unwrap_or("");
+        let counts = match value.split_once('[') {
+            Some((_, counts)) => counts.trim_end_matches(']'),
+            None => value,
+        };
+        counts.split_whitespace().filter_map(|count| count.parse().ok()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_fds, Activity};
+
+    #[test]
+    fn fds() {
+        let fds = parse_fds("\
+    5 : st=0x000121(cl heopi W:sRa R:srA) ref=0x0 gid=0 tmask=0x1 umask=0x0 prmsk=0x1 \
+pwmsk=0x0 owner=0x55d0c0a1b2c0 iocb=0x55d0c09e1f20(listener_accept) back=0 l.st=RDY \
+fe=http-in
+   12 : st=0x000122(cl heopI W:sRa R:srA) ref=0x0 gid=0 tmask=0x1 umask=0x0 owner=0x7f10 \
+iocb=0x55d0c09e2a10(sock_conn_iocb) back=1 cflg=0x00000300 fam=ipv4 rport=80 px=static \
+mux=H1 ctx=0x7f20 xprt=RAW >
+").unwrap();
+        assert_eq!(fds.len(), 2);
+        assert_eq!(fds[0].fd, 5);
+        assert_eq!(fds[0].state, "0x000121(cl heopi W:sRa R:srA)");
+        assert_eq!(fds[0].handler.as_deref(), Some("listener_accept"));
+        assert_eq!(fds[0].frontend.as_deref(), Some("http-in"));
+        assert_eq!(fds[1].handler.as_deref(), Some("sock_conn_iocb"));
+        assert_eq!(fds[1].value("px"), Some("static"));
+        assert_eq!(fds[1].value("xprt"), Some("RAW"));
+        assert_eq!(fds[1].backend, None);
+        assert!(parse_fds("Permission denied\n").is_none());
+    }
+
+    #[test]
+    fn activity() {
+        let activity = Activity::parse("\
+thread_id: 1 (1..2)
+date_now: 1689000000.123456
+ctxsw: 12345 [ 6000 6345 ]
+tasksw: 2345 [ 1000 1345 ]
+empty_rq: 123 [ 60 63 ]
+long_rq: 0 [ 0 0 ]
+loops: 6391 [ 3195 3196 ]
+poll_io: 4000 [ 2000 2000 ]
+avg_loop_us: 12 [ 10 14 ]
+");
+        assert_eq!(activity.loops, Some(6391));
+        assert_eq!(activity.context_switches, Some(12345));
+        assert_eq!(activity.task_switches, Some(2345));
+        assert_eq!(activity.long_run_queues, Some(0));
+        assert_eq!(activity.poll_expired, None);
+        assert_eq!(activity.per_thread("loops"), vec![3195, 3196]);
+        assert_eq!(activity.total("avg_loop_us"), Some(12));
+        assert_eq!(activity.value("thread_id"), Some("1 (1..2)"));
+
+        // before there were totals.
+        let activity = Activity::parse("thread_id: 0\nloops: 3195 3196\n");
+        assert_eq!(activity.loops, Some(6391));
+        assert_eq!(activity.per_thread("loops"), vec![3195, 3196]);
+    }
+}
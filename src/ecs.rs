@@ -0,0 +1,184 @@
+use std::io;
+use std::io::Write;
+use std::net::IpAddr;
+use std::str;
+
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde_json::{json, Value};
+
+use crate::entry::LogEntry;
+use crate::integer::parse_field;
+
+// the version of the Elastic Common Schema ecs_document follows.
+pub const ECS_VERSION: &str = "8.11.0";
+
+// `entry` as an Elastic Common Schema document, for Elasticsearch or OpenSearch to take in as it
+// is. accept_date is taken to be in `timezone` and becomes @timestamp, Tt becomes event.duration in
+// nanoseconds, and the request is split into http.* and url.*. what ECS has no field for goes under
+// haproxy.* with the names Filebeat's haproxy module uses, so its dashboards work too. fields
+// haproxy didn't log, like the timers of a request which never got that far, are left out.
+pub fn ecs_document<Tz: TimeZone>(entry: &LogEntry, timezone: &Tz) -> Value {
+    let status = parse_field(entry.status_code);
+    let outcome = match status {
+        Some(100..=399) => "success",
+        Some(400..=599) => "failure",
+        _ => "unknown",
+    };
+    let mut document = json!({
+        "ecs": {"version": ECS_VERSION},
+        "event": {
+            "kind": "event",
+            "category": ["web"],
+            "type": ["access"],
+            "outcome": outcome,
+            "module": "haproxy",
+        },
+        "process": {"name": text(entry.process_name)},
+        "source": {"address": text(entry.client_ip)},
+        "haproxy": {
+            "frontend_name": text(entry.frontend_name),
+            "backend_name": text(entry.backend_name),
+            "server_name": text(entry.server_name),
+            "termination_state": text(entry.termination_state),
+            "http": {"request": {"raw_request_line": text(entry.http_request)}},
+        },
+    });
+
+    let accepted = entry.accept_date_time().ok()
+        .and_then(|accepted| timezone.from_local_datetime(&accepted).earliest());
+    if let Some(accepted) = accepted {
+        let timestamp = accepted.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Millis, true);
+        document["@timestamp"] = timestamp.into();
+    }
+    if let Some(total) = timer(entry.total_time) {
+        document["event"]["duration"] = (total * 1_000_000).into();
+    }
+    set(&mut document["process"], "pid", parse_field(entry.pid));
+    if str::from_utf8(entry.client_ip).is_ok_and(|ip| ip.parse::<IpAddr>().is_ok()) {
+        document["source"]["ip"] = text(entry.client_ip).into();
+    }
+    set(&mut document["source"], "port", parse_field(entry.client_port));
+
+    if let Some(method) = entry.http_method() {
+        document["http"]["request"]["method"] = text(method).into();
+    }
+    if let Some(version) = entry.http_version() {
+        let version = version.strip_prefix(b"HTTP/").unwrap_or(version);
+        document["http"]["version"] = text(version).into();
+    }
+    set(&mut document["http"], "response.status_code", status.filter(|&status| status >= 0));
+    if let Ok(bytes) = entry.bytes_read() {
+        document["http"]["response"]["body"]["bytes"] = bytes.into();
+    }
+    if let Some(uri) = entry.http_uri() {
+        let mut url = json!({"original": text(uri)});
+        let mut parts = uri.splitn(2, |&c| c == b'?');
+        url["path"] = text(parts.next().unwrap_or(b"")).into();
+        if let Some(query) = parts.next() {
+            url["query"] = text(query).into();
+        }
+        document["url"] = url;
+    }
+
+    let haproxy = &mut document["haproxy"];
+    set(haproxy, "http.request.time_wait_ms", timer(entry.request_time));
+    set(haproxy, "total_waiting_time_ms", timer(entry.queue_time));
+    set(haproxy, "connection_wait_time_ms", timer(entry.connect_time));
+    set(haproxy, "http.request.time_wait_without_data_ms", timer(entry.response_time));
+    set(haproxy, "connections.active", parse_field(entry.active_connections));
+    set(haproxy, "connections.frontend", parse_field(entry.frontend_connections));
+    set(haproxy, "connections.backend", parse_field(entry.backend_connections));
+    set(haproxy, "connections.server", parse_field(entry.server_connections));
+    set(haproxy, "connections.retries", parse_field(entry.retried_connections));
+    set(haproxy, "server_queue", parse_field(entry.server_queue));
+    set(haproxy, "backend_queue", parse_field(entry.backend_queue));
+    for (field, name) in [(entry.captured_request_cookie, "request"),
+                          (entry.captured_response_cookie, "response")] {
+        if field != b"-" {
+            haproxy["http"][name]["captured_cookie"] = text(field).into();
+        }
+    }
+    for (captures, name) in [(entry.captures[0], "request"), (entry.captures[1], "response")] {
+        if !captures.is_empty() {
+            let headers: Vec<String> = captures.split(|&c| c == b'|').map(text).collect();
+            haproxy["http"][name]["captured_headers"] = headers.into();
+        }
+    }
+    document
+}
+
+// writes ecs_document to `out` on a line of its own, for JSON lines output.
+pub fn write_entry_ecs<W: Write, Tz: TimeZone>(entry: &LogEntry, timezone: &Tz, out: &mut W)
+                                               -> io::Result<()> {
+    serde_json::to_writer(&mut *out, &ecs_document(entry, timezone))?;
+    out.write_all(b"\n")
+}
+
+// sets the member at a dotted `path` under `object` when there's a value for it.
+fn set(object: &mut Value, path: &str, value: Option<i64>) {
+    if let Some(value) = value {
+        let mut object = object;
+        for part in path.split('.') {
+            object = &mut object[part];
+        }
+        *object = value.into();
+    }
+}
+
+// a timer, which haproxy logs as -1 when the request never got that far.
+fn timer(field: &[u8]) -> Option<i64> {
+    parse_field(field).filter(|&millis| millis >= 0)
+}
+
+fn text(field: &[u8]) -> String {
+    String::from_utf8_lossy(field).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::ecs_document;
+    use crate::entry::LogEntry;
+    use chrono::{FixedOffset, Utc};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn documents() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 10/0/30/69/109 404 2750 - - ---- 1/1/1/1/+1 0/0 \
+                     {1wt.eu|curl} \"GET /missing?x=1 HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let document = ecs_document(&entry, &Utc);
+        assert_eq!(document["@timestamp"], "2009-02-06T12:14:14.655Z");
+        assert_eq!(document["event"]["duration"], 109_000_000);
+        assert_eq!(document["event"]["outcome"], "failure");
+        assert_eq!(document["source"], json!({"address": "10.0.1.2", "ip": "10.0.1.2",
+                                               "port": 33317}));
+        assert_eq!(document["http"], json!({
+            "request": {"method": "GET"},
+            "response": {"status_code": 404, "body": {"bytes": 2750}},
+            "version": "1.1",
+        }));
+        assert_eq!(document["url"], json!({"original": "/missing?x=1", "path": "/missing",
+                                            "query": "x=1"}));
+        assert_eq!(document["haproxy"]["connections"]["retries"], 1);
+        assert_eq!(document["haproxy"]["http"]["request"]["time_wait_ms"], 10);
+        assert_eq!(document["haproxy"]["http"]["request"]["captured_headers"],
+                   json!(["1wt.eu", "curl"]));
+        assert_eq!(document["haproxy"]["http"]["request"]["captured_cookie"], Value::Null);
+
+        let paris = FixedOffset::east_opt(3600).unwrap();
+        assert_eq!(ecs_document(&entry, &paris)["@timestamp"], "2009-02-06T11:14:14.655Z");
+    }
+
+    #[test]
+    fn unfinished() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/<NOSRV> -1/-1/-1/-1/+3000 -1 0 - - CQ-- 1/1/1/0/0 0/5 \
+                     \"GET / HTTP/1.1\"";
+        let document = ecs_document(&LogEntry::from_bytes(line).unwrap(), &Utc);
+        assert_eq!(document["event"]["duration"], 3_000_000_000i64);
+        assert_eq!(document["event"]["outcome"], "unknown");
+        assert_eq!(document["http"]["response"], json!({"body": {"bytes": 0}}));
+        assert_eq!(document["haproxy"]["http"]["request"].get("time_wait_ms"), None);
+    }
+}
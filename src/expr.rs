@@ -0,0 +1,353 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::result;
+use std::str;
+
+use crate::entry::LogEntry;
+use crate::field::{canonical_field_name, Field};
+use crate::integer::parse_i64;
+
+#[derive(Debug)]
+pub enum ExprError {
+    UnknownField(String),
+    Syntax(String),
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExprError::UnknownField(ref field) => write!(f, "unknown field '{}'", field),
+            ExprError::Syntax(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, ExprError>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Number {
+    Integer(i64),
+    Decimal(f64),
+}
+
+impl Number {
+    pub fn as_decimal(self) -> f64 {
+        match self {
+            Number::Integer(i) => i as f64,
+            Number::Decimal(d) => d,
+        }
+    }
+
+    pub fn apply(self, op: Operator, rhs: Number) -> Option<Number> {
+        match (op, self, rhs) {
+            (Operator::Divide, lhs, rhs) => {
+                let divisor = rhs.as_decimal();
+                if divisor == 0.0 {
+                    None
+                } else {
+                    Some(Number::Decimal(lhs.as_decimal() / divisor))
+                }
+            },
+            (Operator::Add, Number::Integer(a), Number::Integer(b)) => {
+                a.checked_add(b).map(Number::Integer)
+            },
+            (Operator::Subtract, Number::Integer(a), Number::Integer(b)) => {
+                a.checked_sub(b).map(Number::Integer)
+            },
+            (Operator::Multiply, Number::Integer(a), Number::Integer(b)) => {
+                a.checked_mul(b).map(Number::Integer)
+            },
+            (Operator::Add, a, b) => Some(Number::Decimal(a.as_decimal() + b.as_decimal())),
+            (Operator::Subtract, a, b) => Some(Number::Decimal(a.as_decimal() - b.as_decimal())),
+            (Operator::Multiply, a, b) => Some(Number::Decimal(a.as_decimal() * b.as_decimal())),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Number::Integer(i) => write!(f, "{}", i),
+            Number::Decimal(d) => write!(f, "{:.3}", d),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Value {
+    Number(Number),
+    Text(Vec<u8>),
+}
+
+impl Value {
+    pub fn as_bytes(&self) -> Cow<'_, [u8]> {
+        match *self {
+            Value::Number(number) => Cow::Owned(number.to_string().into_bytes()),
+            Value::Text(ref text) => Cow::Borrowed(text),
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match *self {
+            Value::Number(number) => write!(out, "{}", number),
+            Value::Text(ref text) => out.write_all(text),
+        }
+    }
+
+    pub fn apply(self, op: Operator, rhs: Value) -> Option<Value> {
+        match (op, self, rhs) {
+            (_, Value::Number(lhs), Value::Number(rhs)) => lhs.apply(op, rhs).map(Value::Number),
+            (Operator::Add, lhs, rhs) => {
+                let mut joined = vec![];
+                lhs.write_to(&mut joined).ok()?;
+                rhs.write_to(&mut joined).ok()?;
+                Some(Value::Text(joined))
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    Field(Field),
+    Integer(i64),
+    Text(Vec<u8>),
+    Binary(Box<Expr>, Operator, Box<Expr>),
+}
+
+impl Expr {
+    pub fn parse(expr: &str) -> Result<Expr> {
+        let mut parser = ExprParser::new(expr);
+        let parsed = parser.parse_sum()?;
+        parser.finish()?;
+
+        Ok(parsed)
+    }
+
+    pub fn evaluate(&self, entry: &LogEntry) -> Option<Value> {
+        match *self {
+            Expr::Field(ref field) => {
+                let content = field.extract_content_from(entry);
+                if field.is_numeric() {
+                    let number = parse_i64(content)?;
+                    Some(Value::Number(Number::Integer(number)))
+                } else {
+                    Some(Value::Text(content.to_vec()))
+                }
+            },
+            Expr::Integer(i) => Some(Value::Number(Number::Integer(i))),
+            Expr::Text(ref text) => Some(Value::Text(text.clone())),
+            Expr::Binary(ref lhs, op, ref rhs) => {
+                lhs.evaluate(entry)?.apply(op, rhs.evaluate(entry)?)
+            },
+        }
+    }
+}
+
+// a tiny recursive descent parser for arithmetic over field names:
+//
+//     sum    := term (('+' | '-') term)*
+//     term   := factor (('*' | '/') factor)*
+//     factor := '-' factor | '(' sum ')' | '"' text '"' | integer | field
+pub(crate) struct ExprParser<'a> {
+    source: &'a str,
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    pub(crate) fn new(source: &'a str) -> ExprParser<'a> {
+        ExprParser {
+            source,
+            input: source.as_bytes(),
+            position: 0,
+        }
+    }
+
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        if self.position != self.input.len() {
+            let rest = &self.source[self.position..];
+            let msg = format!("unexpected '{}' in '{}'", rest, self.source);
+            return Err(ExprError::Syntax(msg));
+        }
+        Ok(())
+    }
+
+    // consume `token` if it's next, skipping any whitespace before it.
+    pub(crate) fn consume(&mut self, token: &[u8]) -> bool {
+        self.skip_whitespace();
+        if self.input[self.position..].starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.input.len() && self.input[self.position] == b' ' {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.position).cloned()
+    }
+
+    pub(crate) fn parse_sum(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_term()?;
+        loop {
+            let op = match self.peek() {
+                Some(b'+') => Operator::Add,
+                Some(b'-') => Operator::Subtract,
+                _ => return Ok(expr),
+            };
+            self.position += 1;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.parse_term()?));
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_factor()?;
+        loop {
+            let op = match self.peek() {
+                Some(b'*') => Operator::Multiply,
+                Some(b'/') => Operator::Divide,
+                _ => return Ok(expr),
+            };
+            self.position += 1;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.parse_factor()?));
+        }
+    }
+
+    fn parse_factor(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(b'-') => {
+                self.position += 1;
+                let negated = self.parse_factor()?;
+                Ok(Expr::Binary(Box::new(Expr::Integer(0)), Operator::Subtract, Box::new(negated)))
+            },
+            Some(b'(') => {
+                self.position += 1;
+                let expr = self.parse_sum()?;
+                if self.peek() != Some(b')') {
+                    return Err(ExprError::Syntax("expected `)`".to_string()));
+                }
+                self.position += 1;
+                Ok(expr)
+            },
+            Some(b'"') => Ok(Expr::Text(self.parse_text()?)),
+            Some(c) if c.is_ascii_digit() => {
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                digits.parse()
+                    .map(Expr::Integer)
+                    .map_err(|err| {
+                        ExprError::Syntax(format!("could not parse number '{}': {}", digits, err))
+                    })
+            },
+            Some(c) if c.is_ascii_alphabetic() || c == b'_' => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || b"_[]".contains(&c));
+                if canonical_field_name(name) == Some("target") {
+                    let backend = Box::new(Expr::Field(Field::BackendName));
+                    let slash = Box::new(Expr::Text(b"/".to_vec()));
+                    let server = Box::new(Expr::Field(Field::ServerName));
+                    let backend_slash = Box::new(Expr::Binary(backend, Operator::Add, slash));
+                    return Ok(Expr::Binary(backend_slash, Operator::Add, server));
+                }
+                Ok(Expr::Field(Field::decode(name)?))
+            },
+            Some(c) => Err(ExprError::Syntax(format!("unexpected '{}'", c as char))),
+            None => Err(ExprError::Syntax("expected a field name or number".to_string())),
+        }
+    }
+
+    pub(crate) fn parse_text(&mut self) -> Result<Vec<u8>> {
+        if !self.consume(b"\"") {
+            return Err(ExprError::Syntax("expected a double quoted string".to_string()));
+        }
+
+        let mut text = vec![];
+        loop {
+            match self.input.get(self.position) {
+                Some(b'"') => break,
+                Some(b'\\') if self.position + 1 < self.input.len() => {
+                    text.push(self.input[self.position + 1]);
+                    self.position += 2;
+                },
+                Some(&c) => {
+                    text.push(c);
+                    self.position += 1;
+                },
+                None => return Err(ExprError::Syntax("expected closing `\"`".to_string())),
+            }
+        }
+        self.position += 1;
+        Ok(text)
+    }
+
+    fn take_while<F: Fn(u8) -> bool>(&mut self, predicate: F) -> &'a str {
+        let start = self.position;
+        while self.position < self.input.len() && predicate(self.input[self.position]) {
+            self.position += 1;
+        }
+        // only ascii bytes were consumed, so this is always valid utf8.
+        str::from_utf8(&self.input[start..self.position]).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Expr, Number, Value};
+    use crate::entry::LogEntry;
+
+    static SAMPLE: &str = concat!("haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] ",
+                                  "http-in static/srv1 10/0/30/69/109 200 2750 - - ---- ",
+                                  "1/1/1/1/0 0/0 \"GET /index.html HTTP/1.1\"");
+
+    fn evaluate(expr: &str) -> Option<Value> {
+        let entry = LogEntry::from_bytes(SAMPLE.as_bytes()).unwrap();
+        Expr::parse(expr).unwrap().evaluate(&entry)
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(evaluate("Tt-Tr"), Some(Value::Number(Number::Integer(40))));
+        assert_eq!(evaluate("(Tq + Tw) * 2"), Some(Value::Number(Number::Integer(20))));
+        assert_eq!(evaluate("-Tt"), Some(Value::Number(Number::Integer(-109))));
+        assert_eq!(evaluate("Tt/2"), Some(Value::Number(Number::Decimal(54.5))));
+        assert_eq!(evaluate("Tt/Tw"), None);
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(evaluate("target"), Some(Value::Text(b"static/srv1".to_vec())));
+        assert_eq!(evaluate("Tt + \"ms\""), Some(Value::Text(b"109ms".to_vec())));
+        assert_eq!(evaluate("\"a\\\"b\""), Some(Value::Text(b"a\"b".to_vec())));
+        assert_eq!(evaluate("backend_name - 1"), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Expr::parse("Tt -").is_err());
+        assert!(Expr::parse("(Tt").is_err());
+        assert!(Expr::parse("Tt Tr").is_err());
+        assert!(Expr::parse("\"open").is_err());
+        assert!(Expr::parse("bogus").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+// a C interface to the parser, for log agents written in C or C++. include/haproxy.h declares it
+// and is generated from this file by the test below. build a library for them to link with
+// `cargo rustc --lib --release --features ffi --crate-type staticlib` (or cdylib).
+//
+// the functions take raw pointers from C, and what they need of them is described in the header.
+#![allow(clippy::missing_safety_doc)]
+#![allow(non_camel_case_types)]
+
+use core::ffi::{c_char, c_int};
+use core::ptr;
+use core::slice;
+
+use crate::entry::{LogEntry, HEADER_FIELDS};
+use crate::field::Field;
+use crate::integer::parse_field;
+
+// the header fields, then the two capture blocks and the request.
+pub const HAPROXY_FIELDS: usize = HEADER_FIELDS + 3;
+
+// a field of the line that was parsed. it isn't nul terminated.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct haproxy_field {
+    pub data: *const c_char,
+    pub len: usize,
+}
+
+// the fields of a parsed line, which point into the line and are good for as long as it is.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct haproxy_entry {
+    pub fields: [haproxy_field; HAPROXY_FIELDS],
+}
+
+// parses the `len` bytes at `line` into `entry`, a trailing newline is fine. 0 when the line
+// parsed and -1 when it didn't, `entry` is left as it was then.
+#[no_mangle]
+pub unsafe extern "C" fn haproxy_parse_line(line: *const c_char, len: usize,
+                                            entry: *mut haproxy_entry) -> c_int {
+    if line.is_null() || entry.is_null() {
+        return -1;
+    }
+    let buf = slice::from_raw_parts(line as *const u8, len);
+    let parsed = match LogEntry::from_bytes(buf) {
+        Ok(parsed) => parsed,
+        Err(_) => return -1,
+    };
+    let fields = parsed.header_fields();
+    let all = fields.iter().chain(&parsed.captures).chain([&parsed.http_request]);
+    let entry = &mut *entry;
+    for (out, field) in entry.fields.iter_mut().zip(all) {
+        *out = haproxy_field { data: field.as_ptr() as *const c_char, len: field.len() };
+    }
+    0
+}
+
+// a field of `entry` by its HAPROXY_FIELD_ id with its length in `len`, or null for an unknown id.
+#[no_mangle]
+pub unsafe extern "C" fn haproxy_entry_field(entry: *const haproxy_entry, field: c_int,
+                                             len: *mut usize) -> *const c_char {
+    match get(entry, field) {
+        Some(field) => {
+            if !len.is_null() {
+                *len = field.len;
+            }
+            field.data
+        },
+        None => ptr::null(),
+    }
+}
+
+// a numeric field of `entry` in `value`. 0 when it is a number and -1 when it isn't, such as for
+// the names. the '+' haproxy puts before Tt and the retries is skipped, a -1 timer is -1.
+#[no_mangle]
+pub unsafe extern "C" fn haproxy_entry_number(entry: *const haproxy_entry, field: c_int,
+                                              value: *mut i64) -> c_int {
+    // a name can look like a number too, like a backend called 42.
+    let numeric = field >= 0 && Field::HEADER.get(field as usize).is_some_and(Field::is_numeric);
+    let number = get(entry, field).filter(|_| numeric).and_then(|field| parse_field(bytes(field)));
+    match number {
+        Some(number) if !value.is_null() => {
+            *value = number;
+            0
+        },
+        _ => -1,
+    }
+}
+
+// accept_date in `millis`, milliseconds since the epoch as though haproxy's local time were
+// utc. 0 when the date parsed and -1 when it didn't.
+#[no_mangle]
+pub unsafe extern "C" fn haproxy_entry_accept_date_ms(entry: *const haproxy_entry,
+                                                      millis: *mut i64) -> c_int {
+    // accept_date is the fifth field.
+    let date = get(entry, 4).and_then(|field| {
+        let date = core::str::from_utf8(bytes(field)).ok()?;
+        chrono::NaiveDateTime::parse_from_str(date, crate::entry::ACCEPT_DATE_FORMAT).ok()
+    });
+    match date {
+        Some(date) if !millis.is_null() => {
+            *millis = date.and_utc().timestamp_millis();
+            0
+        },
+        _ => -1,
+    }
+}
+
+unsafe fn get(entry: *const haproxy_entry, field: c_int) -> Option<haproxy_field> {
+    if entry.is_null() || field < 0 {
+        return None;
+    }
+    (*entry).fields.get(field as usize).copied()
+}
+
+unsafe fn bytes<'a>(field: haproxy_field) -> &'a [u8] {
+    if field.data.is_null() {
+        return b"";
+    }
+    slice::from_raw_parts(field.data as *const u8, field.len)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{haproxy_entry, haproxy_entry_accept_date_ms, haproxy_entry_field,
+                haproxy_entry_number, haproxy_field, haproxy_parse_line, HAPROXY_FIELDS};
+    use crate::entry::HEADER_FIELD_NAMES;
+    use core::ffi::c_char;
+    use std::fmt::Write;
+    use std::fs;
+    use std::{env, ptr, slice};
+
+    const HEADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/include/haproxy.h");
+
+    fn field_names() -> Vec<&'static str> {
+        let mut names = HEADER_FIELD_NAMES.to_vec();
+        names.extend(["captured_request_headers", "captured_response_headers", "http_request"]);
+        names
+    }
+
+    fn c_header() -> String {
+        let mut header = String::from(
+"/* the C interface to haproxy.rs's log parser, generated by the `header` test in src/ffi.rs with
+ * HAPROXY_WRITE_HEADER=1 cargo test --features ffi. don't edit it by hand. */
+#ifndef HAPROXY_H
+#define HAPROXY_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+
+/* the ids of an entry's fields, in the order they're logged. */
+enum haproxy_field_id {
+");
+        for (id, name) in field_names().iter().enumerate() {
+            writeln!(header, "    HAPROXY_FIELD_{} = {},", name.to_uppercase(), id).unwrap();
+        }
+        write!(header, "    HAPROXY_FIELD_COUNT = {}\n}};\n", HAPROXY_FIELDS).unwrap();
+        header.push_str(
+"
+/* a field of the line that was parsed. it isn't nul terminated. */
+struct haproxy_field {
+    const char *data;
+    size_t len;
+};
+
+/* the fields of a parsed line, which point into the line and are good for as long as it is. */
+struct haproxy_entry {
+    struct haproxy_field fields[HAPROXY_FIELD_COUNT];
+};
+
+/* parses the `len` bytes at `line` into `entry`, a trailing newline is fine. 0 when the line
+ * parsed and -1 when it didn't, `entry` is left as it was then. */
+int haproxy_parse_line(const char *line, size_t len, struct haproxy_entry *entry);
+
+/* a field of `entry` with its length in `len`, which may be null, or null for an unknown id. */
+const char *haproxy_entry_field(const struct haproxy_entry *entry, int field, size_t *len);
+
+/* a numeric field of `entry` in `value`. 0 when it is a number and -1 when it isn't, such as
+ * for the names. the '+' haproxy puts before Tt and the retries is skipped, a -1 timer is -1. */
+int haproxy_entry_number(const struct haproxy_entry *entry, int field, int64_t *value);
+
+/* accept_date in `millis`, milliseconds since the epoch as though haproxy's local time were
+ * utc. 0 when the date parsed and -1 when it didn't. */
+int haproxy_entry_accept_date_ms(const struct haproxy_entry *entry, int64_t *millis);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
+");
+        header
+    }
+
+    #[test]
+    fn header() {
+        let header = c_header();
+        if env::var_os("HAPROXY_WRITE_HEADER").is_some() {
+            fs::write(HEADER_PATH, &header).unwrap();
+        }
+        assert!(fs::read_to_string(HEADER_PATH).unwrap() == header,
+                "include/haproxy.h is out of date, regenerate it with HAPROXY_WRITE_HEADER=1");
+    }
+
+    #[test]
+    fn parse_line() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 -1/0/30/69/+109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                     {1wt.eu} \"GET / HTTP/1.1\"\n";
+        let empty = haproxy_field { data: ptr::null(), len: 0 };
+        let mut entry = haproxy_entry { fields: [empty; HAPROXY_FIELDS] };
+        let names = field_names();
+        let id = |name| names.iter().position(|&field| field == name).unwrap() as i32;
+        unsafe {
+            let line_ptr = line.as_ptr() as *const c_char;
+            assert_eq!(haproxy_parse_line(line_ptr, line.len(), &mut entry), 0);
+
+            let mut len = 0;
+            let server = haproxy_entry_field(&entry, id("server_name"), &mut len);
+            assert_eq!(slice::from_raw_parts(server as *const u8, len), b"srv1");
+            let request = haproxy_entry_field(&entry, id("http_request"), &mut len);
+            assert_eq!(slice::from_raw_parts(request as *const u8, len), b"GET / HTTP/1.1");
+            assert!(haproxy_entry_field(&entry, HAPROXY_FIELDS as i32, &mut len).is_null());
+
+            let mut value = 0;
+            assert_eq!(haproxy_entry_number(&entry, id("status_code"), &mut value), 0);
+            assert_eq!(value, 200);
+            assert_eq!(haproxy_entry_number(&entry, id("total_time"), &mut value), 0);
+            assert_eq!(value, 109);
+            assert_eq!(haproxy_entry_number(&entry, id("request_time"), &mut value), 0);
+            assert_eq!(value, -1);
+            assert_eq!(haproxy_entry_number(&entry, id("frontend_name"), &mut value), -1);
+            let numbered = String::from_utf8(line.to_vec()).unwrap().replace("static/", "42/");
+            assert_eq!(haproxy_parse_line(numbered.as_ptr() as *const c_char, numbered.len(),
+                                          &mut entry), 0);
+            assert_eq!(haproxy_entry_number(&entry, id("backend_name"), &mut value), -1);
+            assert_eq!(haproxy_parse_line(line_ptr, line.len(), &mut entry), 0);
+            assert_eq!(haproxy_entry_accept_date_ms(&entry, &mut value), 0);
+            assert_eq!(value, 1233922454655);
+
+            assert_eq!(haproxy_parse_line(line_ptr, 20, &mut entry), -1);
+            assert_eq!(haproxy_parse_line(ptr::null(), 0, &mut entry), -1);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+// the fields themselves need nothing beyond core, for the wasm and C parsers; decoding their names
+// and writing them out need std.
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::{IoSlice, Write};
+#[cfg(feature = "std")]
+use std::num::ParseIntError;
+
+use crate::entry::{LogEntry, HEADER_FIELDS};
+#[cfg(feature = "std")]
+use crate::expr::ExprError;
+
+// every selectable field name in the order it appears in a log entry, along with its aliases.
+#[cfg(feature = "std")]
+pub static FIELD_NAMES: &[(&str, &[&str])] = &[
+    ("process_name", &[]),
+    ("pid", &[]),
+    ("client_ip", &["ip"]),
+    ("client_port", &["port"]),
+    ("accept_date", &["date"]),
+    ("frontend_name", &["frontend"]),
+    ("backend_name", &["backend"]),
+    ("server_name", &["server"]),
+    ("Tq", &[]),
+    ("Tw", &[]),
+    ("Tc", &[]),
+    ("Tr", &[]),
+    ("Tt", &[]),
+    ("status_code", &["status"]),
+    ("status_class", &[]),
+    ("bytes_read", &["bytes"]),
+    ("captured_request_cookie", &[]),
+    ("captured_response_cookie", &[]),
+    ("termination_state", &["termination"]),
+    ("actconn", &[]),
+    ("feconn", &[]),
+    ("beconn", &[]),
+    ("srv_conn", &[]),
+    ("retries", &[]),
+    ("srv_queue", &[]),
+    ("backend_queue", &[]),
+    ("http_request", &["request"]),
+    ("http_method", &["method"]),
+    ("http_uri", &["uri"]),
+    ("http_version", &["version"]),
+    ("target", &[]),
+    ("captured_header[i][j]", &[]),
+];
+
+#[cfg(feature = "std")]
+pub fn canonical_field_name(field: &str) -> Option<&'static str> {
+    FIELD_NAMES
+        .iter()
+        .find(|&&(name, aliases)| {
+            name.eq_ignore_ascii_case(field) ||
+                aliases.iter().any(|alias| alias.eq_ignore_ascii_case(field))
+        })
+        .map(|&(name, _)| name)
+}
+
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    ProcessName,
+    ProcessId,
+    ClientIp,
+    ClientPort,
+    AcceptDate,
+    FrontendName,
+    BackendName,
+    ServerName,
+
+    RequestTime,
+    QueueTime,
+    ConnectTime,
+    ResponseTime,
+    TotalTime,
+
+    StatusCode,
+    BytesRead,
+    CapturedRequestCookie,
+    CapturedResponseCookie,
+    TerminationState,
+
+    ActiveConnections,
+    FrontendConnections,
+    BackendConnections,
+    ServerConnections,
+    RetriedConnections,
+
+    ServerQueue,
+    BackendQueue,
+    HttpRequest,
+
+    HttpMethod,
+    HttpUri,
+    HttpVersion,
+    StatusClass,
+
+    CapturedHeader(usize, usize),
+}
+
+
+impl Field {
+    // the fields of LogEntry::header_fields, in the same order.
+    pub(crate) const HEADER: [Field; HEADER_FIELDS] = [
+        Field::ProcessName, Field::ProcessId, Field::ClientIp, Field::ClientPort,
+        Field::AcceptDate, Field::FrontendName, Field::BackendName, Field::ServerName,
+        Field::RequestTime, Field::QueueTime, Field::ConnectTime, Field::ResponseTime,
+        Field::TotalTime, Field::StatusCode, Field::BytesRead, Field::CapturedRequestCookie,
+        Field::CapturedResponseCookie, Field::TerminationState, Field::ActiveConnections,
+        Field::FrontendConnections, Field::BackendConnections, Field::ServerConnections,
+        Field::RetriedConnections, Field::ServerQueue, Field::BackendQueue,
+    ];
+
+    #[cfg(feature = "std")]
+    pub fn decode(field: &str) -> Result<Field, ExprError> {
+        Ok(match canonical_field_name(field).unwrap_or(field) {
+            "process_name" => Field::ProcessName,
+            "pid" => Field::ProcessId,
+            "client_ip" => Field::ClientIp,
+            "client_port" => Field::ClientPort,
+            "accept_date" => Field::AcceptDate,
+            "frontend_name" => Field::FrontendName,
+            "backend_name" => Field::BackendName,
+            "server_name" => Field::ServerName,
+            "Tq" => Field::RequestTime,
+            "Tw" => Field::QueueTime,
+            "Tc" => Field::ConnectTime,
+            "Tr" => Field::ResponseTime,
+            "Tt" => Field::TotalTime,
+            "status_code" => Field::StatusCode,
+            "bytes_read" => Field::BytesRead,
+            "captured_request_cookie" => Field::CapturedRequestCookie,
+            "captured_response_cookie" => Field::CapturedResponseCookie,
+            "termination_state" => Field::TerminationState,
+            "actconn" => Field::ActiveConnections,
+            "feconn" => Field::FrontendConnections,
+            "beconn" => Field::BackendConnections,
+            "srv_conn" => Field::ServerConnections,
+            "retries" => Field::RetriedConnections,
+            "srv_queue" => Field::ServerQueue,
+            "backend_queue" => Field::BackendQueue,
+            "http_request" => Field::HttpRequest,
+
+            "http_method" => Field::HttpMethod,
+            "http_uri" => Field::HttpUri,
+            "http_version" => Field::HttpVersion,
+            "status_class" => Field::StatusClass,
+
+            field => {
+                let lowered = field.to_ascii_lowercase();
+                if lowered.starts_with("captured_header[") {
+                    // looks like: "captured_header[i][j]"
+                    if !lowered.ends_with("]") {
+                        let msg = "captured_header: expected final `]`".to_string();
+                        return Err(ExprError::Syntax(msg));
+                    }
+
+                    let parse_result: Result<Vec<usize>, ParseIntError> = lowered
+                        .trim_start_matches("captured_header[")
+                        .trim_end_matches(']')
+                        .split("][")
+                        .map(|s| s.parse())
+                        .collect();
+
+                    let indices = match parse_result{
+                        Ok(indices) => indices,
+                        Err(err) => {
+                            let msg = format!("captured_header: could not parse index: {}", err);
+                            return Err(ExprError::Syntax(msg));
+                        },
+                    };
+
+                    if indices.len() != 2 {
+                        let msg = "captured_header: not enough indices".to_string();
+                        return Err(ExprError::Syntax(msg));
+                    }
+
+                    if indices[0] > 1 {
+                        let msg = "captured_header: the first index must be 0 or 1".to_string();
+                        return Err(ExprError::Syntax(msg));
+                    }
+
+                    Field::CapturedHeader(indices[0], indices[1])
+                } else {
+                    return Err(ExprError::UnknownField(field.to_string()));
+                }
+            },
+        })
+    }
+
+    // the C interface has the fields already, it only asks which are numeric.
+    #[cfg_attr(not(any(feature = "std", feature = "wasm")), allow(dead_code))]
+    pub fn extract_content_from<'a>(&self, entry: &LogEntry<'a>) -> &'a [u8] {
+        match *self {
+            Field::ProcessName => entry.process_name,
+            Field::ProcessId => entry.pid,
+            Field::ClientIp => entry.client_ip,
+            Field::ClientPort => entry.client_port,
+            Field::AcceptDate => entry.accept_date,
+            Field::FrontendName => entry.frontend_name,
+            Field::BackendName => entry.backend_name,
+            Field::ServerName => entry.server_name,
+            Field::RequestTime => entry.request_time,
+            Field::QueueTime => entry.queue_time,
+            Field::ConnectTime  => entry.connect_time,
+            Field::ResponseTime => entry.response_time,
+            Field::TotalTime => entry.total_time,
+            Field::StatusCode => entry.status_code,
+            Field::BytesRead => entry.bytes_read,
+            Field::CapturedRequestCookie => entry.captured_request_cookie,
+            Field::CapturedResponseCookie => entry.captured_response_cookie,
+            Field::TerminationState => entry.termination_state,
+            Field::ActiveConnections => entry.active_connections,
+            Field::FrontendConnections => entry.frontend_connections,
+            Field::BackendConnections => entry.backend_connections,
+            Field::ServerConnections => entry.server_connections,
+            Field::RetriedConnections => entry.retried_connections,
+            Field::ServerQueue => entry.server_queue,
+            Field::BackendQueue => entry.backend_queue,
+            Field::HttpRequest => entry.http_request,
+
+            Field::HttpMethod => entry.http_method().unwrap_or(b""),
+            Field::HttpUri => entry.http_uri().unwrap_or(b""),
+            Field::HttpVersion => entry.http_version().unwrap_or(b""),
+            Field::StatusClass => entry.status_class(),
+            Field::CapturedHeader(i, j) => entry.captured_header(i, j).unwrap_or(b""),
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(*self,
+            Field::ProcessId |
+            Field::ClientPort |
+            Field::RequestTime |
+            Field::QueueTime |
+            Field::ConnectTime |
+            Field::ResponseTime |
+            Field::TotalTime |
+            Field::StatusCode |
+            Field::BytesRead |
+            Field::ActiveConnections |
+            Field::FrontendConnections |
+            Field::BackendConnections |
+            Field::ServerConnections |
+            Field::RetriedConnections |
+            Field::ServerQueue |
+            Field::BackendQueue)
+    }
+}
+
+// write `fields` of `entry` to `out` separated by `delimiter`, leaving the line ending to the
+// caller. every field is a slice of the line or a constant, so with a Vec<u8> which has grown
+// large enough over earlier lines this allocates nothing.
+#[cfg(feature = "std")]
+pub fn write_fields_into<W: Write>(out: &mut W, entry: &LogEntry, fields: &[Field],
+                                   delimiter: &[u8]) -> io::Result<()> {
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            out.write_all(delimiter)?;
+        }
+        out.write_all(field.extract_content_from(entry))?;
+    }
+    Ok(())
+}
+
+// how many slices write_fields_vectored hands to the writer at once.
+#[cfg(feature = "std")]
+const MAX_SLICES: usize = 64;
+
+// like write_fields_into followed by `end`, but the fields and delimiters are handed to `out` as
+// slices of the line with write_vectored rather than copied one by one. that saves a copy when
+// `out` is unbuffered, or when a field like a long URI doesn't fit in its buffer.
+#[cfg(feature = "std")]
+pub fn write_fields_vectored<W: Write>(out: &mut W, entry: &LogEntry, fields: &[Field],
+                                       delimiter: &[u8], end: &[u8]) -> io::Result<()> {
+    let mut slices = [IoSlice::new(b""); MAX_SLICES];
+    let mut count = 0;
+    for (i, field) in fields.iter().enumerate() {
+        // room for a delimiter, the field and `end`.
+        if count + 3 > MAX_SLICES {
+            write_all_vectored(out, &mut slices[..count])?;
+            count = 0;
+        }
+        if i != 0 && !delimiter.is_empty() {
+            slices[count] = IoSlice::new(delimiter);
+            count += 1;
+        }
+        let content = field.extract_content_from(entry);
+        if !content.is_empty() {
+            slices[count] = IoSlice::new(content);
+            count += 1;
+        }
+    }
+    if !end.is_empty() {
+        slices[count] = IoSlice::new(end);
+        count += 1;
+    }
+    write_all_vectored(out, &mut slices[..count])
+}
+
+// Write::write_all_vectored isn't stable yet.
+#[cfg(feature = "std")]
+fn write_all_vectored<W: Write>(out: &mut W, mut slices: &mut [IoSlice]) -> io::Result<()> {
+    while !slices.is_empty() {
+        match out.write_vectored(slices) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write fields")),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{write_fields_into, write_fields_vectored, Field};
+    use crate::entry::LogEntry;
+    use std::io;
+    use std::io::Write;
+
+    // takes at most a few bytes per write, to check partial writes are carried on from.
+    struct Stingy {
+        written: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for Stingy {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            let n = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn decode_names() {
+        assert_eq!(Field::decode("client_ip").unwrap(), Field::ClientIp);
+        assert_eq!(Field::decode("Tt").unwrap(), Field::TotalTime);
+        assert!(Field::decode("bogus").is_err());
+    }
+
+    #[test]
+    fn decode_aliases_and_case() {
+        assert_eq!(Field::decode("ip").unwrap(), Field::ClientIp);
+        assert_eq!(Field::decode("STATUS").unwrap(), Field::StatusCode);
+        assert_eq!(Field::decode("tt").unwrap(), Field::TotalTime);
+        assert_eq!(Field::decode("TQ").unwrap(), Field::RequestTime);
+    }
+
+    #[test]
+    fn decode_captured_header() {
+        assert_eq!(Field::decode("captured_header[1][2]").unwrap(), Field::CapturedHeader(1, 2));
+        assert_eq!(Field::decode("Captured_Header[0][0]").unwrap(), Field::CapturedHeader(0, 0));
+        assert!(Field::decode("captured_header[2][0]").is_err());
+        assert!(Field::decode("captured_header[0]").is_err());
+        assert!(Field::decode("captured_header[0][x]").is_err());
+    }
+
+    #[test]
+    fn header() {
+        let sample = concat!("haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] ",
+                             "http-in static/srv1 10/2/30/69/109 503 2750 a b ---- ",
+                             "11/12/13/14/+15 16/17 {1wt.eu} \"GET /index.html HTTP/1.1\"");
+        let entry = LogEntry::from_bytes(sample.as_bytes()).unwrap();
+        let fields = Field::HEADER.map(|field| field.extract_content_from(&entry));
+        assert_eq!(fields, entry.header_fields());
+    }
+
+    #[test]
+    fn write_fields() {
+        let sample = concat!("haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] ",
+                             "http-in static/srv1 10/0/30/69/109 503 2750 - - ---- ",
+                             "1/1/1/1/0 0/0 {1wt.eu} \"GET /index.html HTTP/1.1\"").as_bytes();
+        let entry = LogEntry::from_bytes(sample).unwrap();
+        let fields = [Field::ClientIp, Field::StatusClass, Field::HttpUri,
+                      Field::CapturedHeader(0, 0), Field::CapturedHeader(1, 0)];
+
+        let mut out = vec![];
+        write_fields_into(&mut out, &entry, &fields, b"\t").unwrap();
+        assert_eq!(out, b"10.0.1.2\t5xx\t/index.html\t1wt.eu\t");
+
+        out.clear();
+        write_fields_into(&mut out, &entry, &fields[..1], b", ").unwrap();
+        write_fields_into(&mut out, &entry, &[], b", ").unwrap();
+        assert_eq!(out, b"10.0.1.2");
+    }
+
+    #[test]
+    fn write_fields_vectors() {
+        let sample = concat!("haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] ",
+                             "http-in static/srv1 10/0/30/69/109 503 2750 - - ---- ",
+                             "1/1/1/1/0 0/0 {1wt.eu} \"GET /index.html HTTP/1.1\"").as_bytes();
+        let entry = LogEntry::from_bytes(sample).unwrap();
+        let fields = [Field::ClientIp, Field::StatusClass, Field::HttpUri,
+                      Field::CapturedHeader(1, 0), Field::BytesRead];
+
+        let mut out = vec![];
+        write_fields_vectored(&mut out, &entry, &fields, b"\t", b"\n").unwrap();
+        assert_eq!(out, b"10.0.1.2\t5xx\t/index.html\t\t2750\n");
+
+        let mut stingy = Stingy { written: vec![], calls: 0 };
+        write_fields_vectored(&mut stingy, &entry, &fields, b", ", b"").unwrap();
+        assert_eq!(stingy.written, b"10.0.1.2, 5xx, /index.html, , 2750");
+        assert!(stingy.calls > 1);
+
+        // more slices than are handed over in one go.
+        let many = vec![Field::StatusCode; 100];
+        let mut out = vec![];
+        write_fields_vectored(&mut out, &entry, &many, b" ", b"\n").unwrap();
+        assert_eq!(out, [&vec!["503"; 100].join(" ")[..], "\n"].concat().as_bytes());
+    }
+}
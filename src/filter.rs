@@ -0,0 +1,242 @@
+use std::cmp::Ordering;
+use std::str;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::bytes::Regex;
+
+use crate::entry::{LogEntry, ACCEPT_DATE_FORMAT};
+use crate::expr::{Expr, ExprError, ExprParser, Number, Result, Value};
+
+// formats accepted for --since and --until, tried in order.
+const DATE_FORMATS: &[&str] = &[
+    ACCEPT_DATE_FORMAT,
+    "%d/%b/%Y:%H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+];
+
+pub fn parse_date(date: &str) -> Option<NaiveDateTime> {
+    for format in DATE_FORMATS {
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(date, format) {
+            return Some(date_time);
+        }
+    }
+
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (&Value::Number(Number::Integer(a)), &Value::Number(Number::Integer(b))) => Some(a.cmp(&b)),
+        (&Value::Number(a), &Value::Number(b)) => a.as_decimal().partial_cmp(&b.as_decimal()),
+        (lhs, rhs) => Some(lhs.as_bytes().cmp(&rhs.as_bytes())),
+    }
+}
+
+#[derive(Debug)]
+pub enum Condition {
+    Compare(Expr, Comparison, Expr),
+    Matches(Expr, Regex),
+    NotMatches(Expr, Regex),
+    Since(NaiveDateTime),
+    Until(NaiveDateTime),
+}
+
+impl Condition {
+    // parses conditions like `status_code >= 500`, `backend_name == "static"` or
+    // `http_uri ~ "^/api/"`.
+    pub fn parse(condition: &str) -> Result<Condition> {
+        let mut parser = ExprParser::new(condition);
+        let lhs = parser.parse_sum()?;
+
+        // longer operators first so `<=` isn't read as `<`.
+        let parsed = if parser.consume(b"!~") {
+            Condition::NotMatches(lhs, parse_regex(&mut parser)?)
+        } else if parser.consume(b"~") {
+            Condition::Matches(lhs, parse_regex(&mut parser)?)
+        } else {
+            let comparison = if parser.consume(b"==") {
+                Comparison::Equal
+            } else if parser.consume(b"!=") {
+                Comparison::NotEqual
+            } else if parser.consume(b"<=") {
+                Comparison::LessOrEqual
+            } else if parser.consume(b">=") {
+                Comparison::GreaterOrEqual
+            } else if parser.consume(b"<") {
+                Comparison::Less
+            } else if parser.consume(b">") {
+                Comparison::Greater
+            } else {
+                let msg = format!("expected a comparison like == or ~ in '{}'", condition);
+                return Err(ExprError::Syntax(msg));
+            };
+            Condition::Compare(lhs, comparison, parser.parse_sum()?)
+        };
+
+        parser.finish()?;
+        Ok(parsed)
+    }
+
+    pub fn since(date: &str) -> Result<Condition> {
+        match parse_date(date) {
+            Some(date_time) => Ok(Condition::Since(date_time)),
+            None => Err(ExprError::Syntax(format!("could not parse date '{}'", date))),
+        }
+    }
+
+    pub fn until(date: &str) -> Result<Condition> {
+        match parse_date(date) {
+            Some(date_time) => Ok(Condition::Until(date_time)),
+            None => Err(ExprError::Syntax(format!("could not parse date '{}'", date))),
+        }
+    }
+
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        match *self {
+            Condition::Compare(ref lhs, comparison, ref rhs) => {
+                match (lhs.evaluate(entry), rhs.evaluate(entry)) {
+                    (Some(lhs), Some(rhs)) => {
+                        compare(&lhs, &rhs).is_some_and(|ordering| comparison.holds(ordering))
+                    },
+                    _ => false,
+                }
+            },
+            Condition::Matches(ref expr, ref regex) => {
+                expr.evaluate(entry).is_some_and(|value| regex.is_match(&value.as_bytes()))
+            },
+            Condition::NotMatches(ref expr, ref regex) => {
+                expr.evaluate(entry).is_some_and(|value| !regex.is_match(&value.as_bytes()))
+            },
+            Condition::Since(ref since) => entry.accept_date_time().is_ok_and(|d| d >= *since),
+            Condition::Until(ref until) => entry.accept_date_time().is_ok_and(|d| d < *until),
+        }
+    }
+}
+
+fn parse_regex(parser: &mut ExprParser) -> Result<Regex> {
+    let pattern = parser.parse_text()?;
+    let pattern = str::from_utf8(&pattern)
+        .map_err(|_| ExprError::Syntax("regex is not valid utf8".to_string()))?;
+    Regex::new(pattern).map_err(|err| ExprError::Syntax(format!("invalid regex: {}", err)))
+}
+
+// a set of conditions which must all hold for an entry to match.
+#[derive(Debug, Default)]
+pub struct Filter {
+    conditions: Vec<Condition>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter {
+            conditions: vec![],
+        }
+    }
+
+    pub fn parse<S: AsRef<str>>(conditions: &[S]) -> Result<Filter> {
+        let mut filter = Filter::new();
+        for condition in conditions {
+            filter.push(Condition::parse(condition.as_ref())?);
+        }
+        Ok(filter)
+    }
+
+    pub fn push(&mut self, condition: Condition) {
+        self.conditions.push(condition);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.conditions.iter().all(|condition| condition.matches(entry))
+    }
+
+    pub(crate) fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Condition, Filter};
+    use crate::entry::LogEntry;
+
+    static SAMPLE: &str = concat!("haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] ",
+                                  "http-in static/srv1 10/0/30/69/109 200 2750 - - ---- ",
+                                  "1/1/1/1/0 0/0 \"GET /index.html HTTP/1.1\"");
+
+    fn matches(condition: &str) -> bool {
+        let entry = LogEntry::from_bytes(SAMPLE.as_bytes()).unwrap();
+        Condition::parse(condition).unwrap().matches(&entry)
+    }
+
+    #[test]
+    fn numeric_comparisons() {
+        assert!(matches("status_code == 200"));
+        assert!(matches("Tt > 100"));
+        assert!(matches("Tt-Tr <= 40"));
+        assert!(!matches("status_code >= 500"));
+        assert!(!matches("Tt < 109"));
+    }
+
+    #[test]
+    fn text_comparisons() {
+        assert!(matches("backend_name == \"static\""));
+        assert!(matches("target != \"static/srv2\""));
+        assert!(!matches("server_name == \"srv2\""));
+    }
+
+    #[test]
+    fn regex_matches() {
+        assert!(matches("http_uri ~ \"^/index\""));
+        assert!(matches("http_method !~ \"POST|PUT\""));
+        assert!(!matches("http_uri ~ \"^/api/\""));
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        assert!(Condition::parse("status_code").is_err());
+        assert!(Condition::parse("status_code = 200").is_err());
+        assert!(Condition::parse("nope == 1").is_err());
+        assert!(Condition::parse("http_uri ~ \"(\"").is_err());
+        assert!(Condition::since("yesterday").is_err());
+    }
+
+    #[test]
+    fn date_range() {
+        let entry = LogEntry::from_bytes(SAMPLE.as_bytes()).unwrap();
+        let mut filter = Filter::new();
+        filter.push(Condition::since("2009-02-06T12:00:00").unwrap());
+        filter.push(Condition::until("06/Feb/2009:12:14:15").unwrap());
+        assert!(filter.matches(&entry));
+
+        filter.push(Condition::since("2009-02-07").unwrap());
+        assert!(!filter.matches(&entry));
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, SystemTime};
+
+use chrono::TimeZone;
+
+use crate::base64::base64;
+use crate::entry::LogEntry;
+use crate::msgpack::{encode_msgpack, write_array_len, write_int, write_map_len, write_str};
+
+const DEFAULT_BATCH_ENTRIES: usize = 1000;
+// how long to wait for fluentd to acknowledge a batch, fluent-bit's default is longer still.
+const ACK_TIMEOUT: Duration = Duration::from_secs(60);
+
+// sends entries to fluentd or fluent-bit over the forward protocol, an encode_msgpack map per
+// event under one tag, in batches of `batch_size`. with `set_require_ack` each batch waits for the
+// server to acknowledge it, so nothing is lost when the server goes away mid-batch. entries
+// written since the last batch are only sent by `flush`, which should be called at the end and
+// whenever the input pauses.
+pub struct FluentForwarder {
+    address: String,
+    tag: String,
+    require_ack: bool,
+    stream: Option<TcpStream>,
+    // the encoded [time, record] pairs of the batch.
+    events: Vec<u8>,
+    count: usize,
+    batch_size: usize,
+    // for chunk ids, which only have to be unique.
+    chunks: u64,
+    random: RandomState,
+}
+
+impl FluentForwarder {
+    // `address` is the in_forward input's, e.g. localhost:24224. nothing connects to it until the
+    // first batch is sent.
+    pub fn new(address: &str, tag: &str) -> FluentForwarder {
+        FluentForwarder {
+            address: address.to_string(),
+            tag: tag.to_string(),
+            require_ack: false,
+            stream: None,
+            events: vec![],
+            count: 0,
+            batch_size: DEFAULT_BATCH_ENTRIES,
+            chunks: 0,
+            random: RandomState::new(),
+        }
+    }
+
+    // whether to wait for each batch to be acknowledged, fluent-bit's `require_ack_response`.
+    pub fn set_require_ack(&mut self, require_ack: bool) {
+        self.require_ack = require_ack;
+    }
+
+    // how many entries go in a message, a thousand by default.
+    pub fn set_batch_size(&mut self, entries: usize) {
+        self.batch_size = entries.max(1);
+    }
+
+    // adds `entry` to the batch as an event at the time it was accepted, with accept_date taken
+    // to be in `timezone`, or at the present when accept_date doesn't parse.
+    pub fn write<Tz: TimeZone>(&mut self, entry: &LogEntry, timezone: &Tz) -> io::Result<()> {
+        let accepted = entry.accept_date_time().ok()
+            .and_then(|accepted| timezone.from_local_datetime(&accepted).earliest());
+        let (seconds, nanos) = match accepted {
+            Some(accepted) => (accepted.timestamp(), accepted.timestamp_subsec_nanos()),
+            None => {
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default();
+                (now.as_secs() as i64, now.subsec_nanos())
+            },
+        };
+        write_array_len(&mut self.events, 2);
+        // the EventTime extension, seconds and nanoseconds.
+        self.events.extend_from_slice(&[0xd7, 0x00]);
+        self.events.extend_from_slice(&(seconds as u32).to_be_bytes());
+        self.events.extend_from_slice(&nanos.to_be_bytes());
+        encode_msgpack(entry, &mut self.events);
+        self.count += 1;
+        if self.count >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // sends the entries written since the last batch, if there are any. a connection which fails
+    // is made again once before giving up, and the batch is kept to try again on the next flush.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        let chunk = if self.require_ack { Some(self.chunk_id()) } else { None };
+        let message = self.message(chunk.as_deref());
+        if let Err(err) = self.send(&message, chunk.as_deref()) {
+            self.stream = None;
+            if self.send(&message, chunk.as_deref()).is_err() {
+                self.stream = None;
+                return Err(err);
+            }
+        }
+        self.events.clear();
+        self.count = 0;
+        Ok(())
+    }
+
+    // a message in forward mode: [tag, [events...], {size, chunk}].
+    fn message(&self, chunk: Option<&str>) -> Vec<u8> {
+        let mut message = Vec::with_capacity(self.events.len() + self.tag.len() + 64);
+        write_array_len(&mut message, 3);
+        write_str(&mut message, self.tag.as_bytes());
+        write_array_len(&mut message, self.count);
+        message.extend_from_slice(&self.events);
+        write_map_len(&mut message, if chunk.is_some() { 2 } else { 1 });
+        write_str(&mut message, b"size");
+        write_int(&mut message, self.count as i64);
+        if let Some(chunk) = chunk {
+            write_str(&mut message, b"chunk");
+            write_str(&mut message, chunk.as_bytes());
+        }
+        message
+    }
+
+    fn send(&mut self, message: &[u8], chunk: Option<&str>) -> io::Result<()> {
+        if self.stream.is_none() {
+            let stream = TcpStream::connect(&self.address)?;
+            stream.set_read_timeout(Some(ACK_TIMEOUT))?;
+            self.stream = Some(stream);
+        }
+        let stream = self.stream.as_mut().unwrap();
+        stream.write_all(message)?;
+        let chunk = match chunk {
+            Some(chunk) => chunk,
+            None => return Ok(()),
+        };
+
+        // the answer is {"ack": chunk}. the chunk id turning up is all that's checked, rather
+        // than decoding MessagePack for the one map.
+        let mut answer = vec![];
+        let mut buffer = [0; 256];
+        while !answer.windows(chunk.len()).any(|window| window == chunk.as_bytes()) {
+            let read = match stream.read(&mut buffer) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              format!("{} closed before acknowledging",
+                                                      self.address)));
+                },
+                Ok(read) => read,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
+                                err.kind() == io::ErrorKind::TimedOut => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut,
+                                              format!("{} didn't acknowledge in time",
+                                                      self.address)));
+                },
+                Err(err) => return Err(err),
+            };
+            answer.extend_from_slice(&buffer[..read]);
+        }
+        Ok(())
+    }
+
+    // 128 random bits in base64, as fluentd's own chunk ids are.
+    fn chunk_id(&mut self) -> String {
+        self.chunks += 1;
+        let mut id = [0; 16];
+        for (i, half) in id.chunks_mut(8).enumerate() {
+            let mut hasher = self.random.build_hasher();
+            hasher.write_u64(self.chunks);
+            hasher.write_usize(i);
+            half.copy_from_slice(&hasher.finish().to_be_bytes());
+        }
+        base64(&id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FluentForwarder;
+    use crate::entry::LogEntry;
+    use chrono::Utc;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const LINE: &[u8] = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                          static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                          \"GET / HTTP/1.1\"";
+
+    #[test]
+    fn forwards() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut message = vec![];
+            let mut buffer = [0; 4096];
+            // the option map ends the message, and with it the 24 characters of the chunk id.
+            while !message.windows(6).any(|window| window == b"\xa5chunk") ||
+                  !message.ends_with(b"==") {
+                let read = stream.read(&mut buffer).unwrap();
+                assert_ne!(read, 0);
+                message.extend_from_slice(&buffer[..read]);
+            }
+            let chunk = message[message.len() - 25..].to_vec();
+            stream.write_all(b"\x81\xa3ack").unwrap();
+            stream.write_all(&chunk).unwrap();
+            message
+        });
+
+        let entry = LogEntry::from_bytes(LINE).unwrap();
+        let mut forwarder = FluentForwarder::new(&address, "haproxy.access");
+        forwarder.set_require_ack(true);
+        forwarder.write(&entry, &Utc).unwrap();
+        forwarder.write(&entry, &Utc).unwrap();
+        forwarder.flush().unwrap();
+        let message = server.join().unwrap();
+
+        // [tag, [[time, record], ...], {size, chunk}]
+        assert!(message.starts_with(b"\x93\xaehaproxy.access\x92\x92\xd7\x00"));
+        assert_eq!(message[20..28], [0x49, 0x8c, 0x29, 0x96, 0x27, 0x0a, 0x81, 0xc0]);
+        assert_eq!(message[28..31], *b"\xde\x00\x1c");
+        assert!(message.windows(9).any(|window| window == b"\x82\xa4size\x02\xa5c"));
+        assert_eq!(forwarder.count, 0);
+    }
+}
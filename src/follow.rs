@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+// reads lines from a file as they're appended, like `tail -F`. if the file is rotated (replaced or
+// truncated) reading starts over from the beginning of the new file.
+pub struct Follow {
+    path: PathBuf,
+    reader: BufReader<File>,
+    inode: u64,
+    position: u64,
+    poll_interval: Duration,
+}
+
+impl Follow {
+    // start following `path` from its current end, so only lines written from now on are read.
+    pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Follow> {
+        let mut follow = Follow::from_start(path)?;
+        follow.position = follow.reader.seek(SeekFrom::End(0))?;
+        Ok(follow)
+    }
+
+    // start following `path` from the beginning, reading everything already in it first.
+    pub fn from_start<P: Into<PathBuf>>(path: P) -> io::Result<Follow> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        let inode = file.metadata()?.ino();
+
+        Ok(Follow {
+            path,
+            reader: BufReader::new(file),
+            inode,
+            position: 0,
+            poll_interval: Duration::from_millis(250),
+        })
+    }
+
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+    }
+
+    // append the next complete line, including its newline, to `buf`. blocks until one is
+    // written.
+    pub fn read_line(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        loop {
+            let read = self.reader.read_until(b'\n', buf)?;
+            self.position += read as u64;
+            if buf.ends_with(b"\n") {
+                return Ok(buf.len() - start);
+            }
+
+            if read == 0 {
+                if self.rotated()? {
+                    // whatever was read of the old file's unterminated last line is all we'll get.
+                    self.reopen()?;
+                    if buf.len() > start {
+                        return Ok(buf.len() - start);
+                    }
+                } else {
+                    thread::sleep(self.poll_interval);
+                }
+            }
+        }
+    }
+
+    fn rotated(&self) -> io::Result<bool> {
+        match self.path.metadata() {
+            Ok(metadata) => Ok(metadata.ino() != self.inode || metadata.len() < self.position),
+            // the old file was moved away and the new one hasn't been created yet.
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        let file = File::open(&self.path)?;
+        self.inode = file.metadata()?.ino();
+        self.reader = BufReader::new(file);
+        self.position = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::process;
+
+    use super::Follow;
+
+    #[test]
+    fn follow_appends_and_truncation() {
+        let path = env::temp_dir().join(format!("haproxy-follow-test-{}", process::id()));
+        fs::write(&path, b"old\n").unwrap();
+
+        let mut follow = Follow::new(&path).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"first\nsec").unwrap();
+
+        let mut buf = vec![];
+        follow.read_line(&mut buf).unwrap();
+        assert_eq!(buf, b"first\n");
+
+        file.write_all(b"ond\n").unwrap();
+        buf.clear();
+        follow.read_line(&mut buf).unwrap();
+        assert_eq!(buf, b"second\n");
+
+        fs::write(&path, b"new\n").unwrap();
+        buf.clear();
+        follow.read_line(&mut buf).unwrap();
+        assert_eq!(buf, b"new\n");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
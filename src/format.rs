@@ -0,0 +1,393 @@
+use std::fmt;
+
+use crate::config::Config;
+use crate::entry::{LogEntry, Result, HEADER_FIELDS};
+use crate::slicer::{SliceError, SliceErrorKind, Slicer};
+
+// the formats haproxy's `option httplog`, `option httpslog` and `option tcplog` stand for.
+pub const HTTPLOG_FORMAT: &str = "%ci:%cp [%tr] %ft %b/%s %TR/%Tw/%Tc/%Tr/%Ta %ST %B %CC %CS \
+                                  %tsc %ac/%fc/%bc/%sc/%rc %sq/%bq %hr %hs %{+Q}r";
+pub const HTTPSLOG_FORMAT: &str = "%ci:%cp [%tr] %ft %b/%s %TR/%Tw/%Tc/%Tr/%Ta %ST %B %CC %CS \
+                                   %tsc %ac/%fc/%bc/%sc/%rc %sq/%bq %hr %hs %{+Q}r \
+                                   %[fc_err]/%[ssl_fc_err,hex]/%[ssl_c_err]/%[ssl_c_ca_err]/\
+                                   %[ssl_fc_is_resumed] %[ssl_fc_sni]/%sslv/%sslc";
+pub const TCPLOG_FORMAT: &str = "%ci:%cp [%t] %ft %b/%s %Tw/%Tc/%Tt %B %ts \
+                                 %ac/%fc/%bc/%sc/%rc %sq/%bq";
+
+// where a variable's text goes in the entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    // one of the header fields, by its position as in LogEntry::from_header_fields.
+    Header(usize),
+    // %hr or %hs, a capture block which is left out of the line when nothing is captured.
+    Capture,
+    Request,
+    // anything else is parsed past and dropped.
+    Ignored,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Item {
+    Text(Vec<u8>),
+    // a run of spaces, which haproxy writes as one space and only if the last thing it wrote
+    // wasn't one, so a capture block which is left out doesn't leave two spaces.
+    Space,
+    // `quoted` is the +Q option, haproxy puts the text in double quotes.
+    Variable { target: Target, quoted: bool },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FormatError(String);
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+// a parser for the lines a `log-format` writes, compiled from the format once. each variable is
+// cut out of the line by the text after it in the format, so two variables need something between
+// them unless the first is quoted or a capture block. the variables LogEntry has a field for end
+// up in it and the rest are skipped, so `%ci %ST %{+Q}r` gives an entry with only those three
+// fields and the others empty.
+//
+// lines start with the syslog header and `process[pid]: ` as for LogEntry::from_bytes, the format
+// describes what's after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogFormat {
+    template: String,
+    items: Vec<Item>,
+}
+
+impl LogFormat {
+    pub fn compile(template: &str) -> std::result::Result<LogFormat, FormatError> {
+        let mut items = vec![];
+        let mut names = vec![];
+        let bytes = template.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b' ' => {
+                    while i < bytes.len() && bytes[i] == b' ' {
+                        i += 1;
+                    }
+                    items.push(Item::Space);
+                },
+                b'%' if bytes.get(i + 1) == Some(&b'%') => {
+                    push_text(&mut items, b"%");
+                    i += 2;
+                },
+                b'%' => {
+                    i += 1;
+                    let mut quoted = false;
+                    if bytes.get(i) == Some(&b'{') {
+                        let end = find(bytes, i, b'}')
+                            .ok_or_else(|| error(format!("unterminated '{{' in {}", template)))?;
+                        quoted = template[i + 1..end].split(',').any(|option| option == "+Q");
+                        i = end + 1;
+                    }
+                    let name = if bytes.get(i) == Some(&b'[') {
+                        let end = find(bytes, i, b']')
+                            .ok_or_else(|| error(format!("unterminated '[' in {}", template)))?;
+                        i = end + 1;
+                        ""
+                    } else {
+                        let start = i;
+                        while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+                            i += 1;
+                        }
+                        if start == i {
+                            return Err(error(format!("missing variable name at byte {} of {}",
+                                                     start, template)));
+                        }
+                        &template[start..i]
+                    };
+                    names.push(name);
+                    items.push(Item::Variable { target: Target::Ignored, quoted });
+                },
+                _ => {
+                    let start = i;
+                    while i < bytes.len() && bytes[i] != b' ' && bytes[i] != b'%' {
+                        i += 1;
+                    }
+                    push_text(&mut items, &bytes[start..i]);
+                },
+            }
+        }
+
+        // %Ta is where %Tt was before haproxy 1.7, it's the total time unless %Tt is there too.
+        let total = if names.contains(&"Tt") { "Tt" } else { "Ta" };
+        let mut names = names.into_iter();
+        let mut previous: Option<Target> = None;
+        for item in &mut items {
+            match *item {
+                Item::Variable { ref mut target, quoted } => {
+                    *target = target_for(names.next().unwrap(), total);
+                    if previous.is_some() {
+                        return Err(error(format!("two variables without anything between them \
+                                                  in {}", template)));
+                    }
+                    if !quoted && *target != Target::Capture {
+                        previous = Some(*target);
+                    }
+                },
+                _ => previous = None,
+            }
+        }
+        Ok(LogFormat { template: template.to_string(), items })
+    }
+
+    // the format set for `frontend` in `config`, see Config::log_format.
+    pub fn from_config(config: &Config, frontend: &str)
+                       -> std::result::Result<LogFormat, FormatError> {
+        let section = config.frontend(frontend)
+            .ok_or_else(|| error(format!("no frontend {}", frontend)))?;
+        let template = config.log_format(section)
+            .ok_or_else(|| error(format!("frontend {} has no log format", frontend)))?;
+        LogFormat::compile(&template)
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn parse<'a>(&self, line: &'a [u8]) -> Result<LogEntry<'a>> {
+        let mut fields: [&[u8]; HEADER_FIELDS] = [b""; HEADER_FIELDS];
+        let mut captures: [&[u8]; 2] = [b""; 2];
+        let mut captured = 0;
+        let mut http_request: &[u8] = b"";
+
+        let mut slicer = Slicer::new(line);
+        fields[0] = slicer.slice_to(b'[')?;
+        fields[1] = slicer.slice_to_seq(b"]: ")?;
+
+        // whether a space was just parsed, which the next Space in the format is then part of.
+        let mut after_space = false;
+        let mut items = self.items.iter().peekable();
+        while let Some(item) = items.next() {
+            let (target, quoted) = match *item {
+                Item::Space => {
+                    if !after_space {
+                        slicer.discard(b" ")?;
+                        after_space = true;
+                    }
+                    continue;
+                },
+                Item::Text(ref text) => {
+                    slicer.discard(text)?;
+                    after_space = false;
+                    continue;
+                },
+                Item::Variable { target, quoted } => (target, quoted),
+            };
+
+            let mut space = false;
+            let value = if quoted {
+                slicer.discard(b"\"")?;
+                if items.peek().is_some() {
+                    slicer.slice_to(b'"')?
+                } else {
+                    slicer.slice_to_or_remainder(b'"')
+                }
+            } else if target == Target::Capture {
+                if slicer.peek() != Some(b'{') {
+                    continue;
+                }
+                slicer.discard(b"{")?;
+                slicer.slice_to(b'}')?
+            } else {
+                match items.peek() {
+                    Some(Item::Space) => {
+                        let value = slicer.slice_to(b' ')?;
+                        items.next();
+                        space = true;
+                        value
+                    },
+                    Some(Item::Text(ref text)) => {
+                        let value = slice_to_text(line, &mut slicer, text)?;
+                        items.next();
+                        value
+                    },
+                    _ => trim_newline(slicer.slice_to_or_remainder(b'\n')),
+                }
+            };
+            after_space = space;
+
+            match target {
+                Target::Header(i) => fields[i] = value,
+                Target::Capture if captured < captures.len() => {
+                    captures[captured] = value;
+                    captured += 1;
+                },
+                Target::Request => http_request = value,
+                Target::Capture | Target::Ignored => {},
+            }
+        }
+        Ok(LogEntry::from_header_fields(&fields, captures, http_request))
+    }
+}
+
+fn error(message: String) -> FormatError {
+    FormatError(message)
+}
+
+fn find(bytes: &[u8], from: usize, c: u8) -> Option<usize> {
+    bytes[from..].iter().position(|&b| b == c).map(|i| from + i)
+}
+
+fn push_text(items: &mut Vec<Item>, text: &[u8]) {
+    match items.last_mut() {
+        Some(Item::Text(ref mut previous)) => previous.extend_from_slice(text),
+        _ => items.push(Item::Text(text.to_vec())),
+    }
+}
+
+fn trim_newline(s: &[u8]) -> &[u8] {
+    let s = s.strip_suffix(b"\n").unwrap_or(s);
+    s.strip_suffix(b"\r").unwrap_or(s)
+}
+
+// up to `text` and past it, as Slicer::slice_to_seq does for the delimiters it knows of at compile
+// time. a missing `text` is reported where the field started.
+fn slice_to_text<'a>(line: &'a [u8], slicer: &mut Slicer<'a>, text: &[u8])
+                     -> Result<&'a [u8]> {
+    let checkpoint = slicer.checkpoint();
+    let start = slicer.offset();
+    loop {
+        if slicer.slice_to(text[0]).is_err() {
+            slicer.restore(checkpoint);
+            let kind = SliceErrorKind::ExpectedToken(text[0]);
+            return Err(SliceError { kind, offset: start, len: line.len() }.into());
+        }
+        if slicer.starts_with(&text[1..]) {
+            let end = slicer.offset() - 1;
+            slicer.discard(&text[1..])?;
+            return Ok(&line[start..end]);
+        }
+    }
+}
+
+fn target_for(name: &str, total: &str) -> Target {
+    let position = match name {
+        "ci" => 2,
+        "cp" => 3,
+        "t" | "tr" | "trl" => 4,
+        "f" | "ft" => 5,
+        "b" => 6,
+        "s" => 7,
+        "TR" | "Tq" => 8,
+        "Tw" => 9,
+        "Tc" => 10,
+        "Tr" => 11,
+        _ if name == total => 12,
+        "ST" => 13,
+        "B" => 14,
+        "CC" => 15,
+        "CS" => 16,
+        "ts" | "tsc" => 17,
+        "ac" => 18,
+        "fc" => 19,
+        "bc" => 20,
+        "sc" => 21,
+        "rc" => 22,
+        "sq" => 23,
+        "bq" => 24,
+        "hr" | "hs" => return Target::Capture,
+        "r" => return Target::Request,
+        _ => return Target::Ignored,
+    };
+    Target::Header(position)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LogFormat, HTTPLOG_FORMAT, TCPLOG_FORMAT};
+    use crate::config::Config;
+    use crate::entry::LogEntry;
+
+    const PREFIX: &str = "Feb  6 12:14:14 localhost haproxy[14389]: ";
+
+    #[test]
+    fn httplog_same_as_from_bytes() {
+        let format = LogFormat::compile(HTTPLOG_FORMAT).unwrap();
+        let lines = [
+            "10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 \
+             2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu} {text/html} \"GET /index.html HTTP/1.1\"\n",
+            "10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 \
+             2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu|} \"GET /index.html HTTP/1.1\"",
+            "10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in~ static/srv1 -1/0/30/69/109 503 \
+             0 - - SC-- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+            "10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 \
+             2750 - - ---- 1/1/1/1/0 0/0 \"GET /truncated",
+        ];
+        for line in &lines {
+            let line = format!("{}{}", PREFIX, line);
+            assert_eq!(format.parse(line.as_bytes()).unwrap(),
+                       LogEntry::from_bytes(line.as_bytes()).unwrap(), "{}", line);
+        }
+        assert!(format.parse(b"haproxy[1]: garbage").is_err());
+    }
+
+    #[test]
+    fn custom() {
+        let format = LogFormat::compile("%ci [%t] %ft %{+Q}r status=%ST %[ssl_fc_sni] %Ta")
+            .unwrap();
+        let line = b"haproxy[1]: 10.0.1.2 [06/Feb/2009:12:14:14.655] www \"GET / HTTP/1.1\" \
+                     status=404 example.com 12\n";
+        let entry = format.parse(line).unwrap();
+        assert_eq!(entry.client_ip, b"10.0.1.2");
+        assert_eq!(entry.client_port, b"");
+        assert_eq!(entry.accept_date, b"06/Feb/2009:12:14:14.655");
+        assert_eq!(entry.frontend_name, b"www");
+        assert_eq!(entry.http_request, b"GET / HTTP/1.1");
+        assert_eq!(entry.status_code().unwrap(), 404);
+        assert_eq!(entry.total_time().unwrap(), 12);
+
+        let tcp = LogFormat::compile(TCPLOG_FORMAT).unwrap();
+        let line = b"haproxy[1]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] db db/pg1 0/1/1056 \
+                     4410 -- 2/2/2/1/0 0/0";
+        let entry = tcp.parse(line).unwrap();
+        assert_eq!(entry.server_name, b"pg1");
+        assert_eq!(entry.total_time().unwrap(), 1056);
+        assert_eq!(entry.termination_state, b"--");
+        assert_eq!(entry.backend_queue, b"0");
+
+        let error = format.parse(b"haproxy[1]: 10.0.1.2 06/Feb").unwrap_err();
+        assert_eq!(error.offset(), Some(21));
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert!(LogFormat::compile("%ci%cp").is_err());
+        assert!(LogFormat::compile("%{+Q ci").is_err());
+        assert!(LogFormat::compile("%[src").is_err());
+        assert!(LogFormat::compile("100%").is_err());
+        assert!(LogFormat::compile("%hr%hs %{+Q}r%ST 100%%").is_ok());
+    }
+
+    #[test]
+    fn from_config() {
+        let config = Config::parse(r#"
+defaults
+    option httplog
+
+frontend www
+    bind :80
+
+frontend api
+    log-format "%ci [%tr] %ST %{+Q}r"
+
+frontend db
+    mode tcp
+    option tcplog
+"#).unwrap();
+        assert_eq!(LogFormat::from_config(&config, "www").unwrap().template(), HTTPLOG_FORMAT);
+        assert_eq!(LogFormat::from_config(&config, "api").unwrap().template(),
+                   "%ci [%tr] %ST %{+Q}r");
+        assert_eq!(LogFormat::from_config(&config, "db").unwrap().template(), TCPLOG_FORMAT);
+        assert!(LogFormat::from_config(&config, "nope").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+
+use chrono::TimeZone;
+use serde_json::{json, Value};
+
+use crate::entry::{LogEntry, HEADER_FIELD_NAMES};
+use crate::field::Field;
+use crate::integer::{parse_field, parse_i64};
+
+// the most chunks a GELF message can be split into over UDP.
+const MAX_CHUNKS: usize = 128;
+// the magic bytes, message id, sequence number and count which start each chunk.
+const CHUNK_HEADER: usize = 12;
+
+// `entry` as a GELF 1.1 message from `host`. the request is the short_message, accept_date taken
+// to be in `timezone` is the timestamp, and every field is an additional field under its
+// serialized name, numbers as numbers. the level is error for 5xx responses and requests which
+// never got one, warning for 4xx and informational otherwise.
+pub fn gelf_message<Tz: TimeZone>(entry: &LogEntry, timezone: &Tz, host: &str) -> Value {
+    let level = match parse_i64(entry.status_code) {
+        Some(400..=499) => 4,
+        Some(100..=399) => 6,
+        _ => 3,
+    };
+    let mut message = json!({
+        "version": "1.1",
+        "host": host,
+        "short_message": String::from_utf8_lossy(entry.http_request),
+        "level": level,
+    });
+    let accepted = entry.accept_date_time().ok()
+        .and_then(|accepted| timezone.from_local_datetime(&accepted).earliest());
+    if let Some(accepted) = accepted {
+        message["timestamp"] = (accepted.timestamp_millis() as f64 / 1000.0).into();
+    }
+
+    for (&name, field) in HEADER_FIELD_NAMES.iter().zip(Field::HEADER) {
+        let key = format!("_{}", name);
+        let content = field.extract_content_from(entry);
+        if field.is_numeric() {
+            if let Some(number) = parse_field(content) {
+                message[key] = number.into();
+            }
+        } else {
+            message[key] = String::from_utf8_lossy(content).into();
+        }
+    }
+    for (key, field) in [("_http_method", entry.http_method()), ("_http_uri", entry.http_uri()),
+                         ("_http_version", entry.http_version())] {
+        if let Some(field) = field {
+            message[key] = String::from_utf8_lossy(field).into();
+        }
+    }
+    for (key, field) in [("_captured_request_headers", entry.captures[0]),
+                         ("_captured_response_headers", entry.captures[1])] {
+        if !field.is_empty() {
+            message[key] = String::from_utf8_lossy(field).into();
+        }
+    }
+    message
+}
+
+// how GelfWriter reaches Graylog.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GelfTransport {
+    // a datagram per message, split into chunks when it's bigger than the chunk size.
+    Udp,
+    // messages delimited by a null byte on one connection.
+    Tcp,
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(BufWriter<TcpStream>),
+}
+
+// sends entries to a Graylog GELF input as gelf_message makes them. over TCP messages are
+// buffered until `flush`, which should be called at the end and whenever the input pauses, and
+// over UDP each is sent as it's written.
+pub struct GelfWriter {
+    connection: Connection,
+    host: String,
+    chunk_size: usize,
+    // for message ids, which only have to be unique.
+    messages: u64,
+    random: RandomState,
+    buffer: Vec<u8>,
+}
+
+impl GelfWriter {
+    // connects to the input at `address`, e.g. graylog:12201, as `host`.
+    pub fn connect(address: &str, transport: GelfTransport, host: &str) -> io::Result<GelfWriter> {
+        let connection = match transport {
+            GelfTransport::Udp => Connection::Udp(connect_udp(address)?),
+            GelfTransport::Tcp => Connection::Tcp(BufWriter::new(TcpStream::connect(address)?)),
+        };
+        Ok(GelfWriter {
+            connection,
+            host: host.to_string(),
+            chunk_size: 1420,
+            messages: 0,
+            random: RandomState::new(),
+            buffer: vec![],
+        })
+    }
+
+    // the biggest datagram sent over UDP, 1420 bytes by default to fit in an ethernet frame
+    // across most networks. graylog takes up to 8192 on a LAN.
+    pub fn set_chunk_size(&mut self, bytes: usize) {
+        self.chunk_size = bytes.max(CHUNK_HEADER + 1);
+    }
+
+    pub fn write<Tz: TimeZone>(&mut self, entry: &LogEntry, timezone: &Tz) -> io::Result<()> {
+        self.buffer.clear();
+        serde_json::to_writer(&mut self.buffer, &gelf_message(entry, timezone, &self.host))?;
+        match self.connection {
+            Connection::Udp(ref socket) => {
+                if self.buffer.len() <= self.chunk_size {
+                    socket.send(&self.buffer)?;
+                    return Ok(());
+                }
+                self.messages += 1;
+                let mut hasher = self.random.build_hasher();
+                hasher.write_u64(self.messages);
+                let id = hasher.finish().to_be_bytes();
+                for chunk in chunks(&self.buffer, id, self.chunk_size)? {
+                    socket.send(&chunk)?;
+                }
+                Ok(())
+            },
+            Connection::Tcp(ref mut stream) => {
+                stream.write_all(&self.buffer)?;
+                stream.write_all(b"\0")
+            },
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.connection {
+            Connection::Udp(_) => Ok(()),
+            Connection::Tcp(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+// `message` split into datagrams of at most `size` bytes with the chunk header graylog reassembles
+// them by. a message which needs more than 128 of them can't be sent.
+fn chunks(message: &[u8], id: [u8; 8], size: usize) -> io::Result<Vec<Vec<u8>>> {
+    let parts: Vec<&[u8]> = message.chunks(size - CHUNK_HEADER).collect();
+    if parts.len() > MAX_CHUNKS {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("a {} byte GELF message needs more than {} chunks",
+                                          message.len(), MAX_CHUNKS)));
+    }
+    Ok(parts.iter().enumerate().map(|(i, part)| {
+        let mut chunk = Vec::with_capacity(CHUNK_HEADER + part.len());
+        chunk.extend_from_slice(&[0x1e, 0x0f]);
+        chunk.extend_from_slice(&id);
+        chunk.extend_from_slice(&[i as u8, parts.len() as u8]);
+        chunk.extend_from_slice(part);
+        chunk
+    }).collect())
+}
+
+// a socket sending to the first of the addresses `address` resolves to which it can, bound to any
+// address of the same family.
+fn connect_udp(address: &str) -> io::Result<UdpSocket> {
+    let mut last_error = None;
+    for target in address.to_socket_addrs()? {
+        let local: SocketAddr = match target {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let result = UdpSocket::bind(local)
+            .and_then(|socket| socket.connect(target).map(|()| socket));
+        match result {
+            Ok(socket) => return Ok(socket),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no addresses", address))
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{chunks, gelf_message, GelfTransport, GelfWriter};
+    use crate::entry::LogEntry;
+    use chrono::Utc;
+    use std::net::UdpSocket;
+
+    const LINE: &[u8] = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                          static/srv1 10/0/30/69/109 503 2750 - - ---- 1/1/1/1/+1 0/0 \
+                          {1wt.eu} \"GET /index.html HTTP/1.1\"";
+
+    #[test]
+    fn messages() {
+        let entry = LogEntry::from_bytes(LINE).unwrap();
+        let message = gelf_message(&entry, &Utc, "lb1");
+        assert_eq!(message["version"], "1.1");
+        assert_eq!(message["host"], "lb1");
+        assert_eq!(message["short_message"], "GET /index.html HTTP/1.1");
+        assert_eq!(message["timestamp"], 1233922454.655);
+        assert_eq!(message["level"], 3);
+        assert_eq!(message["_status_code"], 503);
+        assert_eq!(message["_retried_connections"], 1);
+        assert_eq!(message["_backend_name"], "static");
+        assert_eq!(message["_http_uri"], "/index.html");
+        assert_eq!(message["_captured_request_headers"], "1wt.eu");
+        assert_eq!(message.get("_captured_response_headers"), None);
+    }
+
+    #[test]
+    fn chunking() {
+        let message = (0..100).collect::<Vec<u8>>();
+        let parts = chunks(&message, *b"messagid", 42).unwrap();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0][..12], *b"\x1e\x0fmessagid\x00\x04");
+        assert_eq!(parts[3][..12], *b"\x1e\x0fmessagid\x03\x04");
+        assert_eq!(parts.iter().map(|part| part.len() - 12).sum::<usize>(), 100);
+        assert!(chunks(&message, *b"messagid", 13).is_ok());
+        assert!(chunks(&[0; 129], *b"messagid", 13).is_err());
+
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let mut writer = GelfWriter::connect(&address, GelfTransport::Udp, "lb1").unwrap();
+        writer.set_chunk_size(200);
+        writer.write(&LogEntry::from_bytes(LINE).unwrap(), &Utc).unwrap();
+        let mut datagram = [0; 1500];
+        let mut whole = vec![];
+        loop {
+            let read = server.recv(&mut datagram).unwrap();
+            assert!(read <= 200);
+            whole.extend_from_slice(&datagram[12..read]);
+            if datagram[10] + 1 == datagram[11] {
+                break;
+            }
+        }
+        let message: serde_json::Value = serde_json::from_slice(&whole).unwrap();
+        assert_eq!(message["_pid"], 14389);
+
+        // an IPv6 input, where the loopback has an IPv6 address.
+        if let Ok(server) = UdpSocket::bind("[::1]:0") {
+            let address = server.local_addr().unwrap().to_string();
+            let mut writer = GelfWriter::connect(&address, GelfTransport::Udp, "lb1").unwrap();
+            writer.write(&LogEntry::from_bytes(LINE).unwrap(), &Utc).unwrap();
+            let read = server.recv(&mut datagram).unwrap();
+            let message: serde_json::Value = serde_json::from_slice(&datagram[..read]).unwrap();
+            assert_eq!(message["host"], "lb1");
+        }
+    }
+}
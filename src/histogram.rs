@@ -0,0 +1,105 @@
+// splits a range of values into buckets, either of equal width or growing exponentially. the
+// last bucket includes its upper bound so the maximum value always lands somewhere.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Buckets {
+    edges: Vec<f64>,
+}
+
+impl Buckets {
+    pub fn linear(min: f64, max: f64, count: usize) -> Buckets {
+        let count = count.max(1);
+        let width = if max > min { (max - min) / count as f64 } else { 1.0 };
+        Buckets {
+            edges: (0..count + 1).map(|i| min + width * i as f64).collect(),
+        }
+    }
+
+    // logarithmic buckets can't start at or below zero, so the first bucket starts at 1 (or
+    // `min` if that's larger) and also collects everything below it.
+    pub fn logarithmic(min: f64, max: f64, count: usize) -> Buckets {
+        let count = count.max(1);
+        let low = min.max(1.0);
+        let high = if max > low { max } else { low * 2.0 };
+        let ratio = (high / low).powf(1.0 / count as f64);
+
+        let mut edges: Vec<f64> = (0..count + 1).map(|i| low * ratio.powi(i as i32)).collect();
+        // avoid the final edge landing just below `max` due to rounding.
+        edges[count] = high;
+        Buckets {
+            edges,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.edges.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the lower and upper edge of bucket `i`.
+    pub fn bounds(&self, i: usize) -> (f64, f64) {
+        (self.edges[i], self.edges[i + 1])
+    }
+
+    pub fn index_of(&self, value: f64) -> Option<usize> {
+        let last = self.len() - 1;
+        if value > self.edges[last + 1] {
+            return None;
+        }
+        // the first edge of logarithmic buckets is clamped; anything below it goes in the first.
+        let i = self.edges[1..].iter().position(|&edge| value < edge).unwrap_or(last);
+        Some(i)
+    }
+
+    pub fn count<I: IntoIterator<Item = f64>>(&self, values: I) -> Vec<u64> {
+        let mut counts = vec![0; self.len()];
+        for value in values {
+            if let Some(i) = self.index_of(value) {
+                counts[i] += 1;
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Buckets;
+
+    #[test]
+    fn linear() {
+        let buckets = Buckets::linear(0.0, 100.0, 4);
+        assert_eq!(buckets.len(), 4);
+        assert_eq!(buckets.bounds(1), (25.0, 50.0));
+        assert_eq!(buckets.index_of(0.0), Some(0));
+        assert_eq!(buckets.index_of(25.0), Some(1));
+        assert_eq!(buckets.index_of(100.0), Some(3));
+        assert_eq!(buckets.index_of(100.5), None);
+    }
+
+    #[test]
+    fn logarithmic() {
+        let buckets = Buckets::logarithmic(0.0, 1000.0, 3);
+        assert_eq!(buckets.bounds(0).0, 1.0);
+        assert!((buckets.bounds(0).1 - 10.0).abs() < 1e-9);
+        assert_eq!(buckets.bounds(2).1, 1000.0);
+        assert_eq!(buckets.index_of(0.0), Some(0));
+        assert_eq!(buckets.index_of(50.0), Some(1));
+        assert_eq!(buckets.index_of(1000.0), Some(2));
+    }
+
+    #[test]
+    fn count() {
+        let buckets = Buckets::linear(0.0, 10.0, 2);
+        assert_eq!(buckets.count(vec![0.0, 1.0, 5.0, 10.0, 11.0]), vec![2, 2]);
+    }
+
+    #[test]
+    fn single_value() {
+        let buckets = Buckets::linear(5.0, 5.0, 3);
+        assert_eq!(buckets.index_of(5.0), Some(0));
+    }
+}
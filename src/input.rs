@@ -0,0 +1,327 @@
+use flate2::read::MultiGzDecoder;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::io::{Cursor, Read};
+use std::ops::Range;
+
+use crate::lines::{long_line_error, LineReader, LongLines};
+use crate::newline::find_newline;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+// formats which are recognized only to say they can't be read, rather than parsing them as logs.
+const UNSUPPORTED_MAGIC: &[(&[u8], &str)] = &[
+    (b"\x28\xb5\x2f\xfd", "zstd"),
+    (b"\xfd7zXZ\x00", "xz"),
+    (b"BZh", "bzip2"),
+];
+
+fn check_magic(start: &[u8]) -> io::Result<bool> {
+    for &(magic, format) in UNSUPPORTED_MAGIC {
+        if start.starts_with(magic) {
+            let message = format!("{} compressed input isn't supported", format);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+    }
+    Ok(start.starts_with(GZIP_MAGIC))
+}
+
+enum Source {
+    // the whole file, lines are handed out straight from the page cache.
+    Mapped { map: Mmap, position: usize },
+    Stream(LineReader<Box<dyn Read>>),
+}
+
+// one input file, memory mapped when it's a regular file so lines are sliced out of it without any
+// read calls or copying, and read through a LineReader when it's stdin ("-"), a pipe or gzipped.
+// gzip is recognized by its magic bytes rather than the file name. haproxy-cut -f ip,status over
+// 800k lines took ~135ms mapped, against ~150ms through a LineReader.
+pub struct Input {
+    source: Source,
+    max_line_length: usize,
+    long_lines: LongLines,
+}
+
+impl Input {
+    fn new(source: Source) -> Input {
+        Input {
+            source,
+            max_line_length: usize::MAX,
+            long_lines: LongLines::Skip,
+        }
+    }
+
+    pub fn open(path: &str) -> io::Result<Input> {
+        if path == "-" {
+            return Input::from_reader(io::stdin());
+        }
+
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        // empty files can't be mapped, and whatever isn't a regular file might not be mappable or
+        // might change size under the map.
+        if !metadata.is_file() || metadata.len() == 0 {
+            return Input::from_reader(file);
+        }
+
+        // the map is only valid as long as nobody truncates the file while it's read, which is
+        // what logrotate's copytruncate does. reading a file which is being truncated is already
+        // hopeless, with a map it ends in SIGBUS rather than garbage.
+        let map = unsafe { Mmap::map(&file)? };
+        #[cfg(unix)]
+        let _ = map.advise(memmap2::Advice::Sequential);
+
+        let source = if check_magic(&map)? {
+            let decoder: Box<dyn Read> = Box::new(MultiGzDecoder::new(Cursor::new(map)));
+            Source::Stream(LineReader::new(decoder))
+        } else {
+            Source::Mapped { map, position: 0 }
+        };
+        Ok(Input::new(source))
+    }
+
+    // read lines from `reader`, decompressing it if it's gzipped.
+    pub fn from_reader<R: Read + 'static>(mut reader: R) -> io::Result<Input> {
+        // look at the first few bytes, and put them back in front of the rest.
+        let mut start = [0; 8];
+        let mut read = 0;
+        while read < start.len() {
+            match reader.read(&mut start[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(err),
+            }
+        }
+        let reader = Cursor::new(start[..read].to_vec()).chain(reader);
+
+        let reader: Box<dyn Read> = if check_magic(&start[..read])? {
+            Box::new(MultiGzDecoder::new(reader))
+        } else {
+            Box::new(reader)
+        };
+        Ok(Input::new(Source::Stream(LineReader::new(reader))))
+    }
+
+    // see LineReader::set_max_line_length.
+    pub fn set_max_line_length(&mut self, max_line_length: usize, long_lines: LongLines) {
+        self.max_line_length = max_line_length;
+        self.long_lines = long_lines;
+        if let Source::Stream(ref mut reader) = self.source {
+            reader.set_max_line_length(max_line_length, long_lines);
+        }
+    }
+
+    // the next line including its newline, the last line might not have one. None at the end of
+    // the input.
+    pub fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        let range = self.next_range()?;
+        Ok(range.map(move |range| self.line(range)))
+    }
+
+    fn next_range(&mut self) -> io::Result<Option<Range<usize>>> {
+        let (map, position) = match self.source {
+            Source::Mapped { ref map, ref mut position } => (map, position),
+            Source::Stream(ref mut reader) => return reader.next_range(),
+        };
+        loop {
+            let start = *position;
+            if start == map.len() {
+                return Ok(None);
+            }
+            let newline = find_newline(&map[start..]).map(|i| start + i);
+            *position = newline.map_or(map.len(), |newline| newline + 1);
+            if newline.unwrap_or(map.len()) - start > self.max_line_length {
+                match self.long_lines {
+                    LongLines::Skip => continue,
+                    LongLines::Truncate => {
+                        return Ok(Some(start..start + self.max_line_length))
+                    },
+                    LongLines::Error => return Err(long_line_error(self.max_line_length)),
+                }
+            }
+            return Ok(Some(start..*position));
+        }
+    }
+
+    fn line(&self, range: Range<usize>) -> &[u8] {
+        match self.source {
+            Source::Mapped { ref map, .. } => &map[range],
+            Source::Stream(ref reader) => reader.line(range),
+        }
+    }
+
+    // what's available without reading, see LineReader::buffer. all of a mapped file is.
+    pub fn buffer(&self) -> &[u8] {
+        match self.source {
+            Source::Mapped { ref map, position } => &map[position..],
+            Source::Stream(ref reader) => reader.buffer(),
+        }
+    }
+}
+
+// the lines of each file in turn, or of stdin if there are none. unlike reading the files as one
+// stream, the last line of a file is never joined with the first of the next when it's missing its
+// newline.
+pub struct Inputs {
+    paths: Vec<String>,
+    opened: usize,
+    current: Option<Input>,
+    max_line_length: usize,
+    long_lines: LongLines,
+}
+
+impl Inputs {
+    pub fn new(paths: &[String]) -> Inputs {
+        let paths = if paths.is_empty() { vec!["-".to_string()] } else { paths.to_vec() };
+        Inputs {
+            paths,
+            opened: 0,
+            current: None,
+            max_line_length: usize::MAX,
+            long_lines: LongLines::Skip,
+        }
+    }
+
+    // see LineReader::set_max_line_length, this applies to every file.
+    pub fn set_max_line_length(&mut self, max_line_length: usize, long_lines: LongLines) {
+        self.max_line_length = max_line_length;
+        self.long_lines = long_lines;
+        if let Some(ref mut input) = self.current {
+            input.set_max_line_length(max_line_length, long_lines);
+        }
+    }
+
+    // the file being read, or the one which failed to open or read, "-" for stdin. none before
+    // the first line is read.
+    pub fn path(&self) -> Option<&str> {
+        self.opened.checked_sub(1).map(|i| self.paths[i].as_str())
+    }
+
+    // the next line of whichever file is being read. an error opening or reading a file is
+    // returned once and reading carries on with the next file after it.
+    pub fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        loop {
+            if let Some(ref mut input) = self.current {
+                if let Some(range) = input.next_range()? {
+                    return Ok(Some(self.current.as_ref().unwrap().line(range)));
+                }
+            }
+            self.current = None;
+            if self.opened == self.paths.len() {
+                return Ok(None);
+            }
+            self.opened += 1;
+            let mut input = Input::open(&self.paths[self.opened - 1])?;
+            input.set_max_line_length(self.max_line_length, self.long_lines);
+            self.current = Some(input);
+        }
+    }
+
+    pub fn buffer(&self) -> &[u8] {
+        self.current.as_ref().map_or(&[], |input| input.buffer())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Input, Inputs};
+    use crate::lines::LongLines;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::env;
+    use std::fs;
+    use std::io::{Cursor, Write};
+    use std::path::PathBuf;
+    use std::process;
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("haproxy-input-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn gzip(contents: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(contents).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn lines(input: &mut Input) -> Vec<Vec<u8>> {
+        let mut lines = vec![];
+        while let Some(line) = input.next_line().unwrap() {
+            lines.push(line.to_vec());
+        }
+        lines
+    }
+
+    #[test]
+    fn mapped_and_read() {
+        let contents = b"first\nsecond\n\nlast";
+        let expected = vec![b"first\n".to_vec(), b"second\n".to_vec(), b"\n".to_vec(),
+                            b"last".to_vec()];
+
+        let path = temp_file("plain", contents);
+        let mut input = Input::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(input.buffer(), &contents[..]);
+        assert_eq!(lines(&mut input), expected);
+        assert!(input.buffer().is_empty());
+        fs::remove_file(&path).unwrap();
+
+        let path = temp_file("plain.gz", &gzip(contents));
+        assert_eq!(lines(&mut Input::open(path.to_str().unwrap()).unwrap()), expected);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(lines(&mut Input::from_reader(&contents[..]).unwrap()), expected);
+        let gzipped = gzip(contents);
+        assert_eq!(lines(&mut Input::from_reader(Cursor::new(gzipped)).unwrap()), expected);
+        assert!(lines(&mut Input::from_reader(&b""[..]).unwrap()).is_empty());
+        assert_eq!(lines(&mut Input::from_reader(&b"\x1f"[..]).unwrap()), vec![b"\x1f".to_vec()]);
+
+        assert!(Input::from_reader(&b"\x28\xb5\x2f\xfd\0\0"[..]).is_err());
+    }
+
+    #[test]
+    fn several_files() {
+        let first = temp_file("first", b"one\ntwo");
+        let empty = temp_file("empty", b"");
+        let second = temp_file("second", &gzip(b"three\n"));
+        let paths: Vec<String> = [&first, &empty, &second].iter()
+            .map(|path| path.to_str().unwrap().to_string())
+            .collect();
+
+        let mut inputs = Inputs::new(&paths);
+        let mut lines = vec![];
+        while let Some(line) = inputs.next_line().unwrap() {
+            lines.push(line.to_vec());
+        }
+        // the missing newline at the end of the first file doesn't join it to the next.
+        assert_eq!(lines, vec![b"one\n".to_vec(), b"two".to_vec(), b"three\n".to_vec()]);
+
+        let mut inputs = Inputs::new(&["/nonexistent".to_string(), paths[0].clone()]);
+        assert_eq!(inputs.path(), None);
+        assert!(inputs.next_line().is_err());
+        assert_eq!(inputs.path(), Some("/nonexistent"));
+        assert_eq!(inputs.next_line().unwrap(), Some(&b"one\n"[..]));
+        assert_eq!(inputs.path(), Some(&*paths[0]));
+
+        let mut inputs = Inputs::new(&paths);
+        inputs.set_max_line_length(3, LongLines::Truncate);
+        assert_eq!(inputs.next_line().unwrap(), Some(&b"one\n"[..]));
+        assert_eq!(inputs.next_line().unwrap(), Some(&b"two"[..]));
+        assert_eq!(inputs.next_line().unwrap(), Some(&b"thr"[..]));
+        inputs.set_max_line_length(3, LongLines::Error);
+        assert_eq!(inputs.next_line().unwrap(), None);
+
+        let mut inputs = Inputs::new(&paths);
+        inputs.set_max_line_length(3, LongLines::Error);
+        assert_eq!(inputs.next_line().unwrap(), Some(&b"one\n"[..]));
+        assert_eq!(inputs.next_line().unwrap(), Some(&b"two"[..]));
+        assert!(inputs.next_line().is_err());
+        assert_eq!(inputs.next_line().unwrap(), None);
+
+        for path in &[first, empty, second] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// integers straight from the bytes of a log line, without checking it's utf8 first and without
+// going through str::parse's general purpose loop. these accept exactly what str::parse does, a `+`
+// or (for i64) a `-` followed by at least one digit, and None is whatever str::parse rejects.
+
+use core::convert::{TryFrom, TryInto};
+
+const ZEROES: u64 = 0x3030_3030_3030_3030;
+// the most digits which always fit in a u64.
+const SAFE_DIGITS: usize = 19;
+
+// the value of 8 ascii digits at once, or None if any of them isn't one.
+#[inline]
+fn parse_eight(digits: &[u8; 8]) -> Option<u64> {
+    // the first digit ends up in the lowest byte.
+    let word = u64::from_le_bytes(*digits);
+
+    // every byte is 0x30..=0x39: its high nibble is 3, and adding 6 doesn't carry into it.
+    let high_nibbles = 0xf0f0_f0f0_f0f0_f0f0;
+    if word & high_nibbles != ZEROES ||
+        word.wrapping_add(0x0606_0606_0606_0606) & high_nibbles != ZEROES {
+        return None;
+    }
+
+    // combine neighbouring digits, then pairs of those, then the two halves.
+    let word = word - ZEROES;
+    let word = (word * 10 + (word >> 8)) & 0x00ff_00ff_00ff_00ff;
+    let word = (word * 100 + (word >> 16)) & 0x0000_ffff_0000_ffff;
+    Some((word * 10000 + (word >> 32)) & 0xffff_ffff)
+}
+
+// most numeric fields are a handful of digits, which a plain loop gets through fastest: padding
+// them out to convert eight at once came out slower than str::parse for 5 or 6 digits. only the 8
+// digit chunks of longer numbers are converted at once. converting five fields of each entry took
+// ~9ns a field this way against ~14ns with str::from_utf8 and parse.
+fn parse_digits(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() {
+        return None;
+    }
+    if digits.len() > SAFE_DIGITS {
+        return digits.iter().try_fold(0u64, |value, &c| {
+            if !c.is_ascii_digit() {
+                return None;
+            }
+            value.checked_mul(10)?.checked_add((c - b'0') as u64)
+        });
+    }
+
+    let (head, chunks) = digits.split_at(digits.len() % 8);
+    let mut value = 0;
+    for &c in head {
+        let digit = c.wrapping_sub(b'0');
+        if digit > 9 {
+            return None;
+        }
+        value = value * 10 + digit as u64;
+    }
+    for chunk in chunks.chunks_exact(8) {
+        value = value * 100_000_000 + parse_eight(chunk.try_into().unwrap())?;
+    }
+    Some(value)
+}
+
+pub fn parse_u64(buf: &[u8]) -> Option<u64> {
+    match buf.split_first() {
+        Some((b'+', digits)) => parse_digits(digits),
+        _ => parse_digits(buf),
+    }
+}
+
+pub fn parse_i64(buf: &[u8]) -> Option<i64> {
+    match buf.split_first() {
+        Some((b'-', digits)) => {
+            let magnitude = parse_digits(digits)?;
+            if magnitude == i64::MIN.unsigned_abs() {
+                Some(i64::MIN)
+            } else {
+                i64::try_from(magnitude).ok().map(|magnitude| -magnitude)
+            }
+        },
+        Some((b'+', digits)) => i64::try_from(parse_digits(digits)?).ok(),
+        _ => i64::try_from(parse_digits(buf)?).ok(),
+    }
+}
+
+// a numeric field as haproxy logs it. Tt and bytes_read have a '+' before them with `option
+// logasap`, and retries does when the request was redispatched.
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) fn parse_field(field: &[u8]) -> Option<i64> {
+    parse_i64(field.strip_prefix(b"+").unwrap_or(field))
+}
+
+// the integer types of LogEntry's accessors.
+pub(crate) trait FromDigits: Sized {
+    fn from_digits(buf: &[u8]) -> Option<Self>;
+}
+
+impl FromDigits for i64 {
+    fn from_digits(buf: &[u8]) -> Option<i64> {
+        parse_i64(buf)
+    }
+}
+
+impl FromDigits for u64 {
+    fn from_digits(buf: &[u8]) -> Option<u64> {
+        parse_u64(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_field, parse_i64, parse_u64};
+    use std::str;
+
+    fn check(input: &[u8]) {
+        let text = str::from_utf8(input).ok();
+        assert_eq!(parse_i64(input), text.and_then(|text| text.parse().ok()), "{:?}", text);
+        assert_eq!(parse_u64(input), text.and_then(|text| text.parse().ok()), "{:?}", text);
+    }
+
+    #[test]
+    fn same_as_str_parse() {
+        let inputs: &[&[u8]] = &[
+            b"", b"+", b"-", b"0", b"-0", b"+0", b"7", b"42", b"-1", b"+5", b"200", b"2750",
+            b"12345678", b"123456789", b"9999999999999999", b"00000000000000000001",
+            b"9223372036854775807", b"9223372036854775808", b"-9223372036854775808",
+            b"-9223372036854775809", b"18446744073709551615", b"18446744073709551616",
+            b"000000000000000000000000000042", b"1 ", b" 1", b"1a", b"12345678x", b"--1", b"+-1",
+            b"/", b":", b"\xff", b"\xc3\xa9", b"1\n", b"0x10", b"1_000",
+        ];
+        for input in inputs {
+            check(input);
+        }
+
+        // every byte in every position of a few lengths, to catch a digit check which lets
+        // something slip through.
+        for len in 1..=10 {
+            for position in 0..len {
+                for byte in 0..=255u8 {
+                    let mut input = vec![b'5'; len];
+                    input[position] = byte;
+                    check(&input);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(parse_field(b"+109"), Some(109));
+        assert_eq!(parse_field(b"+-1"), Some(-1));
+        assert_eq!(parse_field(b"-1"), Some(-1));
+        assert_eq!(parse_field(b"+"), None);
+        assert_eq!(parse_field(b"-"), None);
+    }
+
+    #[test]
+    fn values() {
+        let mut n: u64 = 1;
+        while let Some(next) = n.checked_mul(3) {
+            for m in &[n - 1, n, n + 1] {
+                assert_eq!(parse_u64(m.to_string().as_bytes()), Some(*m));
+                let signed = -(*m as i128);
+                if signed >= i64::MIN as i128 {
+                    assert_eq!(parse_i64(signed.to_string().as_bytes()), Some(signed as i64));
+                }
+            }
+            n = next;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::io;
+use std::io::Write;
+
+use crate::entry::LogEntry;
+use crate::expr::ExprError;
+use crate::field::{Field, FIELD_NAMES};
+use crate::integer::parse_field;
+
+// which fields write_entry_json writes and the name of each, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FieldSet {
+    fields: Vec<(String, Field)>,
+}
+
+impl FieldSet {
+    pub fn new() -> FieldSet {
+        FieldSet::default()
+    }
+
+    // every field in FIELD_NAMES, captured headers aside.
+    pub fn all() -> FieldSet {
+        let mut set = FieldSet::new();
+        for &(name, _) in FIELD_NAMES {
+            if let Ok(field) = Field::decode(name) {
+                set.push(name, field);
+            }
+        }
+        set
+    }
+
+    // a comma separated list of field names or aliases, as haproxy-cut's --fields takes them
+    // without expressions. each field is named as it's given, like haproxy-cut's columns.
+    pub fn parse(list: &str) -> Result<FieldSet, ExprError> {
+        let mut set = FieldSet::new();
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let field = Field::decode(name)?;
+            set.push(name, field);
+        }
+        Ok(set)
+    }
+
+    pub fn push(&mut self, name: &str, field: Field) {
+        self.fields.push((name.to_string(), field));
+    }
+
+    // each field with its name, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Field)> {
+        self.fields.iter().map(|(name, field)| (&**name, *field))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+// write `fields` of `entry` to `out` as a JSON object on a line of its own, for JSON lines output.
+// numeric fields are numbers, or null when haproxy logged something else there, and the rest are
+// strings of what was logged.
+pub fn write_entry_json<W: Write>(entry: &LogEntry, out: &mut W, fields: &FieldSet)
+                                  -> io::Result<()> {
+    out.write_all(b"{")?;
+    for (i, (name, field)) in fields.fields.iter().enumerate() {
+        if i != 0 {
+            out.write_all(b",")?;
+        }
+        write_string(out, name.as_bytes())?;
+        out.write_all(b":")?;
+        let content = field.extract_content_from(entry);
+        if field.is_numeric() {
+            match parse_field(content) {
+                Some(number) => write!(out, "{}", number)?,
+                None => out.write_all(b"null")?,
+            }
+        } else {
+            write_string(out, content)?;
+        }
+    }
+    out.write_all(b"}\n")
+}
+
+fn write_string<W: Write>(out: &mut W, content: &[u8]) -> io::Result<()> {
+    serde_json::to_writer(out, &*String::from_utf8_lossy(content)).map_err(io::Error::from)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_entry_json, FieldSet};
+    use crate::entry::LogEntry;
+    use crate::field::Field;
+
+    #[test]
+    fn objects() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 10/-1/30/69/+109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                     {\"quoted\\\"} \"GET /index.html HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+
+        let fields = FieldSet::parse("ip, status,Tw,Tt,method,captured_header[0][0]").unwrap();
+        let mut out = vec![];
+        write_entry_json(&entry, &mut out, &fields).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "{\"ip\":\"10.0.1.2\",\"status\":200,\"Tw\":-1,\"Tt\":109,\
+                    \"method\":\"GET\",\"captured_header[0][0]\":\"\\\"quoted\\\\\\\"\"}\n");
+
+        let mut fields = FieldSet::new();
+        fields.push("pid", Field::ProcessName);
+        let mut out = vec![];
+        write_entry_json(&entry, &mut out, &fields).unwrap();
+        assert_eq!(out, b"{\"pid\":\"haproxy\"}\n");
+
+        let all = FieldSet::all();
+        let mut out = vec![];
+        write_entry_json(&entry, &mut out, &all).unwrap();
+        let object: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(object.as_object().unwrap().len(), all.len());
+        assert_eq!(object["bytes_read"], 2750);
+        assert_eq!(object["status_class"], "2xx");
+
+        assert!(FieldSet::parse("ip,nope").is_err());
+        assert!(FieldSet::parse("").unwrap().is_empty());
+    }
+}
@@ -1,4 +1,213 @@
+// only the log entry parser is built without the default `std` feature, it needs nothing beyond
+// core so it can be embedded where std isn't available.
+#![cfg_attr(not(feature = "std"), no_std)]
+
 mod slicer;
 mod entry;
+mod integer;
+mod newline;
+#[cfg(any(feature = "std", feature = "wasm", feature = "ffi"))]
+mod field;
+#[cfg(feature = "std")]
+mod expr;
+#[cfg(feature = "std")]
+mod filter;
+#[cfg(feature = "std")]
+mod table;
+#[cfg(feature = "std")]
+mod follow;
+#[cfg(feature = "std")]
+mod histogram;
+#[cfg(feature = "std")]
+mod color;
+#[cfg(feature = "std")]
+mod runtime;
+#[cfg(feature = "std")]
+mod captured;
+#[cfg(feature = "std")]
+mod diagnostics;
+#[cfg(any(feature = "scrape", feature = "fluentd"))]
+mod base64;
+#[cfg(feature = "scrape")]
+mod scrape;
+#[cfg(feature = "std")]
+mod plan;
+#[cfg(feature = "std")]
+mod lines;
+#[cfg(feature = "std")]
+mod input;
+#[cfg(feature = "std")]
+mod owned;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(feature = "std")]
+mod format;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "std")]
+mod metrics;
+#[cfg(feature = "clickhouse")]
+mod clickhouse;
+#[cfg(feature = "std")]
+mod msgpack;
+#[cfg(feature = "std")]
+mod ecs;
+#[cfg(feature = "std")]
+mod attributes;
+#[cfg(feature = "cli")]
+mod timezone;
+#[cfg(feature = "fluentd")]
+mod fluentd;
+#[cfg(feature = "gelf")]
+mod gelf;
+#[cfg(feature = "splunk")]
+mod splunk;
+#[cfg(feature = "loki")]
+mod loki;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "std")]
+mod agg;
+#[cfg(feature = "std")]
+mod sessions;
+#[cfg(feature = "std")]
+mod slo;
+#[cfg(feature = "std")]
+mod anomaly;
+#[cfg(feature = "std")]
+mod topk;
+#[cfg(feature = "std")]
+mod ratelimit;
+#[cfg(feature = "std")]
+mod queueing;
+#[cfg(feature = "std")]
+mod retries;
+#[cfg(feature = "std")]
+mod concurrency;
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "arena")]
+mod arena;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "avro")]
+mod avro;
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "pyo3")]
+mod pyo3;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "otel")]
+mod otel;
 
 pub use self::entry::*;
+pub use self::integer::{parse_i64, parse_u64};
+pub use self::slicer::{Checkpoint, SliceError, SliceErrorKind, Slicer};
+#[cfg(feature = "std")]
+pub use self::field::{canonical_field_name, write_fields_into, write_fields_vectored, Field,
+                      FIELD_NAMES};
+#[cfg(feature = "std")]
+pub use self::expr::{Expr, ExprError, Number, Operator, Value};
+#[cfg(feature = "std")]
+pub use self::filter::{parse_date, Comparison, Condition, Filter};
+#[cfg(feature = "std")]
+pub use self::table::Table;
+#[cfg(feature = "std")]
+pub use self::follow::Follow;
+#[cfg(feature = "std")]
+pub use self::histogram::Buckets;
+#[cfg(feature = "std")]
+pub use self::color::{color_for, COLOR_BOLD_RED, COLOR_GREEN, COLOR_RED, COLOR_RESET, COLOR_YELLOW};
+#[cfg(feature = "std")]
+pub use self::runtime::{parse_info, parse_proc, Address, Info, OnProcess, PatternEntry,
+                        PatternList, Process, ProcessInfo, Runtime, RuntimeClient, RuntimeError,
+                        RuntimeSession, ServerState, StatFilter, StatKind, StatRow, StatsCsv,
+                        StickTable, TableEntry, TableOperator, TableQuery, Weight};
+#[cfg(feature = "scrape")]
+pub use self::scrape::StatsPage;
+#[cfg(feature = "std")]
+pub use self::captured::{parse_errors, CapturedError, Direction};
+#[cfg(feature = "std")]
+pub use self::diagnostics::{parse_fds, Activity, FileDescriptor};
+#[cfg(feature = "std")]
+pub use self::plan::Plan;
+#[cfg(feature = "std")]
+pub use self::lines::{LineReader, LongLines};
+#[cfg(feature = "std")]
+pub use self::input::{Input, Inputs};
+#[cfg(feature = "std")]
+pub use self::owned::OwnedLogEntry;
+#[cfg(feature = "std")]
+pub use self::config::{CaptureSlot, Captures, Config, ConfigError, Directive, Section,
+                       SectionKind, UnknownName};
+#[cfg(feature = "std")]
+pub use self::format::{FormatError, LogFormat, HTTPLOG_FORMAT, HTTPSLOG_FORMAT, TCPLOG_FORMAT};
+#[cfg(feature = "std")]
+pub use self::json::{write_entry_json, FieldSet};
+#[cfg(feature = "std")]
+pub use self::metrics::{LogMetrics, DEFAULT_BUCKETS};
+#[cfg(feature = "clickhouse")]
+pub use self::clickhouse::{encode_clickhouse_row, ClickHouseFormat, ClickHouseWriter,
+                           CLICKHOUSE_DDL};
+#[cfg(feature = "std")]
+pub use self::msgpack::encode_msgpack;
+#[cfg(feature = "std")]
+pub use self::ecs::{ecs_document, write_entry_ecs, ECS_VERSION};
+#[cfg(feature = "std")]
+pub use self::attributes::entry_attributes;
+#[cfg(feature = "cli")]
+pub use self::timezone::TimeZoneArg;
+#[cfg(feature = "fluentd")]
+pub use self::fluentd::FluentForwarder;
+#[cfg(feature = "gelf")]
+pub use self::gelf::{gelf_message, GelfTransport, GelfWriter};
+#[cfg(feature = "splunk")]
+pub use self::splunk::SplunkHecWriter;
+#[cfg(feature = "loki")]
+pub use self::loki::{LokiLineFormat, LokiWriter};
+#[cfg(feature = "s3")]
+pub use self::s3::{S3Format, S3Writer, DEFAULT_S3_KEY_TEMPLATE};
+#[cfg(feature = "std")]
+pub use self::agg::{GroupedDigests, TDigest, DEFAULT_COMPRESSION};
+#[cfg(feature = "std")]
+pub use self::sessions::{Session, Sessionizer};
+#[cfg(feature = "std")]
+pub use self::slo::{Objective, Outcome, RollingWindow, SloCounts};
+#[cfg(feature = "std")]
+pub use self::anomaly::{AnomalyDetector, AnomalyEvent, Signal};
+#[cfg(feature = "std")]
+pub use self::topk::{HeavyHitter, HeavyHitters, TopK};
+#[cfg(feature = "std")]
+pub use self::ratelimit::{ClientRates, Offender};
+#[cfg(feature = "std")]
+pub use self::queueing::{Correlation, QueueAnalysis, QueueStats};
+#[cfg(feature = "std")]
+pub use self::retries::{RetryCounts, RetryStorm, RetryStorms};
+#[cfg(feature = "std")]
+pub use self::concurrency::{BackendConcurrency, ConcurrencyInterval, ConcurrencyTrends,
+                            FrontendConcurrency, Gauge};
+#[cfg(feature = "async")]
+pub use self::stream::LogStream;
+#[cfg(feature = "arena")]
+pub use self::arena::ArenaLogEntry;
+#[cfg(feature = "arrow")]
+pub use self::arrow::{arrow_schema, ArrowBuilder};
+#[cfg(feature = "parquet")]
+pub use self::parquet::{ParquetCompression, ParquetLogWriter};
+#[cfg(feature = "avro")]
+pub use self::avro::{encode_avro, encode_avro_message, AVRO_SCHEMA};
+#[cfg(feature = "csv")]
+pub use self::csv::CsvLogWriter;
+#[cfg(feature = "ffi")]
+pub use self::ffi::{haproxy_entry, haproxy_entry_accept_date_ms, haproxy_entry_field,
+                  haproxy_entry_number, haproxy_field, haproxy_parse_line, HAPROXY_FIELDS};
+#[cfg(feature = "wasm")]
+pub use self::wasm::{parse_line, parse_lines};
+#[cfg(feature = "otel")]
+pub use self::otel::{accept_time, emit_log_record, fill_log_record, otel_attributes, record_span,
+                   span_builder};
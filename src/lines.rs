@@ -0,0 +1,259 @@
+use std::io;
+use std::io::Read;
+use std::ops::Range;
+
+use crate::newline::find_newline;
+
+const DEFAULT_CAPACITY: usize = 256 * 1024;
+
+// what to do with a line longer than the limit set with set_max_line_length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LongLines {
+    // leave it out, as if it wasn't there.
+    Skip,
+    // hand out as much of it as the limit allows, without its newline, and drop the rest. like
+    // the last line of an input without one, a caller writing it out has to end it itself.
+    Truncate,
+    // return an error in its place, the next call carries on with the line after it.
+    Error,
+}
+
+pub(crate) fn long_line_error(max_line_length: usize) -> io::Error {
+    let message = format!("line longer than {} bytes", max_line_length);
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// reads lines into one large buffer and hands out slices of it, rather than copying each line into
+// a buffer of its own like BufRead::read_until. a line which runs past the end of the buffer is
+// moved to its start before reading more, and the buffer doubles for a line longer than all of it,
+// up to the maximum line length if there is one.
+pub struct LineReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    // buffer[start..end] has been read but not yet returned.
+    start: usize,
+    end: usize,
+    eof: bool,
+    max_line_length: usize,
+    long_lines: LongLines,
+    // whether what's read until the next newline is the rest of a line which was too long.
+    discarding: bool,
+}
+
+impl<R: Read> LineReader<R> {
+    pub fn new(inner: R) -> LineReader<R> {
+        LineReader::with_capacity(DEFAULT_CAPACITY, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: R) -> LineReader<R> {
+        LineReader {
+            inner,
+            buffer: vec![0; capacity.max(1)],
+            start: 0,
+            end: 0,
+            eof: false,
+            max_line_length: usize::MAX,
+            long_lines: LongLines::Skip,
+            discarding: false,
+        }
+    }
+
+    // deal with lines of more than `max_line_length` bytes, not counting the newline, according
+    // to `long_lines`. there's no limit by default, so a single huge line is read into memory.
+    pub fn set_max_line_length(&mut self, max_line_length: usize, long_lines: LongLines) {
+        self.max_line_length = max_line_length;
+        self.long_lines = long_lines;
+    }
+
+    // the next line including its newline, the last line might not have one. None at the end of
+    // the input. after an error reading, only what was read before it is returned.
+    pub fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        let range = self.next_range()?;
+        Ok(range.map(move |range| &self.buffer[range]))
+    }
+
+    // where the next line is in the buffer, which is only valid until the next call.
+    pub(crate) fn next_range(&mut self) -> io::Result<Option<Range<usize>>> {
+        // where to carry on looking for the newline, everything before it has been searched.
+        let mut searched = self.start;
+        loop {
+            if let Some(i) = find_newline(&self.buffer[searched..self.end]) {
+                let line_start = self.start;
+                let newline = searched + i;
+                self.start = newline + 1;
+                searched = self.start;
+                if self.discarding {
+                    self.discarding = false;
+                    continue;
+                }
+                if newline - line_start > self.max_line_length {
+                    match self.long_lines {
+                        LongLines::Skip => continue,
+                        LongLines::Truncate => {
+                            return Ok(Some(line_start..line_start + self.max_line_length))
+                        },
+                        LongLines::Error => return Err(long_line_error(self.max_line_length)),
+                    }
+                }
+                return Ok(Some(line_start..self.start));
+            }
+            searched = self.end;
+
+            // the line is already too long without its end in sight, so drop what there is of it
+            // rather than letting the buffer grow.
+            if self.discarding || self.end - self.start > self.max_line_length {
+                let line_start = self.start;
+                let already_discarding = self.discarding;
+                self.discarding = true;
+                self.start = 0;
+                self.end = 0;
+                searched = 0;
+                if !already_discarding {
+                    match self.long_lines {
+                        LongLines::Skip => {},
+                        LongLines::Truncate => {
+                            return Ok(Some(line_start..line_start + self.max_line_length))
+                        },
+                        LongLines::Error => return Err(long_line_error(self.max_line_length)),
+                    }
+                }
+            }
+
+            if self.eof {
+                if self.start == self.end {
+                    return Ok(None);
+                }
+                let line_start = self.start;
+                self.start = self.end;
+                return Ok(Some(line_start..self.end));
+            }
+
+            if self.end == self.buffer.len() {
+                if self.start > 0 {
+                    self.buffer.copy_within(self.start..self.end, 0);
+                    searched -= self.start;
+                    self.end -= self.start;
+                    self.start = 0;
+                } else {
+                    let doubled = self.buffer.len() * 2;
+                    self.buffer.resize(doubled, 0);
+                }
+            }
+
+            match self.inner.read(&mut self.buffer[self.end..]) {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.end += read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => {
+                    self.eof = true;
+                    return Err(err);
+                },
+            }
+        }
+    }
+
+    pub(crate) fn line(&self, range: Range<usize>) -> &[u8] {
+        &self.buffer[range]
+    }
+
+    // what has been read but not returned yet. when it's empty the next call has to read, and so
+    // might block waiting for input.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer[self.start..self.end]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LineReader, LongLines};
+    use std::io;
+    use std::io::Read;
+
+    // hands out its input a few bytes at a time, like a pipe.
+    struct Trickle<'a> {
+        input: &'a [u8],
+        step: usize,
+    }
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.input.len());
+            buf[..n].copy_from_slice(&self.input[..n]);
+            self.input = &self.input[n..];
+            Ok(n)
+        }
+    }
+
+    fn lines(input: &[u8], capacity: usize, step: usize) -> Vec<Vec<u8>> {
+        let mut reader = LineReader::with_capacity(capacity, Trickle { input, step });
+        let mut lines = vec![];
+        while let Some(line) = reader.next_line().unwrap() {
+            lines.push(line.to_vec());
+        }
+        lines
+    }
+
+    // the lines read with a limit, with errors as "!".
+    fn limited(input: &[u8], capacity: usize, step: usize, long_lines: LongLines) -> Vec<Vec<u8>> {
+        let mut reader = LineReader::with_capacity(capacity, Trickle { input, step });
+        reader.set_max_line_length(4, long_lines);
+        let mut lines = vec![];
+        loop {
+            match reader.next_line() {
+                Ok(Some(line)) => lines.push(line.to_vec()),
+                Ok(None) => break,
+                Err(_) => lines.push(b"!".to_vec()),
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn split_lines() {
+        let input = b"first\nsecond line\n\nlast";
+        let expected: Vec<Vec<u8>> = vec![b"first\n".to_vec(), b"second line\n".to_vec(),
+                                          b"\n".to_vec(), b"last".to_vec()];
+        for &capacity in &[1, 3, 8, 64] {
+            for &step in &[1, 2, 5, 100] {
+                assert_eq!(lines(input, capacity, step), expected);
+            }
+        }
+
+        assert!(lines(b"", 8, 8).is_empty());
+        assert_eq!(lines(b"a\n", 8, 8), vec![b"a\n".to_vec()]);
+    }
+
+    #[test]
+    fn long_lines() {
+        let input = b"abcd\nabcdefghij\nab\nabcde";
+        for &capacity in &[1, 3, 8, 64] {
+            for &step in &[1, 2, 5, 100] {
+                assert_eq!(limited(input, capacity, step, LongLines::Skip),
+                           vec![b"abcd\n".to_vec(), b"ab\n".to_vec()]);
+                assert_eq!(limited(input, capacity, step, LongLines::Truncate),
+                           vec![b"abcd\n".to_vec(), b"abcd".to_vec(), b"ab\n".to_vec(),
+                                b"abcd".to_vec()]);
+                assert_eq!(limited(input, capacity, step, LongLines::Error),
+                           vec![b"abcd\n".to_vec(), b"!".to_vec(), b"ab\n".to_vec(),
+                                b"!".to_vec()]);
+            }
+        }
+
+        // the buffer doesn't grow to hold a long line which is going to be dropped anyway.
+        let long = [b'x'; 10000];
+        let mut reader = LineReader::with_capacity(16, &long[..]);
+        reader.set_max_line_length(8, LongLines::Skip);
+        assert_eq!(reader.next_line().unwrap(), None);
+        assert_eq!(reader.buffer.len(), 16);
+    }
+
+    #[test]
+    fn buffered() {
+        let mut reader = LineReader::with_capacity(64, &b"one\ntwo\n"[..]);
+        assert_eq!(reader.next_line().unwrap(), Some(&b"one\n"[..]));
+        assert_eq!(reader.buffer(), b"two\n");
+        assert_eq!(reader.next_line().unwrap(), Some(&b"two\n"[..]));
+        assert!(reader.buffer().is_empty());
+        assert_eq!(reader.next_line().unwrap(), None);
+    }
+}
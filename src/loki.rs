@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::TimeZone;
+
+use crate::entry::LogEntry;
+use crate::integer::parse_field;
+use crate::json::{write_entry_json, FieldSet};
+
+const DEFAULT_BATCH_LINES: usize = 1000;
+const DEFAULT_MAX_LINES: usize = 100_000;
+
+// how the fields which aren't labels make up each log line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LokiLineFormat {
+    // name=value pairs, which LogQL's `| logfmt` takes apart.
+    Logfmt,
+    // write_entry_json's objects, for `| json`.
+    Json,
+}
+
+// pushes entries to Grafana Loki's push API, a stream for each combination of the label fields'
+// values and every other field in the line. labels should be few and of low cardinality, like the
+// frontend, backend and status_class which are the default. a push which fails because Loki is
+// busy or can't be reached is tried again after a second, then two, and so on `retries` times, and
+// after that the batch waits as long again before `write` pushes it, with entries past `max_lines`
+// refused until it's pushed. one Loki refuses, like entries too old or out of order, is dropped.
+// entries written since the last push are only sent by `flush`, which should be called at the end
+// and whenever the input pauses.
+pub struct LokiWriter {
+    url: String,
+    labels: FieldSet,
+    line_fields: FieldSet,
+    format: LokiLineFormat,
+    tenant: Option<String>,
+    agent: ureq::Agent,
+    // [timestamp in nanoseconds, line] pairs by the label values of their stream.
+    streams: BTreeMap<Vec<String>, Vec<(i64, String)>>,
+    lines: usize,
+    batch_size: usize,
+    max_lines: usize,
+    retries: u32,
+    retry_delay: Duration,
+    // when write can push again after a push failed.
+    backoff_until: Option<Instant>,
+}
+
+impl LokiWriter {
+    // `url` is Loki's, e.g. http://localhost:3100, which entries are pushed to under
+    // /loki/api/v1/push.
+    pub fn new(url: &str, format: LokiLineFormat) -> LokiWriter {
+        let labels = FieldSet::parse("frontend_name,backend_name,status_class").unwrap();
+        let mut writer = LokiWriter {
+            url: format!("{}/loki/api/v1/push", url.trim_end_matches('/')),
+            labels: FieldSet::new(),
+            line_fields: FieldSet::new(),
+            format,
+            tenant: None,
+            agent: ureq::Agent::new(),
+            streams: BTreeMap::new(),
+            lines: 0,
+            batch_size: DEFAULT_BATCH_LINES,
+            max_lines: DEFAULT_MAX_LINES,
+            retries: 3,
+            retry_delay: Duration::from_secs(1),
+            backoff_until: None,
+        };
+        writer.set_labels(labels);
+        writer
+    }
+
+    // the fields whose values label streams, under the names in the set. Loki only takes names
+    // of letters, digits and underscores, so those are what to name them.
+    pub fn set_labels(&mut self, labels: FieldSet) {
+        let mut line_fields = FieldSet::new();
+        for (name, field) in FieldSet::all().iter() {
+            if !labels.iter().any(|(_, label)| label == field) {
+                line_fields.push(name, field);
+            }
+        }
+        self.labels = labels;
+        self.line_fields = line_fields;
+    }
+
+    // the tenant for Loki's multi-tenancy, sent as X-Scope-OrgID.
+    pub fn set_tenant(&mut self, tenant: &str) {
+        self.tenant = Some(tenant.to_string());
+    }
+
+    // how many entries go in a push, a thousand by default.
+    pub fn set_batch_size(&mut self, entries: usize) {
+        self.batch_size = entries.max(1);
+    }
+
+    // how many entries can wait to be pushed while Loki can't be reached, a hundred thousand by
+    // default.
+    pub fn set_max_lines(&mut self, entries: usize) {
+        self.max_lines = entries.max(1);
+    }
+
+    // how many times a push is tried again, three by default.
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    // adds `entry` to its stream at the time it was accepted, with accept_date taken to be in
+    // `timezone`. an entry whose accept_date doesn't parse is left out, Loki needs a time, and one
+    // which would make more than `max_lines` wait is left out with an error.
+    pub fn write<Tz: TimeZone>(&mut self, entry: &LogEntry, timezone: &Tz) -> io::Result<()> {
+        if self.lines >= self.max_lines {
+            return Err(io::Error::other(format!("{} entries are waiting to be pushed to {}",
+                                                self.lines, self.url)));
+        }
+        let accepted = entry.accept_date_time().ok()
+            .and_then(|accepted| timezone.from_local_datetime(&accepted).earliest());
+        let nanos = match accepted.and_then(|accepted| accepted.timestamp_nanos_opt()) {
+            Some(nanos) => nanos,
+            None => return Ok(()),
+        };
+        let labels = self.labels.iter()
+            .map(|(_, field)| field.extract_content_from(entry))
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+            .collect();
+        let line = match self.format {
+            LokiLineFormat::Logfmt => logfmt(entry, &self.line_fields),
+            LokiLineFormat::Json => {
+                let mut line = vec![];
+                write_entry_json(entry, &mut line, &self.line_fields)?;
+                line.pop();
+                String::from_utf8_lossy(&line).into_owned()
+            },
+        };
+        self.streams.entry(labels).or_default().push((nanos, line));
+        self.lines += 1;
+        let backing_off = self.backoff_until.is_some_and(|until| Instant::now() < until);
+        if self.lines >= self.batch_size && !backing_off {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // pushes the entries written since the last push, if there are any. they're kept when Loki
+    // is busy or can't be reached after the retries, to try again on the next flush, and dropped
+    // when it refuses them, since pushing them again would only be refused again.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.lines == 0 {
+            return Ok(());
+        }
+        let body = self.body();
+        let mut delay = self.retry_delay;
+        let mut attempts = 0;
+        loop {
+            let mut request = self.agent.post(&self.url).set("Content-Type", "application/json");
+            if let Some(ref tenant) = self.tenant {
+                request = request.set("X-Scope-OrgID", tenant);
+            }
+            let err = match request.send_bytes(&body) {
+                Ok(_) => break,
+                // busy, or overloaded, which is worth waiting out.
+                Err(ureq::Error::Status(status @ (429 | 500..=599), response)) => {
+                    status_error(&self.url, status, response)
+                },
+                Err(ureq::Error::Status(status, response)) => {
+                    self.streams.clear();
+                    self.lines = 0;
+                    return Err(status_error(&self.url, status, response));
+                },
+                Err(ureq::Error::Transport(err)) => io::Error::other(err),
+            };
+            if attempts == self.retries {
+                self.backoff_until = Some(Instant::now() + delay);
+                return Err(err);
+            }
+            attempts += 1;
+            thread::sleep(delay);
+            delay *= 2;
+        }
+        self.streams.clear();
+        self.lines = 0;
+        self.backoff_until = None;
+        Ok(())
+    }
+
+    // {"streams": [{"stream": {label: value}, "values": [["nanoseconds", line]]}]}
+    fn body(&self) -> Vec<u8> {
+        let streams: Vec<serde_json::Value> = self.streams.iter().map(|(values, lines)| {
+            let stream: serde_json::Map<String, serde_json::Value> = self.labels.iter()
+                .zip(values)
+                .map(|((name, _), value)| (name.to_string(), value.clone().into()))
+                .collect();
+            let values: Vec<serde_json::Value> = lines.iter()
+                .map(|(nanos, line)| serde_json::json!([nanos.to_string(), line]))
+                .collect();
+            serde_json::json!({"stream": stream, "values": values})
+        }).collect();
+        serde_json::to_vec(&serde_json::json!({"streams": streams})).unwrap()
+    }
+}
+
+fn status_error(url: &str, status: u16, response: ureq::Response) -> io::Error {
+    let message = response.into_string().unwrap_or_default();
+    io::Error::other(format!("{} answered {}: {}", url, status, message.trim()))
+}
+
+// `fields` of `entry` as logfmt. numeric fields are left out when haproxy logged something else
+// there, and values are quoted when they have to be.
+fn logfmt(entry: &LogEntry, fields: &FieldSet) -> String {
+    let mut line = vec![];
+    for (name, field) in fields.iter() {
+        let content = field.extract_content_from(entry);
+        let start = line.len();
+        if !line.is_empty() {
+            line.push(b' ');
+        }
+        write!(line, "{}=", name).unwrap();
+        if field.is_numeric() {
+            match parse_field(content) {
+                Some(number) => write!(line, "{}", number).unwrap(),
+                None => line.truncate(start),
+            }
+        } else if content.is_empty() ||
+                  content.iter().any(|&c| c <= b' ' || c == b'"' || c == b'=' || c == b'\\') {
+            serde_json::to_writer(&mut line, &*String::from_utf8_lossy(content)).unwrap();
+        } else {
+            line.extend_from_slice(content);
+        }
+    }
+    String::from_utf8_lossy(&line).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{logfmt, LokiLineFormat, LokiWriter};
+    use crate::entry::LogEntry;
+    use crate::json::FieldSet;
+    use chrono::Utc;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    const LINE: &[u8] = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                          static/srv1 10/-1/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                          \"GET /a=b HTTP/1.1\"";
+
+    #[test]
+    fn lines() {
+        let entry = LogEntry::from_bytes(LINE).unwrap();
+        let fields = FieldSet::parse("status,Tw,captured_request_cookie,uri,request").unwrap();
+        assert_eq!(logfmt(&entry, &fields),
+                   "status=200 Tw=-1 captured_request_cookie=- uri=\"/a=b\" \
+                    request=\"GET /a=b HTTP/1.1\"");
+    }
+
+    #[test]
+    fn pushes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut headers = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                headers.push(line.trim_end().to_string());
+            }
+            assert_eq!(headers[0], "POST /loki/api/v1/push HTTP/1.1");
+            assert!(headers.contains(&"X-Scope-OrgID: edge".to_string()));
+            let length: usize = headers.iter()
+                .find_map(|header| header.strip_prefix("Content-Length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(&stream, "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").unwrap();
+            body
+        });
+
+        let entry = LogEntry::from_bytes(LINE).unwrap();
+        let other = String::from_utf8(LINE.to_vec()).unwrap().replace(" 200 ", " 503 ");
+        let other = LogEntry::from_bytes(other.as_bytes()).unwrap();
+        let mut writer = LokiWriter::new(&url, LokiLineFormat::Json);
+        writer.set_tenant("edge");
+        writer.write(&entry, &Utc).unwrap();
+        writer.write(&other, &Utc).unwrap();
+        writer.write(&entry, &Utc).unwrap();
+        writer.flush().unwrap();
+
+        let body: serde_json::Value = serde_json::from_slice(&server.join().unwrap()).unwrap();
+        let streams = body["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0]["stream"], serde_json::json!({
+            "frontend_name": "http-in",
+            "backend_name": "static",
+            "status_class": "2xx",
+        }));
+        assert_eq!(streams[0]["values"].as_array().unwrap().len(), 2);
+        assert_eq!(streams[0]["values"][0][0], "1233922454655000000");
+        let line: serde_json::Value =
+            serde_json::from_str(streams[0]["values"][0][1].as_str().unwrap()).unwrap();
+        assert_eq!(line["status_code"], 200);
+        assert_eq!(line.get("frontend_name"), None);
+        assert_eq!(streams[1]["stream"]["status_class"], "5xx");
+    }
+
+    #[test]
+    fn retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut bodies = vec![];
+            for answer in ["503 Service Unavailable", "204 No Content", "400 Bad Request"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.trim_end().strip_prefix("Content-Length: ") {
+                        length = value.parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(body);
+
+                let message = "entry too far behind";
+                write!(&stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                       answer, message.len(), message).unwrap();
+            }
+            bodies
+        });
+
+        let entry = LogEntry::from_bytes(LINE).unwrap();
+        let mut writer = LokiWriter::new(&url, LokiLineFormat::Logfmt);
+        writer.retry_delay = Duration::from_millis(1);
+        writer.write(&entry, &Utc).unwrap();
+        writer.flush().unwrap();
+        writer.write(&entry, &Utc).unwrap();
+        let err = writer.flush().unwrap_err();
+        assert!(err.to_string().ends_with("answered 400: entry too far behind"));
+        // the refused entry isn't pushed again.
+        writer.flush().unwrap();
+
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(bodies[0], bodies[1]);
+        assert_eq!(bodies[1], bodies[2]);
+    }
+
+    #[test]
+    fn backoff() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut bodies = vec![];
+            for answer in ["503 Service Unavailable", "204 No Content"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.trim_end().strip_prefix("Content-Length: ") {
+                        length = value.parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(body);
+                write!(&stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                       answer).unwrap();
+            }
+            bodies
+        });
+
+        let entry = LogEntry::from_bytes(LINE).unwrap();
+        let mut writer = LokiWriter::new(&url, LokiLineFormat::Logfmt);
+        writer.set_batch_size(1);
+        writer.set_max_lines(2);
+        writer.set_retries(0);
+        writer.retry_delay = Duration::from_secs(60);
+        assert!(writer.write(&entry, &Utc).is_err());
+        // the failed push isn't tried again by the next write, and the batch stops growing.
+        writer.write(&entry, &Utc).unwrap();
+        let err = writer.write(&entry, &Utc).unwrap_err();
+        assert!(err.to_string().starts_with("2 entries are waiting to be pushed to"));
+        writer.flush().unwrap();
+
+        let bodies = server.join().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bodies[1]).unwrap();
+        assert_eq!(body["streams"][0]["values"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::agg::TDigest;
+use crate::entry::LogEntry;
+use crate::field::Field;
+use crate::topk::HeavyHitters;
+
+// latency histogram bucket upper bounds in seconds, the same as the Prometheus client libraries'.
+pub const DEFAULT_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+                                      10.0];
+
+struct Histogram {
+    // cumulative counts per bucket, as Prometheus wants them, plus the sum of observations.
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new(buckets: usize) -> Histogram {
+        Histogram {
+            counts: vec![0; buckets],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, bounds: &[f64], value: f64) {
+        for (count, &bound) in self.counts.iter_mut().zip(bounds) {
+            if value <= bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+// Prometheus metrics about the entries added to it, which `render` writes in the text exposition
+// format. haproxy-exporter serves them, and anything else can by putting one behind a lock.
+//
+//     haproxy_log_requests_total              entries, by frontend, backend and status_class
+//     haproxy_log_bytes_read_total            bytes sent to clients, by the same labels
+//     haproxy_log_response_time_seconds       histogram of Tr, by frontend and backend
+//     haproxy_log_total_time_seconds          histogram of Tt, by frontend and backend
+//     haproxy_log_invalid_lines_total         lines that failed to parse
+//
+// and with set_quantiles, summaries of Tr and Tt by frontend and backend estimated by TDigests:
+//
+//     haproxy_log_response_time_quantiles_seconds
+//     haproxy_log_total_time_quantiles_seconds
+//
+// and with add_heavy_hitters, the estimated number of requests of the most frequent values of a
+// field since the metrics were created, by field and value:
+//
+//     haproxy_log_top_requests
+//
+// timers of -1, for sessions which never got that far, aren't observed by the histograms.
+pub struct LogMetrics {
+    buckets: Vec<f64>,
+    // keyed by (frontend, backend, status_class).
+    requests: BTreeMap<(String, String, String), (u64, u64)>,
+    // keyed by (frontend, backend), for Tr and Tt.
+    latencies: BTreeMap<(String, String), (Histogram, Histogram)>,
+    quantiles: Vec<f64>,
+    // keyed the same as latencies, only once there are quantiles.
+    digests: BTreeMap<(String, String), (TDigest, TDigest)>,
+    // the name to label each with and how many values to render.
+    heavy_hitters: Vec<(String, usize, HeavyHitters)>,
+    invalid_lines: u64,
+}
+
+impl Default for LogMetrics {
+    fn default() -> LogMetrics {
+        LogMetrics::new(DEFAULT_BUCKETS.to_vec())
+    }
+}
+
+impl LogMetrics {
+    // `buckets` are the histograms' upper bounds in seconds, in any order.
+    pub fn new(mut buckets: Vec<f64>) -> LogMetrics {
+        buckets.sort_by(|a, b| a.total_cmp(b));
+        LogMetrics {
+            buckets,
+            requests: BTreeMap::new(),
+            latencies: BTreeMap::new(),
+            quantiles: vec![],
+            digests: BTreeMap::new(),
+            heavy_hitters: vec![],
+            invalid_lines: 0,
+        }
+    }
+
+    // also render summaries of Tr and Tt at these quantiles, between 0 and 1, e.g. 0.5 and 0.99.
+    pub fn set_quantiles(&mut self, mut quantiles: Vec<f64>) {
+        quantiles.sort_by(|a, b| a.total_cmp(b));
+        self.quantiles = quantiles;
+    }
+
+    // also render the `n` most frequent values of `field`, labelled with `name`, e.g. client_ip.
+    // ten times as many are tracked, so the counts of the top ones are close.
+    pub fn add_heavy_hitters(&mut self, name: &str, field: Field, n: usize) {
+        let hitters = HeavyHitters::new(field, n.saturating_mul(10).max(100));
+        self.heavy_hitters.push((name.to_string(), n, hitters));
+    }
+
+    pub fn add(&mut self, entry: &LogEntry) {
+        let frontend = String::from_utf8_lossy(entry.frontend_name).into_owned();
+        let backend = String::from_utf8_lossy(entry.backend_name).into_owned();
+        let status_class = String::from_utf8_lossy(entry.status_class()).into_owned();
+
+        for (_, _, hitters) in &mut self.heavy_hitters {
+            hitters.add(entry);
+        }
+
+        let bytes = entry.bytes_read().unwrap_or(0);
+        let counters = self.requests.entry((frontend.clone(), backend.clone(), status_class))
+            .or_insert((0, 0));
+        counters.0 += 1;
+        counters.1 += bytes;
+
+        let response_time = entry.response_time().ok().filter(|&millis| millis >= 0)
+            .map(|millis| millis as f64 / 1000.0);
+        let total_time = entry.total_time().ok().filter(|&millis| millis >= 0)
+            .map(|millis| millis as f64 / 1000.0);
+
+        if !self.quantiles.is_empty() {
+            let digests = self.digests.entry((frontend.clone(), backend.clone())).or_default();
+            if let Some(seconds) = response_time {
+                digests.0.add(seconds);
+            }
+            if let Some(seconds) = total_time {
+                digests.1.add(seconds);
+            }
+        }
+
+        let buckets = &self.buckets;
+        let (response_times, total_times) = self.latencies.entry((frontend, backend))
+            .or_insert_with(|| (Histogram::new(buckets.len()), Histogram::new(buckets.len())));
+        if let Some(seconds) = response_time {
+            response_times.observe(buckets, seconds);
+        }
+        if let Some(seconds) = total_time {
+            total_times.observe(buckets, seconds);
+        }
+    }
+
+    // counts a line which didn't parse.
+    pub fn add_invalid_line(&mut self) {
+        self.invalid_lines += 1;
+    }
+
+    // the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP haproxy_log_requests_total Requests logged by haproxy.\n");
+        out.push_str("# TYPE haproxy_log_requests_total counter\n");
+        for ((frontend, backend, status_class), &(requests, _)) in &self.requests {
+            let _ = writeln!(out, "haproxy_log_requests_total{{frontend=\"{}\",backend=\"{}\",\
+                                   status_class=\"{}\"}} {}",
+                             escape(frontend), escape(backend), escape(status_class), requests);
+        }
+
+        out.push_str("# HELP haproxy_log_bytes_read_total Bytes sent to clients.\n");
+        out.push_str("# TYPE haproxy_log_bytes_read_total counter\n");
+        for ((frontend, backend, status_class), &(_, bytes)) in &self.requests {
+            let _ = writeln!(out, "haproxy_log_bytes_read_total{{frontend=\"{}\",backend=\"{}\",\
+                                   status_class=\"{}\"}} {}",
+                             escape(frontend), escape(backend), escape(status_class), bytes);
+        }
+
+        let histograms = [
+            ("haproxy_log_response_time_seconds", "Time waiting for the server to respond (Tr)."),
+            ("haproxy_log_total_time_seconds", "Total time of the session (Tt)."),
+        ];
+        for (i, &(name, help)) in histograms.iter().enumerate() {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+            for ((frontend, backend), pair) in &self.latencies {
+                let histogram = if i == 0 { &pair.0 } else { &pair.1 };
+                let labels = format!("frontend=\"{}\",backend=\"{}\"", escape(frontend),
+                                     escape(backend));
+                for (&bound, count) in self.buckets.iter().zip(&histogram.counts) {
+                    let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound,
+                                     count);
+                }
+                let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels,
+                                 histogram.count);
+                let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+                let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+            }
+        }
+
+        let summaries = [
+            ("haproxy_log_response_time_quantiles_seconds",
+             "Estimated quantiles of the time waiting for the server to respond (Tr)."),
+            ("haproxy_log_total_time_quantiles_seconds",
+             "Estimated quantiles of the total time of the session (Tt)."),
+        ];
+        for (i, &(name, help)) in summaries.iter().enumerate() {
+            if self.quantiles.is_empty() {
+                break;
+            }
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} summary", name);
+            for ((frontend, backend), pair) in &self.digests {
+                let digest = if i == 0 { &pair.0 } else { &pair.1 };
+                let labels = format!("frontend=\"{}\",backend=\"{}\"", escape(frontend),
+                                     escape(backend));
+                for &q in &self.quantiles {
+                    let value = digest.quantile(q).map_or("NaN".to_string(), |v| v.to_string());
+                    let _ = writeln!(out, "{}{{{},quantile=\"{}\"}} {}", name, labels, q, value);
+                }
+                let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, digest.sum());
+                let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, digest.count());
+            }
+        }
+
+        if !self.heavy_hitters.is_empty() {
+            out.push_str("# HELP haproxy_log_top_requests Estimated requests of the most frequent \
+                          values of a field.\n");
+            out.push_str("# TYPE haproxy_log_top_requests gauge\n");
+        }
+        for (name, n, hitters) in &self.heavy_hitters {
+            for hitter in hitters.top(*n) {
+                let _ = writeln!(out, "haproxy_log_top_requests{{field=\"{}\",value=\"{}\"}} {}",
+                                 escape(name), escape(&String::from_utf8_lossy(hitter.key)),
+                                 hitter.count);
+            }
+        }
+
+        out.push_str("# HELP haproxy_log_invalid_lines_total Lines which failed to parse.\n");
+        out.push_str("# TYPE haproxy_log_invalid_lines_total counter\n");
+        let _ = writeln!(out, "haproxy_log_invalid_lines_total {}", self.invalid_lines);
+        out
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::LogMetrics;
+    use crate::entry::LogEntry;
+    use crate::field::Field;
+
+    #[test]
+    fn exposition() {
+        let lines: &[&[u8]] = &[
+            b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.2:33318 [06/Feb/2009:12:14:15.655] http-in static/srv1 \
+              10/0/30/2000/2109 200 250 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.3:33319 [06/Feb/2009:12:14:16.655] http-in st\"atic/<NOSRV> \
+              -1/-1/-1/-1/+3000 -1 0 - - CQ-- 1/1/1/0/0 0/5 \"GET / HTTP/1.1\"",
+        ];
+        let mut metrics = LogMetrics::new(vec![1.0, 0.1]);
+        metrics.add_heavy_hitters("client_ip", Field::ClientIp, 1);
+        for line in lines {
+            metrics.add(&LogEntry::from_bytes(line).unwrap());
+        }
+        metrics.add_invalid_line();
+        let text = metrics.render();
+        assert!(!text.contains("quantile"));
+        let has = |line: &str| text.lines().any(|l| l == line);
+
+        assert!(has("haproxy_log_requests_total{frontend=\"http-in\",backend=\"static\",\
+                     status_class=\"2xx\"} 2"));
+        assert!(has("haproxy_log_bytes_read_total{frontend=\"http-in\",backend=\"static\",\
+                     status_class=\"2xx\"} 3000"));
+        assert!(has("haproxy_log_requests_total{frontend=\"http-in\",backend=\"st\\\"atic\",\
+                     status_class=\"ERR\"} 1"));
+        assert!(has("haproxy_log_response_time_seconds_bucket{frontend=\"http-in\",\
+                     backend=\"static\",le=\"0.1\"} 1"));
+        assert!(has("haproxy_log_response_time_seconds_bucket{frontend=\"http-in\",\
+                     backend=\"static\",le=\"1\"} 1"));
+        assert!(has("haproxy_log_response_time_seconds_count{frontend=\"http-in\",\
+                     backend=\"static\"} 2"));
+        assert!(has("haproxy_log_total_time_seconds_count{frontend=\"http-in\",\
+                     backend=\"st\\\"atic\"} 1"));
+        assert!(has("haproxy_log_response_time_seconds_count{frontend=\"http-in\",\
+                     backend=\"st\\\"atic\"} 0"));
+        assert!(has("haproxy_log_invalid_lines_total 1"));
+        assert!(has("haproxy_log_top_requests{field=\"client_ip\",value=\"10.0.1.2\"} 2"));
+        assert!(!text.contains("10.0.1.3"));
+    }
+
+    #[test]
+    fn quantiles() {
+        let lines: &[&[u8]] = &[
+            b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.2:33318 [06/Feb/2009:12:14:15.655] http-in static/srv1 \
+              10/0/30/2000/2109 200 250 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.3:33319 [06/Feb/2009:12:14:16.655] http-in api/<NOSRV> \
+              -1/-1/-1/-1/+3000 -1 0 - - CQ-- 1/1/1/0/0 0/5 \"GET / HTTP/1.1\"",
+        ];
+        let mut metrics = LogMetrics::default();
+        metrics.set_quantiles(vec![0.99, 0.5]);
+        for line in lines {
+            metrics.add(&LogEntry::from_bytes(line).unwrap());
+        }
+        let text = metrics.render();
+        let has = |line: &str| text.lines().any(|l| l == line);
+
+        assert!(has("# TYPE haproxy_log_total_time_quantiles_seconds summary"));
+        assert!(has("haproxy_log_total_time_quantiles_seconds{frontend=\"http-in\",\
+                     backend=\"static\",quantile=\"0.5\"} 0.109"));
+        assert!(has("haproxy_log_total_time_quantiles_seconds{frontend=\"http-in\",\
+                     backend=\"static\",quantile=\"0.99\"} 2.109"));
+        assert!(has("haproxy_log_total_time_quantiles_seconds_count{frontend=\"http-in\",\
+                     backend=\"api\"} 1"));
+        assert!(has("haproxy_log_response_time_quantiles_seconds{frontend=\"http-in\",\
+                     backend=\"api\",quantile=\"0.99\"} NaN"));
+        assert!(has("haproxy_log_response_time_quantiles_seconds_count{frontend=\"http-in\",\
+                     backend=\"api\"} 0"));
+    }
+}
@@ -0,0 +1,178 @@
+use std::str;
+
+use crate::entry::{LogEntry, HEADER_FIELD_NAMES};
+use crate::field::Field;
+use crate::integer::parse_field;
+
+// appends `entry` to `out` as a MessagePack map of every field, the capture blocks and the request
+// included, under the names it's serialized with. numbers are integers in as few bytes as they fit,
+// or nil when haproxy logged something else there, and the rest are strings, or bin in the rare
+// case a field isn't utf8. entries aren't delimited, a MessagePack reader takes one map at a time.
+pub fn encode_msgpack(entry: &LogEntry, out: &mut Vec<u8>) {
+    write_map_len(out, HEADER_FIELD_NAMES.len() + 3);
+    for (&name, field) in HEADER_FIELD_NAMES.iter().zip(Field::HEADER) {
+        write_str(out, name.as_bytes());
+        let content = field.extract_content_from(entry);
+        if field.is_numeric() {
+            match parse_field(content) {
+                Some(number) => write_int(out, number),
+                None => out.push(0xc0),
+            }
+        } else {
+            write_text(out, content);
+        }
+    }
+    write_str(out, b"captured_request_headers");
+    write_text(out, entry.captures[0]);
+    write_str(out, b"captured_response_headers");
+    write_text(out, entry.captures[1]);
+    write_str(out, b"http_request");
+    write_text(out, entry.http_request);
+}
+
+pub(crate) fn write_map_len(out: &mut Vec<u8>, len: usize) {
+    match len {
+        0..=15 => out.push(0x80 | len as u8),
+        16..=0xffff => {
+            out.push(0xde);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        _ => {
+            out.push(0xdf);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        },
+    }
+}
+
+// only fluentd's forward mode wraps entries in arrays.
+#[cfg(feature = "fluentd")]
+pub(crate) fn write_array_len(out: &mut Vec<u8>, len: usize) {
+    match len {
+        0..=15 => out.push(0x90 | len as u8),
+        16..=0xffff => {
+            out.push(0xdc);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        _ => {
+            out.push(0xdd);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        },
+    }
+}
+
+// a str, which MessagePack means to be utf8 but doesn't check.
+pub(crate) fn write_str(out: &mut Vec<u8>, text: &[u8]) {
+    match text.len() {
+        len @ 0..=31 => out.push(0xa0 | len as u8),
+        len @ 32..=0xff => out.extend_from_slice(&[0xd9, len as u8]),
+        len @ 0x100..=0xffff => {
+            out.push(0xda);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        len => {
+            out.push(0xdb);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        },
+    }
+    out.extend_from_slice(text);
+}
+
+// a field as a str, or as bin when it isn't utf8.
+fn write_text(out: &mut Vec<u8>, text: &[u8]) {
+    if str::from_utf8(text).is_ok() {
+        return write_str(out, text);
+    }
+    match text.len() {
+        len @ 0..=0xff => out.extend_from_slice(&[0xc4, len as u8]),
+        len @ 0x100..=0xffff => {
+            out.push(0xc5);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        len => {
+            out.push(0xc6);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        },
+    }
+    out.extend_from_slice(text);
+}
+
+// an integer in the smallest encoding which holds it.
+pub(crate) fn write_int(out: &mut Vec<u8>, number: i64) {
+    match number {
+        -32..=0x7f => out.push(number as u8),
+        0x80..=0xff => out.extend_from_slice(&[0xcc, number as u8]),
+        0x100..=0xffff => {
+            out.push(0xcd);
+            out.extend_from_slice(&(number as u16).to_be_bytes());
+        },
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xce);
+            out.extend_from_slice(&(number as u32).to_be_bytes());
+        },
+        -0x80..=-33 => out.extend_from_slice(&[0xd0, number as u8]),
+        -0x8000..=-0x81 => {
+            out.push(0xd1);
+            out.extend_from_slice(&(number as i16).to_be_bytes());
+        },
+        -0x8000_0000..=-0x8001 => {
+            out.push(0xd2);
+            out.extend_from_slice(&(number as i32).to_be_bytes());
+        },
+        _ if number > 0 => {
+            out.push(0xcf);
+            out.extend_from_slice(&(number as u64).to_be_bytes());
+        },
+        _ => {
+            out.push(0xd3);
+            out.extend_from_slice(&number.to_be_bytes());
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{encode_msgpack, write_int};
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn integers() {
+        let cases: &[(i64, &[u8])] = &[
+            (0, b"\x00"),
+            (127, b"\x7f"),
+            (128, b"\xcc\x80"),
+            (33317, b"\xcd\x82\x25"),
+            (1 << 16, b"\xce\x00\x01\x00\x00"),
+            (1 << 32, b"\xcf\x00\x00\x00\x01\x00\x00\x00\x00"),
+            (-1, b"\xff"),
+            (-32, b"\xe0"),
+            (-33, b"\xd0\xdf"),
+            (-129, b"\xd1\xff\x7f"),
+            (-0x8001, b"\xd2\xff\xff\x7f\xff"),
+            (i64::MIN, b"\xd3\x80\x00\x00\x00\x00\x00\x00\x00"),
+        ];
+        for &(number, expected) in cases {
+            let mut out = vec![];
+            write_int(&mut out, number);
+            assert_eq!(out, expected, "{}", number);
+        }
+    }
+
+    #[test]
+    fn entries() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 -1/0/30/69/+109 200 2750 - - ---- 1/1/1/1/+1 0/0 \
+                     {1wt.eu} \"GET / HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let mut out = vec![];
+        encode_msgpack(&entry, &mut out);
+
+        assert!(out.starts_with(b"\xde\x00\x1c\xacprocess_name\xa7haproxy\xa3pid\xcd\x38\x35"));
+        let has = |pair: &[u8]| out.windows(pair.len()).any(|window| window == pair);
+        assert!(has(b"\xacrequest_time\xff"));
+        assert!(has(b"\xaatotal_time\x6d"));
+        assert!(has(b"\xabstatus_code\xcc\xc8"));
+        assert!(has(b"\xb3retried_connections\x01"));
+        assert!(has(b"\xb8captured_request_headers\xa61wt.eu"));
+        assert!(out.ends_with(b"\xachttp_request\xaeGET / HTTP/1.1"));
+    }
+}
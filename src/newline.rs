@@ -0,0 +1,50 @@
+// lines are long enough that looking at a word at a time pays off, unlike the short fields the
+// slicer searches. haproxy-cut -f ip,status over 800k generated lines took ~205ms with a plain
+// loop and ~150ms with this, the same as with memchr, where BufRead::read_until took ~160ms.
+#[cfg(not(feature = "memchr"))]
+pub(crate) fn find_newline(buffer: &[u8]) -> Option<usize> {
+    const ONES: u64 = 0x0101_0101_0101_0101;
+    const HIGHS: u64 = 0x8080_8080_8080_8080;
+    let newlines = ONES * b'\n' as u64;
+
+    let mut chunks = buffer.chunks_exact(8);
+    let mut offset = 0;
+    for chunk in &mut chunks {
+        // a byte of `word` is zero where there's a newline. the lowest byte flagged here is always
+        // the first zero byte, only the ones above it can be wrong.
+        let word = u64::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3],
+                                       chunk[4], chunk[5], chunk[6], chunk[7]]) ^ newlines;
+        let zeroes = word.wrapping_sub(ONES) & !word & HIGHS;
+        if zeroes != 0 {
+            return Some(offset + zeroes.trailing_zeros() as usize / 8);
+        }
+        offset += 8;
+    }
+    chunks.remainder().iter().position(|&c| c == b'\n').map(|i| offset + i)
+}
+
+#[cfg(feature = "memchr")]
+pub(crate) fn find_newline(buffer: &[u8]) -> Option<usize> {
+    memchr::memchr(b'\n', buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::find_newline;
+
+    #[test]
+    fn newlines() {
+        assert_eq!(find_newline(b""), None);
+        assert_eq!(find_newline(b"abc"), None);
+        assert_eq!(find_newline(b"0123456789abcdef"), None);
+        for i in 0..20 {
+            let mut line = vec![b'x'; 20];
+            line[i] = b'\n';
+            assert_eq!(find_newline(&line), Some(i));
+            // a newline after the first mustn't be mistaken for it.
+            line[19] = b'\n';
+            assert_eq!(find_newline(&line), Some(i));
+        }
+        assert_eq!(find_newline(b"\x0b\x0b\x0b\n\x8a\x0a"), Some(3));
+    }
+}
@@ -0,0 +1,201 @@
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+
+use chrono::TimeZone;
+use opentelemetry::logs::{AnyValue, LogRecord, Logger, Severity};
+use opentelemetry::trace::{Span, SpanBuilder, SpanKind, Status, Tracer};
+use opentelemetry::{KeyValue, Value};
+
+use crate::attributes::entry_attributes;
+use crate::entry::LogEntry;
+use crate::expr::{Number, Value as ExprValue};
+
+// entry_attributes as OpenTelemetry's, the same as haproxy-trace sends.
+pub fn otel_attributes(entry: &LogEntry) -> Vec<KeyValue> {
+    entry_attributes(entry).into_iter()
+        .map(|(key, value)| match value {
+            ExprValue::Number(Number::Integer(number)) => KeyValue::new(key, number),
+            ExprValue::Number(Number::Decimal(number)) => KeyValue::new(key, number),
+            ExprValue::Text(text) => {
+                KeyValue::new(key, String::from_utf8_lossy(&text).into_owned())
+            },
+        })
+        .collect()
+}
+
+// when the request was accepted, with accept_date taken to be in `timezone`. the earlier reading
+// is used for local times a clock change makes ambiguous, and none for ones it skips.
+pub fn accept_time<Tz: TimeZone>(entry: &LogEntry, timezone: &Tz) -> Option<SystemTime> {
+    let accepted = entry.accept_date_time().ok()?;
+    let millis = timezone.from_local_datetime(&accepted).earliest()?.timestamp_millis();
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_millis(u64::try_from(millis).ok()?))
+}
+
+// sets up `record` for `entry`: the request as the body, otel_attributes and the time it was
+// accepted. 5xx responses and requests which never got one are errors, and 4xx are warnings.
+pub fn fill_log_record<R: LogRecord, Tz: TimeZone>(entry: &LogEntry, timezone: &Tz,
+                                                   record: &mut R) {
+    record.set_event_name("haproxy.http");
+    if let Some(accepted) = accept_time(entry, timezone) {
+        record.set_timestamp(accepted);
+    }
+    let (severity, severity_text) = match entry.status_code() {
+        Ok(400..=499) => (Severity::Warn, "WARN"),
+        Ok(100..=499) => (Severity::Info, "INFO"),
+        _ => (Severity::Error, "ERROR"),
+    };
+    record.set_severity_number(severity);
+    record.set_severity_text(severity_text);
+    record.set_body(AnyValue::from(String::from_utf8_lossy(entry.http_request).into_owned()));
+    for attribute in otel_attributes(entry) {
+        record.add_attribute(attribute.key, any_value(attribute.value));
+    }
+}
+
+// emits `entry` through `logger` as fill_log_record sets it up.
+pub fn emit_log_record<L: Logger, Tz: TimeZone>(logger: &L, entry: &LogEntry, timezone: &Tz) {
+    let mut record = logger.create_log_record();
+    fill_log_record(entry, timezone, &mut record);
+    logger.emit(record);
+}
+
+// a server span for `entry` named after its method and path, from when it was accepted for as
+// long as Tt, with otel_attributes. like the log records, it's an error for a 5xx or no response.
+// none when accept_date doesn't parse.
+pub fn span_builder<Tz: TimeZone>(entry: &LogEntry, timezone: &Tz) -> Option<SpanBuilder> {
+    let start = accept_time(entry, timezone)?;
+    let total = entry.total_time().ok().filter(|&total| total >= 0).unwrap_or(0);
+    let method = String::from_utf8_lossy(entry.http_method().unwrap_or(b""));
+    let uri = entry.http_uri().unwrap_or(b"");
+    let path = String::from_utf8_lossy(uri.split(|&c| c == b'?').next().unwrap_or(uri));
+    let name = format!("{} {}", method, path).trim().to_string();
+
+    let mut builder = SpanBuilder::from_name(name)
+        .with_kind(SpanKind::Server)
+        .with_start_time(start)
+        .with_end_time(start + Duration::from_millis(total as u64))
+        .with_attributes(otel_attributes(entry));
+    if !matches!(entry.status_code(), Ok(100..=499)) {
+        builder = builder.with_status(Status::error("no response or a server error"));
+    }
+    Some(builder)
+}
+
+// records `entry` as a span with `tracer`, ended when the request was. false when accept_date
+// doesn't parse and there's nothing to record.
+pub fn record_span<T: Tracer, Tz: TimeZone>(tracer: &T, entry: &LogEntry, timezone: &Tz)
+                                            -> bool {
+    let builder = match span_builder(entry, timezone) {
+        Some(builder) => builder,
+        None => return false,
+    };
+    let end = builder.end_time;
+    let mut span = tracer.build(builder);
+    match end {
+        Some(end) => span.end_with_timestamp(end),
+        None => span.end(),
+    }
+    true
+}
+
+fn any_value(value: Value) -> AnyValue {
+    match value {
+        Value::Bool(value) => AnyValue::Boolean(value),
+        Value::I64(value) => AnyValue::Int(value),
+        Value::F64(value) => AnyValue::Double(value),
+        value => AnyValue::String(value.to_string().into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fill_log_record, otel_attributes, span_builder};
+    use crate::entry::LogEntry;
+    use chrono::{FixedOffset, Utc};
+    use opentelemetry::logs::{AnyValue, LogRecord, Severity};
+    use opentelemetry::trace::{SpanKind, Status};
+    use opentelemetry::{Key, KeyValue};
+    use std::borrow::Cow;
+    use std::time::{Duration, SystemTime};
+
+    #[derive(Default)]
+    struct Record {
+        timestamp: Option<SystemTime>,
+        severity: Option<Severity>,
+        body: Option<AnyValue>,
+        attributes: Vec<(Key, AnyValue)>,
+    }
+
+    impl LogRecord for Record {
+        fn set_event_name(&mut self, _name: &'static str) {}
+        fn set_target<T: Into<Cow<'static, str>>>(&mut self, _target: T) {}
+        fn set_timestamp(&mut self, timestamp: SystemTime) {
+            self.timestamp = Some(timestamp);
+        }
+        fn set_observed_timestamp(&mut self, _timestamp: SystemTime) {}
+        fn set_severity_text(&mut self, _text: &'static str) {}
+        fn set_severity_number(&mut self, number: Severity) {
+            self.severity = Some(number);
+        }
+        fn set_body(&mut self, body: AnyValue) {
+            self.body = Some(body);
+        }
+        fn add_attributes<I, K, V>(&mut self, attributes: I)
+            where I: IntoIterator<Item = (K, V)>, K: Into<Key>, V: Into<AnyValue> {
+            for (key, value) in attributes {
+                self.add_attribute(key, value);
+            }
+        }
+        fn add_attribute<K: Into<Key>, V: Into<AnyValue>>(&mut self, key: K, value: V) {
+            self.attributes.push((key.into(), value.into()));
+        }
+    }
+
+    #[test]
+    fn records() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 10/0/30/69/109 404 2750 - - ---- 1/1/1/1/0 0/0 \
+                     \"GET /missing?x=1 HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let attributes = otel_attributes(&entry);
+        assert!(attributes.contains(&KeyValue::new("http.request.method", "GET")));
+        assert!(attributes.contains(&KeyValue::new("url.path", "/missing")));
+        assert!(attributes.contains(&KeyValue::new("http.response.status_code", 404)));
+        assert!(attributes.contains(&KeyValue::new("haproxy.server", "srv1")));
+        assert!(!attributes.iter().any(|attribute| attribute.key.as_str() == "server.address"));
+        assert!(attributes.contains(&KeyValue::new("client.port", 33317)));
+
+        let mut record = Record::default();
+        fill_log_record(&entry, &Utc, &mut record);
+        let accepted = SystemTime::UNIX_EPOCH + Duration::from_millis(1233922454655);
+        assert_eq!(record.timestamp, Some(accepted));
+        assert_eq!(record.severity, Some(Severity::Warn));
+        assert_eq!(record.body, Some(AnyValue::from("GET /missing?x=1 HTTP/1.1")));
+        assert!(record.attributes.contains(&(Key::new("http.response.status_code"),
+                                             AnyValue::Int(404))));
+
+        let paris = FixedOffset::east_opt(3600).unwrap();
+        let mut record = Record::default();
+        fill_log_record(&entry, &paris, &mut record);
+        assert_eq!(record.timestamp, Some(accepted - Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn spans() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/<NOSRV> -1/-1/-1/-1/+3000 -1 0 - - CQ-- 1/1/1/0/0 0/5 \
+                     \"GET / HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let builder = span_builder(&entry, &Utc).unwrap();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_millis(1233922454655);
+        assert_eq!(builder.name, "GET /");
+        assert_eq!(builder.span_kind, Some(SpanKind::Server));
+        assert_eq!(builder.start_time, Some(start));
+        assert_eq!(builder.end_time, Some(start + Duration::from_secs(3)));
+        assert!(matches!(builder.status, Status::Error { .. }));
+        let attributes = builder.attributes.unwrap();
+        assert!(!attributes.iter().any(|attribute| {
+            attribute.key.as_str() == "http.response.status_code"
+        }));
+    }
+}
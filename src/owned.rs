@@ -0,0 +1,101 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::entry::{LogEntry, Result, HEADER_FIELDS};
+
+// the header fields, then both capture blocks and the request.
+const FIELDS: usize = HEADER_FIELDS + 3;
+
+// an entry which owns its line, for handing entries to other tasks or keeping them past the buffer
+// they were read into. it's parsed once and keeps where each field is, so getting the LogEntry back
+// out with `entry` is only slicing.
+#[derive(Clone, PartialEq)]
+pub struct OwnedLogEntry {
+    line: Vec<u8>,
+    fields: [Range<usize>; FIELDS],
+}
+
+impl OwnedLogEntry {
+    pub fn parse(line: Vec<u8>) -> Result<OwnedLogEntry> {
+        let fields = field_ranges(&line, &LogEntry::from_bytes(&line)?);
+        Ok(OwnedLogEntry { line, fields })
+    }
+
+    pub fn entry(&self) -> LogEntry<'_> {
+        entry_from_ranges(&self.line, &self.fields)
+    }
+
+    // the line as it was parsed, without a newline if it was read through a LogStream.
+    pub fn line(&self) -> &[u8] {
+        &self.line
+    }
+
+    pub fn into_line(self) -> Vec<u8> {
+        self.line
+    }
+}
+
+// where each field of `entry` is in `line`, which it was parsed from. the parser hands out empty
+// slices of constants for fields a line doesn't have, those are empty ranges here.
+pub(crate) fn field_ranges(line: &[u8], entry: &LogEntry) -> [Range<usize>; FIELDS] {
+    let header = entry.header_fields();
+    let slices = header.iter().chain(entry.captures.iter()).chain(Some(&entry.http_request));
+    let mut fields: [Range<usize>; FIELDS] = Default::default();
+    for (range, slice) in fields.iter_mut().zip(slices) {
+        let start = (slice.as_ptr() as usize).wrapping_sub(line.as_ptr() as usize);
+        if !slice.is_empty() && start <= line.len() {
+            *range = start..start + slice.len();
+        }
+    }
+    fields
+}
+
+// the entry field_ranges was given back, sliced out of `line` or a copy of it.
+pub(crate) fn entry_from_ranges<'a>(line: &'a [u8], fields: &[Range<usize>; FIELDS])
+                                    -> LogEntry<'a> {
+    let field = |i: usize| &line[fields[i].clone()];
+    let mut header: [&[u8]; HEADER_FIELDS] = [b""; HEADER_FIELDS];
+    for (i, slice) in header.iter_mut().enumerate() {
+        *slice = field(i);
+    }
+    LogEntry::from_header_fields(&header, [field(HEADER_FIELDS), field(HEADER_FIELDS + 1)],
+                                 field(HEADER_FIELDS + 2))
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for OwnedLogEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S)
+                                      -> std::result::Result<S::Ok, S::Error> {
+        self.entry().serialize(serializer)
+    }
+}
+
+impl fmt::Debug for OwnedLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.entry().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OwnedLogEntry;
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn same_as_borrowed() {
+        let lines: &[&[u8]] = &[
+            b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu} {} \
+              \"GET /index.html HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"",
+        ];
+        for &line in lines {
+            let owned = OwnedLogEntry::parse(line.to_vec()).unwrap();
+            assert_eq!(owned.entry(), LogEntry::from_bytes(line).unwrap());
+            assert_eq!(owned.clone().entry(), owned.entry());
+            assert_eq!(owned.into_line(), line);
+        }
+        assert!(OwnedLogEntry::parse(b"garbage".to_vec()).is_err());
+    }
+}
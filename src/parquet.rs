@@ -0,0 +1,136 @@
+use std::io;
+use std::io::Write;
+
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::{GzipLevel, ZstdLevel};
+use ::parquet::file::properties::WriterProperties;
+
+use crate::arrow::{arrow_schema, ArrowBuilder};
+use crate::entry::LogEntry;
+
+const DEFAULT_ROW_GROUP_SIZE: usize = 1024 * 1024;
+
+// how the columns of a parquet file are compressed. zstd takes a level from 1 to 22 and gzip one
+// from 0 to 10, out of range levels are an error when the writer is made.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParquetCompression {
+    Uncompressed,
+    Snappy,
+    Gzip(u32),
+    Zstd(i32),
+}
+
+impl ParquetCompression {
+    fn to_parquet(self) -> io::Result<::parquet::basic::Compression> {
+        use ::parquet::basic::Compression;
+
+        Ok(match self {
+            ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Gzip(level) => {
+                Compression::GZIP(GzipLevel::try_new(level).map_err(invalid_input)?)
+            },
+            ParquetCompression::Zstd(level) => {
+                Compression::ZSTD(ZstdLevel::try_new(level).map_err(invalid_input)?)
+            },
+        })
+    }
+}
+
+// writes entries to a parquet file with the columns of arrow_schema, a row group every
+// `row_group_size` entries. nothing is complete until `finish`, which writes the footer.
+pub struct ParquetLogWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    builder: ArrowBuilder,
+    row_group_size: usize,
+}
+
+impl<W: Write + Send> ParquetLogWriter<W> {
+    pub fn new(out: W, compression: ParquetCompression) -> io::Result<ParquetLogWriter<W>> {
+        let properties = WriterProperties::builder()
+            .set_compression(compression.to_parquet()?)
+            // row groups are cut here rather than by the writer, see set_row_group_size.
+            .set_max_row_group_size(usize::MAX)
+            .build();
+        let writer = ArrowWriter::try_new(out, arrow_schema(), Some(properties))
+            .map_err(io::Error::other)?;
+        Ok(ParquetLogWriter {
+            writer,
+            builder: ArrowBuilder::new(),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+        })
+    }
+
+    // how many entries go in each row group, a million by default. the entries of a row group
+    // are held in memory until it's written.
+    pub fn set_row_group_size(&mut self, entries: usize) {
+        self.row_group_size = entries.max(1);
+    }
+
+    pub fn write(&mut self, entry: &LogEntry) -> io::Result<()> {
+        self.builder.append(entry);
+        if self.builder.len() >= self.row_group_size {
+            self.flush_row_group()?;
+        }
+        Ok(())
+    }
+
+    // writes the entries so far as a row group of their own, however few there are.
+    pub fn flush_row_group(&mut self) -> io::Result<()> {
+        if self.builder.is_empty() {
+            return Ok(());
+        }
+        self.writer.write(&self.builder.finish()).map_err(io::Error::other)?;
+        self.writer.flush().map_err(io::Error::other)
+    }
+
+    // writes what's left and the file's footer, and hands back where it was written.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_row_group()?;
+        self.writer.into_inner().map_err(io::Error::other)
+    }
+}
+
+fn invalid_input<E: std::error::Error>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ParquetCompression, ParquetLogWriter};
+    use crate::entry::LogEntry;
+    use ::parquet::file::reader::{FileReader, SerializedFileReader};
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io;
+    use std::process;
+
+    #[test]
+    fn row_groups() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                     \"GET / HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let path = env::temp_dir().join(format!("haproxy-parquet-{}.parquet", process::id()));
+
+        let mut writer = ParquetLogWriter::new(File::create(&path).unwrap(),
+                                               ParquetCompression::Zstd(3)).unwrap();
+        writer.set_row_group_size(2);
+        for _ in 0..5 {
+            writer.write(&entry).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows(), 5);
+        let sizes: Vec<i64> = metadata.row_groups().iter().map(|group| group.num_rows()).collect();
+        assert_eq!(sizes, [2, 2, 1]);
+        assert_eq!(metadata.file_metadata().schema_descr().column(0).name(), "process_name");
+        fs::remove_file(&path).unwrap();
+
+        let err = ParquetLogWriter::new(vec![], ParquetCompression::Zstd(30)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::entry::{LogEntry, Result, HEADER_FIELDS};
+use crate::expr::Expr;
+use crate::field::Field;
+use crate::filter::{Condition, Filter};
+
+// how much of a line has to be parsed to get at some set of fields. a line is parsed from the
+// start, so a plan is the number of leading fields needed and whether the capture blocks and the
+// request after them are.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Plan {
+    header_fields: usize,
+    tail: bool,
+}
+
+impl Plan {
+    // a plan which needs nothing, add to it what will be used.
+    pub fn new() -> Plan {
+        Plan::default()
+    }
+
+    // a plan which parses the whole line, like LogEntry::from_bytes.
+    pub fn full() -> Plan {
+        Plan {
+            header_fields: HEADER_FIELDS,
+            tail: true,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.tail
+    }
+
+    // how many of the leading fields are parsed, the rest are left empty.
+    pub fn header_fields(&self) -> usize {
+        if self.tail {
+            HEADER_FIELDS
+        } else {
+            self.header_fields
+        }
+    }
+
+    pub fn add_field(&mut self, field: &Field) {
+        let position = match *field {
+            Field::ProcessName => 0,
+            Field::ProcessId => 1,
+            Field::ClientIp => 2,
+            Field::ClientPort => 3,
+            Field::AcceptDate => 4,
+            Field::FrontendName => 5,
+            Field::BackendName => 6,
+            Field::ServerName => 7,
+            Field::RequestTime => 8,
+            Field::QueueTime => 9,
+            Field::ConnectTime => 10,
+            Field::ResponseTime => 11,
+            Field::TotalTime => 12,
+            Field::StatusCode | Field::StatusClass => 13,
+            Field::BytesRead => 14,
+            Field::CapturedRequestCookie => 15,
+            Field::CapturedResponseCookie => 16,
+            Field::TerminationState => 17,
+            Field::ActiveConnections => 18,
+            Field::FrontendConnections => 19,
+            Field::BackendConnections => 20,
+            Field::ServerConnections => 21,
+            Field::RetriedConnections => 22,
+            Field::ServerQueue => 23,
+            Field::BackendQueue => 24,
+            Field::HttpRequest |
+            Field::HttpMethod |
+            Field::HttpUri |
+            Field::HttpVersion |
+            Field::CapturedHeader(_, _) => {
+                self.tail = true;
+                return;
+            },
+        };
+        self.header_fields = self.header_fields.max(position + 1);
+    }
+
+    pub fn add_expr(&mut self, expr: &Expr) {
+        match *expr {
+            Expr::Field(ref field) => self.add_field(field),
+            Expr::Integer(_) | Expr::Text(_) => {},
+            Expr::Binary(ref lhs, _, ref rhs) => {
+                self.add_expr(lhs);
+                self.add_expr(rhs);
+            },
+        }
+    }
+
+    pub fn add_filter(&mut self, filter: &Filter) {
+        for condition in filter.conditions() {
+            match *condition {
+                Condition::Compare(ref lhs, _, ref rhs) => {
+                    self.add_expr(lhs);
+                    self.add_expr(rhs);
+                },
+                Condition::Matches(ref expr, _) | Condition::NotMatches(ref expr, _) => {
+                    self.add_expr(expr)
+                },
+                Condition::Since(_) | Condition::Until(_) => self.add_field(&Field::AcceptDate),
+            }
+        }
+    }
+
+    // parse `buf` as far as this plan needs. fields beyond that are left empty, though the header
+    // is always checked to be haproxy's. the capture blocks and the request after it aren't when
+    // they aren't needed, so a line which from_bytes would reject for those can parse fine.
+    pub fn parse<'a>(&self, buf: &'a [u8]) -> Result<LogEntry<'a>> {
+        if self.is_full() {
+            LogEntry::from_bytes(buf)
+        } else {
+            LogEntry::from_bytes_partial(buf, self.header_fields)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Plan;
+    use crate::entry::{LogEntry, HEADER_FIELDS};
+    use crate::expr::Expr;
+    use crate::field::Field;
+    use crate::filter::Filter;
+
+    static SAMPLE: &str = concat!("haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] ",
+                                  "http-in static/srv1 10/0/30/69/109 200 2750 - - ---- ",
+                                  "1/1/1/1/0 0/0 {1wt.eu} \"GET /index.html HTTP/1.1\"");
+
+    #[test]
+    fn fields_needed() {
+        let mut plan = Plan::new();
+        assert_eq!(plan.header_fields(), 0);
+
+        plan.add_field(&Field::ClientIp);
+        assert_eq!(plan.header_fields(), 3);
+        plan.add_expr(&Expr::parse("Tt - Tr").unwrap());
+        assert_eq!(plan.header_fields(), 13);
+        plan.add_field(&Field::ProcessName);
+        assert_eq!(plan.header_fields(), 13);
+        assert!(!plan.is_full());
+
+        plan.add_filter(&Filter::parse(&["uri ~ \"^/api\""]).unwrap());
+        assert!(plan.is_full());
+        assert_eq!(plan.header_fields(), HEADER_FIELDS);
+    }
+
+    #[test]
+    fn parse_partially() {
+        let mut plan = Plan::new();
+        plan.add_field(&Field::StatusClass);
+
+        let entry = plan.parse(SAMPLE.as_bytes()).unwrap();
+        let full = LogEntry::from_bytes(SAMPLE.as_bytes()).unwrap();
+        assert_eq!(entry.client_ip, full.client_ip);
+        assert_eq!(entry.status_code, full.status_code);
+        assert_eq!(entry.bytes_read, b"");
+        assert_eq!(entry.captures[0], b"");
+        assert_eq!(entry.http_request, b"");
+
+        // nothing after the header is looked at, but the whole header has to be there.
+        let truncated = &SAMPLE[..SAMPLE.find("{1wt").unwrap()];
+        assert!(LogEntry::from_bytes(truncated.as_bytes()).is_err());
+        assert_eq!(plan.parse(truncated.as_bytes()).unwrap().status_code, b"200");
+        let truncated = &SAMPLE[..SAMPLE.find(" 2750").unwrap() + 1];
+        assert!(plan.parse(truncated.as_bytes()).is_err());
+        assert!(plan.parse(&truncated.as_bytes()[..40]).is_err());
+
+        assert_eq!(Plan::full().parse(SAMPLE.as_bytes()).unwrap(), full);
+    }
+
+    #[test]
+    fn reject_other_programs() {
+        let lines: &[&[u8]] = &[
+            b"sshd[2211]: pam_unix(sshd:session): session opened for user deploy by (uid=0)",
+            b"CRON[8713]: (root) CMD (command -v debian-sa1 > /dev/null && debian-sa1 1 1)",
+        ];
+        for field in [Field::ProcessName, Field::ClientIp] {
+            let mut plan = Plan::new();
+            plan.add_field(&field);
+            for line in lines {
+                assert!(plan.parse(line).is_err(), "{}", String::from_utf8_lossy(line));
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use ::pyo3::exceptions::{PyKeyError, PyValueError};
+use ::pyo3::prelude::*;
+use ::pyo3::types::{PyBytes, PyDict, PyList};
+
+use crate::field::{Field, FIELD_NAMES};
+use crate::integer::parse_field;
+use crate::owned::OwnedLogEntry;
+
+// the python module, built into a wheel by maturin with pyproject.toml. `parse_line` gives back
+// a LogEntry, which reads like a dict of FIELD_NAMES, so `dict(entry)` or a list of
+// `entry.to_dict()`s makes a pandas DataFrame.
+#[pymodule]
+fn haproxy(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(parse_line, module)?)?;
+    module.add_class::<PyLogEntry>()?;
+    Ok(())
+}
+
+// parses a line given as bytes or str, a trailing newline is fine. a line which doesn't parse is a
+// ValueError.
+#[pyfunction]
+fn parse_line(line: &Bound<'_, PyAny>) -> PyResult<PyLogEntry> {
+    let line = match line.downcast::<PyBytes>() {
+        Ok(bytes) => bytes.as_bytes().to_vec(),
+        Err(_) => line.extract::<String>()?.into_bytes(),
+    };
+    match OwnedLogEntry::parse(line) {
+        Ok(entry) => Ok(PyLogEntry { entry }),
+        Err(err) => Err(PyValueError::new_err(err.to_string())),
+    }
+}
+
+// a parsed line. fields are looked up by name or alias like haproxy-cut's, captured headers
+// included, and iterating goes through the names in FIELD_NAMES. numeric fields are ints, or None
+// when haproxy logged something else there, and the rest are strs.
+#[pyclass(name = "LogEntry", mapping, frozen)]
+struct PyLogEntry {
+    entry: OwnedLogEntry,
+}
+
+#[pymethods]
+impl PyLogEntry {
+    fn __getitem__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        match Field::decode(name) {
+            Ok(field) => self.value(py, field),
+            Err(_) => Err(PyKeyError::new_err(name.to_string())),
+        }
+    }
+
+    #[pyo3(signature = (name, default=None))]
+    fn get(&self, py: Python<'_>, name: &str, default: Option<PyObject>) -> PyResult<PyObject> {
+        match Field::decode(name) {
+            Ok(field) => self.value(py, field),
+            Err(_) => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    fn __contains__(&self, name: &str) -> bool {
+        Field::decode(name).is_ok()
+    }
+
+    fn __len__(&self) -> usize {
+        names().count()
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(self.keys(py)?.call_method0("__iter__")?.unbind())
+    }
+
+    fn keys<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        PyList::new(py, names().map(|(name, _)| name).collect::<Vec<_>>())
+    }
+
+    fn values<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let values = names()
+            .map(|(_, field)| self.value(py, field))
+            .collect::<PyResult<Vec<_>>>()?;
+        PyList::new(py, values)
+    }
+
+    fn items<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let items = names()
+            .map(|(name, field)| Ok((name, self.value(py, field)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        PyList::new(py, items)
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (name, field) in names() {
+            dict.set_item(name, self.value(py, field)?)?;
+        }
+        Ok(dict)
+    }
+
+    // the line as it was parsed.
+    #[getter]
+    fn line<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.entry.line())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("LogEntry({:?})", String::from_utf8_lossy(self.entry.line()))
+    }
+}
+
+impl PyLogEntry {
+    fn value(&self, py: Python<'_>, field: Field) -> PyResult<PyObject> {
+        let content = field.extract_content_from(&self.entry.entry());
+        if field.is_numeric() {
+            let number = parse_field(content);
+            Ok(number.into_pyobject(py)?.unbind())
+        } else {
+            Ok(String::from_utf8_lossy(content).into_pyobject(py)?.into_any().unbind())
+        }
+    }
+}
+
+// the names in FIELD_NAMES which are fields, captured_header[i][j] aside.
+fn names() -> impl Iterator<Item = (&'static str, Field)> {
+    FIELD_NAMES.iter().filter_map(|&(name, _)| Some((name, Field::decode(name).ok()?)))
+}
+
+#[cfg(test)]
+mod test {
+    use ::pyo3::prelude::*;
+    use ::pyo3::types::{IntoPyDict, PyModule};
+
+    #[test]
+    fn entries() {
+        ::pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "haproxy").unwrap();
+            super::haproxy(&module).unwrap();
+            let globals = [("haproxy", module)].into_py_dict(py).unwrap();
+            let run = |code: &str| py.run(&std::ffi::CString::new(code).unwrap(), Some(&globals),
+                                          None);
+
+            run(r#"
+entry = haproxy.parse_line(b'haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in '
+                           b'static/srv1 -1/0/30/69/+109 200 2750 - - ---- 1/1/1/1/0 0/0 '
+                           b'{1wt.eu} "GET /index.html HTTP/1.1"\n')
+assert entry['status'] == 200
+assert entry['status_code'] == 200
+assert entry['Tq'] == -1
+assert entry['Tt'] == 109
+assert entry['server'] == 'srv1'
+assert entry['captured_header[0][0]'] == '1wt.eu'
+assert entry.get('nope', 'x') == 'x'
+assert 'uri' in entry and 'nope' not in entry
+as_dict = dict(entry)
+assert len(as_dict) == len(entry) == len(entry.keys())
+assert as_dict == entry.to_dict()
+assert as_dict['http_uri'] == '/index.html'
+assert list(entry)[0] == 'process_name'
+assert entry.line.startswith(b'haproxy[14389]')
+try:
+    entry['nope']
+    assert False
+except KeyError:
+    pass
+try:
+    haproxy.parse_line('not a log line')
+    assert False
+except ValueError:
+    pass
+assert haproxy.parse_line(entry.line.decode())['pid'] == 14389
+"#).unwrap();
+        });
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+
+use crate::entry::LogEntry;
+
+// the running means and co-moments for a pearson correlation between two series, kept the way
+// Welford's algorithm does so that series far from zero, like timestamps or large byte counts,
+// don't lose their variance to cancellation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Correlation {
+    n: f64,
+    mean_x: f64,
+    mean_y: f64,
+    // the sums of squared differences from the mean, and of the products of both differences.
+    m2_x: f64,
+    m2_y: f64,
+    c_xy: f64,
+}
+
+impl Correlation {
+    pub fn add(&mut self, x: f64, y: f64) {
+        self.n += 1.0;
+        let dx = x - self.mean_x;
+        let dy = y - self.mean_y;
+        self.mean_x += dx / self.n;
+        self.mean_y += dy / self.n;
+        self.m2_x += dx * (x - self.mean_x);
+        self.m2_y += dy * (y - self.mean_y);
+        self.c_xy += dx * (y - self.mean_y);
+    }
+
+    // combines the two the way Chan et al.'s parallel algorithm does.
+    pub fn merge(&mut self, other: &Correlation) {
+        if other.n == 0.0 {
+            return;
+        }
+        if self.n == 0.0 {
+            *self = *other;
+            return;
+        }
+        let n = self.n + other.n;
+        let dx = other.mean_x - self.mean_x;
+        let dy = other.mean_y - self.mean_y;
+        let weight = self.n * other.n / n;
+        self.mean_x += dx * other.n / n;
+        self.mean_y += dy * other.n / n;
+        self.m2_x += other.m2_x + dx * dx * weight;
+        self.m2_y += other.m2_y + dy * dy * weight;
+        self.c_xy += other.c_xy + dx * dy * weight;
+        self.n = n;
+    }
+
+    // between -1 and 1, none when either series never varied.
+    pub fn coefficient(&self) -> Option<f64> {
+        if self.m2_x <= 0.0 || self.m2_y <= 0.0 {
+            None
+        } else {
+            Some(self.c_xy / (self.m2_x * self.m2_y).sqrt())
+        }
+    }
+}
+
+// how much of the latency of a backend's requests was spent queued.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct QueueStats {
+    pub requests: u64,
+    // requests which waited behind others in the server or backend queue.
+    pub queued: u64,
+    // requests whose Tt was at least the QueueAnalysis' threshold.
+    pub slow: u64,
+    pub slow_queued: u64,
+    // slow requests which spent at least half of Tt in the queue.
+    pub queue_dominated: u64,
+    // Tw and Tt summed over the slow requests, in milliseconds.
+    pub slow_wait: i64,
+    pub slow_total: i64,
+    depth_latency: Correlation,
+    wait_latency: Correlation,
+}
+
+impl QueueStats {
+    pub fn merge(&mut self, other: &QueueStats) {
+        self.requests += other.requests;
+        self.queued += other.queued;
+        self.slow += other.slow;
+        self.slow_queued += other.slow_queued;
+        self.queue_dominated += other.queue_dominated;
+        self.slow_wait += other.slow_wait;
+        self.slow_total += other.slow_total;
+        self.depth_latency.merge(&other.depth_latency);
+        self.wait_latency.merge(&other.wait_latency);
+    }
+
+    // the fraction of slow requests which were slow mostly because they were queued, none without
+    // any slow requests. close to 1 means raising maxconn (or adding servers) would help, close to
+    // 0 means the servers themselves are slow and more connections to them would only make it
+    // worse.
+    pub fn queue_dominated_fraction(&self) -> Option<f64> {
+        if self.slow == 0 {
+            None
+        } else {
+            Some(self.queue_dominated as f64 / self.slow as f64)
+        }
+    }
+
+    // the fraction of the slow requests' total time which was spent queued.
+    pub fn slow_wait_fraction(&self) -> Option<f64> {
+        if self.slow_total <= 0 {
+            None
+        } else {
+            Some(self.slow_wait as f64 / self.slow_total as f64)
+        }
+    }
+
+    // the correlation between srv_queue + backend_queue and Tt.
+    pub fn depth_correlation(&self) -> Option<f64> {
+        self.depth_latency.coefficient()
+    }
+
+    // the correlation between Tw and Tt.
+    pub fn wait_correlation(&self) -> Option<f64> {
+        self.wait_latency.coefficient()
+    }
+}
+
+// QueueStats per backend, to tell whether its slow requests were slow because they waited for a
+// connection slot or because its servers were slow to respond.
+#[derive(Clone, Debug)]
+pub struct QueueAnalysis {
+    slow: i64,
+    backends: BTreeMap<String, QueueStats>,
+}
+
+impl QueueAnalysis {
+    // counts requests which took `slow` milliseconds or more as slow.
+    pub fn new(slow: i64) -> QueueAnalysis {
+        QueueAnalysis {
+            slow,
+            backends: BTreeMap::new(),
+        }
+    }
+
+    pub fn slow(&self) -> i64 {
+        self.slow
+    }
+
+    // counts `entry` towards its backend. entries without a Tt, like those logged with logasap
+    // before completing, are only counted as requests.
+    pub fn add(&mut self, entry: &LogEntry) {
+        let backend = String::from_utf8_lossy(entry.backend_name).into_owned();
+        let stats = self.backends.entry(backend).or_default();
+        stats.requests += 1;
+
+        let depth = entry.server_queue().unwrap_or(0) + entry.backend_queue().unwrap_or(0);
+        let total_time = match entry.total_time() {
+            Ok(total_time) if total_time >= 0 => total_time,
+            _ => return,
+        };
+        // Tw is -1 for sessions which never got out of the queue, which waited for all of Tt
+        // once their request was in.
+        let wait = match entry.queue_time() {
+            Ok(wait) if wait >= 0 => wait,
+            _ if depth > 0 => (total_time - entry.request_time().unwrap_or(0).max(0)).max(0),
+            _ => 0,
+        };
+        let queued = depth > 0 || wait > 0;
+        if queued {
+            stats.queued += 1;
+        }
+        stats.depth_latency.add(depth as f64, total_time as f64);
+        stats.wait_latency.add(wait as f64, total_time as f64);
+
+        if total_time >= self.slow {
+            stats.slow += 1;
+            stats.slow_wait += wait;
+            stats.slow_total += total_time;
+            if queued {
+                stats.slow_queued += 1;
+            }
+            if wait > 0 && 2 * wait >= total_time {
+                stats.queue_dominated += 1;
+            }
+        }
+    }
+
+    pub fn get(&self, backend: &str) -> Option<&QueueStats> {
+        self.backends.get(backend)
+    }
+
+    // every backend seen, by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &QueueStats)> {
+        self.backends.iter().map(|(name, stats)| (name.as_str(), stats))
+    }
+
+    // every backend's stats together.
+    pub fn total(&self) -> QueueStats {
+        let mut total = QueueStats::default();
+        for stats in self.backends.values() {
+            total.merge(stats);
+        }
+        total
+    }
+
+    pub fn len(&self) -> usize {
+        self.backends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.backends.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Correlation, QueueAnalysis};
+    use crate::entry::test::TestLine;
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn correlation() {
+        let mut correlation = Correlation::default();
+        assert_eq!(correlation.coefficient(), None);
+        for i in 0..10 {
+            correlation.add(i as f64, 2.0 * i as f64 + 1.0);
+        }
+        assert!((correlation.coefficient().unwrap() - 1.0).abs() < 1e-9);
+        let mut merged = Correlation::default();
+        merged.merge(&correlation);
+        assert_eq!(merged, correlation);
+    }
+
+    #[test]
+    fn correlation_far_from_zero() {
+        let mut correlation = Correlation::default();
+        let (mut first, mut second) = (Correlation::default(), Correlation::default());
+        for i in 0..10 {
+            let (x, y) = (1e9 + i as f64, 1e9 - 3.0 * i as f64 + (i % 2) as f64);
+            correlation.add(x, y);
+            if i < 4 {
+                first.add(x, y);
+            } else {
+                second.add(x, y);
+            }
+        }
+        let coefficient = correlation.coefficient().unwrap();
+        assert!((coefficient + 0.998338).abs() < 1e-6, "{}", coefficient);
+        first.merge(&second);
+        assert!((first.coefficient().unwrap() - coefficient).abs() < 1e-9);
+
+        let mut constant = Correlation::default();
+        for i in 0..10 {
+            constant.add(1e9, i as f64);
+        }
+        assert_eq!(constant.coefficient(), None);
+    }
+
+    #[test]
+    fn queueing() {
+        let lines = [
+            TestLine::new(),
+            // slow, but from the server.
+            TestLine::new().timers("10/0/30/1900/1940"),
+            // slow, waiting behind others.
+            TestLine::new().timers("10/1500/30/69/1609").queues("3/0"),
+            TestLine::new().timers("10/800/30/69/909").queues("2/0"),
+            // never left the queue.
+            TestLine::new().timers("10/-1/-1/-1/3010").queues("0/12"),
+            TestLine::new().backend("dynamic").timers("10/0/30/1200/1240"),
+            TestLine::new().backend("dynamic").timers("10/0/30/69/-1"),
+        ].map(|line| line.to_string());
+        let mut analysis = QueueAnalysis::new(1000);
+        for line in &lines {
+            analysis.add(&LogEntry::from_bytes(line.as_bytes()).unwrap());
+        }
+        assert_eq!(analysis.len(), 2);
+
+        let stats = analysis.get("static").unwrap();
+        assert_eq!((stats.requests, stats.queued, stats.slow), (5, 3, 3));
+        assert_eq!((stats.slow_queued, stats.queue_dominated), (2, 2));
+        assert_eq!((stats.slow_wait, stats.slow_total), (1500 + 3000, 1940 + 1609 + 3010));
+        assert!((stats.queue_dominated_fraction().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert!(stats.depth_correlation().unwrap() > 0.5);
+        assert!(stats.wait_correlation().unwrap() > 0.5);
+
+        let stats = analysis.get("dynamic").unwrap();
+        assert_eq!((stats.requests, stats.slow, stats.queue_dominated), (2, 1, 0));
+        assert_eq!(stats.queue_dominated_fraction(), Some(0.0));
+        assert_eq!(stats.depth_correlation(), None);
+
+        let total = analysis.total();
+        assert_eq!((total.requests, total.slow, total.queue_dominated), (7, 4, 2));
+        assert_eq!(QueueAnalysis::new(5000).total().queue_dominated_fraction(), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, NaiveDateTime};
+
+use crate::entry::LogEntry;
+
+// a client which sent more than the limit within a window, with how it fared overall.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Offender {
+    pub client: String,
+    pub requests: u64,
+    // the most requests it sent within any one window.
+    pub peak: u64,
+    // when the windows over the limit ended, the first and the last of them.
+    pub first_exceeded: NaiveDateTime,
+    pub last_exceeded: NaiveDateTime,
+    // requests by the first digit of the status code, 0 being those without a response.
+    pub status_classes: [u64; 6],
+}
+
+impl Offender {
+    // the percentage of its requests which got `class`xx, or no response for 0.
+    pub fn status_percentage(&self, class: usize) -> f64 {
+        100.0 * self.status_classes[class] as f64 / self.requests.max(1) as f64
+    }
+}
+
+#[derive(Default)]
+struct Client {
+    // requests per second within the current window, oldest first.
+    seconds: VecDeque<(i64, u64)>,
+    in_window: u64,
+    requests: u64,
+    peak: u64,
+    status_classes: [u64; 6],
+    exceeded: Option<(i64, i64)>,
+}
+
+// per-client request rates over a sliding window of `window` seconds, to find the clients which
+// sent more than `limit` requests within one, e.g. to deny them with a map or acl through the
+// runtime api. entries are expected in roughly chronological order; late ones count towards the
+// second they're added in.
+pub struct ClientRates {
+    window: i64,
+    limit: u64,
+    clients: HashMap<String, Client>,
+}
+
+impl ClientRates {
+    pub fn new(window: i64, limit: u64) -> ClientRates {
+        ClientRates {
+            window: window.max(1),
+            limit,
+            clients: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &LogEntry) {
+        let second = match entry.accept_date_time() {
+            Ok(accepted) => accepted.and_utc().timestamp(),
+            Err(_) => return,
+        };
+        let name = String::from_utf8_lossy(entry.client_ip).into_owned();
+        let client = self.clients.entry(name).or_default();
+
+        let class = match entry.status_code() {
+            Ok(status) if (100..600).contains(&status) => (status / 100) as usize,
+            _ => 0,
+        };
+        client.status_classes[class] += 1;
+        client.requests += 1;
+
+        let now = client.seconds.back().map_or(second, |&(latest, _)| latest.max(second));
+        while let Some(&(oldest, count)) = client.seconds.front() {
+            if oldest > now - self.window {
+                break;
+            }
+            client.in_window -= count;
+            client.seconds.pop_front();
+        }
+        match client.seconds.back_mut() {
+            Some((latest, count)) if *latest == now => *count += 1,
+            _ => client.seconds.push_back((now, 1)),
+        }
+        client.in_window += 1;
+
+        client.peak = client.peak.max(client.in_window);
+        if client.in_window > self.limit {
+            let first = client.exceeded.map_or(now, |(first, _)| first);
+            client.exceeded = Some((first, now));
+        }
+    }
+
+    // how many clients have been seen.
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    // every client which went over the limit, those with the highest peaks first.
+    pub fn offenders(&self) -> Vec<Offender> {
+        let time = |second: i64| {
+            DateTime::from_timestamp(second, 0).map(|time| time.naive_utc()).unwrap_or_default()
+        };
+        let mut offenders: Vec<Offender> = self.clients.iter()
+            .filter_map(|(name, client)| {
+                let (first, last) = client.exceeded?;
+                Some(Offender {
+                    client: name.clone(),
+                    requests: client.requests,
+                    peak: client.peak,
+                    first_exceeded: time(first),
+                    last_exceeded: time(last),
+                    status_classes: client.status_classes,
+                })
+            })
+            .collect();
+        offenders.sort_by(|a, b| b.peak.cmp(&a.peak).then_with(|| a.client.cmp(&b.client)));
+        offenders
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ClientRates;
+    use crate::entry::test::TestLine;
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn offenders() {
+        let mut rates = ClientRates::new(10, 5);
+        // six requests within ten seconds, then a quiet one, and a client which stays under.
+        for (second, status) in [(0, "200"), (2, "200"), (4, "429"), (6, "429"), (8, "429"),
+                                 (9, "503"), (30, "200")] {
+            let line = TestLine::new().time(&format!("12:14:{:02}", second)).status(status)
+                .to_string();
+            rates.add(&LogEntry::from_bytes(line.as_bytes()).unwrap());
+        }
+        for second in 0..5 {
+            let line = TestLine::new().client("10.0.1.3").time(&format!("12:14:{:02}", second))
+                .to_string();
+            rates.add(&LogEntry::from_bytes(line.as_bytes()).unwrap());
+        }
+        assert_eq!(rates.len(), 2);
+
+        let offenders = rates.offenders();
+        assert_eq!(offenders.len(), 1);
+        let offender = &offenders[0];
+        assert_eq!(offender.client, "10.0.1.2");
+        assert_eq!((offender.requests, offender.peak), (7, 6));
+        assert_eq!(offender.first_exceeded.to_string(), "2009-02-06 12:14:09");
+        assert_eq!(offender.first_exceeded, offender.last_exceeded);
+        assert_eq!(offender.status_classes, [0, 0, 3, 0, 3, 1]);
+        assert!((offender.status_percentage(4) - 300.0 / 7.0).abs() < 1e-9);
+    }
+}
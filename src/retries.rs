@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDateTime};
+
+use crate::entry::LogEntry;
+
+// what a server's requests went through in a window, or a run of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RetryCounts {
+    pub requests: u64,
+    // requests with at least one retry or a redispatch.
+    pub retried: u64,
+    // the connection retries logged, summed.
+    pub retries: u64,
+    // requests redispatched to another server, logged with a leading +.
+    pub redispatches: u64,
+}
+
+impl RetryCounts {
+    pub fn add(&mut self, entry: &LogEntry) {
+        self.requests += 1;
+        let retries = entry.retried_connections().unwrap_or(0);
+        let redispatched = entry.retried_connections.first() == Some(&b'+');
+        self.retries += retries;
+        if redispatched {
+            self.redispatches += 1;
+        }
+        if retries > 0 || redispatched {
+            self.retried += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &RetryCounts) {
+        self.requests += other.requests;
+        self.retried += other.retried;
+        self.retries += other.retries;
+        self.redispatches += other.redispatches;
+    }
+}
+
+// a run of consecutive windows in which a server had at least the minimum of retried requests.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RetryStorm {
+    pub backend: String,
+    pub server: String,
+    // the start of the first window and the end of the last, in the time zone entries were logged
+    // in.
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub counts: RetryCounts,
+}
+
+#[derive(Default)]
+struct Server {
+    // the current window's start, in seconds since the epoch, and what's been seen of it.
+    current: Option<(i64, RetryCounts)>,
+    // the windows which had enough retried requests, oldest first.
+    bursts: Vec<(i64, RetryCounts)>,
+}
+
+// finds retry storms: bursts of retried and redispatched requests to a server within short windows,
+// which tend to come before a server starts flapping between up and down. each server's entries are
+// counted in fixed windows of `window` seconds, and a window with at least `min_retried` retried
+// requests is a burst, consecutive bursts making up one storm. entries are expected in roughly
+// chronological order; late ones are counted in their server's current window. memory is bounded
+// by the number of servers and bursts, not the length of the stream.
+pub struct RetryStorms {
+    window: i64,
+    min_retried: u64,
+    servers: HashMap<(String, String), Server>,
+}
+
+impl RetryStorms {
+    pub fn new(window: i64, min_retried: u64) -> RetryStorms {
+        RetryStorms {
+            window: window.max(1),
+            min_retried: min_retried.max(1),
+            servers: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &LogEntry) {
+        let start = match entry.accept_date_time() {
+            Ok(accepted) => accepted.and_utc().timestamp().div_euclid(self.window) * self.window,
+            Err(_) => return,
+        };
+        let key = (String::from_utf8_lossy(entry.backend_name).into_owned(),
+                   String::from_utf8_lossy(entry.server_name).into_owned());
+        let server = self.servers.entry(key).or_default();
+        match server.current {
+            Some((current, counts)) if current < start => {
+                if counts.retried >= self.min_retried {
+                    server.bursts.push((current, counts));
+                }
+                server.current = Some((start, RetryCounts::default()));
+            },
+            Some(_) => {},
+            None => server.current = Some((start, RetryCounts::default())),
+        }
+        if let Some((_, counts)) = server.current.as_mut() {
+            counts.add(entry);
+        }
+    }
+
+    // every storm so far, including any still going on, the earliest first.
+    pub fn storms(&self) -> Vec<RetryStorm> {
+        let time = |second: i64| {
+            DateTime::from_timestamp(second, 0).map(|time| time.naive_utc()).unwrap_or_default()
+        };
+        let mut storms = vec![];
+        for ((backend, server), state) in &self.servers {
+            let current = state.current.filter(|(_, counts)| counts.retried >= self.min_retried);
+            let mut runs: Vec<(i64, i64, RetryCounts)> = vec![];
+            for &(start, counts) in state.bursts.iter().chain(current.iter()) {
+                match runs.last_mut() {
+                    Some((_, end, total)) if *end == start => {
+                        *end = start + self.window;
+                        total.merge(&counts);
+                    },
+                    _ => runs.push((start, start + self.window, counts)),
+                }
+            }
+            storms.extend(runs.into_iter().map(|(start, end, counts)| RetryStorm {
+                backend: backend.clone(),
+                server: server.clone(),
+                start: time(start),
+                end: time(end),
+                counts,
+            }));
+        }
+        storms.sort_by(|a, b| {
+            a.start.cmp(&b.start)
+                .then_with(|| a.backend.cmp(&b.backend))
+                .then_with(|| a.server.cmp(&b.server))
+        });
+        storms
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RetryStorms;
+    use crate::entry::test::TestLine;
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn storms() {
+        let lines = [
+            TestLine::new().time("12:00:05"),
+            TestLine::new().time("12:00:10").connections("1/1/1/1/1"),
+            TestLine::new().time("12:00:20").connections("1/1/1/1/3"),
+            TestLine::new().time("12:01:00").connections("1/1/1/1/+1"),
+            TestLine::new().time("12:01:30").connections("1/1/1/1/2"),
+            TestLine::new().time("12:02:00"),
+            // a single retry isn't a storm.
+            TestLine::new().server("srv2").time("12:00:30").connections("1/1/1/1/1"),
+            TestLine::new().time("12:05:00").connections("1/1/1/1/1"),
+            TestLine::new().time("12:05:10").connections("1/1/1/1/+2"),
+            TestLine::new().time("12:05:20"),
+        ].map(|line| line.to_string());
+        let mut storms = RetryStorms::new(60, 2);
+        for line in &lines {
+            storms.add(&LogEntry::from_bytes(line.as_bytes()).unwrap());
+        }
+        let storms = storms.storms();
+        assert_eq!(storms.len(), 2, "{:?}", storms);
+
+        assert_eq!((&*storms[0].backend, &*storms[0].server), ("static", "srv1"));
+        assert_eq!(storms[0].start.to_string(), "2009-02-06 12:00:00");
+        assert_eq!(storms[0].end.to_string(), "2009-02-06 12:02:00");
+        let counts = storms[0].counts;
+        assert_eq!((counts.requests, counts.retried, counts.retries), (5, 4, 7));
+        assert_eq!(counts.redispatches, 1);
+
+        // still going on at the end.
+        assert_eq!(storms[1].start.to_string(), "2009-02-06 12:05:00");
+        assert_eq!(storms[1].counts.redispatches, 1);
+    }
+}
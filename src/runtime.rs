@@ -0,0 +1,1418 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::captured::{parse_errors, CapturedError};
+use crate::diagnostics::{parse_fds, Activity, FileDescriptor};
+
+// the commands of haproxy's runtime api, with their answers parsed. they're the same whether each
+// command gets a connection of its own with a RuntimeClient or they share a RuntimeSession.
+pub trait Runtime {
+    // the answer to `command` as haproxy wrote it.
+    fn execute(&mut self, command: &str) -> io::Result<String>;
+
+    // the same commands sent through the master cli to one of the processes it manages.
+    fn on_process(self, process: Process) -> OnProcess<Self> where Self: Sized {
+        OnProcess { runtime: self, process }
+    }
+
+    // the master and the workers it has, which only the master cli answers. workers left from
+    // before a reload are there too, with `old` set, until they're done with their connections.
+    fn show_proc(&mut self) -> io::Result<Vec<ProcessInfo>> {
+        let response = self.execute("show proc")?;
+        if !response.starts_with("#<PID>") {
+            return Err(unexpected_response(&response));
+        }
+        Ok(parse_proc(&response))
+    }
+
+    fn show_stat_csv(&mut self) -> io::Result<StatsCsv> {
+        let response = self.execute("show stat")?;
+        StatsCsv::parse(&response).ok_or_else(|| unexpected_response(&response))
+    }
+
+    fn show_stat(&mut self, filter: &StatFilter) -> io::Result<Vec<StatRow>> {
+        let response = self.execute(&filter.command())?;
+        let stats = StatsCsv::parse(&response).ok_or_else(|| unexpected_response(&response))?;
+        Ok(filter.rows(&stats))
+    }
+
+    fn show_info(&mut self) -> io::Result<Info> {
+        let response = self.execute("show info")?;
+        if !response.starts_with("Name:") {
+            return Err(unexpected_response(&response));
+        }
+        Ok(Info::parse(&response))
+    }
+
+    // the invalid requests and responses haproxy has kept. it answers with only a count when there
+    // are none, anything else is an error.
+    fn show_errors(&mut self) -> io::Result<Vec<CapturedError>> {
+        let response = self.execute("show errors")?;
+        if !response.starts_with("Total events captured") {
+            return Err(unexpected_response(&response));
+        }
+        Ok(parse_errors(&response))
+    }
+
+    // every file descriptor haproxy has open and what it's for.
+    fn show_fd(&mut self) -> io::Result<Vec<FileDescriptor>> {
+        let response = self.execute("show fd")?;
+        parse_fds(&response).ok_or_else(|| unexpected_response(&response))
+    }
+
+    fn show_activity(&mut self) -> io::Result<Activity> {
+        let response = self.execute("show activity")?;
+        if !response.starts_with("thread_id:") {
+            return Err(unexpected_response(&response));
+        }
+        Ok(Activity::parse(&response))
+    }
+
+    // `server` is backend/server, as in the `target` field of haproxy-cut.
+    fn disable_server(&mut self, server: &str) -> io::Result<()> {
+        self.run(&format!("disable server {}", check_server(server)?))
+    }
+
+    fn enable_server(&mut self, server: &str) -> io::Result<()> {
+        self.run(&format!("enable server {}", check_server(server)?))
+    }
+
+    fn set_server_state(&mut self, server: &str, state: ServerState) -> io::Result<()> {
+        self.run(&format!("set server {} state {}", check_server(server)?, state.as_str()))
+    }
+
+    fn set_weight(&mut self, server: &str, weight: Weight) -> io::Result<()> {
+        let weight = match weight {
+            Weight::Absolute(weight) => weight.to_string(),
+            Weight::Percent(percent) => format!("{}%", percent),
+        };
+        self.run(&format!("set weight {} {}", check_server(server)?, weight))
+    }
+
+    // the maps loaded from files or declared, with what `show map` says of each.
+    fn show_maps(&mut self) -> io::Result<Vec<PatternList>> {
+        let response = self.execute("show map")?;
+        parse_pattern_lists(&response).ok_or_else(|| unexpected_response(&response))
+    }
+
+    // the entries of `map`, which is its file name or #<id>.
+    fn show_map(&mut self, map: &str) -> io::Result<Vec<PatternEntry>> {
+        let response = self.execute(&format!("show map {}", escape_word(map)))?;
+        parse_pattern_entries(&response).ok_or_else(|| unexpected_response(&response))
+    }
+
+    fn add_map(&mut self, map: &str, key: &str, value: &str) -> io::Result<()> {
+        self.run(&format!("add map {} {} {}", escape_word(map), escape_word(key),
+                          escape_word(value)))
+    }
+
+    // changes every entry for `key`, or the one entry `#<reference>` names.
+    fn set_map(&mut self, map: &str, key: &str, value: &str) -> io::Result<()> {
+        self.run(&format!("set map {} {} {}", escape_word(map), escape_word(key),
+                          escape_word(value)))
+    }
+
+    fn del_map(&mut self, map: &str, key: &str) -> io::Result<()> {
+        self.run(&format!("del map {} {}", escape_word(map), escape_word(key)))
+    }
+
+    fn clear_map(&mut self, map: &str) -> io::Result<()> {
+        self.run(&format!("clear map {}", escape_word(map)))
+    }
+
+    // the same for acl files, whose entries have no value.
+    fn show_acls(&mut self) -> io::Result<Vec<PatternList>> {
+        let response = self.execute("show acl")?;
+        parse_pattern_lists(&response).ok_or_else(|| unexpected_response(&response))
+    }
+
+    fn show_acl(&mut self, acl: &str) -> io::Result<Vec<PatternEntry>> {
+        let response = self.execute(&format!("show acl {}", escape_word(acl)))?;
+        parse_pattern_entries(&response).ok_or_else(|| unexpected_response(&response))
+    }
+
+    fn add_acl(&mut self, acl: &str, pattern: &str) -> io::Result<()> {
+        self.run(&format!("add acl {} {}", escape_word(acl), escape_word(pattern)))
+    }
+
+    fn del_acl(&mut self, acl: &str, pattern: &str) -> io::Result<()> {
+        self.run(&format!("del acl {} {}", escape_word(acl), escape_word(pattern)))
+    }
+
+    fn clear_acl(&mut self, acl: &str) -> io::Result<()> {
+        self.run(&format!("clear acl {}", escape_word(acl)))
+    }
+
+    // the stick tables, from their `# table:` lines.
+    fn show_tables(&mut self) -> io::Result<Vec<StickTable>> {
+        let response = self.execute("show table")?;
+        let tables: Option<Vec<StickTable>> = response.lines()
+            .filter(|line| !line.is_empty())
+            .map(StickTable::parse)
+            .collect();
+        tables.ok_or_else(|| unexpected_response(&response))
+    }
+
+    // the entries of `table` which `query` picks.
+    fn show_table(&mut self, table: &str, query: &TableQuery) -> io::Result<Vec<TableEntry>> {
+        let command = format!("show table {}{}", escape_word(table), query.arguments());
+        let response = self.execute(&command)?;
+        parse_table_entries(&response).ok_or_else(|| unexpected_response(&response))
+    }
+
+    // removes the entries of `table` which `query` picks, all of them for the default query.
+    fn clear_table(&mut self, table: &str, query: &TableQuery) -> io::Result<()> {
+        self.run(&format!("clear table {}{}", escape_word(table), query.arguments()))
+    }
+
+    // sets data of the entry for `key`, e.g. `&[("gpc0", 0)]`, creating it if it isn't there.
+    fn set_table(&mut self, table: &str, key: &str, data: &[(&str, i64)]) -> io::Result<()> {
+        let mut command = format!("set table {} key {}", escape_word(table), escape_word(key));
+        for &(data_type, value) in data {
+            command.push_str(&format!(" data.{} {}", escape_word(data_type), value));
+        }
+        self.run(&command)
+    }
+
+    // a command which changes something, haproxy answers those with nothing but an empty line
+    // when they work and says why otherwise, which becomes a RuntimeError.
+    fn run(&mut self, command: &str) -> io::Result<()> {
+        let response = self.execute(command)?;
+        if response.trim().is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::other(RuntimeError {
+                command: command.to_string(),
+                message: response.trim().to_string(),
+            }))
+        }
+    }
+}
+
+impl<R: Runtime + ?Sized> Runtime for &mut R {
+    fn execute(&mut self, command: &str) -> io::Result<String> {
+        (**self).execute(command)
+    }
+}
+
+// a process of a master-worker haproxy, for the master cli (`-S` or `stats socket ... master`
+// in the master's global section) to pass commands on to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Process {
+    Master,
+    // a worker by its relative pid, 1 for the current one.
+    Worker(u32),
+    // a process by its system pid, which is how old workers can be reached.
+    Pid(u32),
+}
+
+impl Process {
+    pub fn prefix(&self) -> String {
+        match *self {
+            Process::Master => "@master".to_string(),
+            Process::Worker(relative) => format!("@{}", relative),
+            Process::Pid(pid) => format!("@!{}", pid),
+        }
+    }
+}
+
+// a Runtime whose commands go to one process through the master cli, see Runtime::on_process.
+pub struct OnProcess<R> {
+    runtime: R,
+    process: Process,
+}
+
+impl<R> OnProcess<R> {
+    pub fn into_inner(self) -> R {
+        self.runtime
+    }
+}
+
+impl<R: Runtime> Runtime for OnProcess<R> {
+    fn execute(&mut self, command: &str) -> io::Result<String> {
+        let command = one_line(command)?;
+        self.runtime.execute(&format!("{} {}", self.process.prefix(), command))
+    }
+}
+
+// a line of `show proc`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProcessInfo {
+    pub pid: u32,
+    // master, worker, or the name of a `program` section.
+    pub kind: String,
+    // the worker's number for Process::Worker, only listed before haproxy 2.5.
+    pub relative_pid: Option<u32>,
+    pub reloads: Option<u32>,
+    pub failed_reloads: Option<u32>,
+    pub uptime: String,
+    pub version: String,
+    pub old: bool,
+}
+
+impl ProcessInfo {
+    pub fn process(&self) -> Process {
+        match self.kind.as_str() {
+            "master" => Process::Master,
+            _ => Process::Pid(self.pid),
+        }
+    }
+}
+
+// the answer to `show proc`: a header naming the columns, then a line for each process under a
+// comment for the workers, old workers and programs. the master's reload count can be followed by
+// `[failedreload: N]`.
+pub fn parse_proc(response: &str) -> Vec<ProcessInfo> {
+    let mut lines = response.lines();
+    let relative = lines.next().is_some_and(|header| header.contains("<relative PID>"));
+    let mut old = false;
+    let mut processes = vec![];
+    for line in lines {
+        if let Some(comment) = line.strip_prefix('#') {
+            old = comment.trim() == "old workers";
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let (pid, kind) = match (words.next().and_then(|pid| pid.parse().ok()), words.next()) {
+            (Some(pid), Some(kind)) => (pid, kind.to_string()),
+            _ => continue,
+        };
+        let mut number = || words.next().and_then(|word| word.parse().ok());
+        let relative_pid = if relative { number() } else { None };
+        let reloads = number();
+        let mut uptime = words.next().unwrap_or("");
+        let mut failed_reloads = None;
+        if uptime == "[failedreload:" {
+            failed_reloads = words.next().and_then(|n| n.trim_end_matches(']').parse().ok());
+            uptime = words.next().unwrap_or("");
+        }
+        processes.push(ProcessInfo {
+            pid,
+            kind,
+            relative_pid,
+            reloads,
+            failed_reloads,
+            uptime: uptime.to_string(),
+            version: words.next().unwrap_or("").to_string(),
+            old,
+        });
+    }
+    processes
+}
+
+// a map or acl as `show map` and `show acl` list them: the id which can stand in for its file as
+// #<id>, the file, and haproxy's description of where it's used.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PatternList {
+    pub id: i64,
+    pub file: String,
+    pub description: String,
+}
+
+// one entry of a map or acl. `reference` is the address haproxy lists it under, which can be given
+// as #<reference> instead of the key to change or delete only this entry when a key is there more
+// than once.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PatternEntry {
+    pub reference: String,
+    pub key: String,
+    // None for an acl.
+    pub value: Option<String>,
+}
+
+// lines like `-1 (/etc/haproxy/hosts.map) pattern loaded from file ...` under a `# id (file)
+// description` header.
+fn parse_pattern_lists(response: &str) -> Option<Vec<PatternList>> {
+    let mut lines = response.lines();
+    if !lines.next()?.starts_with("# id") {
+        return None;
+    }
+    let lists = lines.filter(|line| !line.is_empty()).map(|line| {
+        let (id, rest) = line.split_once(' ')?;
+        let (file, description) = rest.strip_prefix('(')?.split_once(')')?;
+        Some(PatternList {
+            id: id.parse().ok()?,
+            file: file.to_string(),
+            description: description.trim().to_string(),
+        })
+    });
+    lists.collect()
+}
+
+// lines like `0x55ddcb3ba210 example.com be_web`, or without the value for an acl.
+fn parse_pattern_entries(response: &str) -> Option<Vec<PatternEntry>> {
+    let entries = response.lines().filter(|line| !line.is_empty()).map(|line| {
+        let (reference, rest) = line.split_once(' ')?;
+        if !reference.starts_with("0x") {
+            return None;
+        }
+        let (key, value) = match rest.split_once(' ') {
+            Some((key, value)) => (key, Some(value.to_string())),
+            None => (rest, None),
+        };
+        Some(PatternEntry { reference: reference.to_string(), key: key.to_string(), value })
+    });
+    entries.collect()
+}
+
+// haproxy splits a command into words on spaces and into commands on ';', a backslash keeps either
+// in a word.
+fn escape_word(word: &str) -> String {
+    let mut escaped = String::with_capacity(word.len());
+    for c in word.chars() {
+        if matches!(c, '\\' | ' ' | ';' | '\t') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// a stick table as haproxy lists it: `# table: http-in, type: ip, size:1048576, used:1`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StickTable {
+    pub name: String,
+    pub key_type: String,
+    pub size: Option<u64>,
+    pub used: Option<u64>,
+}
+
+impl StickTable {
+    fn parse(line: &str) -> Option<StickTable> {
+        let mut table = StickTable {
+            name: String::new(),
+            key_type: String::new(),
+            size: None,
+            used: None,
+        };
+        for part in line.strip_prefix('#')?.split(',') {
+            let (name, value) = part.split_once(':')?;
+            let value = value.trim();
+            match name.trim() {
+                "table" => table.name = value.to_string(),
+                "type" => table.key_type = value.to_string(),
+                "size" => table.size = value.parse().ok(),
+                "used" => table.used = value.parse().ok(),
+                _ => {},
+            }
+        }
+        Some(table)
+    }
+}
+
+// how `show table` and `clear table` compare an entry's data with a value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TableOperator {
+    Eq,
+    Ne,
+    Le,
+    Lt,
+    Ge,
+    Gt,
+}
+
+impl TableOperator {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TableOperator::Eq => "eq",
+            TableOperator::Ne => "ne",
+            TableOperator::Le => "le",
+            TableOperator::Lt => "lt",
+            TableOperator::Ge => "ge",
+            TableOperator::Gt => "gt",
+        }
+    }
+}
+
+// which entries of a stick table a command is about: the one for `key`, or those whose data
+// matches every one of `data`, e.g. `("http_req_rate", TableOperator::Gt, 100)`. haproxy takes
+// either a key or data filters, not both.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableQuery {
+    pub key: Option<String>,
+    pub data: Vec<(String, TableOperator, i64)>,
+}
+
+impl TableQuery {
+    fn arguments(&self) -> String {
+        let mut arguments = String::new();
+        if let Some(ref key) = self.key {
+            arguments.push_str(&format!(" key {}", escape_word(key)));
+        }
+        for &(ref data_type, operator, value) in &self.data {
+            arguments.push_str(&format!(" data.{} {} {}", escape_word(data_type),
+                                        operator.as_str(), value));
+        }
+        arguments
+    }
+}
+
+// one entry of a stick table, from a line like
+// `0x55d0c0a1b2c0: key=10.0.1.2 use=0 exp=28812 http_req_rate(10000)=1`. every `name=value` is in
+// `values` in order, the data too with their period in the name as haproxy writes them.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableEntry {
+    pub reference: String,
+    pub key: String,
+    // how many streams are using the entry right now.
+    pub use_count: Option<u64>,
+    // milliseconds until the entry expires.
+    pub expires: Option<u64>,
+    pub values: Vec<(String, String)>,
+}
+
+impl TableEntry {
+    fn parse(line: &str) -> Option<TableEntry> {
+        let mut words = line.split_whitespace();
+        let reference = words.next()?.strip_suffix(':')?;
+        let values: Vec<(String, String)> = words
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let value = |name: &str| {
+            values.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+        };
+        Some(TableEntry {
+            reference: reference.to_string(),
+            key: value("key")?.to_string(),
+            use_count: value("use").and_then(|count| count.parse().ok()),
+            expires: value("exp").and_then(|exp| exp.parse().ok()),
+            values,
+        })
+    }
+
+    // a data type's value, by its name without the period, e.g. "http_req_rate".
+    pub fn data(&self, data_type: &str) -> Option<&str> {
+        self.values.iter()
+            .find(|(name, _)| name.split('(').next() == Some(data_type))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// the `# table:` line and then a line for each entry. anything else is an error message.
+fn parse_table_entries(response: &str) -> Option<Vec<TableEntry>> {
+    response.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(TableEntry::parse)
+        .collect()
+}
+
+// what haproxy said when it wouldn't do what a command asked, e.g. "No such server.". it's the
+// inner error of the io::Error the command returns.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RuntimeError {
+    pub command: String,
+    pub message: String,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.command, self.message)
+    }
+}
+
+impl Error for RuntimeError {}
+
+// the states `set server ... state` puts a server in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ServerState {
+    // taking traffic again.
+    Ready,
+    // keeping the connections it has and any persistent ones, but no new ones.
+    Drain,
+    // out of the farm, for maintenance.
+    Maint,
+}
+
+impl ServerState {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ServerState::Ready => "ready",
+            ServerState::Drain => "drain",
+            ServerState::Maint => "maint",
+        }
+    }
+}
+
+// a weight for `set weight`, either as it is or relative to the one in the configuration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Weight {
+    Absolute(u32),
+    Percent(u32),
+}
+
+// a server has to be named with its backend, and the name can't carry another command along:
+// haproxy splits a command's words at whitespace and runs what follows a `;` as the next command.
+fn check_server(server: &str) -> io::Result<&str> {
+    let separates = |c: char| c.is_whitespace() || c == ';';
+    match server.split_once('/') {
+        Some((backend, name)) if !backend.is_empty() && !name.is_empty()
+                                 && !server.contains(separates) => Ok(server),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                format!("{} isn't a backend/server", server))),
+    }
+}
+
+// where the runtime api listens, as written after `stats socket`: a unix socket's path, or
+// `ipv4@host:port` or `ipv6@[addr]:port` for a tcp one.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Address {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl Address {
+    pub fn parse(address: &str) -> Address {
+        for prefix in &["ipv4@", "ipv6@", "tcp@", "tcp4@", "tcp6@"] {
+            if let Some(host) = address.strip_prefix(prefix) {
+                return Address::Tcp(host.to_string());
+            }
+        }
+        Address::Unix(PathBuf::from(address.strip_prefix("unix@").unwrap_or(address)))
+    }
+}
+
+// either kind of connection to the runtime api.
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    fn connect(address: &Address, timeout: Option<Duration>) -> io::Result<Stream> {
+        let stream = match *address {
+            Address::Unix(ref path) => Stream::Unix(UnixStream::connect(path)?),
+            Address::Tcp(ref host) => Stream::Tcp(connect_tcp(host, timeout)?),
+        };
+        match stream {
+            Stream::Unix(ref stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)?;
+            },
+            Stream::Tcp(ref stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)?;
+            },
+        }
+        Ok(stream)
+    }
+}
+
+// the first of the addresses `host` resolves to which takes the connection.
+fn connect_tcp(host: &str, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in host.to_socket_addrs()? {
+        let result = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+            None => TcpStream::connect(address),
+        };
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no addresses", host))
+    }))
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Unix(ref mut stream) => stream.read(buf),
+            Stream::Tcp(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Unix(ref mut stream) => stream.write(buf),
+            Stream::Tcp(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Unix(ref mut stream) => stream.flush(),
+            Stream::Tcp(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+// a client for haproxy's runtime api, the socket configured with `stats socket`.
+pub struct RuntimeClient {
+    address: Address,
+    timeout: Option<Duration>,
+}
+
+impl RuntimeClient {
+    // a client for the unix socket at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> RuntimeClient {
+        RuntimeClient::connect_to(Address::Unix(path.as_ref().to_path_buf()))
+    }
+
+    pub fn connect_to(address: Address) -> RuntimeClient {
+        RuntimeClient {
+            address,
+            timeout: None,
+        }
+    }
+
+    // how long connecting, sending a command or waiting on its answer can take before it fails
+    // with TimedOut or WouldBlock. there's no limit by default.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    // haproxy closes the connection after answering a single command, so each one gets its own.
+    pub fn execute(&self, command: &str) -> io::Result<String> {
+        let command = one_line(command)?;
+        let mut stream = Stream::connect(&self.address, self.timeout)?;
+        stream.write_all(command.as_bytes())?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    // a connection which stays open for any number of commands, see RuntimeSession.
+    pub fn session(&self) -> io::Result<RuntimeSession> {
+        let mut session = RuntimeSession {
+            stream: Stream::connect(&self.address, self.timeout)?,
+            buffer: vec![],
+        };
+        // the answer to `prompt` is only the prompt itself.
+        session.execute("prompt")?;
+        Ok(session)
+    }
+}
+
+// a connection in haproxy's interactive mode, where it writes a "> " prompt after each answer
+// rather than closing the connection. that prompt is how the end of an answer is found, so it's
+// taken off each answer to look the same as RuntimeClient::execute's.
+pub struct RuntimeSession {
+    stream: Stream,
+    buffer: Vec<u8>,
+}
+
+impl RuntimeSession {
+    pub fn execute(&mut self, command: &str) -> io::Result<String> {
+        self.stream.write_all(one_line(command)?.as_bytes())?;
+
+        // haproxy writes nothing after the prompt until it has the next command, so the answer is
+        // done once what's been read ends with one.
+        let mut chunk = [0; 4096];
+        loop {
+            if let Some(prompt) = prompt_start(&self.buffer) {
+                let mut response = mem::take(&mut self.buffer);
+                response.truncate(prompt);
+                return String::from_utf8(response)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "haproxy closed the connection"));
+                },
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+// where the prompt at the end of `buffer` starts, if it ends with one. a worker's prompt is "> ",
+// the master cli's is "master> " or the pid of the worker it's talking to before the "> ".
+fn prompt_start(buffer: &[u8]) -> Option<usize> {
+    let start = buffer.iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+    let prompt = buffer[start..].strip_suffix(b"> ")?;
+    if prompt.iter().all(|&c| c.is_ascii_alphanumeric()) { Some(start) } else { None }
+}
+
+impl Runtime for RuntimeClient {
+    fn execute(&mut self, command: &str) -> io::Result<String> {
+        RuntimeClient::execute(self, command)
+    }
+}
+
+impl Runtime for RuntimeSession {
+    fn execute(&mut self, command: &str) -> io::Result<String> {
+        RuntimeSession::execute(self, command)
+    }
+}
+
+// a command is one line, haproxy would take anything after a newline as the next command.
+fn one_line(command: &str) -> io::Result<String> {
+    let command = command.trim_end();
+    if command.contains('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "a runtime api command can't contain a newline"));
+    }
+    Ok(format!("{}\n", command))
+}
+
+// errors come back as plain text where the command's output would have been.
+fn unexpected_response(response: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, response.trim().to_string())
+}
+
+// the csv haproxy writes for `show stat`, with one row per frontend, backend and server.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatsCsv {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl StatsCsv {
+    // the header line starts with "# " and every line ends with a trailing comma.
+    pub fn parse(csv: &str) -> Option<StatsCsv> {
+        let mut lines = csv.lines().filter(|line| !line.is_empty());
+        let header = lines.next()?.strip_prefix("# ")?;
+        let split = |line: &str| -> Vec<String> {
+            line.strip_suffix(',').unwrap_or(line).split(',').map(|s| s.to_string()).collect()
+        };
+
+        Some(StatsCsv {
+            columns: split(header),
+            rows: lines.map(split).collect(),
+        })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn rows(&self) -> &[Vec<String>] {
+        &self.rows
+    }
+
+    pub fn value<'a>(&self, row: &'a [String], column: &str) -> Option<&'a str> {
+        let index = self.columns.iter().position(|c| c == column)?;
+        row.get(index).map(|value| value.as_str())
+    }
+}
+
+// what a row of `show stat` is about, from its `type` column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum StatKind {
+    Frontend,
+    Backend,
+    Server,
+    Listener,
+}
+
+impl StatKind {
+    fn from_column(value: &str) -> Option<StatKind> {
+        match value {
+            "0" => Some(StatKind::Frontend),
+            "1" => Some(StatKind::Backend),
+            "2" => Some(StatKind::Server),
+            "3" => Some(StatKind::Listener),
+            _ => None,
+        }
+    }
+
+    // the bit `show stat`'s type argument selects these rows with. listeners are only there with
+    // `option socket-stats` and have no bit of their own, they come with the frontends.
+    fn mask(&self) -> u32 {
+        match *self {
+            StatKind::Frontend | StatKind::Listener => 1,
+            StatKind::Backend => 2,
+            StatKind::Server => 4,
+        }
+    }
+}
+
+// one row of `show stat`. the columns most monitoring looks at are parsed, everything is also in
+// `values` by its column name. counters haproxy leaves empty for this kind of row are None.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatRow {
+    pub proxy: String,
+    pub name: String,
+    pub kind: Option<StatKind>,
+    pub status: String,
+    pub weight: Option<u64>,
+    pub current_queue: Option<u64>,
+    pub current_sessions: Option<u64>,
+    pub max_sessions: Option<u64>,
+    pub total_sessions: Option<u64>,
+    pub bytes_in: Option<u64>,
+    pub bytes_out: Option<u64>,
+    pub request_errors: Option<u64>,
+    pub connection_errors: Option<u64>,
+    pub response_errors: Option<u64>,
+    pub values: Vec<(String, String)>,
+}
+
+impl StatRow {
+    fn new(stats: &StatsCsv, row: &[String], fields: Option<&[String]>) -> StatRow {
+        let value = |column: &str| stats.value(row, column).unwrap_or("");
+        let number = |column: &str| value(column).parse().ok();
+        let values = stats.columns().iter()
+            .zip(row)
+            .filter(|&(column, _)| fields.is_none_or(|fields| fields.contains(column)))
+            .map(|(column, value)| (column.clone(), value.clone()))
+            .collect();
+        StatRow {
+            proxy: value("pxname").to_string(),
+            name: value("svname").to_string(),
+            kind: StatKind::from_column(value("type")),
+            status: value("status").to_string(),
+            weight: number("weight"),
+            current_queue: number("qcur"),
+            current_sessions: number("scur"),
+            max_sessions: number("smax"),
+            total_sessions: number("stot"),
+            bytes_in: number("bin"),
+            bytes_out: number("bout"),
+            request_errors: number("ereq"),
+            connection_errors: number("econ"),
+            response_errors: number("eresp"),
+            values,
+        }
+    }
+
+    pub fn value(&self, column: &str) -> Option<&str> {
+        self.values.iter().find(|(name, _)| name == column).map(|(_, value)| value.as_str())
+    }
+}
+
+// which rows and columns of `show stat` to keep, everything by default. the proxy and kinds are
+// sent along with the command so haproxy leaves the rest out, the server is picked out here since
+// haproxy only takes its numeric id.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatFilter {
+    pub proxy: Option<String>,
+    pub server: Option<String>,
+    // empty for every kind.
+    pub kinds: Vec<StatKind>,
+    // the columns kept in StatRow::values, all of them if None.
+    pub fields: Option<Vec<String>>,
+}
+
+impl StatFilter {
+    pub fn command(&self) -> String {
+        if self.proxy.is_none() && self.kinds.is_empty() {
+            return "show stat".to_string();
+        }
+        let mask = match self.kinds.iter().fold(0, |mask, kind| mask | kind.mask()) {
+            0 => "-1".to_string(),
+            mask => mask.to_string(),
+        };
+        let proxy = self.proxy.as_deref().map_or("-1".to_string(), escape_word);
+        format!("show stat {} {} -1", proxy, mask)
+    }
+
+    pub fn rows(&self, stats: &StatsCsv) -> Vec<StatRow> {
+        stats.rows().iter()
+            .map(|row| StatRow::new(stats, row, self.fields.as_deref()))
+            .filter(|row| self.proxy.as_ref().is_none_or(|proxy| *proxy == row.proxy))
+            .filter(|row| self.server.as_ref().is_none_or(|server| *server == row.name))
+            .filter(|row| {
+                self.kinds.is_empty() || row.kind.is_some_and(|kind| self.kinds.contains(&kind))
+            })
+            .collect()
+    }
+}
+
+// the process-wide numbers of `show info`. anything which isn't there, as with an older haproxy
+// which doesn't have it yet, is None or empty, and every line is also in `values`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Info {
+    pub name: String,
+    pub version: String,
+    pub pid: Option<u32>,
+    pub threads: Option<u64>,
+    pub uptime: Option<Duration>,
+    pub max_connections: Option<u64>,
+    pub hard_max_connections: Option<u64>,
+    pub current_connections: Option<u64>,
+    pub cumulative_connections: Option<u64>,
+    pub cumulative_requests: Option<u64>,
+    pub max_connection_rate: Option<u64>,
+    pub connection_rate: Option<u64>,
+    pub connection_rate_limit: Option<u64>,
+    pub session_rate: Option<u64>,
+    pub session_rate_limit: Option<u64>,
+    pub max_pipes: Option<u64>,
+    pub idle_percent: Option<u64>,
+    pub values: Vec<(String, String)>,
+}
+
+impl Info {
+    pub fn parse(info: &str) -> Info {
+        let values = parse_info(info);
+        let value = |name: &str| {
+            values.iter().find(|(n, _)| n == name).map_or("", |(_, value)| value.as_str())
+        };
+        let number = |name: &str| value(name).parse().ok();
+        Info {
+            name: value("Name").to_string(),
+            version: value("Version").to_string(),
+            pid: value("Pid").parse().ok(),
+            threads: number("Nbthread"),
+            uptime: number("Uptime_sec").map(Duration::from_secs),
+            max_connections: number("Maxconn"),
+            hard_max_connections: number("Hard_maxconn"),
+            current_connections: number("CurrConns"),
+            cumulative_connections: number("CumConns"),
+            cumulative_requests: number("CumReq"),
+            max_connection_rate: number("MaxConnRate"),
+            connection_rate: number("ConnRate"),
+            connection_rate_limit: number("ConnRateLimit"),
+            session_rate: number("SessRate"),
+            session_rate_limit: number("SessRateLimit"),
+            max_pipes: number("Maxpipes"),
+            idle_percent: number("Idle_pct"),
+            values,
+        }
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+// `show info` answers with one `Name: value` line per setting.
+pub fn parse_info(info: &str) -> Vec<(String, String)> {
+    info.lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::process;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{escape_word, parse_info, parse_pattern_entries, parse_pattern_lists, parse_proc,
+                parse_table_entries, Address, Info, PatternEntry, Process, Runtime, RuntimeClient,
+                RuntimeError, ServerState, StatFilter, StatKind, StatsCsv, TableOperator,
+                TableQuery, Weight};
+
+    static STAT: &str = concat!("# pxname,svname,qcur,scur,status,type,\n",
+                                "http-in,FRONTEND,,3,OPEN,0,\n",
+                                "static,srv1,0,1,UP,2,\n",
+                                "\n");
+
+    #[test]
+    fn stats_csv() {
+        let stats = StatsCsv::parse(STAT).unwrap();
+        assert_eq!(stats.columns(), ["pxname", "svname", "qcur", "scur", "status", "type"]);
+        assert_eq!(stats.rows().len(), 2);
+        assert_eq!(stats.value(&stats.rows()[1], "svname"), Some("srv1"));
+        assert_eq!(stats.value(&stats.rows()[0], "qcur"), Some(""));
+        assert_eq!(stats.value(&stats.rows()[0], "bogus"), None);
+
+        assert!(StatsCsv::parse("Unknown command.\n").is_none());
+    }
+
+    #[test]
+    fn stat_rows() {
+        let stats = StatsCsv::parse(STAT).unwrap();
+        let rows = StatFilter::default().rows(&stats);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].kind, Some(StatKind::Frontend));
+        assert_eq!(rows[0].current_queue, None);
+        assert_eq!(rows[0].current_sessions, Some(3));
+        assert_eq!(rows[1].name, "srv1");
+        assert_eq!(rows[1].status, "UP");
+        assert_eq!(rows[1].value("qcur"), Some("0"));
+
+        let filter = StatFilter {
+            proxy: Some("static".to_string()),
+            fields: Some(vec!["scur".to_string()]),
+            ..StatFilter::default()
+        };
+        assert_eq!(filter.command(), "show stat static -1 -1");
+        let rows = filter.rows(&stats);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].values, [("scur".to_string(), "1".to_string())]);
+        assert_eq!(rows[0].current_sessions, Some(1));
+
+        let filter = StatFilter {
+            server: Some("srv1".to_string()),
+            kinds: vec![StatKind::Backend, StatKind::Server],
+            ..StatFilter::default()
+        };
+        assert_eq!(filter.command(), "show stat -1 6 -1");
+        assert_eq!(filter.rows(&stats)[0].proxy, "static");
+        assert_eq!(StatFilter::default().command(), "show stat");
+
+        let filter = StatFilter {
+            proxy: Some("static; shutdown frontend http-in".to_string()),
+            ..StatFilter::default()
+        };
+        assert_eq!(filter.command(), "show stat static\\;\\ shutdown\\ frontend\\ http-in -1 -1");
+    }
+
+    #[test]
+    fn info() {
+        let info = parse_info("Name: HAProxy\nVersion: 2.8.3\nUptime: 0d 1h02m03s\n");
+        assert_eq!(info[1], ("Version".to_string(), "2.8.3".to_string()));
+        assert_eq!(info[2].1, "0d 1h02m03s");
+
+        let info = Info::parse("Name: HAProxy\nVersion: 2.8.3\nPid: 4242\nUptime_sec: 3723\n\
+                                Maxconn: 4000\nCurrConns: 12\nCumConns: 98765\nConnRate: \
+                                7\nIdle_pct: 99\n");
+        assert_eq!(info.version, "2.8.3");
+        assert_eq!(info.pid, Some(4242));
+        assert_eq!(info.uptime, Some(Duration::from_secs(3723)));
+        assert_eq!(info.max_connections, Some(4000));
+        assert_eq!(info.current_connections, Some(12));
+        assert_eq!(info.cumulative_connections, Some(98765));
+        assert_eq!(info.connection_rate, Some(7));
+        assert_eq!(info.idle_percent, Some(99));
+        assert_eq!(info.hard_max_connections, None);
+        assert_eq!(info.value("Pid"), Some("4242"));
+    }
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("haproxy-runtime-{}-{}", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn execute() {
+        let path = socket_path("execute");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut command = String::new();
+            BufReader::new(&stream).read_line(&mut command).unwrap();
+            assert_eq!(command, "show stat\n");
+            (&stream).write_all(STAT.as_bytes()).unwrap();
+        });
+
+        let stats = RuntimeClient::new(&path).show_stat_csv().unwrap();
+        assert_eq!(stats.rows()[0][0], "http-in");
+
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let err = RuntimeClient::new(&path).execute("show info\nshutdown sessions").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn session() {
+        let path = socket_path("session");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            // each command and the answer to it, written in pieces as haproxy might.
+            let answers: &[(&str, &[&str])] = &[
+                ("prompt\n", &["\n> "]),
+                ("show info\n", &["Name: HAProxy\nVersion: 2.8.3\n", "\n", "> "]),
+                ("show stat\n", &[STAT, "\n> "]),
+                ("disable server static/srv1\n", &["\n", ">", " "]),
+                ("set server static/srv1 state drain\n", &["\n> "]),
+                ("set weight static/srv9 50%\n", &["No such server.\n\n> "]),
+            ];
+            for &(expected, pieces) in answers {
+                let mut command = String::new();
+                reader.read_line(&mut command).unwrap();
+                assert_eq!(command, expected);
+                for piece in pieces {
+                    (&stream).write_all(piece.as_bytes()).unwrap();
+                    (&stream).flush().unwrap();
+                }
+            }
+        });
+
+        let mut session = RuntimeClient::new(&path).session().unwrap();
+        assert_eq!(session.show_info().unwrap().name, "HAProxy");
+        assert_eq!(session.show_stat(&StatFilter::default()).unwrap().len(), 2);
+        session.disable_server("static/srv1").unwrap();
+        session.set_server_state("static/srv1", ServerState::Drain).unwrap();
+        let err = session.set_weight("static/srv9", Weight::Percent(50)).unwrap_err();
+        let err = err.get_ref().unwrap().downcast_ref::<RuntimeError>().unwrap();
+        assert_eq!(err.message, "No such server.");
+        assert_eq!(err.to_string(), "set weight static/srv9 50%: No such server.");
+        // nothing is sent for a server which isn't named with its backend.
+        for server in &["srv1", "static/", "static/srv1 ; shutdown sessions",
+                        "be/srv;shutdown sessions", "static/srv1;shutdown"] {
+            let err = session.enable_server(server).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        // the server closes the connection once it's out of answers.
+        assert!(session.execute("show info").is_err());
+
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn proc() {
+        let processes = parse_proc("\
+#<PID>          <type>          <reloads>       <uptime>        <version>
+1162            master          5 [failedreload: 1]       0d00h02m07s     2.9.0
+# workers
+1271            worker          1               0d00h00m00s     2.9.0
+# old workers
+1233            worker          3               0d00h00m43s     2.9.0
+# programs
+1244            foo             0               0d00h00m00s     -
+");
+        assert_eq!(processes.len(), 4);
+        assert_eq!(processes[0].process(), Process::Master);
+        assert_eq!(processes[0].reloads, Some(5));
+        assert_eq!(processes[0].failed_reloads, Some(1));
+        assert_eq!(processes[0].uptime, "0d00h02m07s");
+        assert_eq!(processes[1].process(), Process::Pid(1271));
+        assert!(!processes[1].old);
+        assert!(processes[2].old);
+        assert_eq!(processes[3].kind, "foo");
+        assert_eq!(processes[3].version, "-");
+
+        let processes = parse_proc("\
+#<PID>          <type>          <relative PID>  <reloads>       <uptime>        <version>
+1162            master          0               5               0d00h02m07s     2.4.22
+# workers
+1271            worker          1               0               0d00h00m00s     2.4.22
+");
+        assert_eq!(processes[1].relative_pid, Some(1));
+        assert_eq!(processes[1].reloads, Some(0));
+        assert_eq!(processes[1].version, "2.4.22");
+    }
+
+    #[test]
+    fn master() {
+        let path = socket_path("master");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let answers: &[(&str, &str)] = &[
+                ("prompt\n", "\nmaster> "),
+                ("show proc\n", "#<PID> <type> <reloads> <uptime> <version>\n\
+                                 1162 master 0 0d00h02m07s 2.9.0\n\nmaster> "),
+                ("@1 show info\n", "Name: HAProxy\nPid: 1271\n\nmaster> "),
+                ("@!1233 set weight static/srv1 10\n", "\nmaster> "),
+            ];
+            for &(expected, answer) in answers {
+                let mut command = String::new();
+                reader.read_line(&mut command).unwrap();
+                assert_eq!(command, expected);
+                (&stream).write_all(answer.as_bytes()).unwrap();
+            }
+        });
+
+        let mut session = RuntimeClient::new(&path).session().unwrap();
+        assert_eq!(session.show_proc().unwrap()[0].pid, 1162);
+        assert_eq!((&mut session).on_process(Process::Worker(1)).show_info().unwrap().pid,
+                   Some(1271));
+        let mut old = session.on_process(Process::Pid(1233));
+        old.set_weight("static/srv1", Weight::Absolute(10)).unwrap();
+
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tcp() {
+        assert_eq!(Address::parse("/run/haproxy.sock"),
+                   Address::Unix(PathBuf::from("/run/haproxy.sock")));
+        assert_eq!(Address::parse("unix@/run/haproxy.sock"),
+                   Address::Unix(PathBuf::from("/run/haproxy.sock")));
+        assert_eq!(Address::parse("ipv6@[::1]:9999"), Address::Tcp("[::1]:9999".to_string()));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("ipv4@{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut command = String::new();
+            BufReader::new(&stream).read_line(&mut command).unwrap();
+            assert_eq!(command, "show info\n");
+            (&stream).write_all(b"Name: HAProxy\nVersion: 2.8.3\n").unwrap();
+            drop(stream);
+
+            // an answer which never comes.
+            let (_stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_millis(500));
+        });
+
+        let mut client = RuntimeClient::connect_to(Address::parse(&address));
+        assert_eq!(client.show_info().unwrap().version, "2.8.3");
+
+        client.set_timeout(Some(Duration::from_millis(50)));
+        let start = Instant::now();
+        let err = client.show_info().unwrap_err();
+        assert!(matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock));
+        assert!(start.elapsed() < Duration::from_millis(400));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn patterns() {
+        let lists = parse_pattern_lists("\
+# id (file) description
+-1 (/etc/haproxy/hosts.map) pattern loaded from file '/etc/haproxy/hosts.map' used by map at \
+file '/etc/haproxy/haproxy.cfg' line 33. curr_ver=0 next_ver=0 entry_cnt=2
+5 () acl 'src' file '/etc/haproxy/haproxy.cfg' line 40. curr_ver=0 next_ver=0 entry_cnt=0
+
+").unwrap();
+        assert_eq!(lists.len(), 2);
+        assert_eq!(lists[0].id, -1);
+        assert_eq!(lists[0].file, "/etc/haproxy/hosts.map");
+        assert!(lists[0].description.starts_with("pattern loaded"));
+        assert_eq!(lists[1].file, "");
+        assert!(parse_pattern_lists("Unknown map identifier.\n").is_none());
+
+        let entries = parse_pattern_entries("0x55d0 example.com be_web\n0x55d1 api.example.com \
+                                             be api\n").unwrap();
+        assert_eq!(entries[0], PatternEntry {
+            reference: "0x55d0".to_string(),
+            key: "example.com".to_string(),
+            value: Some("be_web".to_string()),
+        });
+        assert_eq!(entries[1].value.as_deref(), Some("be api"));
+        let entries = parse_pattern_entries("0x55d2 10.0.0.0/8\n").unwrap();
+        assert_eq!(entries[0].value, None);
+        assert_eq!(parse_pattern_entries("").unwrap(), []);
+        assert!(parse_pattern_entries("Unknown ACL identifier.\n").is_none());
+
+        assert_eq!(escape_word("a b;c\\d"), "a\\ b\\;c\\\\d");
+    }
+
+    #[test]
+    fn maps() {
+        let path = socket_path("maps");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let answers: &[(&str, &str)] = &[
+                ("prompt\n", "\n> "),
+                ("add map #1 example.com be\\ web\n", "\n> "),
+                ("set map #1 #0x55d0 be_api\n", "\n> "),
+                ("show map #1\n", "0x55d0 example.com be_api\n\n> "),
+                ("del acl /etc/haproxy/deny.acl 10.0.0.1\n", "Key not found.\n\n> "),
+            ];
+            for &(expected, answer) in answers {
+                let mut command = String::new();
+                reader.read_line(&mut command).unwrap();
+                assert_eq!(command, expected);
+                (&stream).write_all(answer.as_bytes()).unwrap();
+            }
+        });
+
+        let mut session = RuntimeClient::new(&path).session().unwrap();
+        session.add_map("#1", "example.com", "be web").unwrap();
+        session.set_map("#1", "#0x55d0", "be_api").unwrap();
+        assert_eq!(session.show_map("#1").unwrap()[0].value.as_deref(), Some("be_api"));
+        assert!(session.del_acl("/etc/haproxy/deny.acl", "10.0.0.1").is_err());
+
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tables() {
+        let entries = parse_table_entries("\
+# table: http-in, type: ip, size:1048576, used:2
+0x55d0c0a1b2c0: key=10.0.1.2 use=0 exp=28812 shard=0 http_req_rate(10000)=1
+0x55d0c0a1b2d0: key=10.0.1.3 use=1 exp=1000 shard=0 http_req_rate(10000)=250 gpc0=3
+
+").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].reference, "0x55d0c0a1b2c0");
+        assert_eq!(entries[0].key, "10.0.1.2");
+        assert_eq!(entries[0].expires, Some(28812));
+        assert_eq!(entries[1].use_count, Some(1));
+        assert_eq!(entries[1].data("http_req_rate"), Some("250"));
+        assert_eq!(entries[1].data("gpc0"), Some("3"));
+        assert_eq!(entries[1].data("gpc1"), None);
+        assert_eq!(entries[1].values[4].0, "http_req_rate(10000)");
+        assert!(parse_table_entries("Unknown table\n").is_none());
+
+        let query = TableQuery {
+            data: vec![("http_req_rate".to_string(), TableOperator::Gt, 100)],
+            ..TableQuery::default()
+        };
+        assert_eq!(query.arguments(), " data.http_req_rate gt 100");
+        let query = TableQuery { key: Some("10.0.1.3".to_string()), ..TableQuery::default() };
+        assert_eq!(query.arguments(), " key 10.0.1.3");
+    }
+
+    #[test]
+    fn table_commands() {
+        let path = socket_path("tables");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let answers: &[(&str, &str)] = &[
+                ("prompt\n", "\n> "),
+                ("show table\n", "# table: http-in, type: ip, size:1048576, used:1\n\n> "),
+                ("show table http-in data.gpc0 ge 1\n",
+                 "# table: http-in, type: ip, size:1048576, used:1\n\
+                  0x1: key=10.0.1.3 use=0 exp=1000 gpc0=3\n\n> "),
+                ("set table http-in key 10.0.1.3 data.gpc0 0\n", "\n> "),
+                ("clear table http-in key 10.0.1.3\n", "\n> "),
+            ];
+            for &(expected, answer) in answers {
+                let mut command = String::new();
+                reader.read_line(&mut command).unwrap();
+                assert_eq!(command, expected);
+                (&stream).write_all(answer.as_bytes()).unwrap();
+            }
+        });
+
+        let mut session = RuntimeClient::new(&path).session().unwrap();
+        let tables = session.show_tables().unwrap();
+        assert_eq!((tables[0].name.as_str(), tables[0].used), ("http-in", Some(1)));
+        let query = TableQuery {
+            data: vec![("gpc0".to_string(), TableOperator::Ge, 1)],
+            ..TableQuery::default()
+        };
+        let entries = session.show_table("http-in", &query).unwrap();
+        assert_eq!(entries[0].key, "10.0.1.3");
+        session.set_table("http-in", "10.0.1.3", &[("gpc0", 0)]).unwrap();
+        let query = TableQuery { key: Some("10.0.1.3".to_string()), ..TableQuery::default() };
+        session.clear_table("http-in", &query).unwrap();
+
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, NaiveDateTime, Timelike, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::entry::LogEntry;
+use crate::json::{write_entry_json, FieldSet};
+#[cfg(feature = "parquet")]
+use crate::parquet::{ParquetCompression, ParquetLogWriter};
+
+// where objects go unless set_key_template says otherwise, partitioned by the date and hour the
+// entries in them were accepted.
+pub const DEFAULT_S3_KEY_TEMPLATE: &str = "haproxy/date={date}/hour={hour}/{start}-{id}.{ext}";
+
+// what S3Writer's objects hold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum S3Format {
+    // write_entry_json's objects of every field, a line each.
+    JsonLines,
+    // a ParquetLogWriter file, zstd compressed.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+enum Batch {
+    JsonLines(Vec<u8>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<ParquetLogWriter<Vec<u8>>>),
+}
+
+// archives entries to an S3 bucket, or any storage with S3's API, as objects of at most
+// `max_bytes` of entries, which are uploaded once they've grown that big or are `max_age` old, and
+// whenever the hour the entries were accepted in changes, so an object never spans partitions.
+// the age is only noticed by `write` and `flush_if_due`, which should be called whenever the input
+// pauses, and what's left is uploaded by `flush`, which should be called at the end. requests are
+// signed with AWS Signature Version 4 when there are credentials, and are anonymous otherwise.
+pub struct S3Writer {
+    endpoint: String,
+    bucket: String,
+    region: String,
+    // the access key id, secret access key and session token.
+    credentials: Option<(String, String, Option<String>)>,
+    key_template: String,
+    format: S3Format,
+    gzip: bool,
+    max_bytes: usize,
+    max_age: Duration,
+    agent: ureq::Agent,
+    fields: FieldSet,
+    batch: Option<Batch>,
+    bytes: usize,
+    started: Instant,
+    // when the batch's first entry was accepted, as logged.
+    start: NaiveDateTime,
+    // an object which failed to upload, with its key, tried again before the next.
+    unsent: Option<(String, Vec<u8>)>,
+    objects: u64,
+    random: RandomState,
+}
+
+impl S3Writer {
+    // `endpoint` is the storage's, e.g. https://s3.us-east-1.amazonaws.com or a MinIO server's,
+    // and objects are addressed by path under it, /bucket/key.
+    pub fn new(endpoint: &str, bucket: &str, format: S3Format) -> S3Writer {
+        S3Writer {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket: bucket.to_string(),
+            region: "us-east-1".to_string(),
+            credentials: None,
+            key_template: DEFAULT_S3_KEY_TEMPLATE.to_string(),
+            format,
+            gzip: false,
+            max_bytes: 64 << 20,
+            max_age: Duration::from_secs(300),
+            agent: ureq::Agent::new(),
+            fields: FieldSet::all(),
+            batch: None,
+            bytes: 0,
+            started: Instant::now(),
+            start: NaiveDateTime::default(),
+            unsent: None,
+            objects: 0,
+            random: RandomState::new(),
+        }
+    }
+
+    // the region requests are signed for, us-east-1 by default.
+    pub fn set_region(&mut self, region: &str) {
+        self.region = region.to_string();
+    }
+
+    pub fn set_credentials(&mut self, access_key_id: &str, secret_access_key: &str,
+                           session_token: Option<&str>) {
+        self.credentials = Some((access_key_id.to_string(), secret_access_key.to_string(),
+                                 session_token.map(str::to_string)));
+    }
+
+    // the key of each object, with {date}, {year}, {month}, {day} and {hour} of when its entries
+    // were accepted, {start} for when the first of them was, {id} for a unique id and {ext} for
+    // the format's extension replaced. DEFAULT_S3_KEY_TEMPLATE by default.
+    pub fn set_key_template(&mut self, template: &str) {
+        self.key_template = template.to_string();
+    }
+
+    // whether to gzip JSON lines objects, which adds .gz to their extension. parquet objects are
+    // compressed anyway.
+    pub fn set_gzip(&mut self, gzip: bool) {
+        self.gzip = gzip;
+    }
+
+    // how big an object gets before it's uploaded, 64MiB by default. for parquet this is the size
+    // of the fields before they're compressed, so objects come out a good deal smaller.
+    pub fn set_max_bytes(&mut self, bytes: usize) {
+        self.max_bytes = bytes.max(1);
+    }
+
+    // how long entries wait to be uploaded, five minutes by default.
+    pub fn set_max_age(&mut self, age: Duration) {
+        self.max_age = age;
+    }
+
+    pub fn write(&mut self, entry: &LogEntry) -> io::Result<()> {
+        let accepted = entry.accept_date_time().ok();
+        let new_hour = accepted.is_some_and(|accepted| hour(accepted) != hour(self.start));
+        if self.batch.is_some() && new_hour {
+            self.flush()?;
+        }
+        if self.batch.is_none() {
+            self.batch = Some(self.new_batch()?);
+            self.bytes = 0;
+            self.started = Instant::now();
+            self.start = accepted.unwrap_or_else(|| Local::now().naive_local());
+        }
+        match self.batch {
+            Some(Batch::JsonLines(ref mut lines)) => {
+                let len = lines.len();
+                write_entry_json(entry, lines, &self.fields)?;
+                self.bytes += lines.len() - len;
+            },
+            #[cfg(feature = "parquet")]
+            Some(Batch::Parquet(ref mut writer)) => {
+                writer.write(entry)?;
+                self.bytes += entry.header_fields().iter().map(|field| field.len()).sum::<usize>() +
+                              entry.captures.iter().map(|field| field.len()).sum::<usize>() +
+                              entry.http_request.len();
+            },
+            None => unreachable!(),
+        }
+        if self.bytes >= self.max_bytes || self.started.elapsed() >= self.max_age {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // uploads the entries so far if they've waited `max_age`.
+    pub fn flush_if_due(&mut self) -> io::Result<()> {
+        if self.batch.is_some() && self.started.elapsed() >= self.max_age {
+            return self.flush();
+        }
+        Ok(())
+    }
+
+    // uploads the entries written since the last upload, if there are any. an object which fails
+    // to upload is kept to try again on the next flush.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some((key, body)) = self.unsent.take() {
+            if let Err(err) = self.put(&key, &body) {
+                self.unsent = Some((key, body));
+                return Err(err);
+            }
+        }
+        let body = match self.batch.take() {
+            Some(Batch::JsonLines(lines)) if self.gzip => {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder.write_all(&lines)?;
+                encoder.finish()?
+            },
+            Some(Batch::JsonLines(lines)) => lines,
+            #[cfg(feature = "parquet")]
+            Some(Batch::Parquet(writer)) => writer.finish()?,
+            None => return Ok(()),
+        };
+        let key = self.key();
+        if let Err(err) = self.put(&key, &body) {
+            self.unsent = Some((key, body));
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn new_batch(&self) -> io::Result<Batch> {
+        Ok(match self.format {
+            S3Format::JsonLines => Batch::JsonLines(vec![]),
+            #[cfg(feature = "parquet")]
+            S3Format::Parquet => {
+                let writer = ParquetLogWriter::new(vec![], ParquetCompression::Zstd(3))?;
+                Batch::Parquet(Box::new(writer))
+            },
+        })
+    }
+
+    fn key(&mut self) -> String {
+        self.objects += 1;
+        let mut hasher = self.random.build_hasher();
+        hasher.write_u64(self.objects);
+        let ext = match self.format {
+            S3Format::JsonLines if self.gzip => "jsonl.gz",
+            S3Format::JsonLines => "jsonl",
+            #[cfg(feature = "parquet")]
+            S3Format::Parquet => "parquet",
+        };
+        self.key_template
+            .replace("{date}", &self.start.format("%Y-%m-%d").to_string())
+            .replace("{year}", &self.start.format("%Y").to_string())
+            .replace("{month}", &self.start.format("%m").to_string())
+            .replace("{day}", &self.start.format("%d").to_string())
+            .replace("{hour}", &self.start.format("%H").to_string())
+            .replace("{start}", &self.start.format("%Y%m%dT%H%M%S").to_string())
+            .replace("{id}", &format!("{:016x}", hasher.finish()))
+            .replace("{ext}", ext)
+    }
+
+    fn put(&self, key: &str, body: &[u8]) -> io::Result<()> {
+        let path = format!("/{}/{}", uri_encode(&self.bucket), uri_encode(key));
+        let url = format!("{}{}", self.endpoint, path);
+        let mut request = self.agent.put(&url);
+        for (name, value) in self.signed_headers(&path, body, Utc::now()) {
+            request = request.set(name, &value);
+        }
+        match request.send_bytes(body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => {
+                // S3 says what was wrong in an XML error document.
+                let message = response.into_string().unwrap_or_default();
+                Err(io::Error::other(format!("{} answered {}: {}", url, status, message.trim())))
+            },
+            Err(ureq::Error::Transport(err)) => Err(io::Error::other(err)),
+        }
+    }
+
+    // the x-amz-* headers and Authorization for a PUT of `body` to `path`, or nothing without
+    // credentials.
+    fn signed_headers(&self, path: &str, body: &[u8], now: DateTime<Utc>)
+                      -> Vec<(&'static str, String)> {
+        let (access_key_id, secret_access_key, session_token) = match self.credentials {
+            Some((ref id, ref secret, ref token)) => (id, secret, token),
+            None => return vec![],
+        };
+        let date = now.format("%Y%m%d").to_string();
+        let time = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = vec![
+            ("host", host(&self.endpoint).to_string()),
+            ("x-amz-content-sha256", hex(&Sha256::digest(body))),
+            ("x-amz-date", time.clone()),
+        ];
+        if let Some(token) = session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+
+        let names = headers.iter().map(|&(name, _)| name).collect::<Vec<_>>().join(";");
+        let mut canonical = format!("PUT\n{}\n\n", path);
+        for (name, value) in &headers {
+            canonical.push_str(&format!("{}:{}\n", name, value.trim()));
+        }
+        canonical.push_str(&format!("\n{}\n{}", names, headers[1].1));
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", time, scope,
+                              hex(&Sha256::digest(canonical.as_bytes())));
+
+        let mut key = hmac(format!("AWS4{}", secret_access_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&key, to_sign.as_bytes()));
+        headers.push(("authorization",
+                      format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, \
+                               Signature={}", access_key_id, scope, names, signature)));
+        // ureq sets Host itself, from the url.
+        headers.remove(0);
+        headers
+    }
+}
+
+fn hour(date_time: NaiveDateTime) -> (chrono::NaiveDate, u32) {
+    (date_time.date(), date_time.hour())
+}
+
+// the endpoint's host, with the port unless it's the scheme's own.
+fn host(endpoint: &str) -> &str {
+    let (scheme, rest) = endpoint.split_once("://").unwrap_or(("https", endpoint));
+    let host = rest.split('/').next().unwrap_or(rest);
+    match (scheme, host.rsplit_once(':')) {
+        ("https", Some((name, "443"))) | ("http", Some((name, "80"))) => name,
+        _ => host,
+    }
+}
+
+// percent-encodes everything but unreserved characters and slashes, as signing wants it.
+fn uri_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for &c in path.as_bytes() {
+        if c.is_ascii_alphanumeric() || b"-._~/".contains(&c) {
+            encoded.push(c as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", c));
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes any key size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{host, uri_encode, S3Format, S3Writer};
+    use crate::entry::LogEntry;
+    use chrono::{TimeZone, Utc};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn signing() {
+        assert_eq!(host("https://s3.amazonaws.com:443/"), "s3.amazonaws.com");
+        assert_eq!(host("http://127.0.0.1:9000"), "127.0.0.1:9000");
+        assert_eq!(uri_encode("haproxy/date=2009-02-06/a b~.jsonl"),
+                   "haproxy/date%3D2009-02-06/a%20b~.jsonl");
+
+        let mut writer = S3Writer::new("http://127.0.0.1:9000", "logs", S3Format::JsonLines);
+        assert!(writer.signed_headers("/logs/a", b"", Utc::now()).is_empty());
+        writer.set_credentials("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", None);
+        let now = Utc.with_ymd_and_hms(2009, 2, 6, 12, 0, 0).unwrap();
+        let headers = writer.signed_headers("/logs/haproxy/date%3D2009-02-06/a%20b.jsonl",
+                                            b"{}\n", now);
+        assert_eq!(headers[1], ("x-amz-date", "20090206T120000Z".to_string()));
+        assert_eq!(headers[2].1,
+                   "AWS4-HMAC-SHA256 \
+                    Credential=AKIDEXAMPLE/20090206/us-east-1/s3/aws4_request, \
+                    SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+                    Signature=c5b5547bb8bf83283a1037810402e2f23e16231a1bc8cce17369dbe1650098ea");
+    }
+
+    #[test]
+    fn uploads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut objects = vec![];
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut headers = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    headers.push(line.trim_end().to_string());
+                }
+                assert!(headers.iter().any(|header| header.starts_with("authorization: AWS4")));
+                let length: usize = headers.iter()
+                    .find_map(|header| header.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                objects.push((headers[0].clone(), body));
+                write!(&stream, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            }
+            objects
+        });
+
+        let line = "haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:59:59.655] http-in \
+                    static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                    \"GET / HTTP/1.1\"";
+        let later = line.replace("12:59:59", "13:00:00");
+        let mut writer = S3Writer::new(&endpoint, "logs", S3Format::JsonLines);
+        writer.set_credentials("AKIDEXAMPLE", "secret", Some("token"));
+        writer.set_key_template("{year}/{month}/{day}/{hour}/{start}.{ext}");
+        writer.write(&LogEntry::from_bytes(line.as_bytes()).unwrap()).unwrap();
+        writer.write(&LogEntry::from_bytes(line.as_bytes()).unwrap()).unwrap();
+        writer.write(&LogEntry::from_bytes(later.as_bytes()).unwrap()).unwrap();
+        writer.flush_if_due().unwrap();
+        writer.flush().unwrap();
+        writer.flush().unwrap();
+
+        let objects = server.join().unwrap();
+        assert_eq!(objects[0].0, "PUT /logs/2009/02/06/12/20090206T125959.jsonl HTTP/1.1");
+        assert_eq!(objects[0].1.iter().filter(|&&c| c == b'\n').count(), 2);
+        assert_eq!(objects[1].0, "PUT /logs/2009/02/06/13/20090206T130000.jsonl HTTP/1.1");
+        let object: serde_json::Value = serde_json::from_slice(&objects[1].1).unwrap();
+        assert_eq!(object["accept_date"], "06/Feb/2009:13:00:00.655");
+    }
+}
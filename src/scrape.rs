@@ -0,0 +1,115 @@
+use std::io;
+use std::io::Read;
+use std::time::Duration;
+
+use crate::base64::base64;
+use crate::runtime::{StatFilter, StatRow, StatsCsv};
+
+// haproxy's http stats page (`stats uri`), for reading the same csv as `show stat` from where only
+// the page is reachable. adding ";csv" to the page's url gets the csv rather than html.
+pub struct StatsPage {
+    url: String,
+    authorization: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl StatsPage {
+    // `url` is the page's, e.g. http://lb.example.com:8404/stats.
+    pub fn new(url: &str) -> StatsPage {
+        StatsPage::with_timeout(url, None)
+    }
+
+    // a page which gives up on haproxy after `timeout`, for connecting and for the whole answer.
+    pub fn with_timeout(url: &str, timeout: Option<Duration>) -> StatsPage {
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(timeout) = timeout {
+            agent = agent.timeout(timeout);
+        }
+        StatsPage {
+            url: format!("{};csv", url.trim_end_matches(";csv")),
+            authorization: None,
+            agent: agent.build(),
+        }
+    }
+
+    // the user and password of `stats auth`.
+    pub fn set_credentials(&mut self, user: &str, password: &str) {
+        let credentials = format!("{}:{}", user, password);
+        self.authorization = Some(format!("Basic {}", base64(credentials.as_bytes())));
+    }
+
+    pub fn fetch(&self) -> io::Result<StatsCsv> {
+        let mut request = self.agent.get(&self.url);
+        if let Some(ref authorization) = self.authorization {
+            request = request.set("Authorization", authorization);
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status @ (401 | 403), _)) => {
+                let message = format!("{} answered {}, check the credentials", self.url, status);
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+            },
+            Err(ureq::Error::Status(status, _)) => {
+                return Err(io::Error::other(format!("{} answered {}", self.url, status)));
+            },
+            Err(ureq::Error::Transport(err)) => return Err(io::Error::other(err)),
+        };
+
+        let mut csv = String::new();
+        response.into_reader().read_to_string(&mut csv)?;
+        StatsCsv::parse(&csv).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{} isn't haproxy's csv", self.url))
+        })
+    }
+
+    // the rows `filter` picks, all of them are fetched since the page can't be asked for less.
+    pub fn fetch_rows(&self, filter: &StatFilter) -> io::Result<Vec<StatRow>> {
+        Ok(filter.rows(&self.fetch()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StatsPage;
+    use crate::runtime::StatFilter;
+    use std::io;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn fetch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stats", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for answer in &["200 OK", "401 Unauthorized"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut request = vec![];
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    request.push(line);
+                }
+                assert_eq!(request[0], "GET /stats;csv HTTP/1.1");
+                assert!(request.contains(&"Authorization: Basic YWRtaW46czNjcmV0".to_string()));
+
+                let body = "# pxname,svname,scur,type,\nhttp-in,FRONTEND,3,0,\nstatic,srv1,1,2,\n";
+                write!(&stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                       answer, body.len(), body).unwrap();
+            }
+        });
+
+        let mut page = StatsPage::new(&url);
+        page.set_credentials("admin", "s3cret");
+        let rows = page.fetch_rows(&StatFilter {
+            proxy: Some("static".to_string()),
+            ..StatFilter::default()
+        }).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].current_sessions, Some(1));
+        assert_eq!(page.fetch().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::mem;
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::entry::LogEntry;
+use crate::expr::Expr;
+
+// a run of requests from the same client without a long pause between them.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Session {
+    pub client: String,
+    // the value of the Sessionizer's key, empty without one or where it has no value.
+    pub key: String,
+    // when the first and last requests were accepted.
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub requests: u64,
+    // sent to the client.
+    pub bytes: u64,
+    // 5xx responses and requests which never got one.
+    pub errors: u64,
+    // in the order they were requested, up to the Sessionizer's max_uris.
+    pub uris: Vec<String>,
+}
+
+impl Session {
+    fn new(client: String, key: String, start: NaiveDateTime) -> Session {
+        Session {
+            client,
+            key,
+            start,
+            end: start,
+            requests: 0,
+            bytes: 0,
+            errors: 0,
+            uris: vec![],
+        }
+    }
+
+    fn add(&mut self, entry: &LogEntry, accepted: NaiveDateTime, max_uris: usize) {
+        self.end = self.end.max(accepted);
+        self.requests += 1;
+        self.bytes += entry.bytes_read().unwrap_or(0);
+        if !entry.status_code().is_ok_and(|status| (100..500).contains(&status)) {
+            self.errors += 1;
+        }
+        if self.uris.len() < max_uris {
+            let uri = entry.http_uri().unwrap_or(b"");
+            self.uris.push(String::from_utf8_lossy(uri).into_owned());
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+// groups a stream of entries into sessions per client IP, and optionally a key such as a cookie,
+// ending each once its client has been idle for longer than the gap. entries are expected in
+// roughly chronological order, as haproxy writes them, and the sessions which can't get any more
+// requests are handed back as they're found, so memory use is bounded by the number of active
+// clients rather than the length of the stream.
+#[derive(Debug)]
+pub struct Sessionizer {
+    gap: Duration,
+    key: Option<Expr>,
+    max_uris: usize,
+    sessions: HashMap<(String, String), Session>,
+    last_sweep: Option<NaiveDateTime>,
+}
+
+impl Sessionizer {
+    pub fn new(gap: Duration) -> Sessionizer {
+        Sessionizer {
+            gap,
+            key: None,
+            max_uris: usize::MAX,
+            sessions: HashMap::new(),
+            last_sweep: None,
+        }
+    }
+
+    // tell clients apart by the value of `key` as well as their IP address.
+    pub fn set_key(&mut self, key: Expr) {
+        self.key = Some(key);
+    }
+
+    // only keep the first `max_uris` URIs of each session.
+    pub fn set_max_uris(&mut self, max_uris: usize) {
+        self.max_uris = max_uris;
+    }
+
+    // adds `entry` to its client's session, returning the sessions it shows have ended in the
+    // order they started. entries whose accept_date doesn't parse are left out.
+    pub fn add(&mut self, entry: &LogEntry) -> Vec<Session> {
+        let accepted = match entry.accept_date_time() {
+            Ok(accepted) => accepted,
+            Err(_) => return vec![],
+        };
+        let gap = self.gap;
+
+        // every so often end the sessions of every client who's been idle too long, not only
+        // the ones which come back.
+        let mut finished = vec![];
+        if self.last_sweep.is_none_or(|last_sweep| accepted - last_sweep > gap) {
+            let idle: Vec<(String, String)> = self.sessions.iter()
+                .filter(|&(_, session)| accepted - session.end > gap)
+                .map(|(key, _)| key.clone())
+                .collect();
+            finished.extend(idle.iter().filter_map(|key| self.sessions.remove(key)));
+            self.last_sweep = Some(accepted);
+        }
+
+        let client = String::from_utf8_lossy(entry.client_ip).into_owned();
+        let key = match self.key.as_ref().and_then(|key| key.evaluate(entry)) {
+            Some(value) => String::from_utf8_lossy(&value.as_bytes()).into_owned(),
+            None => String::new(),
+        };
+        let session = self.sessions.entry((client.clone(), key.clone()))
+            .or_insert_with(|| Session::new(client.clone(), key.clone(), accepted));
+        if accepted - session.end > gap {
+            finished.push(mem::replace(session, Session::new(client, key, accepted)));
+        }
+        session.add(entry, accepted, self.max_uris);
+
+        finished.sort_by_key(|session| session.start);
+        finished
+    }
+
+    // how many sessions might still get more requests.
+    pub fn active(&self) -> usize {
+        self.sessions.len()
+    }
+
+    // ends every session, at the end of the stream, in the order they started.
+    pub fn finish(self) -> Vec<Session> {
+        let mut remaining: Vec<Session> = self.sessions.into_values().collect();
+        remaining.sort_by_key(|session| session.start);
+        remaining
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sessionizer;
+    use crate::entry::test::TestLine;
+    use crate::entry::LogEntry;
+    use crate::expr::Expr;
+    use chrono::Duration;
+
+    #[test]
+    fn sessions() {
+        let lines = [
+            TestLine::new().time("12:00:00").cookie("a"),
+            TestLine::new().client("10.0.1.3").time("12:00:10").cookie("a").uri("/other"),
+            TestLine::new().time("12:05:00").status("503").cookie("a").uri("/login"),
+            TestLine::new().time("12:06:00").cookie("b"),
+            // more than the gap after the first client's last request.
+            TestLine::new().time("12:40:00").cookie("a").uri("/again"),
+        ].map(|line| line.bytes("100").to_string());
+        let mut sessionizer = Sessionizer::new(Duration::minutes(30));
+        sessionizer.set_key(Expr::parse("captured_request_cookie").unwrap());
+        sessionizer.set_max_uris(1);
+
+        let mut finished = vec![];
+        for line in &lines {
+            finished.extend(sessionizer.add(&LogEntry::from_bytes(line.as_bytes()).unwrap()));
+        }
+        assert_eq!(finished.len(), 3);
+        assert_eq!(sessionizer.active(), 1);
+        assert_eq!((&*finished[0].client, &*finished[0].key), ("10.0.1.2", "a"));
+        assert_eq!(finished[0].requests, 2);
+        assert_eq!(finished[0].errors, 1);
+        assert_eq!(finished[0].bytes, 200);
+        assert_eq!(finished[0].uris, ["/"]);
+        assert_eq!(finished[0].duration(), Duration::minutes(5));
+        assert_eq!(&*finished[1].client, "10.0.1.3");
+        assert_eq!(&*finished[2].key, "b");
+
+        let remaining = sessionizer.finish();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].uris, ["/again"]);
+        assert_eq!(remaining[0].start, remaining[0].end);
+    }
+}
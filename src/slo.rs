@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+
+use crate::entry::LogEntry;
+
+// a service level objective: the percentage of requests which should be good, where good is
+// getting a response other than a 5xx and, with a latency threshold, taking (Tt) less than it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Objective {
+    target: f64,
+    latency: Option<i64>,
+}
+
+// why a request did or didn't meet an Objective. a request which was both is unavailable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Outcome {
+    Good,
+    // no response at all, or a 5xx.
+    Unavailable,
+    // at or over the latency threshold, or never completed.
+    Slow,
+}
+
+impl Objective {
+    // an availability objective of `target` percent, clamped to 0 to 100.
+    pub fn new(target: f64) -> Objective {
+        Objective {
+            target: target.clamp(0.0, 100.0),
+            latency: None,
+        }
+    }
+
+    // also require requests to complete in under `millis` milliseconds.
+    pub fn set_latency(&mut self, millis: i64) {
+        self.latency = Some(millis);
+    }
+
+    pub fn target(&self) -> f64 {
+        self.target
+    }
+
+    pub fn latency(&self) -> Option<i64> {
+        self.latency
+    }
+
+    // the fraction of requests allowed to be bad, the error budget per request.
+    pub fn allowed(&self) -> f64 {
+        (100.0 - self.target) / 100.0
+    }
+
+    pub fn classify(&self, entry: &LogEntry) -> Outcome {
+        if !entry.status_code().is_ok_and(|status| (100..500).contains(&status)) {
+            return Outcome::Unavailable;
+        }
+        // Tt is -1 for sessions which never completed, those never met the objective.
+        match self.latency {
+            Some(latency) if !entry.total_time().is_ok_and(|t| (0..latency).contains(&t)) => {
+                Outcome::Slow
+            },
+            _ => Outcome::Good,
+        }
+    }
+}
+
+// how many requests had each Outcome.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SloCounts {
+    pub requests: u64,
+    pub unavailable: u64,
+    pub slow: u64,
+}
+
+impl SloCounts {
+    pub fn add(&mut self, outcome: Outcome) {
+        self.requests += 1;
+        match outcome {
+            Outcome::Good => {},
+            Outcome::Unavailable => self.unavailable += 1,
+            Outcome::Slow => self.slow += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &SloCounts) {
+        self.requests += other.requests;
+        self.unavailable += other.unavailable;
+        self.slow += other.slow;
+    }
+
+    fn remove(&mut self, other: &SloCounts) {
+        self.requests -= other.requests;
+        self.unavailable -= other.unavailable;
+        self.slow -= other.slow;
+    }
+
+    pub fn bad(&self) -> u64 {
+        self.unavailable + self.slow
+    }
+
+    // the percentage of good requests, 100 when there weren't any.
+    pub fn compliance(&self) -> f64 {
+        if self.requests == 0 {
+            100.0
+        } else {
+            100.0 * (self.requests - self.bad()) as f64 / self.requests as f64
+        }
+    }
+
+    // the number of bad requests `objective` allows out of these.
+    pub fn budget(&self, objective: &Objective) -> f64 {
+        objective.allowed() * self.requests as f64
+    }
+
+    // how many times faster than `objective` allows the error budget is being spent: 1 spends
+    // exactly the budget, 2 would spend it in half the time. an objective of 100% has no budget,
+    // so any bad request burns it infinitely fast.
+    pub fn burn_rate(&self, objective: &Objective) -> f64 {
+        let allowed = objective.allowed();
+        if self.requests == 0 {
+            0.0
+        } else if allowed <= 0.0 {
+            if self.bad() == 0 { 0.0 } else { f64::INFINITY }
+        } else {
+            self.bad() as f64 / self.requests as f64 / allowed
+        }
+    }
+}
+
+// the SloCounts of the last `length` seconds of a stream, for watching a burn rate as entries
+// arrive. times are seconds on any clock which doesn't go backwards, like the unix time an entry
+// was accepted, and outcomes are kept per second so memory is bounded by the length.
+#[derive(Clone, Debug)]
+pub struct RollingWindow {
+    length: i64,
+    // oldest first, at most one per second.
+    seconds: VecDeque<(i64, SloCounts)>,
+    counts: SloCounts,
+}
+
+impl RollingWindow {
+    pub fn new(length: i64) -> RollingWindow {
+        RollingWindow {
+            length: length.max(1),
+            seconds: VecDeque::new(),
+            counts: SloCounts::default(),
+        }
+    }
+
+    // counts `outcome` at `time`, moving the window up to it. outcomes older than the window are
+    // left out.
+    pub fn add(&mut self, time: i64, outcome: Outcome) {
+        self.advance(time);
+        if self.seconds.back().is_some_and(|&(latest, _)| time <= latest - self.length) {
+            return;
+        }
+        let i = match self.seconds.iter().rposition(|&(second, _)| second <= time) {
+            Some(i) if self.seconds[i].0 == time => i,
+            found => {
+                // times are nearly always in order, but late ones go where they belong.
+                let i = found.map_or(0, |i| i + 1);
+                self.seconds.insert(i, (time, SloCounts::default()));
+                i
+            },
+        };
+        self.seconds[i].1.add(outcome);
+        self.counts.add(outcome);
+    }
+
+    // moves the end of the window to `now`, dropping what's older than the window.
+    pub fn advance(&mut self, now: i64) {
+        while let Some(&(second, counts)) = self.seconds.front() {
+            if second > now - self.length {
+                break;
+            }
+            self.counts.remove(&counts);
+            self.seconds.pop_front();
+        }
+    }
+
+    pub fn counts(&self) -> SloCounts {
+        self.counts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Objective, Outcome, RollingWindow, SloCounts};
+    use crate::entry::test::TestLine;
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn outcomes() {
+        let mut objective = Objective::new(99.0);
+        let outcome = |objective: &Objective, timers, status| {
+            let line = TestLine::new().timers(timers).status(status).to_string();
+            objective.classify(&LogEntry::from_bytes(line.as_bytes()).unwrap())
+        };
+        assert_eq!(outcome(&objective, "10/0/30/69/109", "200"), Outcome::Good);
+        assert_eq!(outcome(&objective, "10/0/30/69/+9000", "404"), Outcome::Good);
+        assert_eq!(outcome(&objective, "10/0/30/69/109", "503"), Outcome::Unavailable);
+        assert_eq!(outcome(&objective, "-1/-1/-1/-1/+3000", "-1"), Outcome::Unavailable);
+        objective.set_latency(500);
+        assert_eq!(outcome(&objective, "10/0/30/69/+9000", "404"), Outcome::Slow);
+        assert_eq!(outcome(&objective, "10/0/30/69/500", "200"), Outcome::Slow);
+        assert_eq!(outcome(&objective, "10/0/30/69/-1", "200"), Outcome::Slow);
+        assert_eq!(outcome(&objective, "10/0/30/69/499", "200"), Outcome::Good);
+
+        let mut counts = SloCounts::default();
+        assert_eq!(counts.compliance(), 100.0);
+        assert_eq!(counts.burn_rate(&objective), 0.0);
+        for i in 0..200 {
+            counts.add(if i < 4 { Outcome::Slow } else { Outcome::Good });
+        }
+        assert_eq!(counts.compliance(), 98.0);
+        assert_eq!(counts.budget(&objective), 2.0);
+        assert!((counts.burn_rate(&objective) - 2.0).abs() < 1e-9);
+        assert_eq!(counts.burn_rate(&Objective::new(100.0)), f64::INFINITY);
+    }
+
+    #[test]
+    fn windows() {
+        let mut window = RollingWindow::new(60);
+        window.add(1000, Outcome::Good);
+        window.add(1000, Outcome::Unavailable);
+        window.add(1030, Outcome::Slow);
+        window.add(1010, Outcome::Good);
+        assert_eq!(window.counts(), SloCounts { requests: 4, unavailable: 1, slow: 1 });
+
+        window.add(1065, Outcome::Good);
+        assert_eq!(window.counts(), SloCounts { requests: 3, unavailable: 0, slow: 1 });
+        // too late to count.
+        window.add(1005, Outcome::Unavailable);
+        assert_eq!(window.counts().requests, 3);
+
+        window.advance(1200);
+        assert_eq!(window.counts(), SloCounts::default());
+    }
+}
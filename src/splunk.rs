@@ -0,0 +1,227 @@
+use std::io;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+use chrono::TimeZone;
+
+use crate::entry::LogEntry;
+use crate::json::{write_entry_json, FieldSet};
+
+const DEFAULT_BATCH_EVENTS: usize = 1000;
+
+// sends entries to a Splunk HTTP Event Collector as JSON events, `batch_size` to a request. each
+// event is FieldSet::all's object of the entry, at the time the request was accepted. a request
+// which fails because Splunk is busy or can't be reached is tried again after a second, then two,
+// and so on `retries` times. one the collector refuses, like for a bad token or event, is dropped.
+// entries written since the last request are only sent by `flush`, which should be called at the
+// end and whenever the input pauses.
+pub struct SplunkHecWriter {
+    url: String,
+    token: String,
+    index: Option<String>,
+    sourcetype: String,
+    host: Option<String>,
+    fields: FieldSet,
+    agent: ureq::Agent,
+    batch: Vec<u8>,
+    events: usize,
+    batch_size: usize,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl SplunkHecWriter {
+    // `url` is the collector's event endpoint, e.g. https://splunk:8088/services/collector/event,
+    // and `token` one of its tokens.
+    pub fn new(url: &str, token: &str) -> SplunkHecWriter {
+        SplunkHecWriter {
+            url: url.to_string(),
+            token: token.to_string(),
+            index: None,
+            sourcetype: "haproxy:http".to_string(),
+            host: None,
+            fields: FieldSet::all(),
+            agent: ureq::Agent::new(),
+            batch: vec![],
+            events: 0,
+            batch_size: DEFAULT_BATCH_EVENTS,
+            retries: 3,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    // the index events go to, the token's default one otherwise.
+    pub fn set_index(&mut self, index: &str) {
+        self.index = Some(index.to_string());
+    }
+
+    // haproxy:http, the Splunk Add-on for HAProxy's, by default.
+    pub fn set_sourcetype(&mut self, sourcetype: &str) {
+        self.sourcetype = sourcetype.to_string();
+    }
+
+    // the host events are from, the collector's idea of it otherwise.
+    pub fn set_host(&mut self, host: &str) {
+        self.host = Some(host.to_string());
+    }
+
+    // how many entries go in a request, a thousand by default.
+    pub fn set_batch_size(&mut self, entries: usize) {
+        self.batch_size = entries.max(1);
+    }
+
+    // how many times a request is tried again, three by default.
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    // adds `entry` to the batch, with accept_date taken to be in `timezone`.
+    pub fn write<Tz: TimeZone>(&mut self, entry: &LogEntry, timezone: &Tz) -> io::Result<()> {
+        let accepted = entry.accept_date_time().ok()
+            .and_then(|accepted| timezone.from_local_datetime(&accepted).earliest());
+        self.batch.push(b'{');
+        if let Some(accepted) = accepted {
+            write!(self.batch, "\"time\":{:.3},", accepted.timestamp_millis() as f64 / 1000.0)?;
+        }
+        write!(self.batch, "\"sourcetype\":")?;
+        serde_json::to_writer(&mut self.batch, &self.sourcetype)?;
+        if let Some(ref index) = self.index {
+            write!(self.batch, ",\"index\":")?;
+            serde_json::to_writer(&mut self.batch, index)?;
+        }
+        if let Some(ref host) = self.host {
+            write!(self.batch, ",\"host\":")?;
+            serde_json::to_writer(&mut self.batch, host)?;
+        }
+        write!(self.batch, ",\"event\":")?;
+        write_entry_json(entry, &mut self.batch, &self.fields)?;
+        // write_entry_json ends the object with a newline, which is fine between events.
+        self.batch.insert(self.batch.len() - 1, b'}');
+        self.events += 1;
+        if self.events >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // sends the entries written since the last request, if there are any. the batch is kept when
+    // the collector is busy or can't be reached after the retries, to try again on the next flush,
+    // and dropped when it refuses the batch, since sending it again would only be refused again.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.events == 0 {
+            return Ok(());
+        }
+        let mut delay = self.retry_delay;
+        let mut attempts = 0;
+        loop {
+            let response = self.agent.post(&self.url)
+                .set("Authorization", &format!("Splunk {}", self.token))
+                .send_bytes(&self.batch);
+            let err = match response {
+                Ok(_) => break,
+                // busy, or overloaded, which is worth waiting out.
+                Err(ureq::Error::Status(status @ (429 | 500..=599), response)) => {
+                    status_error(&self.url, status, response)
+                },
+                Err(ureq::Error::Status(status, response)) => {
+                    self.batch.clear();
+                    self.events = 0;
+                    return Err(status_error(&self.url, status, response));
+                },
+                Err(ureq::Error::Transport(err)) => io::Error::other(err),
+            };
+            if attempts == self.retries {
+                return Err(err);
+            }
+            attempts += 1;
+            thread::sleep(delay);
+            delay *= 2;
+        }
+        self.batch.clear();
+        self.events = 0;
+        Ok(())
+    }
+}
+
+// the collector says what went wrong as {"text": ..., "code": ...}.
+fn status_error(url: &str, status: u16, response: ureq::Response) -> io::Error {
+    let message = response.into_string().unwrap_or_default();
+    io::Error::other(format!("{} answered {}: {}", url, status, message.trim()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::SplunkHecWriter;
+    use crate::entry::LogEntry;
+    use chrono::Utc;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn events() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/services/collector/event", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut bodies = vec![];
+            for answer in &["503 Service Unavailable", "200 OK", "403 Forbidden"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut headers = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    headers.push(line.trim_end().to_string());
+                }
+                assert!(headers.contains(&"Authorization: Splunk 0000-1111".to_string()));
+                let length: usize = headers.iter()
+                    .find_map(|header| header.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(body);
+
+                let message = r#"{"text":"Invalid token","code":4}"#;
+                write!(&stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                       answer, message.len(), message).unwrap();
+            }
+            bodies
+        });
+
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                     \"GET / HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let mut writer = SplunkHecWriter::new(&url, "0000-1111");
+        writer.set_index("web");
+        writer.retry_delay = Duration::from_millis(1);
+        writer.write(&entry, &Utc).unwrap();
+        writer.write(&entry, &Utc).unwrap();
+        writer.flush().unwrap();
+        writer.write(&entry, &Utc).unwrap();
+        let err = writer.flush().unwrap_err();
+        assert!(err.to_string().ends_with("answered 403: {\"text\":\"Invalid token\",\"code\":4}"));
+        // the refused batch isn't sent again.
+        writer.flush().unwrap();
+
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies[0], bodies[1]);
+        let events: Vec<serde_json::Value> = serde_json::Deserializer::from_slice(&bodies[0])
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["time"], 1233922454.655);
+        assert_eq!(events[0]["sourcetype"], "haproxy:http");
+        assert_eq!(events[0]["index"], "web");
+        assert_eq!(events[0]["event"]["status_code"], 200);
+        assert_eq!(events[0]["event"]["backend_name"], "static");
+    }
+}
@@ -0,0 +1,141 @@
+use futures_core::Stream;
+use std::future;
+use std::io;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncBufRead;
+
+use crate::newline::find_newline;
+use crate::owned::OwnedLogEntry;
+
+// the entries of an async reader, a socket or a file being followed, for services which would
+// otherwise need a thread blocked on a LineReader. lines are parsed without their newline and the
+// last line is parsed at the end of the input even without one. blank lines and lines which don't
+// parse are skipped, the latter are counted by invalid_lines. each entry owns its line, so they can
+// be sent on to other tasks.
+pub struct LogStream<R> {
+    reader: R,
+    line: Vec<u8>,
+    invalid: u64,
+}
+
+impl<R: AsyncBufRead + Unpin> LogStream<R> {
+    pub fn new(reader: R) -> LogStream<R> {
+        LogStream {
+            reader,
+            line: vec![],
+            invalid: 0,
+        }
+    }
+
+    // the next entry, or None at the end of the input. the same as StreamExt::next without needing
+    // futures or tokio-stream for it.
+    pub async fn next_entry(&mut self) -> io::Result<Option<OwnedLogEntry>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await.transpose()
+    }
+
+    pub fn invalid_lines(&self) -> u64 {
+        self.invalid
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for LogStream<R> {
+    type Item = io::Result<OwnedLogEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            let buf = match Pin::new(&mut this.reader).poll_fill_buf(cx) {
+                Poll::Ready(Ok(buf)) => buf,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending,
+            };
+            if buf.is_empty() && this.line.is_empty() {
+                return Poll::Ready(None);
+            }
+
+            // a line split across reads is put together in `line`, a whole one is copied once.
+            let consumed = match find_newline(buf) {
+                Some(i) => {
+                    this.line.extend_from_slice(&buf[..i]);
+                    i + 1
+                },
+                None if buf.is_empty() => 0,
+                None => {
+                    let len = buf.len();
+                    this.line.extend_from_slice(buf);
+                    Pin::new(&mut this.reader).consume(len);
+                    continue;
+                },
+            };
+            Pin::new(&mut this.reader).consume(consumed);
+
+            let line = mem::take(&mut this.line);
+            if line.is_empty() {
+                continue;
+            }
+            match OwnedLogEntry::parse(line) {
+                Ok(entry) => return Poll::Ready(Some(Ok(entry))),
+                Err(_) => this.invalid += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LogStream;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncBufRead, AsyncRead, BufReader, ReadBuf};
+
+    const LINE: &[u8] = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                          static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \
+                          \"GET /index.html HTTP/1.1\"";
+
+    // hands out `chunk` bytes of `input` at a time, as a socket might.
+    struct Chunked {
+        input: &'static [u8],
+        chunk: usize,
+    }
+
+    impl AsyncRead for Chunked {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context, buf: &mut ReadBuf)
+                     -> Poll<io::Result<()>> {
+            let n = self.chunk.min(self.input.len()).min(buf.remaining());
+            buf.put_slice(&self.input[..n]);
+            self.input = &self.input[n..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn collect<R: AsyncBufRead + Unpin>(reader: R) -> (Vec<Vec<u8>>, u64) {
+        let mut stream = LogStream::new(reader);
+        let mut lines = vec![];
+        while let Some(entry) = stream.next_entry().await.unwrap() {
+            assert_eq!(entry.entry().status_code().unwrap(), 200);
+            lines.push(entry.into_line());
+        }
+        (lines, stream.invalid_lines())
+    }
+
+    #[tokio::test]
+    async fn entries() {
+        let input: &'static [u8] = Box::leak([LINE, b"\n\ngarbage\n", LINE, b"\n", LINE].concat()
+                                                 .into_boxed_slice());
+        let expected = (vec![LINE.to_vec(); 3], 1);
+        assert_eq!(collect(input).await, expected);
+        for &chunk in &[1, 7, 64, 4096] {
+            let reader = BufReader::with_capacity(16, Chunked { input, chunk });
+            assert_eq!(collect(reader).await, expected);
+        }
+        assert_eq!(collect(&b""[..]).await, (vec![], 0));
+        assert_eq!(collect(&b"\n\n"[..]).await, (vec![], 0));
+    }
+}
@@ -0,0 +1,99 @@
+use std::io;
+use std::io::Write;
+
+// a simple table of text cells, printed either as aligned columns for people or separated by a
+// delimiter for other tools.
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<S: ToString>(header: &[S]) -> Table {
+        Table {
+            header: header.iter().map(|s| s.to_string()).collect(),
+            rows: vec![],
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn write_delimited<W: Write>(&self, out: &mut W, delimiter: &str) -> io::Result<()> {
+        writeln!(out, "{}", self.header.join(delimiter))?;
+        for row in &self.rows {
+            writeln!(out, "{}", row.join(delimiter))?;
+        }
+        Ok(())
+    }
+
+    // the first column is left aligned since it's usually a name, the rest are right aligned.
+    pub fn write_aligned<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut widths: Vec<usize> = self.header.iter().map(|cell| cell.chars().count()).collect();
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate() {
+                let width = cell.chars().count();
+                if i >= widths.len() {
+                    widths.push(width);
+                } else if width > widths[i] {
+                    widths[i] = width;
+                }
+            }
+        }
+
+        for row in Some(&self.header).into_iter().chain(self.rows.iter()) {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                let padding = widths[i] - cell.chars().count();
+                if i == 0 {
+                    line.push_str(cell);
+                    line.extend(std::iter::repeat_n(' ', padding));
+                } else {
+                    line.push_str("  ");
+                    line.extend(std::iter::repeat_n(' ', padding));
+                    line.push_str(cell);
+                }
+            }
+            writeln!(out, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Table;
+
+    fn sample() -> Table {
+        let mut table = Table::new(&["name", "requests"]);
+        table.push(vec!["static".to_string(), "12".to_string()]);
+        table.push(vec!["dynamic-backend".to_string(), "3".to_string()]);
+        table
+    }
+
+    #[test]
+    fn aligned() {
+        let mut out = vec![];
+        sample().write_aligned(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!("name             requests\n",
+                                                            "static                 12\n",
+                                                            "dynamic-backend         3\n"));
+    }
+
+    #[test]
+    fn delimited() {
+        let mut out = vec![];
+        sample().write_delimited(&mut out, "\t").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "name\trequests\nstatic\t12\ndynamic-backend\t3\n");
+    }
+}
@@ -0,0 +1,86 @@
+use std::io;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::ecs::write_entry_ecs;
+use crate::entry::LogEntry;
+
+// a timezone as the haproxy-* tools take it with --tz and --assume-tz: UTC, Local or an IANA name
+// like Europe/Paris.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeZoneArg {
+    Utc,
+    Local,
+    Named(Tz),
+}
+
+impl rustc_serialize::Decodable for TimeZoneArg {
+    fn decode<D: rustc_serialize::Decoder>(d: &mut D) -> Result<TimeZoneArg, D::Error> {
+        let name = d.read_str()?;
+
+        match &*name.to_ascii_lowercase() {
+            "utc" => Ok(TimeZoneArg::Utc),
+            "local" => Ok(TimeZoneArg::Local),
+            _ => match name.parse() {
+                Ok(tz) => Ok(TimeZoneArg::Named(tz)),
+                Err(_) => Err(d.error(&format!("unknown timezone '{}'", name))),
+            },
+        }
+    }
+}
+
+impl TimeZoneArg {
+    pub fn localize(&self, date_time: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        // a local time can be ambiguous or skipped entirely around DST changes. haproxy has the
+        // same problem, so just take the earlier reading.
+        match *self {
+            TimeZoneArg::Utc => Some(Utc.from_utc_datetime(date_time).fixed_offset()),
+            TimeZoneArg::Local => {
+                Local.from_local_datetime(date_time).earliest().map(|d| d.fixed_offset())
+            },
+            TimeZoneArg::Named(tz) => {
+                tz.from_local_datetime(date_time).earliest().map(|d| d.fixed_offset())
+            },
+        }
+    }
+
+    // appends write_entry_ecs's document for `entry` with accept_date taken to be in this zone.
+    pub fn write_ecs(&self, entry: &LogEntry, out: &mut Vec<u8>) -> io::Result<()> {
+        match *self {
+            TimeZoneArg::Utc => write_entry_ecs(entry, &Utc, out),
+            TimeZoneArg::Local => write_entry_ecs(entry, &Local, out),
+            TimeZoneArg::Named(tz) => write_entry_ecs(entry, &tz, out),
+        }
+    }
+
+    pub fn convert(&self, date_time: &DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match *self {
+            TimeZoneArg::Utc => date_time.with_timezone(&Utc).fixed_offset(),
+            TimeZoneArg::Local => date_time.with_timezone(&Local).fixed_offset(),
+            TimeZoneArg::Named(tz) => date_time.with_timezone(&tz).fixed_offset(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TimeZoneArg;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn localize() {
+        let paris = TimeZoneArg::Named("Europe/Paris".parse().unwrap());
+        let date_time = NaiveDate::from_ymd_opt(2009, 2, 6).unwrap()
+            .and_hms_opt(12, 14, 14)
+            .unwrap();
+        let localized = paris.localize(&date_time).unwrap();
+        assert_eq!(localized.offset().local_minus_utc(), 3600);
+        assert_eq!(TimeZoneArg::Utc.convert(&localized).naive_local(),
+                   date_time - chrono::Duration::hours(1));
+
+        // skipped when the clocks went forward.
+        let skipped = NaiveDate::from_ymd_opt(2009, 3, 29).unwrap().and_hms_opt(2, 30, 0).unwrap();
+        assert_eq!(paris.localize(&skipped), None);
+    }
+}
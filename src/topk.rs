@@ -0,0 +1,195 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::entry::LogEntry;
+use crate::field::Field;
+
+// one of the most frequent keys TopK has seen. `count` is an estimate which may be over the true
+// count, by at most `error`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HeavyHitter<'a> {
+    pub key: &'a [u8],
+    pub count: u64,
+    pub error: u64,
+}
+
+// the most frequent keys of an unbounded stream, in memory bounded by `capacity` keys, using the
+// space saving algorithm: once full, a new key replaces the least frequent one and inherits its
+// count as the error. any key seen more than total / capacity times is guaranteed to be kept, so
+// keeping ten times as many keys as will be asked for gives good estimates of the top ones.
+#[derive(Clone, Debug)]
+pub struct TopK {
+    capacity: usize,
+    // the count and error of each key kept.
+    counters: HashMap<Vec<u8>, (u64, u64)>,
+    // the same keys ordered by count, to find the least frequent quickly.
+    by_count: BTreeSet<(u64, Vec<u8>)>,
+    total: u64,
+}
+
+impl TopK {
+    pub fn new(capacity: usize) -> TopK {
+        TopK {
+            capacity: capacity.max(1),
+            counters: HashMap::new(),
+            by_count: BTreeSet::new(),
+            total: 0,
+        }
+    }
+
+    pub fn add(&mut self, key: &[u8]) {
+        self.add_weighted(key, 1);
+    }
+
+    // counts `key` `weight` times, e.g. for counting bytes rather than requests.
+    pub fn add_weighted(&mut self, key: &[u8], weight: u64) {
+        self.total += weight;
+        if let Some(counter) = self.counters.get_mut(key) {
+            self.by_count.remove(&(counter.0, key.to_vec()));
+            counter.0 += weight;
+            self.by_count.insert((counter.0, key.to_vec()));
+            return;
+        }
+
+        let mut error = 0;
+        if self.counters.len() >= self.capacity {
+            if let Some((least, evicted)) = self.by_count.pop_first() {
+                self.counters.remove(&evicted);
+                error = least;
+            }
+        }
+        self.counters.insert(key.to_vec(), (error + weight, error));
+        self.by_count.insert((error + weight, key.to_vec()));
+    }
+
+    // up to `n` of the most frequent keys, most frequent first.
+    pub fn top(&self, n: usize) -> Vec<HeavyHitter<'_>> {
+        self.by_count.iter().rev().take(n)
+            .map(|(count, key)| HeavyHitter {
+                key,
+                count: *count,
+                error: self.counters[key].1,
+            })
+            .collect()
+    }
+
+    // the weight of everything added, kept or not.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.clear();
+        self.by_count.clear();
+        self.total = 0;
+    }
+}
+
+// a TopK of the values of a field of each entry, e.g. the busiest clients. http_uri is counted by
+// its path, since the query string would make almost every one unique.
+#[derive(Clone, Debug)]
+pub struct HeavyHitters {
+    field: Field,
+    top: TopK,
+}
+
+impl HeavyHitters {
+    pub fn new(field: Field, capacity: usize) -> HeavyHitters {
+        HeavyHitters {
+            field,
+            top: TopK::new(capacity),
+        }
+    }
+
+    pub fn add(&mut self, entry: &LogEntry) {
+        let mut value = self.field.extract_content_from(entry);
+        if self.field == Field::HttpUri {
+            value = value.split(|&c| c == b'?').next().unwrap_or(value);
+        }
+        self.top.add(value);
+    }
+
+    pub fn field(&self) -> Field {
+        self.field
+    }
+
+    pub fn top(&self, n: usize) -> Vec<HeavyHitter<'_>> {
+        self.top.top(n)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.top.total()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HeavyHitter, HeavyHitters, TopK};
+    use crate::entry::LogEntry;
+    use crate::field::Field;
+
+    #[test]
+    fn space_saving() {
+        let mut top = TopK::new(3);
+        for key in ["a", "b", "a", "c", "a", "b"] {
+            top.add(key.as_bytes());
+        }
+        assert_eq!(top.top(2), [
+            HeavyHitter { key: b"a", count: 3, error: 0 },
+            HeavyHitter { key: b"b", count: 2, error: 0 },
+        ]);
+
+        // replaces c, the least frequent, and takes its count as the error.
+        top.add(b"d");
+        assert_eq!(top.len(), 3);
+        assert_eq!(top.total(), 7);
+        assert!(top.top(3).contains(&HeavyHitter { key: b"d", count: 2, error: 1 }));
+
+        // a skewed stream of many keys keeps the heavy ones with good counts.
+        let mut top = TopK::new(50);
+        for i in 0..100_000u64 {
+            let key = if i % 10 < 3 { format!("heavy{}", i % 10) } else { format!("key{}", i) };
+            top.add(key.as_bytes());
+        }
+        let heavy = top.top(3);
+        assert!(heavy.iter().all(|hitter| hitter.key.starts_with(b"heavy")));
+        for hitter in heavy {
+            assert!(hitter.count >= 10_000 && hitter.count - hitter.error <= 10_000);
+        }
+
+        top.add_weighted(b"bytes", 1_000_000);
+        assert_eq!(top.top(1)[0].key, b"bytes");
+        top.clear();
+        assert!(top.is_empty());
+    }
+
+    #[test]
+    fn fields() {
+        let lines: &[&[u8]] = &[
+            b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET /a?x=1 HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.2:33318 [06/Feb/2009:12:14:15.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET /a?x=2 HTTP/1.1\"",
+            b"haproxy[14389]: 10.0.1.3:33319 [06/Feb/2009:12:14:16.655] http-in static/srv1 \
+              10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET /b HTTP/1.1\"",
+        ];
+        let mut clients = HeavyHitters::new(Field::ClientIp, 10);
+        let mut paths = HeavyHitters::new(Field::HttpUri, 10);
+        for line in lines {
+            let entry = LogEntry::from_bytes(line).unwrap();
+            clients.add(&entry);
+            paths.add(&entry);
+        }
+        assert_eq!(clients.top(1), [HeavyHitter { key: b"10.0.1.2", count: 2, error: 0 }]);
+        assert_eq!(paths.top(1), [HeavyHitter { key: b"/a", count: 2, error: 0 }]);
+        assert_eq!(paths.total(), 3);
+    }
+}
@@ -0,0 +1,106 @@
+// the parser for javascript, in the browser or a worker. it doesn't need std, so the package is
+// built with `wasm-pack build -- --no-default-features --features wasm`.
+extern crate alloc;
+
+use alloc::string::ToString;
+use core::str;
+
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::entry::{LogEntry, HEADER_FIELD_NAMES};
+use crate::field::Field;
+use crate::integer::parse_field;
+
+// what a field becomes in javascript.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value<'a> {
+    // a string, or a Uint8Array in the rare case the field isn't utf8.
+    Text(&'a [u8]),
+    // a number, or null when haproxy logged something else there.
+    Number(Option<i64>),
+}
+
+// parses a line into a plain object with a member for each field of LogEntry, the capture blocks
+// and the request included, under the names it's serialized with. numbers are numbers, and a line
+// which doesn't parse throws.
+#[wasm_bindgen(js_name = parseLine)]
+pub fn parse_line(line: &str) -> Result<Object, JsError> {
+    match LogEntry::from_bytes(line.as_bytes()) {
+        Ok(entry) => Ok(object(&entry)),
+        Err(err) => Err(JsError::new(&err.to_string())),
+    }
+}
+
+// parses every line of `text` into an array of objects like parseLine's, for a whole file read in
+// the browser. lines which don't parse are left out.
+#[wasm_bindgen(js_name = parseLines)]
+pub fn parse_lines(text: &str) -> Array {
+    let entries = Array::new();
+    for line in text.lines() {
+        if let Ok(entry) = LogEntry::from_bytes(line.as_bytes()) {
+            entries.push(&object(&entry));
+        }
+    }
+    entries
+}
+
+fn object(entry: &LogEntry) -> Object {
+    let object = Object::new();
+    values(entry, |name, value| {
+        let value = match value {
+            Value::Text(text) => match str::from_utf8(text) {
+                Ok(text) => JsValue::from_str(text),
+                Err(_) => Uint8Array::from(text).into(),
+            },
+            Value::Number(Some(number)) => JsValue::from_f64(number as f64),
+            Value::Number(None) => JsValue::NULL,
+        };
+        // setting a member of a plain object can't fail.
+        let _ = Reflect::set(&object, &JsValue::from_str(name), &value);
+    });
+    object
+}
+
+// calls `each` with the name and value of every field, in the order they're logged.
+fn values<'a, F: FnMut(&'static str, Value<'a>)>(entry: &LogEntry<'a>, mut each: F) {
+    for (&name, field) in HEADER_FIELD_NAMES.iter().zip(Field::HEADER) {
+        let content = field.extract_content_from(entry);
+        if field.is_numeric() {
+            each(name, Value::Number(parse_field(content)));
+        } else {
+            each(name, Value::Text(content));
+        }
+    }
+    each("captured_request_headers", Value::Text(entry.captures[0]));
+    each("captured_response_headers", Value::Text(entry.captures[1]));
+    each("http_request", Value::Text(entry.http_request));
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{values, Value};
+    use crate::entry::LogEntry;
+
+    #[test]
+    fn fields() {
+        let line = b"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in \
+                     static/srv1 -1/0/30/69/+109 200 2750 - - ---- 1/1/1/1/+1 0/0 \
+                     {1wt.eu} \"GET / HTTP/1.1\"";
+        let entry = LogEntry::from_bytes(line).unwrap();
+        let mut fields = vec![];
+        values(&entry, |name, value| fields.push((name, value)));
+
+        assert_eq!(fields.len(), 28);
+        assert_eq!(fields[0], ("process_name", Value::Text(b"haproxy")));
+        assert_eq!(fields[1], ("pid", Value::Number(Some(14389))));
+        assert_eq!(fields[4], ("accept_date", Value::Text(b"06/Feb/2009:12:14:14.655")));
+        assert_eq!(fields[8], ("request_time", Value::Number(Some(-1))));
+        assert_eq!(fields[12], ("total_time", Value::Number(Some(109))));
+        assert_eq!(fields[13], ("status_code", Value::Number(Some(200))));
+        assert_eq!(fields[15], ("captured_request_cookie", Value::Text(b"-")));
+        assert_eq!(fields[22], ("retried_connections", Value::Number(Some(1))));
+        assert_eq!(fields[25], ("captured_request_headers", Value::Text(b"1wt.eu")));
+        assert_eq!(fields[27], ("http_request", Value::Text(b"GET / HTTP/1.1")));
+    }
+}
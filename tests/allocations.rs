@@ -0,0 +1,90 @@
+// a binary of its own, since replacing the global allocator would put every other test under it.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use haproxy::{recycle_entries, write_fields_into, Field, LogEntry, Plan};
+
+// counts allocations made by the current thread, so tests running alongside don't interfere.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(|count| count.get());
+    f();
+    ALLOCATIONS.with(|count| count.get()) - before
+}
+
+#[test]
+fn parse_without_allocating() {
+    let sample = concat!("haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] ",
+                         "http-in static/srv1 10/0/30/69/109 200 2750 cookie_in cookie_out ---- ",
+                         "1/1/1/1/0 0/0 {1wt.eu} {} \"GET /index.html HTTP/1.1\"").as_bytes();
+    let mut plan = Plan::new();
+    plan.add_field(&Field::StatusCode);
+    let fields = [Field::ClientIp, Field::StatusClass, Field::HttpUri,
+                  Field::CapturedHeader(0, 0)];
+    let mut out: Vec<u8> = Vec::with_capacity(1024);
+
+    let count = allocations(|| {
+        let entry = LogEntry::from_bytes(sample).unwrap();
+        assert_eq!(entry.total_time().unwrap(), 109);
+        assert_eq!(entry.http_uri().unwrap(), b"/index.html");
+        assert!(entry.accept_date_time().is_ok());
+        write_fields_into(&mut out, &entry, &fields, b"\t").unwrap();
+
+        assert!(LogEntry::from_bytes_single_pass(sample).is_ok());
+        assert!(plan.parse(sample).is_ok());
+        assert!(LogEntry::from_bytes(&sample[..100]).is_err());
+        assert!(LogEntry::from_bytes(b"").is_err());
+    });
+    assert_eq!(count, 0);
+
+    // make sure the allocator is really counting.
+    assert!(allocations(|| drop(std::hint::black_box(vec![0u8; 16]))) > 0);
+}
+
+#[test]
+fn parse_many_into_recycled_entries() {
+    let line = concat!("haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] ",
+                       "http-in static/srv1 10/0/30/69/109 200 2750 - - ---- ",
+                       "1/1/1/1/0 0/0 \"GET /index.html HTTP/1.1\"");
+    let first = format!("{}\n{}\n", line, line);
+    let mut entries = vec![];
+    assert_eq!(LogEntry::parse_many(first.as_bytes(), &mut entries), 0);
+
+    let second = format!("{}\n{}", line, line);
+    let capacity = entries.capacity();
+    let mut entries = recycle_entries(entries);
+    let count = allocations(|| {
+        assert_eq!(LogEntry::parse_many(second.as_bytes(), &mut entries), 0);
+    });
+    assert_eq!(count, 0);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries.capacity(), capacity);
+}